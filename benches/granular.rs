@@ -0,0 +1,161 @@
+//! Criterion benchmarks for the granular engine.
+//!
+//! Run with `cargo bench`. Results land in `target/criterion`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use granular_effect::engine::{
+    apply_tukey, Engine, FrameParams, ParamSource, RING_SEC, TUKEY_ALPHA,
+};
+use granular_effect::macros::{MacroMap, Macros, MACRO_COUNT};
+use granular_effect::modulation::{ModDest, ModSlot, ModSource, MOD_SLOTS};
+use rand::{rngs::SmallRng, SeedableRng};
+use std::hint::black_box;
+
+const SR: f32 = 44_100.0;
+const N_CH: usize = 2;
+const MAX_BLOCK: usize = 4096;
+
+/// リングを埋めた状態のエンジンを作る
+fn prepared_engine() -> Engine {
+    let mut engine = Engine::default();
+    engine.initialize(SR, N_CH, MAX_BLOCK);
+    let mut rng = SmallRng::seed_from_u64(1);
+    let mut params = FrameParams {
+        density: 0.0,
+        ..FrameParams::default()
+    };
+    let ring_len = ((SR * RING_SEC) as usize).next_power_of_two();
+    let mut filled = 0;
+    let mut l = vec![0.0; MAX_BLOCK];
+    let mut r = vec![0.0; MAX_BLOCK];
+    while filled < ring_len {
+        for (i, (a, b)) in l.iter_mut().zip(r.iter_mut()).enumerate() {
+            let t = (filled + i) as f32 / SR;
+            *a = (t * 440.0 * std::f32::consts::TAU).sin();
+            *b = *a;
+        }
+        let mut io: [&mut [f32]; N_CH] = [&mut l, &mut r];
+        engine.process(&mut io, &mut params, &mut rng);
+        filled += MAX_BLOCK;
+    }
+    engine
+}
+
+fn bench_window(c: &mut Criterion) {
+    let mut group = c.benchmark_group("window");
+    for len in [441usize, 4_410, 44_100] {
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &len, |b, &len| {
+            let mut buf = vec![1.0f32; len];
+            b.iter(|| apply_tukey(black_box(&mut buf), TUKEY_ALPHA));
+        });
+    }
+    group.finish();
+}
+
+fn bench_spawn(c: &mut Criterion) {
+    let mut engine = prepared_engine();
+    let mut rng = SmallRng::seed_from_u64(2);
+    let min_len = (0.02 * SR) as usize;
+    let max_len = (0.5 * SR) as usize;
+    c.bench_function("spawn_grain", |b| {
+        b.iter(|| {
+            engine.spawn_grain(&mut rng, min_len, max_len, N_CH, 0, 1.0);
+            engine.clear_grains();
+        })
+    });
+}
+
+fn bench_process(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process");
+    for block in [64usize, 512, 4096] {
+        group.throughput(Throughput::Elements(block as u64));
+        for density in [0.1f32, 0.5, 1.0] {
+            let id = BenchmarkId::new(format!("density_{density}"), block);
+            group.bench_with_input(id, &block, |b, &block| {
+                let mut params = FrameParams {
+                    density,
+                    ..FrameParams::default()
+                };
+                let mut rng = SmallRng::seed_from_u64(3);
+                let mut engine = prepared_engine();
+                let mut l = vec![0.1f32; block];
+                let mut r = vec![0.1f32; block];
+                b.iter(|| {
+                    let mut io: [&mut [f32]; N_CH] = [&mut l, &mut r];
+                    engine.process(black_box(&mut io), &mut params, &mut rng);
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+/// 毎サンプル同じフレームを返すが、止まっているとは言わないパラメータ源 (スムーザーが動いている間と同じ扱い)
+struct PerSample(FrameParams);
+
+impl ParamSource for PerSample {
+    fn next_frame(&mut self) -> FrameParams {
+        self.0
+    }
+}
+
+/// シーンのモーフィング・マクロ・モジュレーションを重ねる費用。
+/// `steady` はチャンクに 1 回、`per_sample` はサンプルごとに重ね直す
+fn bench_params(c: &mut Criterion) {
+    let mut group = c.benchmark_group("params");
+    let maps: [MacroMap; MACRO_COUNT] = [
+        vec![("density".into(), 0.4), ("max_ms".into(), -0.5)],
+        vec![("mix".into(), 0.3)],
+        Vec::new(),
+        Vec::new(),
+    ];
+    let mut slots = [ModSlot::default(); MOD_SLOTS];
+    slots[0] = ModSlot {
+        source: ModSource::Lfo1,
+        dest: ModDest::Density,
+        depth: 0.3,
+    };
+    let params = FrameParams {
+        density: 0.3,
+        scene_morph: true,
+        morph: 0.5,
+        macros: [0.6, 0.2, 0.0, 0.0],
+        mod_slots: slots,
+        ..FrameParams::default()
+    };
+    for block in [64usize, 512, 4096] {
+        group.throughput(Throughput::Elements(block as u64));
+        for steady in [true, false] {
+            let name = if steady { "steady" } else { "per_sample" };
+            let id = BenchmarkId::new(name, block);
+            group.bench_with_input(id, &block, |b, &block| {
+                let mut fixed = params;
+                let mut per_sample = PerSample(params);
+                let mut rng = SmallRng::seed_from_u64(4);
+                let mut engine = prepared_engine();
+                engine.set_macros(Macros::from_maps(&maps));
+                let mut l = vec![0.1f32; block];
+                let mut r = vec![0.1f32; block];
+                b.iter(|| {
+                    let mut io: [&mut [f32]; N_CH] = [&mut l, &mut r];
+                    if steady {
+                        engine.process(black_box(&mut io), &mut fixed, &mut rng);
+                    } else {
+                        engine.process(black_box(&mut io), &mut per_sample, &mut rng);
+                    }
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_window,
+    bench_spawn,
+    bench_process,
+    bench_params
+);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+"""Generate golden fixtures for the Rust engine's regression tests.
+
+This is NOT the original Python implementation of the effect, which is not part
+of this repository. It is an independent model of Sync trigger mode, written
+for these tests: fixed-length grains from the newest input at a regular
+interval, with no randomness. Grains are shaped with the Tukey window as
+defined by ``scipy.signal.windows.tukey``, written out here so no SciPy install
+is needed.
+
+Randomized modes (Random / Tempo) draw from Rust's RNG and cannot be reproduced
+bit-for-bit here, so they are covered by the block-size and property tests
+instead.
+
+Fixtures are mono 32-bit float WAV files written to ``tests/golden``. Run from the
+repository root:
+
+    python3 scripts/make_golden.py
+"""
+
+import math
+import os
+import struct
+
+OUT_DIR = os.path.join(os.path.dirname(__file__), '..', 'tests', 'golden')
+
+# Must match src/engine.rs
+RING_SEC = 5.0
+TUKEY_ALPHA = 0.2
+
+# Sync mode case
+SYNC_SR = 8000
+SYNC_FRAMES = 4000
+SYNC_GRAIN_LEN = 200  # min_ms = max_ms = 25 ms at 8 kHz
+SYNC_OVERLAP = 4
+
+
+def f32(x):
+    """Round a Python float to the nearest f32."""
+    return struct.unpack('<f', struct.pack('<f', x))[0]
+
+
+def tukey(m, alpha):
+    """scipy.signal.windows.tukey(m, alpha, sym=True)"""
+    if m <= 1 or alpha <= 0:
+        return [1.0] * m
+    alpha = min(alpha, 1.0)
+    width = int(math.floor(alpha * (m - 1) / 2.0))
+    w = []
+    for n in range(m):
+        if n <= width:
+            w.append(0.5 * (1 + math.cos(math.pi * (-1 + 2.0 * n / alpha / (m - 1)))))
+        elif n >= m - width - 1:
+            w.append(0.5 * (1 + math.cos(math.pi * (-2.0 / alpha + 1 + 2.0 * n / alpha / (m - 1)))))
+        else:
+            w.append(1.0)
+    return w
+
+
+def write_wav(path, sr, samples):
+    data = b''.join(struct.pack('<f', s) for s in samples)
+    header = b'RIFF' + struct.pack('<I', 36 + len(data)) + b'WAVEfmt '
+    header += struct.pack('<IHHIIHH', 16, 3, 1, sr, sr * 4, 4, 32)
+    header += b'data' + struct.pack('<I', len(data))
+    with open(path, 'wb') as f:
+        f.write(header + data)
+
+
+def sync_input():
+    return [
+        f32(0.5 * math.sin(2 * math.pi * 220 * t / SYNC_SR)
+            + 0.25 * math.sin(2 * math.pi * 567 * t / SYNC_SR))
+        for t in range(SYNC_FRAMES)
+    ]
+
+
+def sync_render(x):
+    """Sync mode, mix = 1: grains of SYNC_GRAIN_LEN samples ending at the newest input,
+    every SYNC_GRAIN_LEN / SYNC_OVERLAP samples, starting to play on the frame they spawn."""
+    ring_len = int(RING_SEC * SYNC_SR)
+    ring = [0.0] * ring_len
+    wr = 0
+    countdown = 0.0
+    window = tukey(SYNC_GRAIN_LEN, TUKEY_ALPHA)
+    grains = []
+    out = []
+    for s in x:
+        ring[wr] = s
+        wr = (wr + 1) % ring_len
+        if countdown <= 0:
+            start = wr - SYNC_GRAIN_LEN
+            buf = [ring[(start + k) % ring_len] * window[k] for k in range(SYNC_GRAIN_LEN)]
+            grains.append([buf, 0])
+            countdown += max(SYNC_GRAIN_LEN / SYNC_OVERLAP, 1.0)
+        countdown -= 1
+        acc = 0.0
+        for g in grains:
+            acc += g[0][g[1]]
+            g[1] += 1
+        grains = [g for g in grains if g[1] < len(g[0])]
+        out.append(acc)
+    return out
+
+
+def main():
+    os.makedirs(OUT_DIR, exist_ok=True)
+    x = sync_input()
+    write_wav(os.path.join(OUT_DIR, 'sync_input.wav'), SYNC_SR, x)
+    write_wav(os.path.join(OUT_DIR, 'sync_expected.wav'), SYNC_SR, sync_render(x))
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,61 @@
+//! Test-only global allocator that counts the allocations made on the current thread while a
+//! closure runs. nih-plug's `assert_process_allocs` only fires inside a running host; this lets
+//! unit tests drive [`crate::engine::Engine::process`] through many settings and fail as soon as
+//! a change reintroduces an allocation on the audio thread.
+//!
+//! `permit_alloc` does not exempt anything here, so tests only cover paths that must never
+//! allocate (not ring growth or sample loading, which hand their allocations to other threads).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAlloc;
+
+thread_local! {
+    /// 数えている最中か
+    static ARMED: Cell<bool> = const { Cell::new(false) };
+    /// 数えている間の確保 (再確保を含む) の回数
+    static COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    // スレッド終了後の確保では thread_local が使えないので無視する
+    let _ = ARMED.try_with(|armed| {
+        if armed.get() {
+            COUNT.with(|c| c.set(c.get() + 1));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// `f` を実行し、その間にこのスレッドで起きた確保の回数を返す
+pub fn allocations(f: impl FnOnce()) -> usize {
+    COUNT.with(|c| c.set(0));
+    ARMED.with(|a| a.set(true));
+    f();
+    ARMED.with(|a| a.set(false));
+    COUNT.with(|c| c.get())
+}
@@ -0,0 +1,648 @@
+//! Input analysis: a YIN pitch tracker on the mono input, scale snapping for tuned grains, and
+//! a coarse loudness map, an onset list and a list of beat-grid positions of the ring for choosing
+//! where grains start. [`FluxTracker`] measures the spectral flux of the input so density can
+//! follow how busy the material is.
+//!
+//! Runs on the audio thread; all buffers are allocated in [`PitchTracker::initialize`],
+//! [`LoudnessMap::initialize`] and [`FluxTracker::initialize`], and the onset and beat lists are
+//! fixed-size arrays.
+
+use arrayvec::ArrayVec;
+use nih_plug::prelude::Enum;
+use rand::Rng;
+use rustfft::{num_complex::Complex32, Fft, FftPlanner};
+use std::sync::Arc;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const YIN_THRESHOLD: f32 = 0.15; // 累積平均正規化差分関数のしきい値
+pub const MIN_PITCH_HZ: f32 = 50.0; // 検出する最低周波数 (解析窓長はこれで決まる)
+pub const MAX_PITCH_HZ: f32 = 2_000.0; // 検出する最高周波数
+pub const MAX_TRANSPOSE_SEMIS: f32 = 12.0; // スケール補正で動かす最大半音数
+pub const LOUDNESS_BLOCK: usize = 512; // ラウドネスマップのブロック長 (サンプル)
+pub const MAX_ONSETS: usize = 64; // 覚えておくオンセットの数 (古いものから忘れる)
+pub const ONSET_RATIO: f32 = 2.0; // 速いエンベロープが遅いエンベロープのこの倍を超えたらオンセット (+6 dB)
+pub const ONSET_FLOOR_DB: f32 = -50.0; // これより小さい音の立ち上がりはオンセットとみなさない (dB)
+pub const ONSET_MIN_GAP_MS: f32 = 50.0; // オンセットの最小間隔 (ミリ秒)。1 つの打撃を何度も拾わない
+pub const MAX_BEAT_MARKS: usize = 256; // 覚えておくグリッド位置の数 (30 秒のリングに 120 BPM の 1/16 が収まる)
+const ONSET_FAST_MS: f32 = 5.0; // 速いエンベロープの戻りの時定数 (ミリ秒、立ち上がりは瞬時)
+const ONSET_SLOW_MS: f32 = 100.0; // 遅いエンベロープの時定数 (ミリ秒)
+pub const FLUX_FFT: usize = 1_024; // スペクトルフラックスの解析フレーム長 (サンプル)
+pub const FLUX_HOP: usize = 256; // スペクトルフラックスを測る間隔 (サンプル)
+pub const FLUX_FULL: f32 = 0.25; // 正規化したフラックスがこれ以上なら density を下げない
+const FLUX_RELEASE_MS: f32 = 300.0; // フラックスが下がるときの時定数 (ミリ秒、フィルの直後にすぐ薄くしない)
+const FLUX_FLOOR_DB: f32 = -60.0; // フレームの RMS がこれ未満ならフラックスを 0 とみなす (dB)
+
+/*──────────────────── 2. Pitch tracker ────────────────*/
+/// YIN によるピッチ検出器。`window` サンプルたまるごと (ホップ = 窓の半分) に解析する。
+pub struct PitchTracker {
+    /// 解析用のリングバッファ (窓長)
+    buf: Vec<f32>,
+    wr: usize,
+    /// 次の解析までの残りサンプル数
+    countdown: usize,
+    /// 差分関数のスクラッチ (窓の半分)
+    diff: Vec<f32>,
+    /// 時系列順に並べ直した窓のスクラッチ
+    frame: Vec<f32>,
+    sr: f32,
+    pitch: Option<f32>,
+}
+
+impl Default for PitchTracker {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            wr: 0,
+            countdown: 0,
+            diff: Vec::new(),
+            frame: Vec::new(),
+            sr: 0.0,
+            pitch: None,
+        }
+    }
+}
+
+impl PitchTracker {
+    /// サンプルレートに合わせて解析窓を確保する。窓の半分が MIN_PITCH_HZ の周期を覆う長さ。
+    pub fn initialize(&mut self, sr: f32) {
+        let window = ((2.0 * sr / MIN_PITCH_HZ) as usize).next_power_of_two();
+        self.buf = vec![0.0; window];
+        self.frame = vec![0.0; window];
+        self.diff = vec![0.0; window / 2];
+        self.sr = sr;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.buf.fill(0.0);
+        self.wr = 0;
+        self.countdown = self.buf.len();
+        self.pitch = None;
+    }
+
+    /// 直近の解析で検出された基本周波数 (Hz)。無声・無音なら None。
+    #[inline]
+    pub fn pitch(&self) -> Option<f32> {
+        self.pitch
+    }
+
+    /// 1 サンプル入力し、ホップごとに解析する
+    #[inline]
+    pub fn push(&mut self, x: f32) {
+        if self.buf.is_empty() {
+            return;
+        }
+        self.buf[self.wr] = x;
+        self.wr = (self.wr + 1) % self.buf.len();
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.buf.len() / 2;
+            self.pitch = self.analyze();
+        }
+    }
+
+    fn analyze(&mut self) -> Option<f32> {
+        let n = self.buf.len();
+        let half = n / 2;
+        for (i, v) in self.frame.iter_mut().enumerate() {
+            *v = self.buf[(self.wr + i) % n];
+        }
+        let x = &self.frame;
+        if x.iter().map(|v| v * v).sum::<f32>() < 1e-6 {
+            return None;
+        }
+
+        // 差分関数 d(τ) と累積平均正規化 d'(τ)
+        let min_tau = ((self.sr / MAX_PITCH_HZ) as usize).max(2);
+        self.diff[0] = 1.0;
+        let mut running = 0.0;
+        for tau in 1..half {
+            let d: f32 = x[..half]
+                .iter()
+                .zip(&x[tau..tau + half])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            running += d;
+            self.diff[tau] = if running > 0.0 {
+                d * tau as f32 / running
+            } else {
+                1.0
+            };
+        }
+
+        // しきい値を下回った最初の谷を探す
+        let mut tau = min_tau;
+        while tau < half - 1 {
+            if self.diff[tau] < YIN_THRESHOLD {
+                while tau + 1 < half - 1 && self.diff[tau + 1] < self.diff[tau] {
+                    tau += 1;
+                }
+                break;
+            }
+            tau += 1;
+        }
+        if tau >= half - 1 {
+            return None;
+        }
+
+        // 放物線補間で τ を細かく求める
+        let (a, b, c) = (self.diff[tau - 1], self.diff[tau], self.diff[tau + 1]);
+        let denom = a - 2.0 * b + c;
+        let shift = if denom.abs() > 1e-9 {
+            (0.5 * (a - c) / denom).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        Some(self.sr / (tau as f32 + shift))
+    }
+}
+
+/*──────────────────── 3. Scale snapping ───────────────*/
+/// グレインを合わせる音階
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    /// 補正しない
+    #[name = "Off"]
+    Off,
+    #[name = "Chromatic"]
+    Chromatic,
+    #[name = "Major"]
+    Major,
+    #[name = "Minor"]
+    Minor,
+    #[name = "Pentatonic"]
+    Pentatonic,
+}
+
+impl Scale {
+    /// ルートからの半音数で表した構成音
+    fn degrees(self) -> &'static [i32] {
+        match self {
+            Scale::Off => &[],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+        }
+    }
+}
+
+/// 検出ピッチ `freq` を `root` (0=C … 11=B) の `scale` 上で最も近い音へ移すための再生速度比。
+/// ピッチが無い場合やスケールが Off の場合は 1.0。
+pub fn snap_ratio(freq: Option<f32>, scale: Scale, root: i32) -> f32 {
+    let Some(freq) = freq.filter(|f| *f > 0.0) else {
+        return 1.0;
+    };
+    let degrees = scale.degrees();
+    if degrees.is_empty() {
+        return 1.0;
+    }
+    let note = 69.0 + 12.0 * (freq / 440.0).log2();
+    let octave = ((note - root as f32) / 12.0).floor() as i32;
+    let mut target = note;
+    let mut best = f32::MAX;
+    for oct in octave - 1..=octave + 1 {
+        for d in degrees {
+            let cand = (root + 12 * oct + d) as f32;
+            if (cand - note).abs() < best {
+                best = (cand - note).abs();
+                target = cand;
+            }
+        }
+    }
+    let semis = (target - note).clamp(-MAX_TRANSPOSE_SEMIS, MAX_TRANSPOSE_SEMIS);
+    2f32.powf(semis / 12.0)
+}
+
+/*──────────────────── 4. Loudness map ─────────────────*/
+/// リングを LOUDNESS_BLOCK サンプルごとに区切った平均二乗値の粗い地図。
+/// 書き込みに合わせてブロックが埋まるたびに更新し、グレインの開始位置を大きい音の区間へ寄せるのに使う。
+#[derive(Default)]
+pub struct LoudnessMap {
+    /// ブロックごとの平均二乗値
+    energy: Vec<f32>,
+    /// 書き込み中のブロックの二乗和
+    acc: f32,
+}
+
+impl LoudnessMap {
+    /// リングが `max_ring_len` まで広がっても確保し直さないよう容量を取り、`ring` から作り直す
+    pub fn initialize(&mut self, max_ring_len: usize, ring: &[f32]) {
+        let blocks = max_ring_len.div_ceil(LOUDNESS_BLOCK);
+        self.energy = Vec::with_capacity(blocks.max(ring.len().div_ceil(LOUDNESS_BLOCK)));
+        self.rebuild(ring);
+    }
+
+    /// `ring` の内容から地図を作り直す (リングを広げて中身を並べ替えたとき)
+    pub fn rebuild(&mut self, ring: &[f32]) {
+        self.energy.clear();
+        self.energy.extend(
+            ring.chunks(LOUDNESS_BLOCK)
+                .map(|b| b.iter().map(|x| x * x).sum::<f32>() / b.len() as f32),
+        );
+        self.acc = 0.0;
+    }
+
+    /// リングを消去したとき
+    pub fn clear(&mut self) {
+        self.energy.fill(0.0);
+        self.acc = 0.0;
+    }
+
+    /// リングの位置 `pos` へ `x` を書き込んだ。ブロックの最後のサンプルならそのブロックを更新する
+    #[inline]
+    pub fn push(&mut self, pos: usize, x: f32) {
+        self.acc += x * x;
+        if (pos + 1).is_multiple_of(LOUDNESS_BLOCK) {
+            if let Some(e) = self.energy.get_mut(pos / LOUDNESS_BLOCK) {
+                *e = self.acc / LOUDNESS_BLOCK as f32;
+            }
+            self.acc = 0.0;
+        }
+    }
+
+    /// 0..end の開始位置を、ブロックの平均二乗値に比例する確率で選ぶ。範囲がすべて無音なら None
+    pub fn pick(&self, rng: &mut impl Rng, end: usize) -> Option<usize> {
+        if end == 0 {
+            return None;
+        }
+        let blocks = &self.energy[..end.div_ceil(LOUDNESS_BLOCK).min(self.energy.len())];
+        let total: f32 = blocks.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut r = rng.random::<f32>() * total;
+        let b = blocks
+            .iter()
+            .position(|e| {
+                r -= e;
+                r < 0.0
+            })
+            .unwrap_or(blocks.len() - 1);
+        let lo = b * LOUDNESS_BLOCK;
+        let hi = (lo + LOUDNESS_BLOCK).min(end);
+        Some(rng.random_range(lo..hi.max(lo + 1)).min(end - 1))
+    }
+}
+
+/*──────────────────── 5. Onsets ───────────────────────*/
+/// 入力の立ち上がりを検出し、その音を書き込んだリングの位置を新しい順に MAX_ONSETS 個まで覚える。
+/// 瞬時に立ち上がるピークエンベロープが、遅い平均エンベロープの ONSET_RATIO 倍を超えた最初のサンプルを
+/// オンセットとする。
+pub struct OnsetDetector {
+    fast: f32,
+    slow: f32,
+    fast_coef: f32,
+    slow_coef: f32,
+    /// 前のオンセットからのサンプル数
+    since: usize,
+    min_gap: usize,
+    /// オンセットのリング位置 (古い順)
+    onsets: ArrayVec<usize, MAX_ONSETS>,
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        let mut detector = Self {
+            fast: 0.0,
+            slow: 0.0,
+            fast_coef: 0.0,
+            slow_coef: 0.0,
+            since: 0,
+            min_gap: 0,
+            onsets: ArrayVec::new(),
+        };
+        detector.initialize(44_100.0);
+        detector
+    }
+}
+
+impl OnsetDetector {
+    pub fn initialize(&mut self, sr: f32) {
+        self.fast_coef = (-1_000.0 / (ONSET_FAST_MS * sr)).exp();
+        self.slow_coef = (-1_000.0 / (ONSET_SLOW_MS * sr)).exp();
+        self.min_gap = (ONSET_MIN_GAP_MS / 1_000.0 * sr) as usize;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.fast = 0.0;
+        self.slow = 0.0;
+        self.since = self.min_gap;
+        self.onsets.clear();
+    }
+
+    /// 覚えているオンセットのリング位置 (古い順)
+    pub fn onsets(&self) -> &[usize] {
+        &self.onsets
+    }
+
+    /// 入力 `x` で 1 サンプル進める。`pos` は `x` を書き込んだリングの位置 (書き込まなかったなら None)。
+    /// 書き込みで上書きされるオンセットは忘れる。オンセットを検出して覚えたら true
+    #[inline]
+    pub fn push(&mut self, x: f32, pos: Option<usize>) -> bool {
+        if let Some(pos) = pos {
+            if self.onsets.first() == Some(&pos) {
+                self.onsets.remove(0);
+            }
+        }
+        let level = x.abs();
+        self.fast = level.max(self.fast * self.fast_coef);
+        self.slow = level + (self.slow - level) * self.slow_coef;
+        self.since = self.since.saturating_add(1);
+        let floor = (ONSET_FLOOR_DB * std::f32::consts::LN_10 / 20.0).exp();
+        let onset =
+            self.fast > floor && self.fast > ONSET_RATIO * self.slow && self.since > self.min_gap;
+        let Some(pos) = pos.filter(|_| onset) else {
+            return false;
+        };
+        self.since = 0;
+        if self.onsets.is_full() {
+            self.onsets.remove(0);
+        }
+        self.onsets.push(pos);
+        true
+    }
+
+    /// リングを並べ替えたとき、覚えている位置を `f` で移す
+    pub fn relocate(&mut self, f: impl Fn(usize) -> usize) {
+        for pos in &mut self.onsets {
+            *pos = f(*pos);
+        }
+    }
+
+    /// リングを消去したとき (エンベロープは残し、消えた音のオンセットだけ忘れる)
+    pub fn forget(&mut self) {
+        self.onsets.clear();
+    }
+}
+
+/*──────────────────── 6. Beat marks ───────────────────*/
+/// ホストの再生位置がグリッド上にあるときに書き込んだリングの位置
+#[derive(Default)]
+pub struct BeatMarks {
+    /// 古い順。書き込み位置がここまで回ってきたら忘れる
+    marks: ArrayVec<usize, MAX_BEAT_MARKS>,
+}
+
+impl BeatMarks {
+    pub fn marks(&self) -> &[usize] {
+        &self.marks
+    }
+
+    /// リングの位置 `pos` に書き込んだ。`on_grid` ならその位置を覚える。
+    /// 書き込みで上書きされる位置は忘れる
+    #[inline]
+    pub fn push(&mut self, pos: usize, on_grid: bool) {
+        if self.marks.first() == Some(&pos) {
+            self.marks.remove(0);
+        }
+        if on_grid {
+            if self.marks.is_full() {
+                self.marks.remove(0);
+            }
+            self.marks.push(pos);
+        }
+    }
+
+    /// リングを並べ替えたとき、覚えている位置を `f` で移す
+    pub fn relocate(&mut self, f: impl Fn(usize) -> usize) {
+        for pos in &mut self.marks {
+            *pos = f(*pos);
+        }
+    }
+
+    /// リングを消去したとき
+    pub fn forget(&mut self) {
+        self.marks.clear();
+    }
+}
+
+/*──────────────────── 7. Spectral flux ────────────────*/
+/// 入力のスペクトルフラックス (前のフレームから増えた振幅の和を振幅の和で割ったもの)。
+/// FLUX_HOP ごとに測り、立ち上がりはすぐに、下がるときは FLUX_RELEASE_MS で追従する。
+/// 持続音ではほぼ 0、打撃やフィルのように変化の多い部分で大きくなる
+#[derive(Default)]
+pub struct FluxTracker {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    /// Hann 窓
+    window: Vec<f32>,
+    /// 直近 FLUX_FFT サンプルの入力 (リング)
+    input: Vec<f32>,
+    /// FFT 用の作業領域
+    spec: Vec<Complex32>,
+    scratch: Vec<Complex32>,
+    /// 前のフレームの振幅 (0..=N/2)
+    prev: Vec<f32>,
+    pos: usize,
+    /// 次のフレームを解析するまでの残りサンプル数
+    countdown: usize,
+    /// 追従させたフラックス
+    level: f32,
+    release: f32,
+}
+
+impl FluxTracker {
+    pub fn initialize(&mut self, sr: f32) {
+        let fft = FftPlanner::new().plan_fft_forward(FLUX_FFT);
+        self.scratch = vec![Complex32::default(); fft.get_inplace_scratch_len()];
+        self.fft = Some(fft);
+        self.window = (0..FLUX_FFT)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FLUX_FFT as f32).cos())
+            .collect();
+        self.input = vec![0.0; FLUX_FFT];
+        self.spec = vec![Complex32::default(); FLUX_FFT];
+        self.prev = vec![0.0; FLUX_FFT / 2 + 1];
+        self.release = (-1_000.0 * FLUX_HOP as f32 / (FLUX_RELEASE_MS * sr)).exp();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.prev.fill(0.0);
+        self.pos = 0;
+        self.countdown = FLUX_HOP;
+        self.level = 0.0;
+    }
+
+    /// 追従させたフラックス (0.0〜1.0)
+    pub fn value(&self) -> f32 {
+        self.level
+    }
+
+    /// density に掛ける倍率。`amount` 0 では 1.0、1 ではフラックスが FLUX_FULL に届かない分だけ下げる
+    pub fn density_scale(&self, amount: f32) -> f32 {
+        let amount = amount.clamp(0.0, 1.0);
+        1.0 - amount + amount * (self.level / FLUX_FULL).min(1.0)
+    }
+
+    /// 入力 `x` で 1 サンプル進める
+    #[inline]
+    pub fn push(&mut self, x: f32) {
+        if self.input.is_empty() {
+            return;
+        }
+        self.input[self.pos] = x;
+        self.pos = (self.pos + 1) % FLUX_FFT;
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = FLUX_HOP;
+            self.frame();
+        }
+    }
+
+    /// 直近 FLUX_FFT サンプル (最も古いものが `pos`) のフラックスを測る
+    fn frame(&mut self) {
+        let Some(fft) = &self.fft else {
+            return;
+        };
+        let mut energy = 0.0;
+        for (k, c) in self.spec.iter_mut().enumerate() {
+            let x = self.input[(self.pos + k) % FLUX_FFT];
+            energy += x * x;
+            *c = Complex32::new(x * self.window[k], 0.0);
+        }
+        fft.process_with_scratch(&mut self.spec, &mut self.scratch);
+        let (mut rise, mut total) = (0.0, 0.0);
+        for (c, prev) in self.spec.iter().zip(&mut self.prev) {
+            let mag = c.norm();
+            rise += (mag - *prev).max(0.0);
+            total += mag;
+            *prev = mag;
+        }
+        let floor = (FLUX_FLOOR_DB * std::f32::consts::LN_10 / 10.0).exp();
+        let flux = if energy / (FLUX_FFT as f32) < floor || total <= 0.0 {
+            0.0
+        } else {
+            (rise / total).min(1.0)
+        };
+        self.level = if flux > self.level {
+            flux
+        } else {
+            flux + (self.level - flux) * self.release
+        };
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flux_separates_sustained_notes_from_busy_passages() {
+        let sr = 48_000.0;
+        let mut flux = FluxTracker::default();
+        flux.initialize(sr);
+        assert_eq!(flux.density_scale(1.0), 0.0);
+        assert_eq!(flux.density_scale(0.0), 1.0);
+
+        // 持続音: 鳴り始めの後はフラックスがほぼ 0 になり、density を大きく下げる
+        let sine = |i: usize| 0.5 * (std::f32::consts::TAU * 440.0 * i as f32 / sr).sin();
+        for i in 0..48_000 {
+            flux.push(sine(i));
+        }
+        assert!(flux.density_scale(1.0) < 0.2, "{}", flux.value());
+        assert!((flux.density_scale(0.5) - (0.5 + 0.5 * flux.density_scale(1.0))).abs() < 1e-6);
+
+        // 50 ms ごとの打撃を重ねると下げなくなり、打撃が止まっても FLUX_RELEASE_MS の間は残る
+        let mut peak = 0.0f32;
+        for i in 48_000..96_000 {
+            let hit = if i % 2_400 < 48 { 0.8 } else { 0.0 };
+            flux.push(sine(i) + hit);
+            peak = peak.max(flux.value());
+        }
+        assert!(peak >= FLUX_FULL, "{peak}");
+        for i in 96_000..96_000 + 2_400 {
+            flux.push(sine(i));
+        }
+        assert!(flux.value() > 0.5 * FLUX_FULL, "{}", flux.value());
+    }
+
+    #[test]
+    fn yin_detects_sine_pitch() {
+        let sr = 44_100.0;
+        let mut tracker = PitchTracker::default();
+        tracker.initialize(sr);
+        for i in 0..8_192 {
+            let t = i as f32 / sr;
+            tracker.push((t * 220.0 * std::f32::consts::TAU).sin());
+        }
+        let f = tracker.pitch().expect("pitch not detected");
+        assert!((f - 220.0).abs() < 2.0, "{f}");
+
+        // 無音では検出しない
+        for _ in 0..8_192 {
+            tracker.push(0.0);
+        }
+        assert_eq!(tracker.pitch(), None);
+    }
+
+    #[test]
+    fn snap_ratio_lands_on_scale() {
+        // A4 (440Hz) は C メジャーの構成音なので補正なし
+        assert!((snap_ratio(Some(440.0), Scale::Major, 0) - 1.0).abs() < 1e-5);
+        // 450Hz は A4 へ下げる
+        let r = snap_ratio(Some(450.0), Scale::Chromatic, 0);
+        assert!((450.0 * r - 440.0).abs() < 0.01, "{r}");
+        // C# より少し低い音は C メジャーでは C へ寄せる
+        let f = 440.0 * 2f32.powf(-8.3 / 12.0);
+        let r = snap_ratio(Some(f), Scale::Major, 0);
+        assert!((r - 2f32.powf(-0.7 / 12.0)).abs() < 1e-4, "{r}");
+        assert_eq!(snap_ratio(None, Scale::Major, 0), 1.0);
+        assert_eq!(snap_ratio(Some(440.0), Scale::Off, 0), 1.0);
+    }
+
+    #[test]
+    fn loudness_map_biases_starts_toward_loud_blocks() {
+        use rand::{rngs::SmallRng, SeedableRng};
+        // 8 ブロックのうち 5 番目だけが大きく、残りは小さなルームトーン
+        let mut ring = vec![0.001f32; 8 * LOUDNESS_BLOCK];
+        let mut map = LoudnessMap::default();
+        map.initialize(ring.len(), &ring);
+        for (i, x) in ring.iter_mut().enumerate() {
+            if i / LOUDNESS_BLOCK == 5 {
+                *x = 0.5;
+            }
+            map.push(i, *x);
+        }
+        let mut rng = SmallRng::seed_from_u64(1);
+        let loud = (0..1_000)
+            .filter_map(|_| map.pick(&mut rng, ring.len() - 100))
+            .filter(|s| s / LOUDNESS_BLOCK == 5)
+            .count();
+        assert!(loud > 990, "{loud}");
+
+        // 消去したリングでは選べない (呼び出し側が一様に選ぶ)
+        map.clear();
+        assert_eq!(map.pick(&mut rng, ring.len()), None);
+    }
+
+    #[test]
+    fn onsets_mark_the_first_sample_of_each_hit() {
+        let sr = 1_000.0;
+        let mut detector = OnsetDetector::default();
+        detector.initialize(sr);
+        // 200 サンプルごとに減衰する打撃。30 サンプル目の小さな揺れは最小間隔に収まるので拾わない
+        let hit = |i: usize| -> f32 {
+            let t = (i % 200) as f32;
+            let wobble = if i % 200 == 30 { 0.4 } else { 0.0 };
+            (-t / 20.0).exp() * 0.8 + wobble
+        };
+        let found: Vec<usize> = (0..1_000)
+            .filter(|&i| detector.push(hit(i), Some(i)))
+            .collect();
+        assert_eq!(found, vec![0, 200, 400, 600, 800]);
+        assert_eq!(detector.onsets(), &found[..]);
+
+        // 書き込みで上書きされたオンセットと、並べ替えたリングの位置
+        detector.push(0.0, Some(0));
+        assert_eq!(detector.onsets(), &[200, 400, 600, 800]);
+        detector.relocate(|p| p - 200);
+        assert_eq!(detector.onsets(), &[0, 200, 400, 600]);
+        // 書き込まなかった入力の立ち上がりは覚えない
+        detector.forget();
+        assert!(!detector.push(1.0, None));
+        assert!(detector.onsets().is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+//! Parameter automation for offline rendering: breakpoint lanes read from a JSON file and
+//! applied sample by sample on top of fixed parameters.
+//!
+//! The file maps continuous parameters, by their [`FrameParams::continuous`] names, to
+//! `[seconds, value]` breakpoints:
+//!
+//! ```json
+//! { "density": [[0, 0.1], [10, 0.9]], "max_ms": [[0, 500], [4, 50], [8, 500]] }
+//! ```
+//!
+//! Values are interpolated linearly between breakpoints, held before the first and after the
+//! last one, and clamped to the parameter's range. Only JSON is read; YAML would need a parser
+//! the plugin does not otherwise depend on.
+
+use crate::engine::{FrameParams, ParamSource};
+use serde_json::Value;
+use std::fmt;
+
+/*──────────────────── 1. Errors ───────────────────────*/
+/// オートメーションファイルの読み込みのエラー
+#[derive(Debug)]
+pub enum AutomationError {
+    Json(serde_json::Error),
+    /// 最上位がパラメータ名からレーンへのオブジェクトではない
+    NotAnObject,
+    /// 連続値のパラメータに無い名前
+    UnknownParam(String),
+    /// レーンが [秒, 値] の空でない配列ではない
+    InvalidLane(String),
+}
+
+impl fmt::Display for AutomationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutomationError::Json(e) => write!(f, "the automation is not valid JSON: {e}"),
+            AutomationError::NotAnObject => {
+                write!(f, "the automation must map parameter names to breakpoints")
+            }
+            AutomationError::UnknownParam(name) => write!(f, "unknown parameter '{name}'"),
+            AutomationError::InvalidLane(name) => write!(
+                f,
+                "breakpoints for '{name}' must be a non-empty list of [seconds, value]"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AutomationError {}
+
+impl From<serde_json::Error> for AutomationError {
+    fn from(e: serde_json::Error) -> Self {
+        AutomationError::Json(e)
+    }
+}
+
+/*──────────────────── 2. Lanes ────────────────────────*/
+/// 1 つのパラメータのブレークポイント (時刻順)
+#[derive(Clone, Debug, PartialEq)]
+struct Lane {
+    /// FrameParams::continuous() での位置
+    index: usize,
+    points: Vec<(f64, f32)>,
+}
+
+impl Lane {
+    /// 時刻 `t` (秒) の値
+    fn value_at(&self, t: f64) -> f32 {
+        let i = self.points.partition_point(|&(time, _)| time <= t);
+        match (i.checked_sub(1).map(|k| self.points[k]), self.points.get(i)) {
+            (Some((t0, v0)), Some(&(t1, v1))) => v0 + (v1 - v0) * ((t - t0) / (t1 - t0)) as f32,
+            (Some((_, v)), None) | (None, Some(&(_, v))) => v,
+            (None, None) => unreachable!("lanes are never empty"),
+        }
+    }
+}
+
+/// 読み込んだオートメーション
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+impl Automation {
+    /// JSON を読む。ブレークポイントは時刻順に並べ替え、値はパラメータの範囲に収める
+    pub fn parse(json: &str) -> Result<Self, AutomationError> {
+        let Value::Object(map) = serde_json::from_str(json)? else {
+            return Err(AutomationError::NotAnObject);
+        };
+        let mut defaults = FrameParams::default();
+        let fields = defaults.continuous();
+        let mut lanes = Vec::new();
+        for (name, lane) in map {
+            let index = fields
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or_else(|| AutomationError::UnknownParam(name.clone()))?;
+            let (min, max) = (fields[index].min, fields[index].max);
+            let point = |p: &Value| match p.as_array().map(Vec::as_slice) {
+                Some([t, v]) => Some((t.as_f64()?, (v.as_f64()? as f32).clamp(min, max))),
+                _ => None,
+            };
+            let mut points = lane
+                .as_array()
+                .filter(|l| !l.is_empty())
+                .and_then(|l| l.iter().map(point).collect::<Option<Vec<_>>>())
+                .ok_or_else(|| AutomationError::InvalidLane(name.clone()))?;
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            lanes.push(Lane { index, points });
+        }
+        Ok(Self { lanes })
+    }
+
+    /// 時刻 `t` (秒) の値を `p` へ書き込む。min_ms は max_ms を超えない
+    pub fn apply(&self, p: &mut FrameParams, t: f64) {
+        if self.lanes.is_empty() {
+            return;
+        }
+        let fields = p.continuous();
+        for lane in &self.lanes {
+            *fields[lane.index].value = lane.value_at(t);
+        }
+        p.limit_min_length();
+    }
+}
+
+/*──────────────────── 3. Parameter source ─────────────*/
+/// 固定のパラメータにオートメーションを重ねて、サンプルごとに値を出す
+pub struct Automated {
+    base: FrameParams,
+    automation: Automation,
+    sr: f64,
+    /// 次に出すフレームの通算位置
+    frame: u64,
+}
+
+impl Automated {
+    pub fn new(base: FrameParams, automation: Automation, sr: f32) -> Self {
+        Self {
+            base,
+            automation,
+            sr: sr as f64,
+            frame: 0,
+        }
+    }
+}
+
+impl ParamSource for Automated {
+    fn next_frame(&mut self) -> FrameParams {
+        let mut p = self.base;
+        self.automation.apply(&mut p, self.frame as f64 / self.sr);
+        self.frame += 1;
+        p
+    }
+
+    /// オートメーションが無ければ固定のパラメータのまま
+    fn is_steady(&self) -> bool {
+        self.automation.lanes.is_empty()
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_are_interpolated_per_sample_and_held_outside() {
+        let automation =
+            Automation::parse(r#"{ "density": [[2, 1.0], [1, 0.0]], "mix": [[0, 5]] }"#).unwrap();
+        let base = FrameParams::default();
+        let mut source = Automated::new(base, automation, 4.0);
+        let frames: Vec<FrameParams> = (0..16).map(|_| source.next_frame()).collect();
+        // 1 秒 (4 フレーム) まで保持し、2 秒へ向けて 0.25 ずつ上がり、その後は保持する
+        let density: Vec<f32> = frames.iter().map(|p| p.density).collect();
+        assert_eq!(&density[..5], &[0.0; 5]);
+        assert_eq!(&density[5..9], &[0.25, 0.5, 0.75, 1.0]);
+        assert!(density[9..].iter().all(|d| *d == 1.0));
+        // 範囲の外の値は収め、自動化していないパラメータは変えない
+        assert!(frames.iter().all(|p| p.mix == 1.0));
+        assert!(frames.iter().all(|p| p.max_ms == base.max_ms));
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        assert!(matches!(
+            Automation::parse("[1, 2]"),
+            Err(AutomationError::NotAnObject)
+        ));
+        assert!(matches!(
+            Automation::parse(r#"{ "loudness": [[0, 1]] }"#),
+            Err(AutomationError::UnknownParam(name)) if name == "loudness"
+        ));
+        for lane in ["[]", "[[0]]", r#"[[0, "high"]]"#, "0.5"] {
+            assert!(matches!(
+                Automation::parse(&format!(r#"{{ "density": {lane} }}"#)),
+                Err(AutomationError::InvalidLane(_))
+            ));
+        }
+        assert!(matches!(
+            Automation::parse("{"),
+            Err(AutomationError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+//! Per-grain character: soft-clip drive, bit-depth reduction and sample-rate reduction.
+//!
+//! The stage runs once on each grain's copied buffer when the grain is spawned, before its window
+//! is applied, so the fades stay clean and the dry path is never touched. `Character Random`
+//! scales every grain's amount by a random factor between `1 - random` and 1, so some grains
+//! come out gritty and others nearly clean.
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MAX_DRIVE_DB: f32 = 36.0; // ドライブの上限 (dB)
+pub const MIN_BITS: f32 = 1.0; // ビット深度の範囲 (MAX_BITS でオフ)
+pub const MAX_BITS: f32 = 24.0;
+pub const MAX_DOWNSAMPLE: f32 = 32.0; // サンプルレートを下げる倍率の上限 (1 でオフ)
+
+/*──────────────────── 2. Character ────────────────────*/
+/// グレインにかけるキャラクター
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Character {
+    /// ソフトクリップの前に掛けるゲイン (dB、0 でオフ)
+    pub drive_db: f32,
+    /// 量子化するビット深度 (小数可、MAX_BITS でオフ)
+    pub bits: f32,
+    /// サンプルを保持する間隔 (サンプル、小数可、1 でオフ)
+    pub downsample: f32,
+}
+
+impl Default for Character {
+    fn default() -> Self {
+        Self {
+            drive_db: 0.0,
+            bits: MAX_BITS,
+            downsample: 1.0,
+        }
+    }
+}
+
+impl Character {
+    /// どの処理もかからないか
+    pub fn is_clean(&self) -> bool {
+        self.drive_db <= 0.0 && self.bits >= MAX_BITS && self.downsample <= 1.0
+    }
+
+    /// 効き具合を `amount` 倍 (0.0=オフ、1.0=そのまま) にしたキャラクター
+    pub fn scaled(self, amount: f32) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        Self {
+            drive_db: self.drive_db.max(0.0) * amount,
+            bits: MAX_BITS - (MAX_BITS - self.bits.clamp(MIN_BITS, MAX_BITS)) * amount,
+            downsample: 1.0 + (self.downsample.clamp(1.0, MAX_DOWNSAMPLE) - 1.0) * amount,
+        }
+    }
+
+    /// `buf` へ サンプルレート低減 → ビット深度低減 → ソフトクリップ の順にかける
+    pub fn apply(&self, buf: &mut [f32]) {
+        if self.downsample > 1.0 {
+            // 小数の間隔で保持し直すので、倍率を動かしても段階的にならない
+            let mut phase = self.downsample;
+            let mut held = 0.0;
+            for x in buf.iter_mut() {
+                if phase >= self.downsample {
+                    phase -= self.downsample;
+                    held = *x;
+                }
+                phase += 1.0;
+                *x = held;
+            }
+        }
+        if self.bits < MAX_BITS {
+            let steps = (self.bits.max(MIN_BITS) - 1.0).exp2();
+            for x in buf.iter_mut() {
+                *x = (*x * steps).round() / steps;
+            }
+        }
+        if self.drive_db > 0.0 {
+            // 小さな信号はドライブの分だけ大きくなり、大きな信号は ±1 へ滑らかに収まる
+            let gain = (self.drive_db * std::f32::consts::LN_10 / 20.0).exp();
+            for x in buf.iter_mut() {
+                *x = (*x * gain).tanh();
+            }
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn character_crushes_holds_and_clips() {
+        let ramp: Vec<f32> = (0..8).map(|i| i as f32 / 8.0).collect();
+
+        let mut buf = ramp.clone();
+        Character::default().apply(&mut buf);
+        assert_eq!(buf, ramp);
+        assert!(Character::default().is_clean());
+
+        // 2 サンプルごとに保持する
+        let mut buf = ramp.clone();
+        Character {
+            downsample: 2.0,
+            ..Character::default()
+        }
+        .apply(&mut buf);
+        assert_eq!(buf, [0.0, 0.0, 0.25, 0.25, 0.5, 0.5, 0.75, 0.75]);
+
+        // 3 ビット: 1/4 刻み
+        let mut buf = ramp.clone();
+        Character {
+            bits: 3.0,
+            ..Character::default()
+        }
+        .apply(&mut buf);
+        assert!(buf.iter().all(|x| (x * 4.0).fract() == 0.0), "{buf:?}");
+
+        // ドライブした信号は ±1 を超えない
+        let mut buf = vec![-2.0, -0.01, 0.01, 2.0];
+        Character {
+            drive_db: 20.0,
+            ..Character::default()
+        }
+        .apply(&mut buf);
+        assert!(buf.iter().all(|x| x.abs() <= 1.0));
+        assert!((buf[2] - 0.1f32.tanh()).abs() < 1e-4);
+
+        // amount 0 ではオフ、1 ではそのまま
+        let c = Character {
+            drive_db: 12.0,
+            bits: 4.0,
+            downsample: 8.0,
+        };
+        assert!(c.scaled(0.0).is_clean());
+        assert_eq!(c.scaled(1.0), c);
+    }
+}
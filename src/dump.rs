@@ -0,0 +1,177 @@
+//! Debug-only WAV dumps (feature `debug-dump`): spawned grains and, optionally, the wet
+//! bus are written to disk from the background thread for offline inspection.
+//!
+//! Files go to `$GRANULAR_DUMP_DIR` (default: `<temp dir>/granular_dump`). Each grain is
+//! written as `grain_NNNNNN.wav`; set `GRANULAR_DUMP_WET=1` to also append the mono sum of
+//! the wet bus to `wet.wav`.
+
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const DUMP_DIR_ENV: &str = "GRANULAR_DUMP_DIR"; // 出力先ディレクトリを指定する環境変数
+pub const DUMP_WET_ENV: &str = "GRANULAR_DUMP_WET"; // 1 ならウェットバスも書き出す
+const HEADER_LEN: u32 = 44; // 32bit float WAV のヘッダ長
+
+/// 出力先ディレクトリ
+pub fn dump_dir() -> PathBuf {
+    std::env::var_os(DUMP_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("granular_dump"))
+}
+
+/// ウェットバスを書き出すかどうか
+pub fn wet_enabled() -> bool {
+    std::env::var(DUMP_WET_ENV).is_ok_and(|v| v == "1")
+}
+
+/*──────────────────── 2. WAV writing ──────────────────*/
+/// モノラル 32bit float WAV のヘッダを `frames` サンプル分として書く
+fn write_header(w: &mut impl Write, sr: u32, frames: u32) -> io::Result<()> {
+    let data_len = frames * 4;
+    w.write_all(b"RIFF")?;
+    w.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVEfmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&3u16.to_le_bytes())?; // WAVE_FORMAT_IEEE_FLOAT
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&sr.to_le_bytes())?;
+    w.write_all(&(sr * 4).to_le_bytes())?;
+    w.write_all(&4u16.to_le_bytes())?;
+    w.write_all(&32u16.to_le_bytes())?;
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())
+}
+
+fn write_samples(w: &mut impl Write, samples: &[f32]) -> io::Result<()> {
+    for x in samples {
+        w.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// `samples` をモノラル 32bit float WAV として `path` へ書く
+pub fn write_wav(path: &Path, sr: f32, samples: &[f32]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write_header(&mut file, sr as u32, samples.len() as u32)?;
+    write_samples(&mut file, samples)?;
+    file.flush()
+}
+
+/// 追記していく WAV ファイル。追記のたびにヘッダの長さを書き直す。
+struct WavAppender {
+    file: File,
+    sr: u32,
+    frames: u32,
+}
+
+impl WavAppender {
+    fn create(path: &Path, sr: f32) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        write_header(&mut file, sr as u32, 0)?;
+        Ok(Self {
+            file,
+            sr: sr as u32,
+            frames: 0,
+        })
+    }
+
+    fn append(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.file.seek(SeekFrom::End(0))?;
+        let mut w = io::BufWriter::new(&mut self.file);
+        write_samples(&mut w, samples)?;
+        w.flush()?;
+        drop(w);
+        self.frames += samples.len() as u32;
+        self.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.file, self.sr, self.frames)
+    }
+}
+
+/*──────────────────── 3. Dumper ───────────────────────*/
+/// バックグラウンドスレッドでダンプを書き出す
+pub struct Dumper {
+    dir: PathBuf,
+    next_grain: AtomicUsize,
+    wet: Mutex<Option<WavAppender>>,
+}
+
+impl Default for Dumper {
+    fn default() -> Self {
+        Self::new(dump_dir())
+    }
+}
+
+impl Dumper {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            next_grain: AtomicUsize::new(0),
+            wet: Mutex::new(None),
+        }
+    }
+
+    /// グレイン 1 つを連番のファイルへ書き出す
+    pub fn grain(&self, samples: &[f32], sr: f32) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let n = self.next_grain.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("grain_{n:06}.wav"));
+        write_wav(&path, sr, samples)?;
+        Ok(path)
+    }
+
+    /// ウェットバスの 1 ブロックを wet.wav へ追記する (最初の呼び出しでファイルを作る)
+    pub fn wet(&self, samples: &[f32], sr: f32) -> io::Result<()> {
+        let mut wet = self.wet.lock().unwrap_or_else(|e| e.into_inner());
+        if wet.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            *wet = Some(WavAppender::create(&self.dir.join("wet.wav"), sr)?);
+        }
+        match wet.as_mut() {
+            Some(w) => w.append(samples),
+            None => Ok(()),
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_f32_wav(path: &Path) -> (u32, Vec<f32>) {
+        let bytes = fs::read(path).unwrap();
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(4) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(40) as usize, bytes.len() - HEADER_LEN as usize);
+        let samples = bytes[HEADER_LEN as usize..]
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        (u32_at(24), samples)
+    }
+
+    #[test]
+    fn dumper_writes_grains_and_appends_wet() {
+        let dir = std::env::temp_dir().join(format!("granular_dump_test_{}", std::process::id()));
+        let dumper = Dumper::new(dir.clone());
+
+        let first = dumper.grain(&[0.0, 0.5, -0.5], 48_000.0).unwrap();
+        let second = dumper.grain(&[1.0], 48_000.0).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(read_f32_wav(&first), (48_000, vec![0.0, 0.5, -0.5]));
+
+        dumper.wet(&[0.25, 0.5], 44_100.0).unwrap();
+        dumper.wet(&[0.75], 44_100.0).unwrap();
+        assert_eq!(
+            read_f32_wav(&dir.join("wet.wav")),
+            (44_100, vec![0.25, 0.5, 0.75])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
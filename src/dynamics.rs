@@ -0,0 +1,389 @@
+//! Small dynamics processors keyed by the wet bus. [`Ducker`] follows the wet envelope and
+//! turns it into a gain for the dry signal, so dense clouds make room instead of stacking on
+//! the input; [`Gate`] mutes the summed grains once they fall below a threshold, cleaning up
+//! quiet residual tails in rhythmic material. [`Expander`] sits before the ring write and turns
+//! down input below its threshold, so hiss and bleed in quiet passages are not captured and
+//! later amplified into the texture. [`Key`] turns the sidechain (the Modulation aux input) into
+//! an on/off key with hold and release, used to freeze the texture while another track plays.
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MAX_DUCK_DB: f32 = 24.0; // ドライを下げる最大量 (dB)
+pub const MIN_DUCK_ATTACK_MS: f32 = 0.1; // エンベロープの立ち上がりの時定数の範囲 (ミリ秒)
+pub const MAX_DUCK_ATTACK_MS: f32 = 100.0;
+pub const MIN_DUCK_RELEASE_MS: f32 = 10.0; // エンベロープの戻りの時定数の範囲 (ミリ秒)
+pub const MAX_DUCK_RELEASE_MS: f32 = 2_000.0;
+pub const MIN_GATE_DB: f32 = -96.0; // ゲートのしきい値の下限 (dB)。これ以下ならゲートは常に開いている
+pub const MIN_GATE_ATTACK_MS: f32 = 0.1; // ゲートが開く時間の範囲 (ミリ秒)
+pub const MAX_GATE_ATTACK_MS: f32 = 50.0;
+pub const MIN_GATE_RELEASE_MS: f32 = 5.0; // ゲートが閉じる時間の範囲 (ミリ秒)
+pub const MAX_GATE_RELEASE_MS: f32 = 1_000.0;
+pub const MAX_EXPAND_RATIO: f32 = 20.0; // 入力エキスパンダーの比率の上限 (ここまで上げるとほぼゲート)
+pub const MAX_KEY_HOLD_MS: f32 = 2_000.0; // サイドチェインのキーを保つ時間の上限 (ミリ秒)
+const EXPAND_ATTACK_MS: f32 = 1.0; // エキスパンダーが開く時間 (ミリ秒)。立ち上がりを削らないよう固定で短く
+const EXPAND_FLOOR_DB: f32 = -96.0; // エキスパンダーで下げる量の下限 (dB)
+
+/// 1 次平滑の時定数 (ミリ秒) とその係数。時定数が変わったときだけ係数を計算し直す
+struct TimeConstant {
+    ms: f32,
+    coef: f32,
+}
+
+impl Default for TimeConstant {
+    fn default() -> Self {
+        Self {
+            ms: f32::NAN,
+            coef: 0.0,
+        }
+    }
+}
+
+impl TimeConstant {
+    /// `ms` ミリ秒で 1/e まで近づく 1 次平滑の係数
+    #[inline]
+    fn coef(&mut self, ms: f32, sr: f32) -> f32 {
+        if ms != self.ms {
+            self.ms = ms;
+            self.coef = (-1_000.0 / (ms * sr)).exp();
+        }
+        self.coef
+    }
+}
+
+/*──────────────────── 2. Ducker ───────────────────────*/
+/// ウェットのピークを attack / release の 1 次平滑で追い、ドライに掛けるゲインを返す。
+/// エンベロープが 1.0 (0 dBFS) のとき depth dB 下げ、それ以下では dB 上で比例させる。
+pub struct Ducker {
+    env: f32,
+    sr: f32,
+    attack: TimeConstant,
+    release: TimeConstant,
+}
+
+impl Default for Ducker {
+    fn default() -> Self {
+        Self {
+            env: 0.0,
+            sr: 44_100.0,
+            attack: TimeConstant::default(),
+            release: TimeConstant::default(),
+        }
+    }
+}
+
+impl Ducker {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.attack = TimeConstant::default();
+        self.release = TimeConstant::default();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.env = 0.0;
+    }
+
+    /// 現在のエンベロープ (リニア)
+    #[inline]
+    pub fn envelope(&self) -> f32 {
+        self.env
+    }
+
+    /// ウェットのレベル `level` (リニア) で 1 サンプル進め、ドライに掛けるゲインを返す
+    #[inline]
+    pub fn next(&mut self, level: f32, depth_db: f32, attack_ms: f32, release_ms: f32) -> f32 {
+        let coef = if level > self.env {
+            let ms = attack_ms.clamp(MIN_DUCK_ATTACK_MS, MAX_DUCK_ATTACK_MS);
+            self.attack.coef(ms, self.sr)
+        } else {
+            let ms = release_ms.clamp(MIN_DUCK_RELEASE_MS, MAX_DUCK_RELEASE_MS);
+            self.release.coef(ms, self.sr)
+        };
+        self.env = level + (self.env - level) * coef;
+
+        let depth = depth_db.clamp(0.0, MAX_DUCK_DB);
+        if depth == 0.0 {
+            return 1.0;
+        }
+        // 10^(-depth × env / 20)
+        (-depth * self.env.min(1.0) * std::f32::consts::LN_10 / 20.0).exp()
+    }
+}
+
+/*──────────────────── 3. Gate ─────────────────────────*/
+/// ウェットのピーク (瞬時に立ち上がり release で下がる) がしきい値を超えている間だけ開くゲート。
+/// 開閉は attack / release の 1 次平滑でゲインを動かすので、クリックにならない。
+pub struct Gate {
+    /// キーのピークエンベロープ (リニア)
+    key: f32,
+    /// 現在のゲイン (0.0=閉, 1.0=開)
+    gain: f32,
+    sr: f32,
+    attack: TimeConstant,
+    release: TimeConstant,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self {
+            key: 0.0,
+            gain: 1.0,
+            sr: 44_100.0,
+            attack: TimeConstant::default(),
+            release: TimeConstant::default(),
+        }
+    }
+}
+
+impl Gate {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.attack = TimeConstant::default();
+        self.release = TimeConstant::default();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.key = 0.0;
+        self.gain = 1.0;
+    }
+
+    /// 閉じている、または閉じかけているなら true (しきい値をオフにしても開ききるまで処理を続ける)
+    #[inline]
+    pub fn is_closing(&self) -> bool {
+        self.gain < 1.0
+    }
+
+    /// ウェットのレベル `level` (リニア) で 1 サンプル進め、ウェットに掛けるゲインを返す。
+    /// しきい値が MIN_GATE_DB 以下ならゲートは常に開いている。
+    #[inline]
+    pub fn next(&mut self, level: f32, threshold_db: f32, attack_ms: f32, release_ms: f32) -> f32 {
+        let release = self.release.coef(
+            release_ms.clamp(MIN_GATE_RELEASE_MS, MAX_GATE_RELEASE_MS),
+            self.sr,
+        );
+        self.key = level.max(self.key * release);
+        let open = threshold_db <= MIN_GATE_DB
+            || self.key > (threshold_db * std::f32::consts::LN_10 / 20.0).exp();
+        let (target, coef) = if open {
+            let ms = attack_ms.clamp(MIN_GATE_ATTACK_MS, MAX_GATE_ATTACK_MS);
+            (1.0, self.attack.coef(ms, self.sr))
+        } else {
+            (0.0, release)
+        };
+        self.gain = target + (self.gain - target) * coef;
+        self.gain
+    }
+}
+
+/*──────────────────── 4. Expander ─────────────────────*/
+/// 入力のピーク (瞬時に立ち上がり release で下がる) がしきい値を下回った分を ratio 倍に広げて下げる
+/// ダウンワードエキスパンダー。しきい値を 1 dB 下回るごとに (ratio - 1) dB 下げる。
+pub struct Expander {
+    /// キーのピークエンベロープ (リニア)
+    key: f32,
+    /// 現在のゲイン (リニア)
+    gain: f32,
+    sr: f32,
+    attack: TimeConstant,
+    release: TimeConstant,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self {
+            key: 0.0,
+            gain: 1.0,
+            sr: 44_100.0,
+            attack: TimeConstant::default(),
+            release: TimeConstant::default(),
+        }
+    }
+}
+
+impl Expander {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.attack = TimeConstant::default();
+        self.release = TimeConstant::default();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.key = 0.0;
+        self.gain = 1.0;
+    }
+
+    /// 入力のレベル `level` (リニア) で 1 サンプル進め、リングへ書く入力に掛けるゲインを返す。
+    /// しきい値が MIN_GATE_DB 以下ならエキスパンダーはオフ (ゲイン 1)。
+    #[inline]
+    pub fn next(&mut self, level: f32, threshold_db: f32, ratio: f32, release_ms: f32) -> f32 {
+        let release = self.release.coef(
+            release_ms.clamp(MIN_GATE_RELEASE_MS, MAX_GATE_RELEASE_MS),
+            self.sr,
+        );
+        self.key = level.max(self.key * release);
+        let target = if threshold_db <= MIN_GATE_DB {
+            1.0
+        } else {
+            let key_db = 20.0 * self.key.max(1e-10).log10();
+            let under = (threshold_db - key_db).max(0.0);
+            let db = (-under * (ratio.clamp(1.0, MAX_EXPAND_RATIO) - 1.0)).max(EXPAND_FLOOR_DB);
+            (db * std::f32::consts::LN_10 / 20.0).exp()
+        };
+        // 下げるときはキーの release に任せ、開くときだけ attack で滑らかにする
+        self.gain = if target < self.gain {
+            target
+        } else {
+            let coef = self.attack.coef(EXPAND_ATTACK_MS, self.sr);
+            target + (self.gain - target) * coef
+        };
+        self.gain
+    }
+}
+
+/*──────────────────── 5. Key ───────────────────────────*/
+/// サイドチェインのピーク (瞬時に立ち上がり release で下がる) がしきい値を超えている間と、
+/// 下回ってから hold の間オンになるキー
+pub struct Key {
+    /// キーのピークエンベロープ (リニア)
+    peak: f32,
+    /// しきい値を下回ってからオンを保つ残りのサンプル数
+    hold: usize,
+    sr: f32,
+    release: TimeConstant,
+}
+
+impl Default for Key {
+    fn default() -> Self {
+        Self {
+            peak: 0.0,
+            hold: 0,
+            sr: 44_100.0,
+            release: TimeConstant::default(),
+        }
+    }
+}
+
+impl Key {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.release = TimeConstant::default();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.peak = 0.0;
+        self.hold = 0;
+    }
+
+    /// サイドチェインのサンプル `x` で 1 サンプル進め、キーがオンなら true を返す
+    #[inline]
+    pub fn next(&mut self, x: f32, threshold_db: f32, hold_ms: f32, release_ms: f32) -> bool {
+        let release = self.release.coef(
+            release_ms.clamp(MIN_GATE_RELEASE_MS, MAX_GATE_RELEASE_MS),
+            self.sr,
+        );
+        self.peak = x.abs().max(self.peak * release);
+        if self.peak > (threshold_db * std::f32::consts::LN_10 / 20.0).exp() {
+            self.hold = (hold_ms.clamp(0.0, MAX_KEY_HOLD_MS) / 1_000.0 * self.sr) as usize;
+            true
+        } else if self.hold > 0 {
+            self.hold -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ducker_follows_wet_with_attack_and_release() {
+        let sr = 1_000.0;
+        let mut ducker = Ducker::default();
+        ducker.initialize(sr);
+
+        // 深さ 0 ならドライはそのまま
+        assert_eq!(ducker.next(1.0, 0.0, 10.0, 100.0), 1.0);
+        ducker.reset();
+
+        // attack 10 ms (10 サンプル) で 1 - 1/e まで立ち上がる
+        for _ in 0..10 {
+            ducker.next(1.0, 12.0, 10.0, 100.0);
+        }
+        let expected = 1.0 - (-1.0f32).exp();
+        assert!((ducker.envelope() - expected).abs() < 1e-4);
+
+        // 十分続けば depth dB 下がる
+        let mut gain = 1.0;
+        for _ in 0..1_000 {
+            gain = ducker.next(1.0, 12.0, 10.0, 100.0);
+        }
+        assert!((gain - 10.0f32.powf(-12.0 / 20.0)).abs() < 1e-4);
+
+        // ウェットが止まると release 100 ms で 1/e まで戻る
+        for _ in 0..100 {
+            gain = ducker.next(0.0, 12.0, 10.0, 100.0);
+        }
+        assert!((ducker.envelope() - (-1.0f32).exp()).abs() < 1e-3);
+        assert!(gain > 10.0f32.powf(-12.0 / 20.0) && gain < 1.0);
+    }
+
+    #[test]
+    fn gate_closes_below_threshold_and_reopens() {
+        let sr = 1_000.0;
+        let mut gate = Gate::default();
+        gate.initialize(sr);
+
+        // しきい値が下限ならいつも開いている
+        assert_eq!(gate.next(0.0, MIN_GATE_DB, 1.0, 10.0), 1.0);
+
+        // -20 dB のしきい値に対して -40 dB の残響は release で閉じていく
+        let mut gain = 1.0;
+        for _ in 0..200 {
+            gain = gate.next(0.01, -20.0, 1.0, 10.0);
+        }
+        assert!(gain < 1e-6, "{gain}");
+
+        // しきい値を超える音が来たら attack で開く
+        for _ in 0..20 {
+            gain = gate.next(0.5, -20.0, 1.0, 10.0);
+        }
+        assert!(gain > 0.99, "{gain}");
+
+        // 一瞬の谷 (ゼロクロス) ではキーのピークが残るので閉じない
+        let dip = gate.next(0.0, -20.0, 1.0, 10.0);
+        assert!(dip > 0.99, "{dip}");
+    }
+
+    #[test]
+    fn expander_turns_down_quiet_input_by_ratio() {
+        let sr = 1_000.0;
+        let mut expander = Expander::default();
+        expander.initialize(sr);
+
+        // しきい値が下限ならオフ
+        assert_eq!(expander.next(0.0, MIN_GATE_DB, 4.0, 100.0), 1.0);
+
+        // -40 dB のしきい値を 20 dB 下回るヒス (-60 dB) は、比率 2 なら 20 dB、比率 4 なら 60 dB 下がる
+        for (ratio, expected_db) in [(2.0, -20.0), (4.0, -60.0)] {
+            expander.reset();
+            let mut gain = 1.0;
+            for _ in 0..100 {
+                gain = expander.next(0.001, -40.0, ratio, 10.0);
+            }
+            assert!((20.0 * gain.log10() - expected_db).abs() < 0.1, "{gain}");
+        }
+
+        // しきい値を超える音はそのまま通す
+        let mut gain = 0.0;
+        for _ in 0..20 {
+            gain = expander.next(0.5, -40.0, 4.0, 10.0);
+        }
+        assert!(gain > 0.99, "{gain}");
+    }
+}
@@ -0,0 +1,7322 @@
+//! Host-independent granular engine: ring buffer, grain pool and wet/dry mixing.
+//!
+//! The plugin wrapper in `lib.rs` feeds it per-sample parameter values through
+//! [`ParamSource`]; benchmarks and tests drive it directly.
+
+use crate::analysis::{
+    snap_ratio, BeatMarks, FluxTracker, LoudnessMap, OnsetDetector, PitchTracker, Scale,
+};
+use crate::character::{Character, MAX_BITS, MAX_DOWNSAMPLE, MAX_DRIVE_DB, MIN_BITS};
+use crate::dynamics::{
+    Ducker, Expander, Gate, Key, MAX_DUCK_ATTACK_MS, MAX_DUCK_DB, MAX_DUCK_RELEASE_MS,
+    MAX_EXPAND_RATIO, MAX_GATE_ATTACK_MS, MAX_GATE_RELEASE_MS, MAX_KEY_HOLD_MS, MIN_DUCK_ATTACK_MS,
+    MIN_DUCK_RELEASE_MS, MIN_GATE_ATTACK_MS, MIN_GATE_DB, MIN_GATE_RELEASE_MS,
+};
+use crate::expression::NoteExpression;
+use crate::filter::{
+    apply_tilt, granulated, Bands, Crossover, DcBlocker, WetFilter, MAX_CROSSOVER_HZ, MAX_CUT_HZ,
+    MAX_TILT_DB, MIN_CROSSOVER_HZ, MIN_CUT_HZ,
+};
+use crate::formant::{psola_read, PitchMode, FALLBACK_PITCH_HZ};
+use crate::interp::{Interpolation, Oversampling};
+use crate::macros::{Macros, MACRO_COUNT};
+use crate::meter::{Meter, Meters, SPAWN_RATE_SEC};
+use crate::modulation::{
+    ChaosMode, LfoShape, ModDest, ModMatrix, ModSlot, ModSource, RandomWalk, WowFlutter, LFO_COUNT,
+    MAX_CHAOS_RATE, MAX_FLUTTER_CENTS, MAX_FLUTTER_RATE, MAX_LFO_RATE, MAX_WALK_RATE,
+    MAX_WOW_CENTS, MAX_WOW_RATE, MIN_CHAOS_RATE, MIN_FLUTTER_RATE, MIN_LFO_RATE, MIN_WALK_RATE,
+    MIN_WOW_RATE, MOD_SLOTS,
+};
+use crate::record::{RecordQueues, WetRecorder};
+use crate::scene::{self, SCENE_COUNT};
+use crate::sequencer::{euclid, Sequence};
+use crate::spectral::{SpectralFreeze, FFT_SEC};
+use crate::stft::{SpectralBlur, MAX_BLUR_SEC};
+use crate::surround::{self, Pan, PanLaw};
+pub use crate::window::apply_tukey;
+use crate::window::{
+    adsr_gain, adsr_lengths, apply_edges, edge_gain, edge_lengths, min_tukey_len, tukey_fade_len,
+    tukey_gain, WindowShape,
+};
+use arrayvec::ArrayVec;
+use crossbeam::queue::ArrayQueue;
+use nih_plug::prelude::Enum;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::sync::Arc;
+
+/*──────────────────── 1. Constants (match Python) ──────*/
+pub const RING_SEC: f32 = 5.0; // 履歴から切り出すモードのリングの長さ (秒)。確保するバッファは 2 の累乗へ切り上げる
+pub const MAX_RING_SEC: f32 = 30.0; // 必要に応じて広げるリングの上限 (秒)
+pub const MAX_GRAINS: usize = 25; // 同時に立ち上がるグレイン数上限
+                                  // TRIGGER_PROB は「density」パラメータで置き換え
+                                  // MIN_MS / MAX_MS は「min_ms」「max_ms」パラメータで置き換え
+pub const TUKEY_ALPHA: f32 = 0.2; // Tukey 窓の形状
+pub const TRIGGER_REF_SEC: f32 = 512.0 / 48_000.0; // density=1.0 で 1 グレインを期待する基準時間 (秒)
+pub const MAX_GRAIN_MS: f32 = 1000.0; // グレイン長の上限 (ミリ秒)。プールのバッファ容量もこれで決まる
+pub const MIN_LENGTH_PCT: f32 = 0.1; // % of Ring のグレイン長の下限 (リングの長さに対する %)
+pub const MAX_LENGTH_PCT: f32 = 20.0; // 同上限 (5 秒のリングで MAX_GRAIN_MS)
+pub const ECO_GRAINS: i32 = 8; // Quality Eco の同時発音数の上限
+pub const NORMAL_GRAINS: i32 = 16; // Quality Normal の同時発音数の上限
+pub const MAX_OFFLINE_GRAINS: usize = 128; // オフラインのバウンス中の同時発音数の上限
+pub const RENDER_WORKERS: usize = 4; // オフラインでグレインを分けて合成する部分和の数 (コア数によらず固定し、出力を再現可能にする)
+pub const PARALLEL_MIN_GRAINS: usize = 32; // これ以上のグレインが鳴っているチャンクだけ並列に合成する
+pub const GRAIN_POOL_SIZE: usize = MAX_OFFLINE_GRAINS + 8; // 再生中 + 処理待ち + フェードアウト中のグレインバッファ総数 (オフライン時)
+pub const SEAM_FADE_MS: f32 = 2.0; // 繰り返すグレインの継ぎ目で ADSR 窓に確保する最小フェード (ミリ秒)
+pub const MAX_REPEATS: i32 = 8; // グレインを繰り返し再生する最大回数
+pub const STEAL_FADE_MS: f32 = 5.0; // 上限時に奪われるグレインのフェードアウト時間 (ミリ秒)
+pub const MAX_FEEDBACK: f32 = 0.95; // リングへの帰還量の上限
+pub const MAX_PRE_DELAY_MS: f32 = 500.0; // ウェットのプリディレイの上限 (ミリ秒)
+pub const MAX_LOOKAHEAD_MS: i32 = 500; // ルックアヘッドでドライを遅らせる時間の上限 (ミリ秒)
+pub const TAIL_FLOOR_DB: f32 = -60.0; // テールの長さを見積もるときに鳴り終わったとみなすレベル (dB)
+pub const MIN_REVERB_SEC: f32 = 0.5; // クラウドリバーブの減衰時間 (-60 dB まで) の範囲 (秒)
+pub const MAX_REVERB_SEC: f32 = 30.0;
+pub const REVERB_MIN_MS: f32 = 150.0; // クラウドリバーブのグレイン長の範囲 (ミリ秒)
+pub const REVERB_MAX_MS: f32 = 600.0;
+pub const REVERB_FEEDBACK: f32 = 0.3; // クラウドリバーブの帰還量 (残響を拡散させる)
+pub const MAX_TRIM_DB: f32 = 24.0; // 入力ゲインの範囲 (±dB)
+pub const CONTINUOUS_COUNT: usize = 80; // FrameParams の連続値のフィールド数 (シーンやマクロの対象)
+pub const MAX_GLIDE_ST: f32 = 24.0; // グライドの開始移調の上限 (半音)
+pub const MAX_PITCH_SPREAD_ST: f32 = 24.0; // グレインごとのランダムな移調の幅の上限 (±半音)
+pub const MAX_PITCH_STEP_ST: f32 = 12.0; // ランダムウォークの 1 グレインあたりの歩幅の上限 (半音)
+pub const MAX_CHORD_VOICES: usize = 4; // Custom の和音の声部数
+pub const MAX_CHORD_ST: f32 = 24.0; // 和音の声部の音程の上限 (±半音)
+pub const DEFAULT_TEMPO: f64 = 120.0; // ホストからテンポが得られない場合の BPM
+pub const DEFAULT_BAR_BEATS: f64 = 4.0; // ホストから拍子が得られない場合の 1 小節の拍数 (4 分音符)
+pub const MAX_REROLL_BARS: i32 = 16; // 乱数列を始め直す間隔の上限 (小節)
+pub const MAX_HUMANIZE_MS: f32 = 50.0; // 同期モードのグレインの開始を遅らせる幅の上限 (ミリ秒)
+pub const MAX_SCATTER_MS: f32 = 200.0; // Random モードのグレインの開始を散らす窓の上限 (ミリ秒)
+pub const MAX_DELAYED_TRIGGERS: usize = 64; // 遅らせて待っているトリガーの上限 (超えたらすぐ生成する)
+pub const MAX_BURST: i32 = 8; // 1 回のトリガーで生成するグレインの数の上限
+pub const BURST_SPREAD_MS: f32 = 20.0; // バーストで足すグレインを遅らせる幅 (ミリ秒)
+pub const BURST_DETUNE_ST: f32 = 0.15; // バーストで足すグレインの移調の幅 (±半音)
+pub const BURST_PAN_DEG: f32 = 30.0; // バーストで足すグレインを元のグレインの方向からずらす幅 (±度)
+pub const BURST_DENSITY_KNEE: f32 = 0.5; // burst_density でバーストを増やし始める density
+pub const MIN_FREEZE_BEATS: f32 = 0.25; // Timed のフリーズを保つ長さの下限 (拍)
+pub const MAX_FREEZE_BEATS: f32 = 64.0; // Timed のフリーズを保つ長さの上限 (拍)
+pub const GUARD_RECOVER: f32 = 0.8; // 負荷が予算のこの割合を下回ったら過負荷保護を解除する
+pub const MIN_EDGE_MS: f32 = 1.0; // Tukey 窓の片側のフェードに最低限確保する長さ (ミリ秒)
+pub const CHUNK_SIZE: usize = 64; // 内部処理の区切り (サンプル)。ホストのブロック長に依存しないよう通算位置で区切る
+pub const GRAIN_BUSES: usize = 4; // グレインを振り分けられる補助出力バスの数
+pub const MAX_CHANNELS: usize = 16; // 処理するチャンネル数の上限 (これを超えるチャンネルはそのまま通す)
+pub const MAX_SPAWN_EVENTS: usize = 256; // 1 ブロックで記録するグレイン生成イベントの上限 (MIDI 出力用)
+pub const MIN_SILENCE_DB: f32 = -96.0; // 無音判定のしきい値の下限 (dB)。これ以下なら判定しない
+pub const MAX_SILENCE_DB: f32 = -20.0; // 無音判定のしきい値の上限 (dB)
+pub const MAX_SILENCE_RETRIES: i32 = 8; // 無音だったときに別の位置を試す最大回数
+pub const MAX_AVOID_REPEATS: i32 = 8; // 重ならないようにする直近のグレインの数の上限
+pub const REPEAT_RETRIES: i32 = 8; // 直近のグレインと重なったときに別の位置を試す最大回数
+pub const LENGTH_SMOOTH_MS: f32 = 20.0; // 生成するグレインの長さを新しい値へ追従させる時定数 (ミリ秒)
+pub const NORMALIZE_SMOOTH_MS: f32 = 50.0; // ウェットの正規化ゲインを追従させる時定数 (ミリ秒)
+pub const ZERO_CROSS_MS: f32 = 10.0; // グレインの端をゼロクロスへずらすときに探す範囲 (ミリ秒、50 Hz の半周期)
+pub const CLEAR_FADE_MS: f32 = 10.0; // リング消去時のグレインのフェードアウトと、消去後の入力のフェードイン (ミリ秒)
+pub const AUDITION_FADE_MS: f32 = 30.0; // audition で mix を 100% へ寄せる / 戻すのにかける時間 (ミリ秒)
+pub const LOOP_FADE_MS: f32 = 50.0; // Play でリングをループさせるときの継ぎ目のクロスフェード (ミリ秒)
+pub const FREEZE_SLOTS: usize = 4; // リングを取り込んでおけるフリーズスロットの数
+pub const SILENCE_PROBE: usize = 256; // 無音判定で RMS を測るサンプル数の上限 (長い区間は間引いて測る)
+
+/*──────────────────── 2. Per-frame parameters ─────────*/
+/// グレインの発生タイミングの決め方
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// density に従って確率的に発生させる (グレインクラウド)
+    #[name = "Random"]
+    Random,
+    /// グレイン長 / overlap の一定間隔で発生させる (タイムストレッチ的な滑らかなテクスチャ)
+    #[name = "Sync"]
+    Sync,
+    /// Sync と同じ間隔で、speed で進む仮想プレイヘッドの位置からグレインを切り出す (タイムストレッチ)
+    #[name = "Stretch"]
+    Stretch,
+    /// ホストのテンポに合わせた音符グリッド上で発生させる (density はステップごとの発生確率)
+    #[name = "Tempo"]
+    Tempo,
+    /// Tempo と同じグリッドを 16 ステップのシーケンスで鳴らす (オンのステップごとに長さと移調を変えた 1 グレイン)
+    #[name = "Step Sequencer"]
+    Sequencer,
+    /// Tempo と同じグリッドで、euclid_steps ステップに euclid_pulses 個の発音を均等に並べたリズムで鳴らす
+    #[name = "Euclidean"]
+    Euclid,
+}
+
+/// Random / Tempo モードのグレインを切り出す元
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// 入力を書き込み続けるリングバッファ
+    #[name = "Live Input"]
+    Live,
+    /// 読み込んだ WAV サンプル (未読み込みならライブ入力)
+    #[name = "Sample"]
+    Sample,
+}
+
+/// Random / Tempo モードでライブ入力から切り出すグレインの開始位置の選び方
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrainStart {
+    /// リング全体から一様に選ぶ
+    #[name = "Uniform"]
+    Uniform,
+    /// リングのラウドネスマップで大きい音の区間へ寄せる (ルームトーンを拾いにくい)
+    #[name = "Loudness"]
+    Loudness,
+    /// 検出したオンセットからちょうど始める (ドラムを打撃ごとに並べ替えるスライスシャッフル)
+    #[name = "Onset"]
+    Onset,
+    /// 書き込んだときにホストの再生位置が division のグリッド上にあった位置から始める
+    /// (切り出した素材がプロジェクトのリズムに揃ったまま並ぶ)
+    #[name = "Beat Grid"]
+    Beat,
+}
+
+/// グレインごとのランダムな移調の選び方
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PitchScatter {
+    /// グレインごとに ±pitch_spread の範囲から独立に選ぶ (一様に散らばる)
+    #[name = "Independent"]
+    Independent,
+    /// 前のグレインの移調から ±pitch_step だけ動かす (±pitch_spread で折り返す)。
+    /// ゆっくり漂う旋律の輪郭になる
+    #[name = "Random Walk"]
+    Walk,
+}
+
+/// リングバッファへの書き込み方 (ルーパーのように使う)
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RingMode {
+    /// 入力で上書きする
+    #[name = "Record"]
+    Record,
+    /// 既存の内容に overdub を掛けて入力を足す
+    #[name = "Overdub"]
+    Overdub,
+    /// 書き込みを止め、今の内容からグレインを切り出し続ける
+    #[name = "Play"]
+    Play,
+}
+
+/// freeze スイッチの働き方 (フットスイッチの CC を割り当てて使う)
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreezeMode {
+    /// 押している間だけフリーズする
+    #[name = "Momentary"]
+    Momentary,
+    /// 押すたびにフリーズとその解除を切り替える
+    #[name = "Latch"]
+    Latch,
+    /// 押した瞬間に取り込み、freeze_beats 拍だけ保つ
+    #[name = "Timed"]
+    Timed,
+    /// サイドチェイン (Modulation 入力) がしきい値を超えている間と、その後 key_hold_ms の間だけフリーズする
+    #[name = "Sidechain"]
+    Sidechain,
+}
+
+/// 補間方式・同時発音数の上限・オーバーサンプリングをまとめて切り替える品質
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quality {
+    /// interpolation / max_grains / oversampling を個別に使う
+    #[name = "Custom"]
+    Custom,
+    /// 線形補間・ECO_GRAINS 声・1x (ノート PC でのトラッキング向け)
+    #[name = "Eco"]
+    Eco,
+    /// 3 次エルミート・NORMAL_GRAINS 声・2x
+    #[name = "Normal"]
+    Normal,
+    /// 窓付き sinc・MAX_GRAINS 声・4x (バウンス向け)
+    #[name = "High"]
+    High,
+}
+
+impl Quality {
+    /// Custom 以外なら、`p` の補間方式・同時発音数の上限・オーバーサンプリングをこの品質のものに置き換える
+    pub fn apply(self, p: &mut FrameParams) {
+        let (interpolation, max_grains, oversampling) = match self {
+            Quality::Custom => return,
+            Quality::Eco => (Interpolation::Linear, ECO_GRAINS, Oversampling::X1),
+            Quality::Normal => (Interpolation::Hermite, NORMAL_GRAINS, Oversampling::X2),
+            Quality::High => (Interpolation::Sinc, MAX_GRAINS as i32, Oversampling::X4),
+        };
+        p.interpolation = interpolation;
+        p.max_grains = max_grains;
+        p.oversampling = oversampling;
+    }
+}
+
+/// グレイン長の指定方法 (どちらも最終的には最小長／最大長として同じ生成処理に渡す)
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthMode {
+    /// 最小長と最大長を直接指定する
+    #[name = "Min / Max"]
+    MinMax,
+    /// 中心の長さと、その前後へ散らす割合 (%) で指定する
+    #[name = "Length + Jitter"]
+    LengthJitter,
+    /// 中心の長さをリングの長さに対する割合 (%) で指定し、前後へ散らす割合は Length + Jitter と同じ。
+    /// リングの長さが変わってもテクスチャの比率が保たれる
+    #[name = "% of Ring"]
+    RingPercent,
+}
+
+/// グレインを補助出力バスへ振り分ける方法 (振り分けたグレインはメインのウェットに混ぜない)
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrainRouting {
+    /// すべてメインのウェットで鳴らす
+    #[name = "Off"]
+    Off,
+    /// 生成順にバス 1, 2, … へ順番に振り分ける
+    #[name = "Round Robin"]
+    RoundRobin,
+    /// グレインごとにランダムなバスへ振り分ける
+    #[name = "Random"]
+    Random,
+}
+
+/// Tempo モードのグリッド間隔
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteDivision {
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/8T"]
+    EighthTriplet,
+    #[name = "1/16"]
+    Sixteenth,
+    #[name = "1/16T"]
+    SixteenthTriplet,
+    #[name = "1/32"]
+    ThirtySecond,
+}
+
+impl NoteDivision {
+    /// 1 ステップの長さ (拍)
+    #[inline]
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::EighthTriplet => 1.0 / 3.0,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::SixteenthTriplet => 1.0 / 6.0,
+            NoteDivision::ThirtySecond => 0.125,
+        }
+    }
+}
+
+/// Sync / Stretch モードで同時に重なるグレイン数
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlap {
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+    #[name = "8x"]
+    X8,
+}
+
+/// シマー: グレインを上へ移調し、ウェットをリングへ帰還させて音を積み上げる
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shimmer {
+    #[name = "Off"]
+    Off,
+    /// すべてのグレインを +12 半音
+    #[name = "Octave"]
+    Octave,
+    /// グレインごとに +7 / +12 半音をランダムに選ぶ
+    #[name = "Fifth + Octave"]
+    FifthOctave,
+}
+
+/// 和音: 1 回のトリガーで同じ区間を移調したグレインを声部の数だけ重ねる
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chord {
+    #[name = "Off"]
+    Off,
+    /// ルート + 完全 5 度
+    #[name = "Fifth"]
+    Fifth,
+    /// ルート + オクターブ
+    #[name = "Octave"]
+    Octave,
+    /// ルート + 完全 5 度 + オクターブ
+    #[name = "Power"]
+    Power,
+    /// 長三和音 (ルート + 4 + 7 半音)
+    #[name = "Major"]
+    Major,
+    /// 短三和音 (ルート + 3 + 7 半音)
+    #[name = "Minor"]
+    Minor,
+    /// 声部ごとの音程とレベルをパラメータ (chord_voices) で決める
+    #[name = "Custom"]
+    Custom,
+}
+
+impl Chord {
+    /// 声部ごとのルートからの音程 (半音)。Off ならルートだけ、Custom は chord_voices で決めるので空
+    pub fn intervals(self) -> &'static [f32] {
+        match self {
+            Chord::Off => &[0.0],
+            Chord::Fifth => &[0.0, 7.0],
+            Chord::Octave => &[0.0, 12.0],
+            Chord::Power => &[0.0, 7.0, 12.0],
+            Chord::Major => &[0.0, 4.0, 7.0],
+            Chord::Minor => &[0.0, 3.0, 7.0],
+            Chord::Custom => &[],
+        }
+    }
+}
+
+/// Custom の和音の 1 声部
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChordVoice {
+    /// ルートからの音程 (半音、±MAX_CHORD_ST)
+    pub interval: f32,
+    /// レベル (0.0〜1.0)。0 の声部は鳴らさない
+    pub level: f32,
+}
+
+impl Overlap {
+    #[inline]
+    pub fn factor(self) -> f32 {
+        match self {
+            Overlap::X2 => 2.0,
+            Overlap::X4 => 4.0,
+            Overlap::X8 => 8.0,
+        }
+    }
+}
+
+/// 1 サンプル分のエンジンパラメータ (プラグインではスムーザーの出力)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameParams {
+    /// グレイン生成確率 (0.0=生成なし, 1.0=TRIGGER_REF_SEC ごとに 1 グレイン)
+    pub density: f32,
+    /// グレインの最小長 (ミリ秒)
+    pub min_ms: f32,
+    /// グレインの最大長 (ミリ秒)
+    pub max_ms: f32,
+    /// RingPercent なら min_ms / max_ms を length_pct と length_jitter から求め直す
+    /// (MinMax / LengthJitter はプラグイン側で min_ms / max_ms に変換済み)
+    pub length_mode: LengthMode,
+    /// RingPercent のグレインの中心の長さ (リングの長さに対する %)
+    pub length_pct: f32,
+    /// RingPercent で中心の長さの前後へ散らす割合 (%)
+    pub length_jitter: f32,
+    /// ウェット／ドライ比率 (0.0=ドライのみ, 1.0=100% ウェット)
+    pub mix: f32,
+    /// true ならミックス後の出力からドライを引いた差分 (グレインが足した分) だけを出力する (モニター用)
+    pub delta: bool,
+    /// true の間は mix を AUDITION_FADE_MS かけて 100% へ寄せ、ウェットだけを聞く (オフにすると mix へ戻る)
+    pub audition: bool,
+    /// グレインの発生方式
+    pub mode: TriggerMode,
+    /// Sync / Stretch モードの重なり数
+    pub overlap: Overlap,
+    /// Stretch モードのプレイヘッド速度 (0=停止, 1=実時間, 2=倍速)
+    pub speed: f32,
+    /// 検出ピッチをこの音階へ合わせるようにグレインを移調する (Off で移調なし)
+    pub scale: Scale,
+    /// 音階のルート (0=C … 11=B)
+    pub root: i32,
+    /// シマーの移調量 (Off で移調なし)
+    pub shimmer: Shimmer,
+    /// 1 回のトリガーで重ねる和音 (同じ区間・同じ窓で声部ごとに移調する)
+    pub chord: Chord,
+    /// chord が Custom のときの声部 (音程とレベル)
+    pub chord_voices: [ChordVoice; MAX_CHORD_VOICES],
+    /// ウェット出力をリングへ戻す量 (0.0=帰還なし)
+    pub feedback: f32,
+    /// スペクトルフリーズのスイッチ (フリーズした瞬間の振幅スペクトルを鳴らし続ける)
+    pub freeze: bool,
+    /// freeze スイッチの働き方
+    pub freeze_mode: FreezeMode,
+    /// Timed のときにフリーズを保つ長さ (拍)
+    pub freeze_beats: f32,
+    /// フリーズ中のウェットに占めるスペクトル再合成の割合 (0.0=グレインのみ, 1.0=スペクトルのみ)
+    pub spectral: f32,
+    /// ウェットの振幅スペクトルを時間方向にぼかす量 (0.0=オフ, 1.0=時定数 MAX_BLUR_SEC)。
+    /// 0 より大きい間はウェットとドライが STFT の FFT 長だけ遅れる
+    pub spectral_blur: f32,
+    /// Tempo モードのグリッド間隔
+    pub division: NoteDivision,
+    /// Tempo モードのスウィング量 (0.0=なし, 1.0=裏拍を半ステップ遅らせる 75% スウィング)
+    pub swing: f32,
+    /// Random 以外のモードで、トリガーごとにグレインの開始を 0〜humanize_ms ミリ秒のランダムな時間だけ遅らせる
+    pub humanize_ms: f32,
+    /// Random モードで、確率に当たったグレインをその時点から scatter_ms ミリ秒の窓の中へ一様に散らす
+    pub scatter_ms: f32,
+    /// 1 回のトリガーで生成するグレインの数 (1〜MAX_BURST)。2 つ目以降は時間・移調・チャンネルを少しずつずらす
+    pub burst: i32,
+    /// density が BURST_DENSITY_KNEE を超えた分だけ burst を MAX_BURST まで増やす (1 つのノブで疎から密まで)
+    pub burst_density: bool,
+    /// Euclidean モードの 1 周のステップ数 (1〜MAX_EUCLID_STEPS)
+    pub euclid_steps: i32,
+    /// Euclidean モードの 1 周の発音数 (0〜euclid_steps)
+    pub euclid_pulses: i32,
+    /// Euclidean モードのパターンを前へ回すステップ数
+    pub euclid_rotation: i32,
+    /// 再生中、この小節数ごとの頭で乱数列をシードから始め直す (0 でオフ、MAX_REROLL_BARS まで)
+    pub reroll_bars: i32,
+    /// true ならホストのトランスポートが再生中のときだけグレインを生成する
+    pub gate: bool,
+    /// gate で停止中もリングへの書き込みを続けるか
+    pub gate_write: bool,
+    /// true なら MIDI ノートを押さえている間だけグレインを生成し、density を押さえているノート数倍にする
+    pub note_gate: bool,
+    /// true なら押さえている MIDI ノートと key_root の音程だけ、新しく生成するグレインを移調する
+    pub key_track: bool,
+    /// key_track で移調しない MIDI ノート番号
+    pub key_root: i32,
+    /// ドライに対してウェットを遅らせる時間 (ミリ秒)
+    pub pre_delay_ms: f32,
+    /// ドライだけを遅らせ、グレインがドライより先の音から鳴るようにする時間 (ミリ秒、0 でオフ)。
+    /// 遅らせた分はレイテンシとして報告する
+    pub lookahead_ms: i32,
+    /// グレインの窓の形
+    pub window: WindowShape,
+    /// ADSR 窓の立ち上がり (グレイン長に対する %)
+    pub attack: f32,
+    /// ADSR 窓の減衰 (グレイン長に対する %)
+    pub decay: f32,
+    /// グレインを再生する回数 (1〜MAX_REPEATS)
+    pub repeats: i32,
+    /// 繰り返しのたびに下げるゲインの割合 (0.0=一定, 1.0=2 回目以降は無音)
+    pub repeat_decay: f32,
+    /// density を揺らすランダムウォークのステップレート (Hz)
+    pub walk_rate: f32,
+    /// ランダムウォークで density を動かす幅 (0.0=なし, 1.0=±1.0)
+    pub walk_depth: f32,
+    /// 入力のスペクトルフラックスが小さい (持続音の) 間に density を下げる量 (0.0=オフ、1.0=止まるまで下げる)
+    pub flux_density: f32,
+    /// リングへ書き込む前に入力へ掛けるゲイン (リニア)
+    pub input_gain: f32,
+    /// true なら入力ゲインをドライにも掛ける
+    pub trim_dry: bool,
+    /// Custom 以外なら interpolation / max_grains / oversampling を品質ごとの組み合わせで置き換える
+    pub quality: Quality,
+    /// グレインを小数位置から読むときの補間方式
+    pub interpolation: Interpolation,
+    /// 同時に鳴らす通常再生中のグレイン数の上限 (1〜MAX_GRAINS)
+    pub max_grains: i32,
+    /// 上方向に移調したグレインを切り出すときに 1 サンプルあたり読む点数
+    pub oversampling: Oversampling,
+    /// 移調の方式 (Resample / Formant)
+    pub pitch_mode: PitchMode,
+    /// 過負荷保護: ブロックの平均同時発音数が voice_budget を超えたらグレインを間引く
+    pub guard: bool,
+    /// 過負荷保護の予算 (1 サンプルあたりに合成するグレイン数の平均)
+    pub voice_budget: i32,
+    /// グレインの開始時の移調をランダムに選ぶ幅 (±半音)。グレインの終わりに向けて本来の速度へ戻る
+    pub glide: f32,
+    /// グレインごとのランダムな移調の幅 (±半音、0=なし) と選び方、ランダムウォークの歩幅 (半音)
+    pub pitch_spread: f32,
+    pub pitch_scatter: PitchScatter,
+    pub pitch_step: f32,
+    /// グレインを順方向→逆方向のピンポンで再生する確率
+    pub pingpong: f32,
+    /// グレインの先頭 (元の速度で読む単音のグレインは終わりも) を近くのゼロクロスへずらす
+    pub zero_cross: bool,
+    /// グレインごとのキャラクター: ソフトクリップのドライブ (dB)、ビット深度、サンプルレートを下げる倍率
+    pub grain_drive_db: f32,
+    pub grain_bits: f32,
+    pub grain_downsample: f32,
+    /// グレインごとにキャラクターの効き具合を 1 - character_random〜1 倍のランダムにする
+    pub character_random: f32,
+    /// グレインのティルト (±dB、正で明るく) と、グレインごとにランダムに足す幅 (±dB)
+    pub grain_tilt_db: f32,
+    pub tilt_random_db: f32,
+    /// Sidechain のフリーズのキーのしきい値 (dB)、下回ってから保つ時間とエンベロープの戻り (ミリ秒)
+    pub key_threshold_db: f32,
+    pub key_hold_ms: f32,
+    pub key_release_ms: f32,
+    /// テープの回転むら: wow (遅い揺れ) と flutter (速い揺れ) の深さ (±セント) とレート (Hz)
+    pub wow_cents: f32,
+    pub wow_rate: f32,
+    pub flutter_cents: f32,
+    pub flutter_rate: f32,
+    /// ウェットのエンベロープでドライを下げる最大量 (dB、0=ダッキングなし)
+    pub duck_db: f32,
+    /// ダッキングのエンベロープの立ち上がりと戻りの時定数 (ミリ秒)
+    pub duck_attack_ms: f32,
+    pub duck_release_ms: f32,
+    /// ウェットのゲートのしきい値 (dB、MIN_GATE_DB 以下でオフ)
+    pub wet_gate_db: f32,
+    /// ウェットのゲートが開く・閉じる時間 (ミリ秒)
+    pub wet_gate_attack_ms: f32,
+    pub wet_gate_release_ms: f32,
+    /// 入力のエキスパンダーのしきい値 (dB、MIN_GATE_DB 以下でオフ)。下回った入力はリングへ書く前に下げる
+    pub input_gate_db: f32,
+    /// 入力のエキスパンダーの比率 (しきい値を 1 dB 下回るごとに ratio - 1 dB 下げる)
+    pub input_gate_ratio: f32,
+    /// 入力のエキスパンダーが閉じる時間 (ミリ秒)
+    pub input_gate_release_ms: f32,
+    /// ウェットの直流を取り除く (DC_BLOCK_HZ の 1 次ハイパス)
+    pub dc_block: bool,
+    /// ウェットのハイパス (Low Cut) / ローパス (High Cut) のカットオフ (Hz、範囲の端でオフ)
+    pub low_cut_hz: f32,
+    pub high_cut_hz: f32,
+    /// 入力を分ける帯域の数と分割周波数 (Hz、Low / Mid と Mid / High)
+    pub bands: Bands,
+    pub crossover_low_hz: f32,
+    pub crossover_high_hz: f32,
+    /// Low / Mid / High をグレインにするか (しない帯域はリングへ書かず、mix によらずそのまま出す)
+    pub granulate_bands: [bool; 3],
+    /// Low / Mid / High でトリガーからグレインを出す確率 (0.0〜1.0)
+    pub band_density: [f32; 3],
+    /// Low / Mid / High のウェットに掛ける mix の倍率 (0.0〜1.0)
+    pub band_mix: [f32; 3],
+    /// 切り出す区間の RMS がこれを下回ったらグレインを作らない (dB、MIN_SILENCE_DB 以下でオフ)
+    pub silence_db: f32,
+    /// 無音だったときに別の位置を試す回数 (Random / Tempo モード)
+    pub silence_retries: i32,
+    /// Random / Tempo モードのグレインの切り出し元 (Sync / Stretch は常にライブ入力)
+    pub source: Source,
+    /// ライブ入力から切り出すグレインの開始位置の選び方
+    pub grain_start: GrainStart,
+    /// 直近のこの数のグレインと切り出す区間が重ならないように選ぶ (Random / Tempo モード、0 でオフ)
+    pub avoid_repeats: i32,
+    /// source が Sample のとき、グレインごとにサンプルから切り出す確率 (残りはライブ入力から)
+    pub source_blend: f32,
+    /// リングへの書き込み方 (Record=上書き, Overdub=重ね書き, Play=停止)
+    pub ring_mode: RingMode,
+    /// Overdub で既存の内容に掛けるゲイン (0.0=上書きと同じ, 1.0=減衰なし)
+    pub overdub: f32,
+    /// オンになった瞬間に今のリングをフリーズスロット 1〜FREEZE_SLOTS へ取り込む (モーメンタリ)
+    pub capture: [bool; FREEZE_SLOTS],
+    /// グレインを切り出すフリーズスロット (0=ライブのリング。取り込んでいないスロットもライブのリング)
+    pub freeze_slot: i32,
+    /// buffer_morph で溶け込ませる先のフリーズスロット (0=ライブのリング)
+    pub morph_slot: i32,
+    /// グレインごとに freeze_slot ではなく morph_slot から切り出す確率 (0.0〜1.0)
+    pub buffer_morph: f32,
+    /// オンになった瞬間にリングを消去する (モーメンタリ)
+    pub clear: bool,
+    /// 消去のときに鳴っているグレインもフェードアウトさせるか
+    pub clear_grains: bool,
+    /// ホストのトランスポートが止まった瞬間に、鳴っているグレインを CLEAR_FADE_MS でフェードアウトさせる
+    pub stop_kill: bool,
+    /// ホストのトランスポートが止まった瞬間にリングも消去する (次の再生で前のパスの素材を鳴らさない)
+    pub stop_clear: bool,
+    /// true なら連続値のパラメータをシーン A と B の補間で置き換える
+    pub scene_morph: bool,
+    /// シーン間の位置 (0.0=A, 1.0=B)
+    pub morph: f32,
+    /// オンになった瞬間に現在の連続値をシーン A / B へ保存する (モーメンタリ)
+    pub store_a: bool,
+    pub store_b: bool,
+    /// マクロの値 (0.0〜1.0)。割り当てた連続値のパラメータを動かす
+    pub macros: [f32; MACRO_COUNT],
+    /// モジュレーションマトリクスの LFO のレート (Hz) と波形
+    pub lfo_rate: [f32; LFO_COUNT],
+    pub lfo_shape: [LfoShape; LFO_COUNT],
+    /// モジュレーションマトリクスのランダムウォークのステップレート (Hz)
+    pub mod_random_rate: f32,
+    /// モジュレーションマトリクスのカオスの選び方、選び直すレート (Hz)、滑らかさ (0.0=段、1.0=漂う)
+    pub chaos_mode: ChaosMode,
+    pub chaos_rate: f32,
+    pub chaos_smooth: f32,
+    /// モジュレーションマトリクスのスロット (元 × 深さ → 先)
+    pub mod_slots: [ModSlot; MOD_SLOTS],
+    /// グレインの補助出力バスへの振り分け方と、使うバスの数 (1〜GRAIN_BUSES)
+    pub routing: GrainRouting,
+    pub routing_buses: i32,
+    /// サラウンド出力でグレインを振る方向の広がり (0.0=正面だけ、1.0=全周)
+    pub surround_spread: f32,
+    /// 2 つのチャンネルの間にパンしたグレインのゲインの法則
+    pub pan_law: PanLaw,
+    /// Asymmetric 窓の立ち上がり・立ち下がり (グレイン長に対する割合)
+    pub window_attack: f32,
+    pub window_release: f32,
+    /// ウェットを 1/√(鳴っているグレイン数) 倍して、density によらず聴感上の音量をそろえる
+    pub normalize: bool,
+    /// クラウドリバーブ: 最大の密度で長いグレインを重ね、帰還と正規化をかけて残響にする
+    /// (mode / density / 長さ / feedback / normalize を上書きする)
+    pub reverb: bool,
+    /// クラウドリバーブの減衰時間 (秒、-60 dB まで)
+    pub reverb_decay: f32,
+}
+
+impl Default for FrameParams {
+    fn default() -> Self {
+        Self {
+            density: 0.2,
+            min_ms: 20.0,
+            max_ms: 500.0,
+            length_mode: LengthMode::MinMax,
+            length_pct: 5.0,
+            length_jitter: 90.0,
+            mix: 1.0,
+            delta: false,
+            audition: false,
+            mode: TriggerMode::Random,
+            overlap: Overlap::X4,
+            speed: 1.0,
+            scale: Scale::Off,
+            root: 0,
+            shimmer: Shimmer::Off,
+            chord: Chord::Off,
+            chord_voices: [
+                ChordVoice {
+                    interval: 0.0,
+                    level: 1.0,
+                },
+                ChordVoice {
+                    interval: 7.0,
+                    level: 1.0,
+                },
+                ChordVoice {
+                    interval: 12.0,
+                    level: 1.0,
+                },
+                ChordVoice::default(),
+            ],
+            feedback: 0.0,
+            freeze: false,
+            freeze_mode: FreezeMode::Momentary,
+            freeze_beats: 4.0,
+            spectral: 0.5,
+            spectral_blur: 0.0,
+            division: NoteDivision::Sixteenth,
+            swing: 0.0,
+            humanize_ms: 0.0,
+            scatter_ms: 0.0,
+            burst: 1,
+            burst_density: false,
+            euclid_steps: 16,
+            euclid_pulses: 5,
+            euclid_rotation: 0,
+            reroll_bars: 0,
+            gate: false,
+            gate_write: true,
+            note_gate: false,
+            key_track: false,
+            key_root: 60,
+            pre_delay_ms: 0.0,
+            lookahead_ms: 0,
+            window: WindowShape::Tukey,
+            attack: 10.0,
+            decay: 10.0,
+            repeats: 1,
+            repeat_decay: 0.0,
+            walk_rate: 0.2,
+            walk_depth: 0.0,
+            flux_density: 0.0,
+            input_gain: 1.0,
+            trim_dry: true,
+            quality: Quality::Custom,
+            interpolation: Interpolation::Linear,
+            max_grains: MAX_GRAINS as i32,
+            oversampling: Oversampling::X1,
+            pitch_mode: PitchMode::Resample,
+            guard: false,
+            voice_budget: MAX_GRAINS as i32,
+            glide: 0.0,
+            pitch_spread: 0.0,
+            pitch_scatter: PitchScatter::Independent,
+            pitch_step: 1.0,
+            pingpong: 0.0,
+            zero_cross: false,
+            grain_drive_db: 0.0,
+            grain_bits: MAX_BITS,
+            grain_downsample: 1.0,
+            character_random: 0.0,
+            grain_tilt_db: 0.0,
+            tilt_random_db: 0.0,
+            key_threshold_db: -30.0,
+            key_hold_ms: 200.0,
+            key_release_ms: 100.0,
+            wow_cents: 0.0,
+            wow_rate: 0.5,
+            flutter_cents: 0.0,
+            flutter_rate: 8.0,
+            duck_db: 0.0,
+            duck_attack_ms: 10.0,
+            duck_release_ms: 250.0,
+            wet_gate_db: MIN_GATE_DB,
+            wet_gate_attack_ms: 1.0,
+            wet_gate_release_ms: 50.0,
+            input_gate_db: MIN_GATE_DB,
+            input_gate_ratio: 4.0,
+            input_gate_release_ms: 100.0,
+            // Python の参照実装には無いので、エンジン単体の既定ではオフ (プラグインの既定はオン)
+            dc_block: false,
+            low_cut_hz: MIN_CUT_HZ,
+            high_cut_hz: MAX_CUT_HZ,
+            bands: Bands::Off,
+            crossover_low_hz: 200.0,
+            crossover_high_hz: 2_000.0,
+            granulate_bands: [false, true, true],
+            band_density: [1.0; 3],
+            band_mix: [1.0; 3],
+            silence_db: MIN_SILENCE_DB,
+            silence_retries: 2,
+            source: Source::Live,
+            grain_start: GrainStart::Uniform,
+            avoid_repeats: 0,
+            source_blend: 1.0,
+            ring_mode: RingMode::Record,
+            overdub: 0.9,
+            capture: [false; FREEZE_SLOTS],
+            freeze_slot: 0,
+            morph_slot: 0,
+            buffer_morph: 0.0,
+            clear: false,
+            clear_grains: true,
+            stop_kill: false,
+            stop_clear: false,
+            scene_morph: false,
+            morph: 0.0,
+            store_a: false,
+            store_b: false,
+            macros: [0.0; MACRO_COUNT],
+            lfo_rate: [1.0; LFO_COUNT],
+            lfo_shape: [LfoShape::Sine; LFO_COUNT],
+            mod_random_rate: 1.0,
+            chaos_mode: ChaosMode::Drunk,
+            chaos_rate: 2.0,
+            chaos_smooth: 0.5,
+            mod_slots: [ModSlot::default(); MOD_SLOTS],
+            routing: GrainRouting::Off,
+            routing_buses: GRAIN_BUSES as i32,
+            surround_spread: 1.0,
+            pan_law: PanLaw::ConstantPower,
+            window_attack: 0.1,
+            window_release: 0.1,
+            normalize: false,
+            reverb: false,
+            reverb_decay: 4.0,
+        }
+    }
+}
+
+/// 連続値のパラメータ 1 つ: フィールド名と、プラグインのパラメータと同じ範囲
+pub struct Continuous<'a> {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub value: &'a mut f32,
+}
+
+impl FrameParams {
+    /// 最小の長さを最大の長さで頭打ちにする。2 つの長さを別々に動かすマクロ・モジュレーション・
+    /// オートメーションは値を重ねた後にこれを呼び、エンジンへ渡すフレームを min_ms <= max_ms に保つ
+    pub fn limit_min_length(&mut self) {
+        self.min_ms = self.min_ms.min(self.max_ms);
+    }
+
+    /// 連続値 (f32) のフィールドとその範囲 (シーンの補間やマクロの対象)。
+    /// f32 のフィールドを足したらここにも足し、CONTINUOUS_COUNT を合わせる。
+    pub fn continuous(&mut self) -> [Continuous<'_>; CONTINUOUS_COUNT] {
+        fn c<'a>(name: &'static str, min: f32, max: f32, value: &'a mut f32) -> Continuous<'a> {
+            Continuous {
+                name,
+                min,
+                max,
+                value,
+            }
+        }
+        let trim = nih_plug::util::db_to_gain(MAX_TRIM_DB);
+        let [lfo1, lfo2] = &mut self.lfo_rate;
+        let [mod1, mod2, mod3, mod4] = &mut self.mod_slots;
+        let [v1, v2, v3, v4] = &mut self.chord_voices;
+        let [low_density, mid_density, high_density] = &mut self.band_density;
+        let [low_mix, mid_mix, high_mix] = &mut self.band_mix;
+        [
+            c("density", 0.0, 1.0, &mut self.density),
+            c("min_ms", 1.0, MAX_GRAIN_MS, &mut self.min_ms),
+            c("max_ms", 1.0, MAX_GRAIN_MS, &mut self.max_ms),
+            c("mix", 0.0, 1.0, &mut self.mix),
+            c("speed", 0.0, 2.0, &mut self.speed),
+            c("feedback", 0.0, MAX_FEEDBACK, &mut self.feedback),
+            c("spectral", 0.0, 1.0, &mut self.spectral),
+            c("swing", 0.0, 1.0, &mut self.swing),
+            c(
+                "pre_delay_ms",
+                0.0,
+                MAX_PRE_DELAY_MS,
+                &mut self.pre_delay_ms,
+            ),
+            c("attack", 0.0, 100.0, &mut self.attack),
+            c("decay", 0.0, 100.0, &mut self.decay),
+            c("repeat_decay", 0.0, 1.0, &mut self.repeat_decay),
+            c(
+                "walk_rate",
+                MIN_WALK_RATE,
+                MAX_WALK_RATE,
+                &mut self.walk_rate,
+            ),
+            c("walk_depth", 0.0, 1.0, &mut self.walk_depth),
+            c("flux_density", 0.0, 1.0, &mut self.flux_density),
+            c("input_gain", 1.0 / trim, trim, &mut self.input_gain),
+            c("glide", 0.0, MAX_GLIDE_ST, &mut self.glide),
+            c("pingpong", 0.0, 1.0, &mut self.pingpong),
+            c("duck_db", 0.0, MAX_DUCK_DB, &mut self.duck_db),
+            c(
+                "duck_attack_ms",
+                MIN_DUCK_ATTACK_MS,
+                MAX_DUCK_ATTACK_MS,
+                &mut self.duck_attack_ms,
+            ),
+            c(
+                "duck_release_ms",
+                MIN_DUCK_RELEASE_MS,
+                MAX_DUCK_RELEASE_MS,
+                &mut self.duck_release_ms,
+            ),
+            c("wet_gate_db", MIN_GATE_DB, 0.0, &mut self.wet_gate_db),
+            c(
+                "wet_gate_attack_ms",
+                MIN_GATE_ATTACK_MS,
+                MAX_GATE_ATTACK_MS,
+                &mut self.wet_gate_attack_ms,
+            ),
+            c(
+                "wet_gate_release_ms",
+                MIN_GATE_RELEASE_MS,
+                MAX_GATE_RELEASE_MS,
+                &mut self.wet_gate_release_ms,
+            ),
+            c(
+                "silence_db",
+                MIN_SILENCE_DB,
+                MAX_SILENCE_DB,
+                &mut self.silence_db,
+            ),
+            c("source_blend", 0.0, 1.0, &mut self.source_blend),
+            c("overdub", 0.0, 1.0, &mut self.overdub),
+            c("lfo1_rate", MIN_LFO_RATE, MAX_LFO_RATE, lfo1),
+            c("lfo2_rate", MIN_LFO_RATE, MAX_LFO_RATE, lfo2),
+            c(
+                "mod_random_rate",
+                MIN_WALK_RATE,
+                MAX_WALK_RATE,
+                &mut self.mod_random_rate,
+            ),
+            c(
+                "chaos_rate",
+                MIN_CHAOS_RATE,
+                MAX_CHAOS_RATE,
+                &mut self.chaos_rate,
+            ),
+            c("chaos_smooth", 0.0, 1.0, &mut self.chaos_smooth),
+            c("mod_1_depth", -1.0, 1.0, &mut mod1.depth),
+            c("mod_2_depth", -1.0, 1.0, &mut mod2.depth),
+            c("mod_3_depth", -1.0, 1.0, &mut mod3.depth),
+            c("mod_4_depth", -1.0, 1.0, &mut mod4.depth),
+            c("window_attack", 0.0, 1.0, &mut self.window_attack),
+            c("window_release", 0.0, 1.0, &mut self.window_release),
+            c(
+                "length_pct",
+                MIN_LENGTH_PCT,
+                MAX_LENGTH_PCT,
+                &mut self.length_pct,
+            ),
+            c("length_jitter", 0.0, 100.0, &mut self.length_jitter),
+            c(
+                "chord_1_interval",
+                -MAX_CHORD_ST,
+                MAX_CHORD_ST,
+                &mut v1.interval,
+            ),
+            c(
+                "chord_2_interval",
+                -MAX_CHORD_ST,
+                MAX_CHORD_ST,
+                &mut v2.interval,
+            ),
+            c(
+                "chord_3_interval",
+                -MAX_CHORD_ST,
+                MAX_CHORD_ST,
+                &mut v3.interval,
+            ),
+            c(
+                "chord_4_interval",
+                -MAX_CHORD_ST,
+                MAX_CHORD_ST,
+                &mut v4.interval,
+            ),
+            c("chord_1_level", 0.0, 1.0, &mut v1.level),
+            c("chord_2_level", 0.0, 1.0, &mut v2.level),
+            c("chord_3_level", 0.0, 1.0, &mut v3.level),
+            c("chord_4_level", 0.0, 1.0, &mut v4.level),
+            c(
+                "reverb_decay",
+                MIN_REVERB_SEC,
+                MAX_REVERB_SEC,
+                &mut self.reverb_decay,
+            ),
+            c("input_gate_db", MIN_GATE_DB, 0.0, &mut self.input_gate_db),
+            c(
+                "input_gate_ratio",
+                1.0,
+                MAX_EXPAND_RATIO,
+                &mut self.input_gate_ratio,
+            ),
+            c(
+                "input_gate_release_ms",
+                MIN_GATE_RELEASE_MS,
+                MAX_GATE_RELEASE_MS,
+                &mut self.input_gate_release_ms,
+            ),
+            c(
+                "pitch_spread",
+                0.0,
+                MAX_PITCH_SPREAD_ST,
+                &mut self.pitch_spread,
+            ),
+            c("pitch_step", 0.0, MAX_PITCH_STEP_ST, &mut self.pitch_step),
+            c("low_cut_hz", MIN_CUT_HZ, MAX_CUT_HZ, &mut self.low_cut_hz),
+            c("high_cut_hz", MIN_CUT_HZ, MAX_CUT_HZ, &mut self.high_cut_hz),
+            c(
+                "crossover_low_hz",
+                MIN_CROSSOVER_HZ,
+                MAX_CROSSOVER_HZ,
+                &mut self.crossover_low_hz,
+            ),
+            c(
+                "crossover_high_hz",
+                MIN_CROSSOVER_HZ,
+                MAX_CROSSOVER_HZ,
+                &mut self.crossover_high_hz,
+            ),
+            c("low_density", 0.0, 1.0, low_density),
+            c("mid_density", 0.0, 1.0, mid_density),
+            c("high_density", 0.0, 1.0, high_density),
+            c("low_mix", 0.0, 1.0, low_mix),
+            c("mid_mix", 0.0, 1.0, mid_mix),
+            c("high_mix", 0.0, 1.0, high_mix),
+            c("buffer_morph", 0.0, 1.0, &mut self.buffer_morph),
+            c("spectral_blur", 0.0, 1.0, &mut self.spectral_blur),
+            c("surround_spread", 0.0, 1.0, &mut self.surround_spread),
+            c(
+                "grain_drive_db",
+                0.0,
+                MAX_DRIVE_DB,
+                &mut self.grain_drive_db,
+            ),
+            c("grain_bits", MIN_BITS, MAX_BITS, &mut self.grain_bits),
+            c(
+                "grain_downsample",
+                1.0,
+                MAX_DOWNSAMPLE,
+                &mut self.grain_downsample,
+            ),
+            c("character_random", 0.0, 1.0, &mut self.character_random),
+            c(
+                "grain_tilt_db",
+                -MAX_TILT_DB,
+                MAX_TILT_DB,
+                &mut self.grain_tilt_db,
+            ),
+            c("tilt_random_db", 0.0, MAX_TILT_DB, &mut self.tilt_random_db),
+            c(
+                "key_threshold_db",
+                MIN_GATE_DB,
+                0.0,
+                &mut self.key_threshold_db,
+            ),
+            c("key_hold_ms", 0.0, MAX_KEY_HOLD_MS, &mut self.key_hold_ms),
+            c(
+                "key_release_ms",
+                MIN_GATE_RELEASE_MS,
+                MAX_GATE_RELEASE_MS,
+                &mut self.key_release_ms,
+            ),
+            c("wow_cents", 0.0, MAX_WOW_CENTS, &mut self.wow_cents),
+            c("wow_rate", MIN_WOW_RATE, MAX_WOW_RATE, &mut self.wow_rate),
+            c(
+                "flutter_cents",
+                0.0,
+                MAX_FLUTTER_CENTS,
+                &mut self.flutter_cents,
+            ),
+            c(
+                "flutter_rate",
+                MIN_FLUTTER_RATE,
+                MAX_FLUTTER_RATE,
+                &mut self.flutter_rate,
+            ),
+        ]
+    }
+}
+
+/// エンジンへサンプルごとのパラメータ値を供給する
+pub trait ParamSource {
+    fn next_frame(&mut self) -> FrameParams;
+
+    /// 直前の `next_frame` と同じフレームを、少なくとも今の `process` の終わりまで返し続けるなら true
+    /// (スムーザーがすべて止まっているなど)。true の間、エンジンは `next_frame` を呼ばずに、
+    /// シーン・マクロ・モジュレーションを重ねたチャンクの先頭のフレームを使い回す
+    #[inline]
+    fn is_steady(&self) -> bool {
+        false
+    }
+}
+
+/// 固定値はそのままパラメータ源として使える
+impl ParamSource for FrameParams {
+    #[inline]
+    fn next_frame(&mut self) -> FrameParams {
+        *self
+    }
+
+    #[inline]
+    fn is_steady(&self) -> bool {
+        true
+    }
+}
+
+/*──────────────────── 3. Grains ───────────────────────*/
+/// ノートが鳴らしたグレインの、ノートのチャンネルと生成時のピッチベンド (半音)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct NoteBend {
+    pub(crate) channel: u8,
+    pub(crate) spawn_st: f32,
+}
+
+pub struct Grain {
+    /// コピーしたサンプル (窓処理済み)。リングを直接読むグレインでは空
+    pub(crate) buf: Vec<f32>,
+    /// リングを直接読むグレインの (リング上の開始位置, 長さ)。None なら `buf` を読む
+    pub(crate) ring: Option<(usize, usize)>,
+    pub(crate) pos: usize,
+    pub(crate) ch: usize,
+    /// 現在のブロック内で再生を開始するフレーム (ブロック途中で生成されたグレイン用)
+    pub(crate) offset: usize,
+    /// ADSR 窓の立ち上がり・減衰のサンプル数 (両方 0 なら切り出し時に窓処理済み)
+    pub(crate) attack: usize,
+    pub(crate) decay: usize,
+    /// 奪われてフェードアウト中なら残りサンプル数 (0 なら通常再生)
+    pub(crate) release: usize,
+    /// フェードアウト全体の長さ (サンプル)
+    pub(crate) release_len: usize,
+    /// 残りの繰り返し回数 (0 なら現在のパスで終わる)
+    pub(crate) repeats_left: usize,
+    /// 現在のパスのゲイン
+    pub(crate) pass_gain: f32,
+    /// パスごとにゲインへ掛ける係数
+    pub(crate) pass_decay: f32,
+    /// 鳴らす補助出力バス (0=メインのウェット、1〜GRAIN_BUSES=そのバス)
+    pub(crate) bus: usize,
+    /// マルチバンドで鳴らす帯域 (0=Low, 1=Mid, 2=High、None なら帯域に絞らない)
+    pub(crate) band: Option<usize>,
+    /// サラウンド出力で VBAP で振る 2 つのスピーカーとゲイン (None なら ch だけへ出す。Some なら ch は pan.ch[0])
+    pub(crate) pan: Option<Pan>,
+    /// 切り出し時にかける Asymmetric 窓の立ち上がり・立ち下がりのサンプル数 (None なら Tukey 窓)
+    pub(crate) edges: Option<(usize, usize)>,
+    /// 鳴っている間に変える読み出しの速度比 (ノートのピッチベンドの生成時からの変化)
+    pub(crate) rate: f32,
+    /// `rate` が 1 でないときの、`pos` と次のサンプルの間の読み出し位置 (0.0〜1.0)
+    pub(crate) frac: f32,
+    /// ノートが鳴らしたグレインなら、そのチャンネルのピッチベンドに追従する
+    pub(crate) bend: Option<NoteBend>,
+}
+
+impl Default for Grain {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            ring: None,
+            pos: 0,
+            ch: 0,
+            offset: 0,
+            attack: 0,
+            decay: 0,
+            release: 0,
+            release_len: 0,
+            repeats_left: 0,
+            pass_gain: 1.0,
+            pass_decay: 1.0,
+            bus: 0,
+            band: None,
+            pan: None,
+            edges: None,
+            rate: 1.0,
+            frac: 0.0,
+            bend: None,
+        }
+    }
+}
+
+impl Grain {
+    #[inline]
+    pub(crate) fn done(&self) -> bool {
+        self.pos >= self.len() && self.repeats_left == 0
+    }
+
+    /// グレインの長さ (サンプル)
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.ring.map_or(self.buf.len(), |(_, len)| len)
+    }
+
+    /// `i` サンプル目の値 (切り出し窓をかける前)
+    #[inline]
+    fn sample(&self, ring: &[f32], i: usize) -> f32 {
+        match self.ring {
+            Some((start, _)) => ring[(start + i) & (ring.len() - 1)],
+            None => self.buf[i],
+        }
+    }
+
+    /// `i` サンプル目の切り出し窓の値。コピーしたグレインは窓処理済みなので 1
+    #[inline]
+    fn window_gain(&self, i: usize) -> f32 {
+        match (self.ring, self.edges) {
+            (None, _) => 1.0,
+            (Some((_, len)), Some((attack, release))) => edge_gain(i, len, attack, release),
+            (Some((_, len)), None) => tukey_gain(i, len, TUKEY_ALPHA),
+        }
+    }
+
+    /// 読み出し時に切り出し窓をかける先頭・末尾のサンプル数 (コピーしたグレインは 0)
+    fn fades(&self) -> (usize, usize) {
+        match (self.ring, self.edges) {
+            (None, _) => (0, 0),
+            (Some(_), Some(edges)) => edges,
+            (Some((_, len)), None) => {
+                let fade = tukey_fade_len(len, TUKEY_ALPHA);
+                (fade, fade)
+            }
+        }
+    }
+
+    /// 切り出し窓をかけたサンプル列 (デバッグ用のダンプとテスト用)
+    #[cfg(any(test, feature = "debug-dump"))]
+    pub(crate) fn samples(&self, ring: &[f32]) -> Vec<f32> {
+        (0..self.len())
+            .map(|i| self.sample(ring, i) * self.window_gain(i))
+            .collect()
+    }
+
+    /// リングを直接読むグレインを、切り出し窓をかけたコピーへ切り替える (リングを書き換える前に呼ぶ)
+    fn detach(&mut self, ring: &[f32], mut buf: Vec<f32>) {
+        buf.clear();
+        buf.extend((0..self.len()).map(|i| self.sample(ring, i) * self.window_gain(i)));
+        self.buf = buf;
+        self.ring = None;
+    }
+
+    /// フェードアウト中かどうか
+    #[inline]
+    pub(crate) fn releasing(&self) -> bool {
+        self.release_len > 0
+    }
+
+    /// 読み出し時にエンベロープをかけるグレインかどうか
+    #[inline]
+    pub(crate) fn enveloped(&self) -> bool {
+        self.attack > 0 || self.decay > 0
+    }
+
+    /// 現在の読み出し位置でのゲイン (切り出し窓 × ADSR エンベロープ × パスのゲイン × フェードアウト)
+    #[inline]
+    fn gain(&self) -> f32 {
+        let env = adsr_gain(self.pos, self.len(), self.attack, self.decay)
+            * self.pass_gain
+            * self.window_gain(self.pos);
+        if self.releasing() {
+            env * self.release.min(self.release_len) as f32 / self.release_len as f32
+        } else {
+            env
+        }
+    }
+
+    /// 現在のパスの続きを `dst` へ加算し、消費したサンプル数を返す。
+    /// ゲインが 1 の通常のグレインは SIMD でまとめて加算する。
+    /// リングを直接読むグレインは両端の切り出し窓の区間だけサンプルごとに計算する。
+    fn render(&mut self, dst: &mut [f32], ring: &[f32]) -> usize {
+        if self.rate != 1.0 {
+            return self.render_bent(dst, ring);
+        }
+        let len = self.len();
+        let n = dst.len().min(len - self.pos);
+        let (head, tail) = self.fades();
+        let body = len.saturating_sub(tail).max(head);
+        let plain = !(self.releasing() || self.enveloped() || self.pass_gain != 1.0);
+        if plain && (head..body).contains(&self.pos) {
+            let n = n.min(body - self.pos);
+            match self.ring {
+                Some((start, _)) => {
+                    // 折り返しをまたぐ場合は 2 つに分けて加算する
+                    let at = (start + self.pos) & (ring.len() - 1);
+                    let first = n.min(ring.len() - at);
+                    mix_add(&mut dst[..first], &ring[at..at + first]);
+                    mix_add(&mut dst[first..n], &ring[..n - first]);
+                }
+                None => mix_add(&mut dst[..n], &self.buf[self.pos..self.pos + n]),
+            }
+            self.pos += n;
+            n
+        } else {
+            // 切り出し窓の両端・ADSR 窓・繰り返しの減衰・奪われたグレインのフェードアウトは
+            // サンプルごとに計算する。フェードアウトが終わったグレインは打ち切る。
+            let n = if self.releasing() {
+                n.min(self.release)
+            } else if plain && self.pos < head {
+                n.min(head - self.pos)
+            } else {
+                n
+            };
+            for d in dst[..n].iter_mut() {
+                *d += self.sample(ring, self.pos) * self.gain();
+                self.pos += 1;
+                if self.releasing() {
+                    self.release -= 1;
+                }
+            }
+            if self.releasing() && self.release == 0 {
+                self.pos = len;
+                self.repeats_left = 0;
+            }
+            n
+        }
+    }
+
+    /// `render` の速度比 `rate` が 1 でない場合: 隣り合うサンプルを線形補間しながら `rate` 倍速で読み、
+    /// パスの終わりかフェードアウトの終わりまでを `dst` へ加算して、書き込んだサンプル数を返す
+    fn render_bent(&mut self, dst: &mut [f32], ring: &[f32]) -> usize {
+        let len = self.len();
+        let mut n = 0;
+        while n < dst.len() && self.pos < len && !(self.releasing() && self.release == 0) {
+            let a = self.sample(ring, self.pos);
+            let b = if self.pos + 1 < len {
+                self.sample(ring, self.pos + 1)
+            } else {
+                0.0
+            };
+            dst[n] += (a + (b - a) * self.frac) * self.gain();
+            n += 1;
+            if self.releasing() {
+                self.release -= 1;
+            }
+            let step = self.frac + self.rate;
+            self.pos = (self.pos + step as usize).min(len);
+            self.frac = step.fract();
+        }
+        if self.releasing() && self.release == 0 {
+            self.pos = len;
+            self.repeats_left = 0;
+        }
+        n
+    }
+
+    /// 切り出し時の窓 (Tukey または Asymmetric) をかける
+    fn apply_window(&mut self) {
+        match self.edges {
+            Some((attack, release)) => apply_edges(&mut self.buf, attack, release),
+            None => apply_tukey(&mut self.buf, TUKEY_ALPHA),
+        }
+    }
+
+    /// パスの終わりに達していて繰り返しが残っていれば先頭へ戻る
+    #[inline]
+    fn next_pass(&mut self) {
+        if self.pos >= self.len() && self.repeats_left > 0 {
+            self.pos = 0;
+            self.frac = 0.0;
+            self.repeats_left -= 1;
+            self.pass_gain *= self.pass_decay;
+        }
+    }
+
+    /// 鳴り終わるまでの残りサンプル数 (残りの繰り返しのパスを含む)
+    fn remaining(&self) -> usize {
+        if self.releasing() {
+            self.release
+        } else {
+            self.len().saturating_sub(self.pos) + self.repeats_left * self.len()
+        }
+    }
+
+    /// `delay` サンプル後から `len` サンプルかけてフェードアウトさせる
+    fn start_release(&mut self, len: usize, delay: usize) {
+        let len = len.max(1);
+        self.release = len + delay;
+        self.release_len = len;
+    }
+}
+
+/// グレインが 1 つ生成されたこと (MIDI 出力などで外部をテクスチャに同期させる)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnEvent {
+    /// ホストのブロック内で鳴り始めるフレーム
+    pub timing: u32,
+    pub ch: usize,
+    /// グレインの長さ (サンプル)
+    pub len: usize,
+    /// 読み出しの速度比 (移調)
+    pub rate: f32,
+}
+
+/// オーディオスレッドとバックグラウンドスレッドで共有するロックフリーキュー
+pub struct GrainQueues {
+    /// 窓処理が終わり、オーディオスレッドに取り込まれるのを待っているグレイン
+    pub(crate) ready: ArrayQueue<Grain>,
+    /// 再利用待ちのサンプルバッファ (容量は MAX_GRAIN_MS 分確保済み)
+    pub(crate) pool: ArrayQueue<Vec<f32>>,
+    /// バックグラウンドで読み込まれ、オーディオスレッドに取り込まれるのを待っているサンプル
+    pub(crate) samples: ArrayQueue<Vec<f32>>,
+    /// 差し替えで外れた古いサンプルとリング。オーディオスレッドで解放しないよう読み込み・確保側が捨てる
+    pub(crate) retired: ArrayQueue<Vec<f32>>,
+    /// バックグラウンドで確保され、オーディオスレッドに取り込まれるのを待っている広いリング
+    pub(crate) rings: ArrayQueue<Vec<f32>>,
+}
+
+impl Default for GrainQueues {
+    fn default() -> Self {
+        Self {
+            ready: ArrayQueue::new(GRAIN_POOL_SIZE),
+            pool: ArrayQueue::new(GRAIN_POOL_SIZE),
+            samples: ArrayQueue::new(1),
+            // 読み込み側が捨てる前に 2 回差し替わることがある
+            retired: ArrayQueue::new(2),
+            rings: ArrayQueue::new(1),
+        }
+    }
+}
+
+/// バックグラウンドスレッド側の処理: 窓をかけて `ready` へ戻す
+pub fn window_grain(queues: &GrainQueues, mut grain: Grain) {
+    grain.apply_window();
+    if let Err(grain) = queues.ready.push(grain) {
+        let _ = queues.pool.push(grain.buf);
+    }
+}
+
+/// バックグラウンドスレッド側の処理: `len` サンプルのリングを確保して `rings` へ渡す。
+/// オーディオスレッドが返した古いバッファはここで解放する。
+pub fn grow_ring(queues: &GrainQueues, len: usize) {
+    while queues.retired.pop().is_some() {}
+    let _ = queues.rings.force_push(vec![0.0; len]);
+}
+
+/// グレインを合成する先のスクラッチ
+struct GrainMix<'a> {
+    wet: &'a mut [Vec<f32>],
+    bus_wet: &'a mut [Vec<Vec<f32>>; GRAIN_BUSES],
+    band_wet: &'a mut [Vec<Vec<f32>>; 3],
+    pan_buf: &'a mut [f32],
+    /// 補助出力バスのスクラッチで足し始める位置 (ホストのブロック内の位置)
+    bus_from: usize,
+    /// 使う補助出力バスの数と、マルチバンドで分けて合成する帯域
+    grain_buses: usize,
+    bands: &'static [usize],
+}
+
+/// `grains` のブロック分 (`n_samples`) を `mix` へ足し、
+/// (合成したサンプル数, メインのウェットで鳴ったグレイン数) を返す
+fn render_grains(
+    grains: &mut [Grain],
+    ring: &[f32],
+    mix: GrainMix,
+    n_ch: usize,
+    n_samples: usize,
+) -> (usize, usize) {
+    let mut rendered = 0;
+    let mut voices = 0;
+    for g in grains {
+        let (out, from) = match (g.bus, g.band) {
+            (bus @ 1..=GRAIN_BUSES, _) if bus <= mix.grain_buses => {
+                (&mut mix.bus_wet[bus - 1][..], mix.bus_from)
+            }
+            (_, Some(band)) if mix.bands.contains(&band) => {
+                voices += usize::from(g.offset < n_samples && !g.done());
+                (&mut mix.band_wet[band][..], 0)
+            }
+            _ => {
+                voices += usize::from(g.offset < n_samples && !g.done());
+                (&mut *mix.wet, 0)
+            }
+        };
+        // サラウンドでパンするグレインはスクラッチへ合成してから、2 つのスピーカーへゲインを掛けて足す
+        let dst = match g.pan {
+            Some(_) => {
+                let buf = &mut mix.pan_buf[..n_samples];
+                buf.fill(0.0);
+                buf
+            }
+            None => &mut out[g.ch % n_ch][from..from + n_samples],
+        };
+        // 繰り返すグレインはブロック内でパスの先頭へ戻って続きを加算する
+        let mut pos = g.offset;
+        while pos < n_samples && !g.done() {
+            pos += g.render(&mut dst[pos..], ring);
+            g.next_pass();
+        }
+        if let Some(pan) = g.pan {
+            for (&ch, &gain) in pan.ch.iter().zip(&pan.gain) {
+                let src = &mix.pan_buf[..n_samples];
+                for (d, x) in out[ch % n_ch][from..from + n_samples].iter_mut().zip(src) {
+                    *d += gain * x;
+                }
+            }
+        }
+        rendered += pos.min(n_samples).saturating_sub(g.offset);
+        g.offset = 0;
+    }
+    (rendered, voices)
+}
+
+/// オフラインで一部のグレインを合成するワーカーの部分和 (チャンネル × CHUNK_SIZE)
+struct RenderWorker {
+    wet: Vec<Vec<f32>>,
+    bus_wet: [Vec<Vec<f32>>; GRAIN_BUSES],
+    band_wet: [Vec<Vec<f32>>; 3],
+    pan_buf: Vec<f32>,
+    /// 直前に合成した (サンプル数, メインのウェットで鳴ったグレイン数)
+    counts: (usize, usize),
+}
+
+impl RenderWorker {
+    fn new(n_ch: usize) -> Self {
+        Self {
+            wet: vec![vec![0.0; CHUNK_SIZE]; n_ch],
+            bus_wet: std::array::from_fn(|_| vec![vec![0.0; CHUNK_SIZE]; n_ch]),
+            band_wet: std::array::from_fn(|_| vec![vec![0.0; CHUNK_SIZE]; n_ch]),
+            pan_buf: vec![0.0; CHUNK_SIZE],
+            counts: (0, 0),
+        }
+    }
+
+    /// 部分和を空にしてから `grains` を合成する
+    fn render(
+        &mut self,
+        grains: &mut [Grain],
+        ring: &[f32],
+        n_ch: usize,
+        n_samples: usize,
+        grain_buses: usize,
+        bands: &'static [usize],
+    ) {
+        let (wet, buses, band_wet) = (&mut self.wet, &mut self.bus_wet, &mut self.band_wet);
+        for set in std::iter::once(wet).chain(&mut buses[..grain_buses]) {
+            for w in &mut set[..n_ch] {
+                w[..n_samples].fill(0.0);
+            }
+        }
+        for &band in bands {
+            for w in &mut band_wet[band][..n_ch] {
+                w[..n_samples].fill(0.0);
+            }
+        }
+        let mix = GrainMix {
+            wet: &mut self.wet,
+            bus_wet: &mut self.bus_wet,
+            band_wet: &mut self.band_wet,
+            pan_buf: &mut self.pan_buf,
+            bus_from: 0,
+            grain_buses,
+            bands,
+        };
+        self.counts = render_grains(grains, ring, mix, n_ch, n_samples);
+    }
+}
+
+/*──────────────────── 4. Engine ───────────────────────*/
+/// グレインを生成するトリガー
+#[derive(Clone, Copy)]
+struct Trigger {
+    /// Step Sequencer のステップ番号
+    step: i64,
+    /// バーストで足すグレインなら元のグレインに対する移調 (半音)。None なら元のグレイン
+    detune: Option<f32>,
+    /// バーストのグレインを振る方向 (度、0 = 正面)。None ならグレインごとにランダムに選ぶ
+    azimuth: Option<f32>,
+}
+
+pub struct Engine {
+    /// リングバッファ。長さは 2 の累乗で、位置の折り返しは `ring_mask` との論理積で行う
+    pub(crate) ring: Vec<f32>,
+    ring_mask: usize,
+    /// リングの本来の長さ (パラメータから決めたサンプル数)。プレイヘッドが遡れる範囲などの計算に使う
+    pub(crate) ring_len: usize,
+    /// 確保済みのリングでは足りない長さ (0 なら足りている) と、バックグラウンドへ確保を頼んだ長さ
+    ring_want: usize,
+    ring_asked: usize,
+    pub(crate) wr: usize,
+    /// 読み込んだ WAV サンプル (エンジンのサンプルレートのモノラル、空なら未読み込み)
+    pub(crate) sample: Vec<f32>,
+    pub(crate) grains: Vec<Grain>,
+    pub(crate) sr: f32,
+    /// ブロック単位のウェット合成用スクラッチ (チャンネル × サンプル)
+    wet: Vec<Vec<f32>>,
+    /// 直前のブロックで補助出力バスへ振り分けたグレイン (バス × チャンネル × サンプル)
+    bus_wet: [Vec<Vec<f32>>; GRAIN_BUSES],
+    /// マルチバンドで帯域ごとに合成したグレイン (帯域 × チャンネル × サンプル)
+    band_wet: [Vec<Vec<f32>>; 3],
+    /// ホストに接続されている補助出力バスの数 (0 なら振り分けない)
+    grain_buses: usize,
+    /// 次に生成するグレインのバス (Grain::bus) と、ラウンドロビンで最後に使ったバス
+    next_bus: usize,
+    last_bus: usize,
+    /// 次に生成するグレインの帯域 (Grain::band)
+    next_band: Option<usize>,
+    /// 次に生成するグレインのパン (Grain::pan)
+    next_pan: Option<Pan>,
+    /// 次に生成するグレインを振る方向 (度。Trigger::azimuth、None なら pick_channel がランダムに選ぶ)
+    next_azimuth: Option<f32>,
+    /// 次に生成するグレインにかけるキャラクター (None ならかけない)
+    next_character: Option<Character>,
+    /// 次に生成するグレインにかけるティルト (dB、0 ならかけない)
+    next_tilt: f32,
+    /// Random Walk の現在の移調 (半音)
+    pitch_walk: f32,
+    /// 直近に切り出したグレインの (サンプルからか, 開始位置, 切り出した長さ)。新しいものが後ろ
+    recent: ArrayVec<(bool, usize, usize), { MAX_AVOID_REPEATS as usize }>,
+    /// 次に生成するグレインのレベル (和音の声部のレベル、Grain::pass_gain の初期値)
+    next_gain: f32,
+    /// 直前のブロックのドライ信号 (入力ゲイン適用後、ミックス前)
+    dry: Vec<Vec<f32>>,
+    /// サンプル単位の mix 値のスクラッチ
+    mix_buf: Vec<f32>,
+    /// パンするグレインを合成してからスピーカーへ振り分けるスクラッチ
+    pan_buf: Vec<f32>,
+    /// このブロックの補助入力 Modulation (先頭 mod_in_len サンプル。set_mod_input で書き込む)
+    mod_in: Vec<f32>,
+    mod_in_len: usize,
+    /// サンプル単位の feedback 値のスクラッチ
+    fb_buf: Vec<f32>,
+    /// サンプル単位のダッキングの深さ (dB) のスクラッチ。ミックス時にドライのゲインで上書きする
+    duck_buf: Vec<f32>,
+    /// サンプル単位のスペクトル再合成出力と、そのブレンド量のスクラッチ
+    spec_buf: Vec<f32>,
+    blend_buf: Vec<f32>,
+    /// サンプル単位のプリディレイ量 (サンプル) のスクラッチ
+    delay_buf: Vec<f32>,
+    /// ウェットバスのプリディレイ用ディレイライン (チャンネルごと)
+    pre_delay: Vec<Vec<f32>>,
+    pre_delay_wr: usize,
+    /// ルックアヘッドでドライを遅らせるディレイライン (チャンネルごと、MAX_LOOKAHEAD_MS 分)
+    lookahead: Vec<Vec<f32>>,
+    lookahead_wr: usize,
+    /// バックグラウンドの窓処理とやり取りするキュー
+    pub(crate) queues: Arc<GrainQueues>,
+    /// 窓処理待ちでバックグラウンドへ渡すグレイン
+    pending: Vec<Grain>,
+    /// true なら窓処理をバックグラウンドへ任せ、false ならその場で行う
+    background_windowing: bool,
+    /// オフラインのバウンス中か (最高品質で鳴らす)
+    offline: bool,
+    /// オフラインでグレインを分けて合成する部分和 (RENDER_WORKERS 個、オンラインでは空)
+    workers: Vec<RenderWorker>,
+    /// Sync / Stretch モードで次のグレインを出すまでの残りサンプル数
+    sync_countdown: f32,
+    /// humanize_ms / scatter_ms で遅らせているトリガーの (生成までの残りサンプル数, Step Sequencer のステップ番号)
+    delayed: ArrayVec<(usize, Trigger), MAX_DELAYED_TRIGGERS>,
+    /// Stretch モードのプレイヘッドが書き込み位置から遅れているサンプル数
+    lag: f32,
+    /// 次に生成するグレインの開始時の速度比 (本来の速度に対する比、1.0=グライドなし)
+    glide_from: f32,
+    /// 次に生成するグレインをピンポン再生するか
+    pingpong: bool,
+    /// 次に生成するグレインを freeze_slot ではなく morph_slot から切り出すか
+    morphing: bool,
+    /// 通算の処理サンプル数を CHUNK_SIZE で割った余り (内部の区切り位置)
+    chunk_phase: usize,
+    /// 現在のチャンクの先頭のリング書き込み位置と、チャンク内でリングへ書き込んだサンプル数
+    chunk_start: usize,
+    chunk_written: usize,
+    /// 現在のチャンクで合成したグレインのサンプル数 (負荷の計測用)
+    chunk_rendered: usize,
+    /// 現在のチャンクで feedback が 0 より大きいフレームがあったか (チャンクの終わりにリングへ帰還が足される)
+    chunk_feedback: bool,
+    /// チャンクの終わりにリングへ戻す帰還信号 (CHUNK_SIZE サンプル)
+    fb_pending: Vec<f32>,
+    /// 直前のチャンクの負荷 (合成したグレインのサンプル数 / CHUNK_SIZE = 平均同時発音数)
+    load: f32,
+    /// 過負荷保護が働いている間は true (同時発音数を voice_budget に制限する)
+    overloaded: bool,
+    /// 入力・ウェット・出力のメーターのバリスティクスと、GUI と共有するレベル
+    meter_in: Meter,
+    meter_wet: Meter,
+    meter_out: Meter,
+    meters: Arc<Meters>,
+    /// このブロックで生成したグレイン数と、上限のために古いグレインを奪ったか
+    spawned: usize,
+    stole: bool,
+    /// このブロックで生成したグレイン (MAX_SPAWN_EVENTS を超えた分は記録しない)
+    spawn_events: ArrayVec<SpawnEvent, MAX_SPAWN_EVENTS>,
+    /// 処理中のチャンクのホストのブロック内での先頭位置
+    block_at: usize,
+    /// 毎秒のグレイン生成数 (SPAWN_RATE_SEC で平均)
+    spawn_rate: f32,
+    /// ウェットの正規化ゲイン (NORMALIZE_SMOOTH_MS で 1/√グレイン数 へ追従する)
+    norm_gain: f32,
+    /// 生成するグレインの (最小, 最大) の長さ (ミリ秒、LENGTH_SMOOTH_MS で追従する。None なら次の値へそのまま揃える)
+    lengths: Option<(f32, f32)>,
+    /// デバッグ用: 窓処理後のグレインのコピーと、ウェットバスのモノラル和
+    #[cfg(feature = "debug-dump")]
+    dumps: Vec<Vec<f32>>,
+    #[cfg(feature = "debug-dump")]
+    wet_dump: Option<Vec<f32>>,
+    /// モノラル入力のピッチ検出器 (グレインの移調量を決める)
+    tracker: PitchTracker,
+    /// リングの粗いラウドネスマップ (Loudness のグレイン開始位置用)
+    loudness: LoudnessMap,
+    /// リングに書き込んだ入力のオンセット (Onset のグレイン開始位置用)
+    onsets: OnsetDetector,
+    /// 入力のスペクトルフラックス (flux_density 用)
+    flux: FluxTracker,
+    /// リングに書き込んだときのグリッド位置 (Beat Grid のグレイン開始位置用)
+    beats: BeatMarks,
+    /// density を揺らすランダムウォーク
+    walk: RandomWalk,
+    /// モジュレーションマトリクス (チャンクごとに評価する)
+    modulation: ModMatrix,
+    /// グレインの再生速度を揺らすテープの回転むら
+    wow: WowFlutter,
+    /// ウェットのエンベロープでドライを下げるダッカー
+    ducker: Ducker,
+    /// ウェットの小さな残りを消すゲート
+    wet_gate: Gate,
+    /// リングへ書く前の入力のエキスパンダー
+    input_gate: Expander,
+    /// Sidechain のフリーズのキー
+    freeze_key: Key,
+    /// ウェットバスの Low Cut / High Cut
+    wet_filter: WetFilter,
+    /// 入力とドライをグレインにする帯域と素通しする帯域に分けるクロスオーバー
+    crossover: Crossover,
+    /// ウェットバスの直流除去
+    dc_blocker: DcBlocker,
+    /// スペクトルフリーズ
+    spectral: SpectralFreeze,
+    /// ウェットバスのスペクトルブラー (掛けている間はドライも同じだけ遅らせる)
+    blur: SpectralBlur,
+    /// Record がオンの間、ウェットバスをバックグラウンドの WAV 書き出しへ送る
+    recorder: WetRecorder,
+    /// 前フレームの freeze 状態 (オンになった瞬間にスペクトルを取り込む)
+    frozen: bool,
+    /// audition で mix を 100% へ寄せている割合 (0.0〜1.0)
+    audition: f32,
+    /// 前フレームの freeze スイッチ (押した瞬間を検出する)
+    freeze_pressed: bool,
+    /// Latch でフリーズしているか
+    freeze_latched: bool,
+    /// Timed のフリーズの残りサンプル数
+    freeze_left: f64,
+    /// 前フレームで ring_mode が Play だったか (Play になった瞬間にリングをループにする)
+    looped: bool,
+    /// ループにしたリングの末尾の、クロスフェードに使って読まなくなった区間の長さ (Play でなければ 0)
+    loop_fade: usize,
+    /// フリーズスロット。取り込んだリングを古い順に並べ、継ぎ目をつないだループ (長さはリングと同じ)
+    slots: [Vec<f32>; FREEZE_SLOTS],
+    /// フリーズスロットのループの長さ (0 なら未取り込み)
+    slot_lens: [usize; FREEZE_SLOTS],
+    /// 前フレームの capture 状態 (オンになった瞬間に取り込む)
+    capturing: [bool; FREEZE_SLOTS],
+    /// 前フレームの clear 状態 (オンになった瞬間にリングを消去する)
+    cleared: bool,
+    /// リング消去後の入力のフェードインの残りサンプル数
+    clear_fade: usize,
+    /// モーフィングするシーン (A / B) と、前フレームの保存トリガーの状態
+    scenes: [FrameParams; SCENE_COUNT],
+    storing: [bool; SCENE_COUNT],
+    /// 保存トリガーでシーンが書き換わり、まだ永続化されていないか
+    scenes_dirty: bool,
+    /// マクロの割り当て
+    macros: Macros,
+    /// Step Sequencer モードのパターン
+    sequence: Sequence,
+    /// 直近のフレームのパラメータ (生成するグレインの窓や繰り返しの設定に使う)
+    frame: FrameParams,
+    /// ホストのトランスポートが再生中か
+    playing: bool,
+    /// 前のサンプルでトランスポートが再生中だったか (止まった瞬間に stop_kill / stop_clear を働かせる)
+    was_playing: bool,
+    /// 押さえている MIDI ノートの数 (note_gate 用)
+    held_notes: usize,
+    /// key_track で移調に使う MIDI ノート番号 (押さえていなければ None)
+    key_note: Option<u8>,
+    /// key_note の表現 (note_gate か key_track がオンの間、新しいグレインのゲイン・ティルト・移調と密度にかける)
+    note_expression: NoteExpression,
+    /// チャンネルごとのノートのピッチベンド (半音)。ノートが鳴らしたグレインが鳴っている間も追従する
+    note_bends: [f32; 16],
+    /// Tempo モードの BPM
+    tempo: f64,
+    /// Tempo モードのグリッドを計算するための再生位置 (サンプル)。
+    /// ホストから位置が得られないときは自走する。
+    clock: i64,
+    /// 1 小節の拍数 (4 分音符)
+    bar_beats: f64,
+    /// reroll_bars で始め直す乱数列のシード
+    reroll_seed: u64,
+    /// reroll_bars の区間の番号と、その区間の頭から始めた乱数列 (オフなら None)
+    reroll_group: Option<i64>,
+    reroll_rng: Option<SmallRng>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self {
+            ring: Vec::new(),
+            ring_mask: 0,
+            ring_len: 0,
+            ring_want: 0,
+            ring_asked: 0,
+            wr: 0,
+            sample: Vec::new(),
+            grains: Vec::with_capacity(GRAIN_POOL_SIZE),
+            sr: 0.0,
+            wet: Vec::new(),
+            bus_wet: Default::default(),
+            band_wet: Default::default(),
+            grain_buses: 0,
+            next_bus: 0,
+            next_band: None,
+            next_pan: None,
+            next_azimuth: None,
+            next_character: None,
+            next_tilt: 0.0,
+            next_gain: 1.0,
+            last_bus: 0,
+            pitch_walk: 0.0,
+            recent: ArrayVec::new(),
+            dry: Vec::new(),
+            mix_buf: Vec::new(),
+            pan_buf: Vec::new(),
+            mod_in: Vec::new(),
+            mod_in_len: 0,
+            fb_buf: Vec::new(),
+            duck_buf: Vec::new(),
+            spec_buf: Vec::new(),
+            blend_buf: Vec::new(),
+            delay_buf: Vec::new(),
+            pre_delay: Vec::new(),
+            pre_delay_wr: 0,
+            lookahead: Vec::new(),
+            lookahead_wr: 0,
+            queues: Arc::new(GrainQueues::default()),
+            pending: Vec::with_capacity(GRAIN_POOL_SIZE),
+            background_windowing: false,
+            offline: false,
+            workers: Vec::new(),
+            sync_countdown: 0.0,
+            delayed: ArrayVec::new(),
+            lag: 0.0,
+            glide_from: 1.0,
+            pingpong: false,
+            morphing: false,
+            chunk_phase: 0,
+            chunk_start: 0,
+            chunk_written: 0,
+            chunk_rendered: 0,
+            chunk_feedback: false,
+            fb_pending: vec![0.0; CHUNK_SIZE],
+            load: 0.0,
+            overloaded: false,
+            meter_in: Meter::default(),
+            meter_wet: Meter::default(),
+            meter_out: Meter::default(),
+            meters: Arc::new(Meters::default()),
+            spawned: 0,
+            spawn_events: ArrayVec::new(),
+            block_at: 0,
+            stole: false,
+            spawn_rate: 0.0,
+            norm_gain: 1.0,
+            lengths: None,
+            #[cfg(feature = "debug-dump")]
+            dumps: Vec::new(),
+            #[cfg(feature = "debug-dump")]
+            wet_dump: None,
+            tracker: PitchTracker::default(),
+            loudness: LoudnessMap::default(),
+            onsets: OnsetDetector::default(),
+            flux: FluxTracker::default(),
+            beats: BeatMarks::default(),
+            walk: RandomWalk::default(),
+            modulation: ModMatrix::default(),
+            wow: WowFlutter::default(),
+            ducker: Ducker::default(),
+            wet_gate: Gate::default(),
+            input_gate: Expander::default(),
+            freeze_key: Key::default(),
+            wet_filter: WetFilter::default(),
+            crossover: Crossover::default(),
+            dc_blocker: DcBlocker::default(),
+            spectral: SpectralFreeze::default(),
+            blur: SpectralBlur::default(),
+            recorder: WetRecorder::default(),
+            frozen: false,
+            audition: 0.0,
+            freeze_pressed: false,
+            freeze_latched: false,
+            freeze_left: 0.0,
+            looped: false,
+            loop_fade: 0,
+            slots: Default::default(),
+            slot_lens: [0; FREEZE_SLOTS],
+            capturing: [false; FREEZE_SLOTS],
+            cleared: false,
+            clear_fade: 0,
+            scenes: [FrameParams::default(); SCENE_COUNT],
+            storing: [false; SCENE_COUNT],
+            scenes_dirty: false,
+            macros: Macros::default(),
+            sequence: Sequence::default(),
+            frame: FrameParams::default(),
+            playing: true,
+            was_playing: true,
+            held_notes: 0,
+            key_note: None,
+            note_expression: NoteExpression::default(),
+            note_bends: [0.0; 16],
+            tempo: DEFAULT_TEMPO,
+            clock: 0,
+            bar_beats: DEFAULT_BAR_BEATS,
+            reroll_seed: 0,
+            reroll_group: None,
+            reroll_rng: None,
+        }
+    }
+}
+
+impl Engine {
+    /// サンプルレート・出力チャンネル数・最大ブロック長に合わせてバッファを確保する。
+    /// サンプルレートが変わった場合は既存のリング内容を新しいレートへ再サンプリングし、
+    /// 書き込み位置も新しいリング長の範囲に収める。
+    pub fn initialize(&mut self, sr: f32, n_ch: usize, max_block: usize) {
+        self.ring_len = self.ring_demand(sr);
+        let new_len = self.ring_len.next_power_of_two();
+        if self.ring.len() != new_len || sr != self.sr {
+            // 旧レートの内容は同じ時間長のまま新しいレートへ再サンプリングし、新しいリングに収まる新しい側を残す
+            let scaled = (self.ring.len() as f64 * sr as f64 / self.sr as f64).round() as usize;
+            let (old, wr) = resample_ring(&self.ring, self.wr, scaled);
+            (self.ring, self.wr) = fit_ring(&old, wr, new_len);
+        }
+        self.ring_mask = new_len - 1;
+        self.looped = false;
+        self.loop_fade = 0;
+        // 旧レートで取り込んだスロットはピッチがずれるので空にする
+        if sr != self.sr || self.slots[0].len() != new_len {
+            self.slots = std::array::from_fn(|_| vec![0.0; new_len]);
+            self.slot_lens = [0; FREEZE_SLOTS];
+        }
+        self.ring_want = 0;
+        self.ring_asked = 0;
+        while self.queues.rings.pop().is_some() {}
+        if sr != self.sr {
+            // 旧レートのサンプルはピッチがずれるので外す (プラグインが新しいレートで読み込み直す)
+            self.sample = Vec::new();
+        }
+        self.sr = sr;
+
+        self.wet = vec![vec![0.0; max_block]; n_ch];
+        self.bus_wet = std::array::from_fn(|_| vec![vec![0.0; max_block]; n_ch]);
+        self.band_wet = std::array::from_fn(|_| vec![vec![0.0; max_block]; n_ch]);
+        self.dry = vec![vec![0.0; max_block]; n_ch];
+        self.mix_buf = vec![0.0; max_block];
+        self.pan_buf = vec![0.0; max_block];
+        self.workers = if self.offline {
+            (0..RENDER_WORKERS)
+                .map(|_| RenderWorker::new(n_ch))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.mod_in = vec![0.0; max_block];
+        self.mod_in_len = 0;
+        self.fb_buf = vec![0.0; max_block];
+        self.duck_buf = vec![0.0; max_block];
+        self.spec_buf = vec![0.0; max_block];
+        self.blend_buf = vec![0.0; max_block];
+        self.delay_buf = vec![0.0; max_block];
+        self.alloc_pre_delay(n_ch);
+        self.alloc_lookahead(n_ch);
+
+        // 旧レートで切り出したグレインはピッチがずれるので破棄し、
+        // プールのバッファも新しいレートの最大グレイン長で確保し直す。
+        self.grains.clear();
+        self.pending.clear();
+        self.refill_pool();
+        self.reset_scheduler();
+        self.tracker.initialize(sr);
+        let max_ring = ((MAX_RING_SEC * sr) as usize).next_power_of_two();
+        self.loudness.initialize(max_ring, &self.ring);
+        self.onsets.initialize(sr);
+        self.flux.initialize(sr);
+        self.walk.initialize(sr);
+        self.modulation.initialize(sr, CHUNK_SIZE);
+        self.wow.initialize(sr);
+        self.ducker.initialize(sr);
+        self.wet_gate.initialize(sr);
+        self.input_gate.initialize(sr);
+        self.freeze_key.initialize(sr);
+        self.wet_filter.initialize(sr, n_ch);
+        self.crossover.initialize(sr, n_ch);
+        self.dc_blocker.initialize(sr, n_ch);
+        self.spectral.initialize(sr);
+        self.blur.initialize(sr, n_ch);
+        self.recorder.initialize(n_ch);
+        self.meter_in.initialize(sr);
+        self.meter_wet.initialize(sr);
+        self.meter_out.initialize(sr);
+        self.frozen = false;
+        self.freeze_latched = false;
+        self.freeze_left = 0.0;
+    }
+
+    /// リングとグレインを消去する。グレインのバッファはプールへ戻す。
+    pub fn reset(&mut self) {
+        self.wr = 0;
+        self.reroll_group = None;
+        self.reroll_rng = None;
+        for line in self.pre_delay.iter_mut().chain(&mut self.lookahead) {
+            line.fill(0.0);
+        }
+        self.clear_grains();
+        self.ring.fill(0.0);
+        self.loudness.clear();
+        self.onsets.reset();
+        self.flux.reset();
+        self.beats.forget();
+        self.reset_scheduler();
+        self.tracker.reset();
+        self.walk.reset();
+        self.modulation.reset();
+        self.wow.reset();
+        self.ducker.reset();
+        self.wet_gate.reset();
+        self.input_gate.reset();
+        self.freeze_key.reset();
+        self.wet_filter.reset();
+        self.crossover.reset();
+        self.dc_blocker.reset();
+        self.spectral.reset();
+        self.blur.reset();
+        self.meter_in.reset();
+        self.meter_wet.reset();
+        self.meter_out.reset();
+        self.spawn_rate = 0.0;
+        self.norm_gain = 1.0;
+        self.lengths = None;
+        self.frozen = false;
+        self.freeze_latched = false;
+        self.freeze_left = 0.0;
+        self.looped = false;
+        self.loop_fade = 0;
+        self.slot_lens = [0; FREEZE_SLOTS];
+        self.clear_fade = 0;
+    }
+
+    /// Sync / Stretch モードのスケジューラを初期状態へ戻す。
+    /// 次のフレームで即座にグレインを出し、プレイヘッドは書き込み位置に揃える。
+    fn reset_scheduler(&mut self) {
+        self.sync_countdown = 0.0;
+        self.delayed.clear();
+        self.lag = 0.0;
+        self.glide_from = 1.0;
+        self.pitch_walk = 0.0;
+        self.recent.clear();
+        self.pingpong = false;
+        self.chunk_phase = 0;
+        self.chunk_start = 0;
+        self.chunk_written = 0;
+        self.chunk_rendered = 0;
+        self.chunk_feedback = false;
+        self.fb_pending.fill(0.0);
+        self.load = 0.0;
+        self.overloaded = false;
+    }
+
+    /// Stretch モードのプレイヘッドを 1 フレーム進める。
+    /// 書き込み位置は 1 サンプル、プレイヘッドは `speed` サンプル進むので遅れは `1 - speed` 増える。
+    /// 書き込み位置に追いついたら実時間で追従し、最大グレイン長を残してリングを使い切ったら
+    /// 最新の入力へ飛び戻る。
+    fn advance_playhead(&mut self, speed: f32) {
+        let limit = self.ring_len as f32 - (MAX_GRAIN_MS / 1_000.0 * self.sr);
+        self.lag = (self.lag + 1.0 - speed.max(0.0)).max(0.0);
+        if limit > 0.0 && self.lag > limit {
+            self.lag -= limit;
+        }
+    }
+
+    /// 現在のパラメータでリングに必要な長さ (サンプル)。
+    /// 直近の入力から切り出す Sync モードは最大グレイン長と内部の区切り (とスペクトルフリーズの解析フレーム) で足りる。
+    /// ほかのモードや Position のモジュレーションは RING_SEC 秒の履歴から切り出す。
+    fn ring_demand(&self, sr: f32) -> usize {
+        let f = &self.frame;
+        if f.reverb {
+            // クラウドリバーブは減衰時間ぶん遡って切り出す
+            let sec =
+                f.reverb_decay.clamp(MIN_REVERB_SEC, MAX_REVERB_SEC) + REVERB_MAX_MS / 1_000.0;
+            return (sec.max(RING_SEC) * sr) as usize;
+        }
+        let position = f
+            .mod_slots
+            .iter()
+            .any(|s| s.source != ModSource::Off && s.dest == ModDest::Position);
+        // % of Ring はリングの長さが基準なので、グレインに合わせて縮めない
+        if f.mode != TriggerMode::Sync || position || f.length_mode == LengthMode::RingPercent {
+            return (RING_SEC * sr) as usize;
+        }
+        let grain = (f.max_ms.clamp(1.0, MAX_GRAIN_MS) / 1_000.0 * sr) as usize;
+        let fft = ((FFT_SEC * sr) as usize).next_power_of_two();
+        (grain + CHUNK_SIZE + f.interpolation.reach()).max(fft)
+    }
+
+    /// 長さ `len` (MAX_RING_SEC まで) のリングが必要になった。確保済みの範囲ならすぐ広げ、
+    /// 足りなければバックグラウンドでの確保を待つ (`take_ring_request`)。
+    fn need_ring(&mut self, len: usize) {
+        let len = len.min((MAX_RING_SEC * self.sr) as usize);
+        if len <= self.ring.len() {
+            self.ring_len = self.ring_len.max(len);
+        } else {
+            self.ring_want = self.ring_want.max(len);
+        }
+    }
+
+    /// バックグラウンドで確保してほしいリングの長さ。同じ長さは一度だけ返す。
+    /// 確保したら `grow_ring` で渡すこと。
+    pub fn take_ring_request(&mut self) -> Option<usize> {
+        let len = self.ring_want.next_power_of_two();
+        if self.ring_want == 0 || len <= self.ring_asked {
+            return None;
+        }
+        self.ring_asked = len;
+        Some(len)
+    }
+
+    /// バックグラウンドで確保された広いリングがあれば、今の内容を古い順に移して差し替える。
+    /// 古いバッファはここでは解放せず、確保側が捨てられるよう `retired` へ返す。
+    fn collect_ring(&mut self) {
+        let Some(mut ring) = self.queues.rings.pop() else {
+            return;
+        };
+        let (old_len, wr) = (self.ring.len(), self.wr);
+        if ring.len() > old_len {
+            ring[..old_len - wr].copy_from_slice(&self.ring[wr..]);
+            ring[old_len - wr..old_len].copy_from_slice(&self.ring[..wr]);
+            // リングを直接読むグレインは書き込み位置からの距離を保って移す
+            for (start, _) in self.grains.iter_mut().filter_map(|g| g.ring.as_mut()) {
+                *start = (*start + old_len - wr) & self.ring_mask;
+            }
+            let mask = self.ring_mask;
+            self.onsets.relocate(|pos| (pos + old_len - wr) & mask);
+            self.beats.relocate(|pos| (pos + old_len - wr) & mask);
+            self.wr = old_len;
+            self.ring_mask = ring.len() - 1;
+            // Play 中なら広いリングで継ぎ目を作り直す
+            self.looped = false;
+            self.loop_fade = 0;
+            ring = std::mem::replace(&mut self.ring, ring);
+            self.loudness.rebuild(&self.ring);
+            self.ring_len = self.ring_len.max(self.ring_want.min(self.ring.len()));
+            if self.ring_want <= self.ring.len() {
+                self.ring_want = 0;
+            }
+        }
+        if let Err(old) = self.queues.retired.push(ring) {
+            nih_plug::util::permit_alloc(|| drop(old));
+        }
+    }
+
+    /// 再生中のグレインを止め、バッファをプールへ戻す
+    pub fn clear_grains(&mut self) {
+        for g in self.grains.drain(..).filter(|g| g.ring.is_none()) {
+            let _ = self.queues.pool.push(g.buf);
+        }
+    }
+
+    /// 窓処理をバックグラウンドスレッドへ任せるかどうか。
+    /// 有効な場合は `drain_pending` で取り出したグレインを `window_grain` に渡すこと。
+    pub fn set_background_windowing(&mut self, enabled: bool) {
+        self.background_windowing = enabled;
+    }
+
+    /// オフラインのバウンス中か。オンなら quality によらず High で鳴らし、同時発音数の上限を
+    /// MAX_OFFLINE_GRAINS へ広げ、過負荷保護も外す。プールを広げるので `initialize` の前に呼ぶこと
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// バックグラウンドの窓処理と共有するキュー
+    pub fn queues(&self) -> Arc<GrainQueues> {
+        self.queues.clone()
+    }
+
+    /// バックグラウンドで読み込まれたサンプルがあれば差し替える。
+    /// 古いバッファはここでは解放せず、読み込み側が捨てられるよう `retired` へ返す。
+    fn collect_sample(&mut self) {
+        if let Some(sample) = self.queues.samples.pop() {
+            let old = std::mem::replace(&mut self.sample, sample);
+            if let Err(old) = self.queues.retired.push(old) {
+                nih_plug::util::permit_alloc(|| drop(old));
+            }
+        }
+    }
+
+    /// モーフィングするシーン `i` (0=A, 1=B)
+    pub fn scene(&self, i: usize) -> &FrameParams {
+        &self.scenes[i]
+    }
+
+    /// 現在のパラメータを設定する。`initialize` の前に呼ぶと、リングをそのパラメータに必要な長さで確保する
+    pub fn set_frame(&mut self, frame: FrameParams) {
+        self.frame = frame;
+    }
+
+    /// シーン `i` を設定する (保存していた状態を戻すときに使う)
+    pub fn set_scene(&mut self, i: usize, scene: FrameParams) {
+        self.scenes[i] = scene;
+    }
+
+    /// 保存トリガーでシーンが書き換わり、まだ永続化されていないか
+    pub fn scenes_dirty(&self) -> bool {
+        self.scenes_dirty
+    }
+
+    /// シーンを永続化したことを記録する
+    pub fn mark_scenes_saved(&mut self) {
+        self.scenes_dirty = false;
+    }
+
+    /// マクロの割り当てを設定する
+    pub fn set_macros(&mut self, macros: Macros) {
+        self.macros = macros;
+    }
+
+    /// Step Sequencer モードのパターンを設定する
+    pub fn set_sequence(&mut self, sequence: Sequence) {
+        self.sequence = sequence;
+    }
+
+    /// モジュレーションマトリクスの MIDI CC の値 (0.0〜1.0) を設定する
+    pub fn set_mod_cc(&mut self, value: f32) {
+        self.modulation.set_cc(value);
+    }
+
+    /// freeze スイッチを freeze_mode に従って 1 サンプル進め、フリーズするかを返す
+    fn freeze_switch(&mut self, p: &FrameParams, key: f32) -> bool {
+        let pressed = p.freeze && !self.freeze_pressed;
+        self.freeze_pressed = p.freeze;
+        if p.freeze_mode != FreezeMode::Latch {
+            self.freeze_latched = false;
+        }
+        if p.freeze_mode != FreezeMode::Timed {
+            self.freeze_left = 0.0;
+        }
+        if p.freeze_mode != FreezeMode::Sidechain {
+            self.freeze_key.reset();
+        }
+        match p.freeze_mode {
+            FreezeMode::Momentary => p.freeze,
+            FreezeMode::Latch => {
+                self.freeze_latched ^= pressed;
+                self.freeze_latched
+            }
+            // 保っている間にもう一度押したら、そこから数え直す
+            FreezeMode::Timed => {
+                if pressed {
+                    let beats = p.freeze_beats.clamp(MIN_FREEZE_BEATS, MAX_FREEZE_BEATS) as f64;
+                    self.freeze_left = beats * 60.0 / self.tempo * self.sr as f64;
+                }
+                let frozen = self.freeze_left > 0.0;
+                self.freeze_left = (self.freeze_left - 1.0).max(0.0);
+                frozen
+            }
+            // スイッチを押している間もフリーズする
+            FreezeMode::Sidechain => {
+                let keyed =
+                    self.freeze_key
+                        .next(key, p.key_threshold_db, p.key_hold_ms, p.key_release_ms);
+                keyed || p.freeze
+            }
+        }
+    }
+
+    /// 押さえている MIDI ノートの数を設定する (note_gate がオンの間、0 ならグレインを生成しない)
+    pub fn set_held_notes(&mut self, n: usize) {
+        self.held_notes = n;
+    }
+
+    /// key_track で移調に使う MIDI ノート番号を設定する (None なら移調しない)
+    pub fn set_key_note(&mut self, note: Option<u8>) {
+        self.key_note = note;
+    }
+
+    /// key_note の表現 (ノートごとの圧力・明るさとチャンネルのピッチベンド) を設定する
+    pub fn set_note_expression(&mut self, expression: NoteExpression) {
+        self.note_expression = expression;
+    }
+
+    /// チャンネルごとのノートのピッチベンド (半音) を設定する。
+    /// ノートが鳴らしたグレインは、次のチャンクから生成時との差の分だけ移調して読む
+    pub fn set_note_bends(&mut self, bends: [f32; 16]) {
+        self.note_bends = bends;
+    }
+
+    /// ノートが鳴らしたグレインの速度比を、生成時からのノートのチャンネルのピッチベンドの変化に合わせる
+    fn bend_grains(&mut self) {
+        for g in self.grains.iter_mut() {
+            if let Some(bend) = g.bend {
+                let st = self.note_bends[bend.channel as usize & 15] - bend.spawn_st;
+                g.rate = (st / 12.0).exp2();
+            }
+        }
+    }
+
+    /// グレインの切り出し元: `sample` なら読み込んだサンプル、そうでなければリング
+    fn source(&self, sample: bool) -> &[f32] {
+        if sample {
+            &self.sample
+        } else {
+            self.slot()
+                .unwrap_or(&self.ring[..self.ring.len() - self.loop_fade])
+        }
+    }
+
+    /// freeze_slot (buffer_morph に当たったグレインは morph_slot) で選んだ、取り込み済みの
+    /// フリーズスロットのループ (ライブのリングを読むなら None)
+    fn slot(&self) -> Option<&[f32]> {
+        let slot = if self.morphing {
+            self.frame.morph_slot
+        } else {
+            self.frame.freeze_slot
+        };
+        let k = usize::try_from(slot).ok()?.checked_sub(1)?;
+        let len = *self.slot_lens.get(k).filter(|len| **len > 0)?;
+        Some(&self.slots[k][..len])
+    }
+
+    /// 今のリングの新しい側からスロットの長さ分を、古い順に並べてフリーズスロット `k` へ取り込み、
+    /// 継ぎ目をリングの Play と同じクロスフェードでつなぐ
+    fn capture_slot(&mut self, k: usize) {
+        let (n, len) = (self.ring.len(), self.slots[k].len());
+        let oldest = (self.wr + n - len.min(n)) & self.ring_mask;
+        let head = len.min(n - oldest);
+        let slot = &mut self.slots[k];
+        slot[..head].copy_from_slice(&self.ring[oldest..oldest + head]);
+        slot[head..].copy_from_slice(&self.ring[..len - head]);
+        let fade = ((LOOP_FADE_MS / 1_000.0 * self.sr) as usize).min(len / 4);
+        join_loop(slot, 0, fade);
+        self.slot_lens[k] = len - fade;
+    }
+
+    /// Play で書き込みを止めたリングを、継ぎ目のないループにする。
+    /// 書き込み位置の直後 (最も古い音) の LOOP_FADE_MS をループの手前の音とみなし、直前 (最も新しい音) の
+    /// 同じ長さと等パワーでクロスフェードする。リングは最も古い音が先頭に来るよう回し、使い切った区間を
+    /// 末尾 (`loop_fade`) に寄せるので、`source` の長さで折り返して読めば継ぎ目を越えてもクリックしない
+    fn close_loop(&mut self) {
+        let n = self.ring.len();
+        let fade = ((LOOP_FADE_MS / 1_000.0 * self.sr) as usize).min(n / 4);
+        let shift = (self.wr + fade) & self.ring_mask;
+        join_loop(&mut self.ring, self.wr, fade);
+        let mask = self.ring_mask;
+        for (start, _) in self.grains.iter_mut().filter_map(|g| g.ring.as_mut()) {
+            *start = (*start + n - shift) & mask;
+        }
+        self.onsets.relocate(|pos| (pos + n - shift) & mask);
+        self.beats.relocate(|pos| (pos + n - shift) & mask);
+        self.wr = n - fade;
+        self.loudness.rebuild(&self.ring);
+        self.loop_fade = fade;
+    }
+
+    /// 窓処理待ちのグレインを取り出す
+    pub fn drain_pending(&mut self) -> std::vec::Drain<'_, Grain> {
+        self.pending.drain(..)
+    }
+
+    /// 直前のブロックで生成したグレイン (時刻順)
+    pub fn spawn_events(&self) -> &[SpawnEvent] {
+        &self.spawn_events
+    }
+
+    /// 現在鳴っているグレイン数
+    pub fn active_grains(&self) -> usize {
+        self.grains.len()
+    }
+
+    /// 入力が無音になってからウェットが鳴り終わるまでのサンプル数の見積もり (ホストへテールとして報告する)。
+    /// 鳴っているグレインの残りと、リングに残った音からグレインを作り続ける分、プリディレイとルックアヘッドを足す。
+    /// 帰還や Overdub でリングの内容が周回ごとに減衰する場合は TAIL_FLOOR_DB まで下がる周回数を掛ける。
+    /// クラウドリバーブ (Record) では、リングの長さの代わりに減衰時間に帰還で遡り直す分
+    /// (平均の遡り時間 × TAIL_FLOOR_DB までの周回数) を足したものを使う。
+    /// リングの内容が減衰しない (Play、overdub=1) か、スペクトルフリーズ中なら None (鳴り続ける)。
+    pub fn tail_len(&self) -> Option<usize> {
+        let f = &self.frame;
+        // スペクトルブラーの振幅が TAIL_FLOOR_DB まで下がる時間と、STFT の遅れ
+        let blur = if self.blur.is_active() {
+            let tau = f.spectral_blur.min(1.0) * MAX_BLUR_SEC * self.sr;
+            self.blur.latency() + (tau * -TAIL_FLOOR_DB / 20.0 * std::f32::consts::LN_10) as usize
+        } else {
+            0
+        };
+        let retain = match f.ring_mode {
+            RingMode::Record => 0.0,
+            RingMode::Overdub => f.overdub.clamp(0.0, 1.0),
+            RingMode::Play => 1.0,
+        };
+        let decay = retain.max(f.feedback.clamp(0.0, MAX_FEEDBACK));
+        if decay >= 1.0 || self.spectral.is_active() {
+            return None;
+        }
+        let cycles = if decay > 0.0 {
+            (TAIL_FLOOR_DB / 20.0 * std::f32::consts::LN_10 / decay.ln()).ceil() as usize
+        } else {
+            1
+        };
+        let grains = self
+            .grains
+            .iter()
+            .chain(&self.pending)
+            .map(Grain::remaining)
+            .max()
+            .unwrap_or(0);
+        let ms = f.max_ms.clamp(0.0, MAX_GRAIN_MS) + f.pre_delay_ms.clamp(0.0, MAX_PRE_DELAY_MS);
+        let ms = (ms / 1_000.0 * self.sr) as usize;
+        // ルックアヘッドで遅らせたドライが出終わるまで
+        let lookahead = self.lookahead_len();
+        if f.reverb && retain == 0.0 {
+            let decay = f.reverb_decay.clamp(MIN_REVERB_SEC, MAX_REVERB_SEC);
+            let reverb = decay + cycles as f32 * self.reverb_mean_lag();
+            return Some(grains.max((reverb * self.sr) as usize) + ms + blur + lookahead);
+        }
+        Some(grains.max(self.ring_len * cycles) + ms + blur + lookahead)
+    }
+
+    /// 入力から出力までの遅れ (サンプル)。スペクトルブラーを掛けている間の STFT の FFT 長と、
+    /// ルックアヘッドでドライを遅らせる分の和
+    pub fn latency(&self) -> usize {
+        let blur = if self.blur.is_active() {
+            self.blur.latency()
+        } else {
+            0
+        };
+        blur + self.lookahead_len()
+    }
+
+    /// クラウドリバーブでグレインを切り出すときに遡る時間の平均 (秒)。
+    /// 遡る時間を指数分布にすると、エネルギーが減衰時間で TAIL_FLOOR_DB まで指数的に下がる残響になる
+    fn reverb_mean_lag(&self) -> f32 {
+        let decay = self
+            .frame
+            .reverb_decay
+            .clamp(MIN_REVERB_SEC, MAX_REVERB_SEC);
+        decay / (-TAIL_FLOOR_DB / 10.0 * std::f32::consts::LN_10)
+    }
+
+    /// クラウドリバーブ用: 書き込み位置から指数分布の時間だけ遡った位置で終わる `src_len` サンプルの開始位置
+    fn reverb_start(&self, rng: &mut impl Rng, src_len: usize) -> usize {
+        let reach = self.frame.interpolation.reach();
+        let room = self.ring_len.saturating_sub(src_len + reach + CHUNK_SIZE);
+        let lag = -(1.0 - rng.random::<f32>()).ln() * self.reverb_mean_lag() * self.sr;
+        let lag = (lag as usize).min(room);
+        (self.wr + self.ring.len() - src_len - reach - lag) & self.ring_mask
+    }
+
+    /// 直前に処理したブロックのドライ信号 (チャンネルごと、入力ゲイン適用後)。
+    /// ドライ専用の補助出力へコピーするために使う。
+    pub fn dry(&self, ch: usize, n_samples: usize) -> &[f32] {
+        self.dry
+            .get(ch)
+            .map_or(&[], |d| &d[..n_samples.min(d.len())])
+    }
+
+    /// ホストに接続されている補助出力バスの数を設定する (GRAIN_BUSES まで)
+    pub fn set_grain_buses(&mut self, n: usize) {
+        self.grain_buses = n.min(GRAIN_BUSES);
+    }
+
+    /// 直前に処理したブロックで補助出力バス `bus` (0 始まり) へ振り分けたグレイン (チャンネルごと、ウェットのみ)
+    pub fn grain_bus(&self, bus: usize, ch: usize, n_samples: usize) -> &[f32] {
+        self.bus_wet
+            .get(bus)
+            .and_then(|b| b.get(ch))
+            .map_or(&[], |w| &w[..n_samples.min(w.len())])
+    }
+
+    /// 入力から検出された基本周波数 (Hz)
+    pub fn detected_pitch(&self) -> Option<f32> {
+        self.tracker.pitch()
+    }
+
+    /// リングバッファ (source が Sample でサンプルを読み込み済みなら source_blend の確率でサンプル) から
+    /// ランダムな区間をプールのバッファへコピーしてグレインを作る。
+    /// バックグラウンド窓処理が有効なら `pending` へ積み、窓処理済みのグレインは次のブロック以降に
+    /// `collect_ready_grains` で取り込まれる。無効ならその場で窓をかけて追加する。
+    /// グレイン数が上限に達している場合、リングが短すぎる場合、プールが空の場合は何もしない。
+    /// `offset` はグレインが鳴り始めるブロック内のフレーム位置、`rate` は再生速度比 (移調量)。
+    /// 直前の `grain_rate` で決めたグライドがあれば、その開始速度から `rate` へ近づける。
+    /// 切り出す区間が無音 (silence_db 未満) なら silence_retries 回まで別の位置を試し、
+    /// それでも無音なら生成しない。avoid_repeats が 1 以上なら、直近のその数のグレインと重なる区間を
+    /// REPEAT_RETRIES 回まで選び直す (重ならない位置が見つからなければ最後の位置から作る)。
+    pub fn spawn_grain(
+        &mut self,
+        rng: &mut impl Rng,
+        min_len: usize,
+        max_len: usize,
+        n_ch: usize,
+        offset: usize,
+        rate: f32,
+    ) {
+        let min_len = min_len.max(self.min_grain_len());
+        let max_len = max_len.max(min_len);
+        // 和音ではいちばん高い声部が最も長く読む
+        let span = glide_mean_rate(rate * self.chord_top(), self.glide_from);
+        let blend = self.frame.source_blend;
+        let sample = self.frame.source == Source::Sample
+            && !self.sample.is_empty()
+            && (blend >= 1.0 || (blend > 0.0 && rng.random::<f32>() < blend));
+        let n = self.source(sample).len();
+        if n <= self.read_len(max_len, span) {
+            if !sample {
+                self.need_ring(self.read_len(max_len, span) + 1);
+            }
+            return;
+        }
+        let len = rng.random_range(min_len..=max_len);
+        let src_len = self.read_len(len, span);
+        let mut start = self.grain_start(rng, sample, n, src_len);
+        let mut retries = self.frame.silence_retries.clamp(0, MAX_SILENCE_RETRIES);
+        while self.is_silent(sample, start, src_len) {
+            if retries == 0 {
+                return;
+            }
+            retries -= 1;
+            start = self.grain_start(rng, sample, n, src_len);
+        }
+        let mut tries = REPEAT_RETRIES;
+        while tries > 0 && self.repeats(sample, start, src_len, n) {
+            tries -= 1;
+            let next = self.grain_start(rng, sample, n, src_len);
+            if !self.is_silent(sample, next, src_len) {
+                start = next;
+            }
+        }
+        if self.recent.is_full() {
+            self.recent.remove(0);
+        }
+        self.recent.push((sample, start, src_len));
+        let ch = self.pick_channel(rng, n_ch);
+        self.push_chord(sample, start as f64, len, rate, ch, offset);
+    }
+
+    /// 長さ `len` のグレインを平均 `span` 倍速で読むのに必要なソースのサンプル数。
+    /// 回転むらで先へ読み進む分も含める
+    fn read_len(&self, len: usize, span: f32) -> usize {
+        source_len(len, span) + self.wow.reach(&self.frame, span)
+    }
+
+    /// ランダムに出すグレインのチャンネルを選ぶ。サラウンドの出力では surround_spread の範囲の
+    /// ランダムな方向 (バーストなら next_azimuth) へ VBAP でパンして (next_pan)、ゲインの大きいほうの
+    /// スピーカーを返す。ステレオではバーストのグレインだけを next_azimuth の方向へパンする。
+    /// パンのゲインは pan_law で掛け直す
+    fn pick_channel(&mut self, rng: &mut impl Rng, n_ch: usize) -> usize {
+        let pan = match (surround::speakers(n_ch), self.next_azimuth) {
+            (Some(speakers), Some(azimuth)) => Some(surround::vbap(azimuth, speakers)),
+            (Some(speakers), None) => Some(surround::vbap(self.random_azimuth(rng), speakers)),
+            (None, Some(azimuth)) if n_ch == 2 => Some(surround::stereo(azimuth)),
+            (None, _) => None,
+        };
+        self.next_pan = pan.map(|pan| self.frame.pan_law.apply(pan));
+        match self.next_pan {
+            Some(pan) => pan.ch[0],
+            None => rng.random_range(0..n_ch),
+        }
+    }
+
+    /// サラウンドの出力でグレインを振るランダムな方向 (surround_spread の範囲、度)
+    fn random_azimuth(&self, rng: &mut impl Rng) -> f32 {
+        let spread = self.frame.surround_spread.clamp(0.0, 1.0);
+        if spread > 0.0 {
+            180.0 * spread * rng.random_range(-1.0f32..=1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// バーストの元のグレインを振る方向 (度)。サラウンドでは random_azimuth、ステレオでは左右の全幅から選ぶ
+    fn burst_azimuth(&self, rng: &mut impl Rng, n_ch: usize) -> f32 {
+        if surround::speakers(n_ch).is_some() {
+            self.random_azimuth(rng)
+        } else {
+            90.0 * rng.random_range(-1.0f32..=1.0)
+        }
+    }
+
+    /// 長さ `n` のソースの `start` から `len` サンプルが、直近の avoid_repeats 個のグレインの
+    /// 切り出した区間と重なるか (リングでは端で折り返して比べる)
+    fn repeats(&self, sample: bool, start: usize, len: usize, n: usize) -> bool {
+        let count = self.frame.avoid_repeats.clamp(0, MAX_AVOID_REPEATS) as usize;
+        self.recent
+            .iter()
+            .rev()
+            .take(count)
+            .filter(|&&(s, _, _)| s == sample)
+            .any(|&(_, other, other_len)| {
+                (other + n - start % n) % n < len || (start + n - other % n) % n < other_len
+            })
+    }
+
+    /// 長さ `n` のソースから `src_len` サンプルを切り出す開始位置。
+    /// クラウドリバーブのリングなら書き込み位置から遡り、Loudness ならラウドネスマップで大きい音の区間へ寄せ、
+    /// Onset なら検出したオンセットから、Beat Grid ならグリッド上で書き込んだ位置から始める。
+    /// それ以外 (または選べる位置が無い) なら一様に選ぶ
+    fn grain_start(&self, rng: &mut impl Rng, sample: bool, n: usize, src_len: usize) -> usize {
+        // フリーズスロットはライブのリングの書き込み位置や解析結果と関係ないので一様に選ぶ
+        let live = !sample && self.slot().is_none();
+        if self.frame.reverb && live {
+            return self.reverb_start(rng, src_len);
+        }
+        let picked = match self.frame.grain_start {
+            GrainStart::Loudness if live => self.loudness.pick(rng, n - src_len),
+            GrainStart::Onset if live => self.marked_start(self.onsets.onsets(), rng, n, src_len),
+            GrainStart::Beat if live => self.marked_start(self.beats.marks(), rng, n, src_len),
+            _ => None,
+        };
+        picked.unwrap_or_else(|| rng.random_range(0..n - src_len))
+    }
+
+    /// 覚えている位置 `marks` (オンセットやグリッド位置) のうち、そこから `src_len` サンプルが
+    /// リングの端を越えず、書き込み済みの範囲に収まるものを一様に選ぶ。無ければ None
+    fn marked_start(
+        &self,
+        marks: &[usize],
+        rng: &mut impl Rng,
+        n: usize,
+        src_len: usize,
+    ) -> Option<usize> {
+        let fits = |&&pos: &&usize| {
+            pos + src_len < n && (self.wr + self.ring.len() - pos) & self.ring_mask >= src_len
+        };
+        let count = marks.iter().filter(fits).count();
+        if count == 0 {
+            return None;
+        }
+        let k = rng.random_range(0..count);
+        marks.iter().filter(fits).nth(k).copied()
+    }
+
+    /// 和音の声部ごとの (ルートに対する速度比, レベル)。Off ならルートだけ、
+    /// Custom なら chord_voices のうちレベルが 0 でない声部
+    fn chord(&self) -> ArrayVec<(f32, f32), MAX_CHORD_VOICES> {
+        let f = &self.frame;
+        match f.chord {
+            Chord::Custom => f
+                .chord_voices
+                .iter()
+                .filter(|v| v.level > 0.0)
+                .map(|v| {
+                    let st = v.interval.clamp(-MAX_CHORD_ST, MAX_CHORD_ST);
+                    ((st / 12.0).exp2(), v.level.min(1.0))
+                })
+                .collect(),
+            preset => preset
+                .intervals()
+                .iter()
+                .map(|&st| ((st / 12.0).exp2(), 1.0))
+                .collect(),
+        }
+    }
+
+    /// いちばん高い声部のルートに対する速度比 (切り出す区間の長さを決める)
+    fn chord_top(&self) -> f32 {
+        self.chord()
+            .iter()
+            .map(|&(ratio, _)| ratio)
+            .reduce(f32::max)
+            .unwrap_or(1.0)
+    }
+
+    /// 和音の声部ごとに `rate` を移調したグレインを、同じ区間・同じ長さ・同じ窓で作る
+    /// (Off ならルートだけ)。同時発音数の上限で場所が作れなければ残りの声部は作らない。
+    /// 声部の音量には鳴らしているノートの圧力を掛ける。
+    fn push_chord(
+        &mut self,
+        sample: bool,
+        start: f64,
+        len: usize,
+        rate: f32,
+        ch: usize,
+        offset: usize,
+    ) {
+        let (start, len) = if self.frame.zero_cross {
+            self.align_to_zero_crossings(sample, start, len, rate)
+        } else {
+            (start, len)
+        };
+        let gain = self.note_expression(&self.frame).gain;
+        for (ratio, level) in self.chord() {
+            if !self.make_room(offset) {
+                return;
+            }
+            self.next_gain = level * gain;
+            self.push_grain(sample, start, len, rate * ratio, ch, offset);
+        }
+        self.next_gain = 1.0;
+    }
+
+    /// グレインの先頭を ZERO_CROSS_MS 以内で最も近いゼロクロスへずらす。元の速度で読む単音のグレインは
+    /// 終わりもゼロクロスへ揃える (長さが変わる)。矩形に近い窓でも端でクリックが出にくくなる。
+    /// リングでは書き込み位置を越えて読まないよう、後ろへずらせる量を制限する。見つからなければそのまま
+    fn align_to_zero_crossings(
+        &self,
+        sample: bool,
+        start: f64,
+        len: usize,
+        rate: f32,
+    ) -> (f64, usize) {
+        let src = self.source(sample);
+        let n = src.len();
+        let radius = (ZERO_CROSS_MS / 1_000.0 * self.sr) as usize;
+        let span = glide_mean_rate(rate * self.chord_top(), self.glide_from);
+        let need = self.read_len(len, span) + self.frame.interpolation.reach();
+        let s = start as usize;
+        let ahead = if sample {
+            n.saturating_sub(s + need)
+        } else if self.slot().is_some() {
+            // フリーズスロットは継ぎ目なく折り返せる
+            radius
+        } else {
+            ((self.wr + n - s) & self.ring_mask).saturating_sub(need)
+        };
+        let Some(d) = zero_crossing_offset(src, s, radius, ahead.min(radius), !sample) else {
+            return (start, len);
+        };
+        let wrap = |i: isize| (i + n as isize) as usize % n;
+        let new_start = wrap(s as isize + d);
+        if span != 1.0 || self.chord().len() != 1 {
+            return (new_start as f64, len);
+        }
+        let back = radius.min(len.saturating_sub(self.min_grain_len().max(1)));
+        let ahead = (ahead as isize - d) as usize;
+        let end = wrap((new_start + len) as isize);
+        match zero_crossing_offset(src, end, back, ahead.min(radius), !sample) {
+            Some(e) => (new_start as f64, (len as isize + e) as usize),
+            None => (new_start as f64, len),
+        }
+    }
+
+    /// 次のブロックのホストのテンポ・再生位置・再生状態を設定する (Tempo モードとトランスポート連動用)。
+    /// テンポと位置が None なら直前の値を使い、位置は自走を続ける。
+    pub fn set_transport(&mut self, tempo: Option<f64>, pos_samples: Option<i64>, playing: bool) {
+        self.playing = playing;
+        if let Some(tempo) = tempo.filter(|t| *t > 0.0) {
+            self.tempo = tempo;
+        }
+        if let Some(pos) = pos_samples {
+            self.clock = pos;
+        }
+    }
+
+    /// ホストの拍子 (分子 / 分母) を設定する。得られなければ直前の値を使う
+    pub fn set_time_signature(&mut self, numerator: Option<i32>, denominator: Option<i32>) {
+        if let (Some(n), Some(d)) = (numerator, denominator) {
+            if n > 0 && d > 0 {
+                self.bar_beats = n as f64 * 4.0 / d as f64;
+            }
+        }
+    }
+
+    /// reroll_bars で小節の頭から始め直す乱数列のシード
+    pub fn set_reroll_seed(&mut self, seed: u64) {
+        if seed != self.reroll_seed {
+            self.reroll_seed = seed;
+            self.reroll_group = None;
+        }
+    }
+
+    /// reroll_bars 小節の区間の長さ (サンプル)。停止中やオフなら None
+    fn reroll_span(&self) -> Option<f64> {
+        let bars = self.frame.reroll_bars.clamp(0, MAX_REROLL_BARS);
+        (bars > 0 && self.playing)
+            .then(|| (bars as f64 * self.bar_beats * 60.0 / self.tempo * self.sr as f64).max(1.0))
+    }
+
+    /// reroll_bars の区間が変わっていれば乱数列をシードから始め直し、次の区間の頭までのサンプル数を返す。
+    /// 停止中やオフの間は外から渡された乱数列を使う
+    fn update_reroll(&mut self) -> usize {
+        let Some(span) = self.reroll_span() else {
+            self.reroll_group = None;
+            self.reroll_rng = None;
+            return usize::MAX;
+        };
+        let group = (self.clock as f64 / span).floor() as i64;
+        if self.reroll_group != Some(group) {
+            // density のランダムウォークも乱数を引く位置が小節の頭から揃うよう始め直す
+            self.reroll_group = Some(group);
+            self.reroll_rng = Some(SmallRng::seed_from_u64(self.reroll_seed));
+            self.walk.reset();
+        }
+        let next = ((group + 1) as f64 * span).ceil() as i64;
+        (next - self.clock).max(1) as usize
+    }
+
+    /// 位置 `t` (サンプル) が `division` のグリッドの何番目のステップに入るか。
+    /// スウィングで遅らせた発音位置も同じステップの中にある
+    fn grid_step(&self, t: i64, division: NoteDivision) -> i64 {
+        let step = division.beats() * 60.0 / self.tempo * self.sr as f64;
+        (t as f64 / step.max(1.0)).floor() as i64
+    }
+
+    /// 再生位置 `t` (サンプル) がスウィング込みのグリッド上の発音位置かどうか。
+    /// 奇数ステップは swing × 半ステップ遅らせる。
+    fn on_grid(&self, t: i64, division: NoteDivision, swing: f32) -> bool {
+        let step = division.beats() * 60.0 / self.tempo * self.sr as f64;
+        if step < 1.0 {
+            return true;
+        }
+        let t = t as f64;
+        let k = (t / step).floor();
+        let delay = if (k as i64).rem_euclid(2) == 1 {
+            swing.clamp(0.0, 1.0) as f64 * step * 0.5
+        } else {
+            0.0
+        };
+        let onset = k * step + delay;
+        onset <= t && onset > t - 1.0
+    }
+
+    /// グレインの再生速度比: 検出ピッチの音階補正とシマーの移調、モジュレーションの Pitch、
+    /// 鳴らしているノートの移調とピッチベンドを掛け合わせる。
+    /// あわせて、これから生成するグレインのグライドの開始速度比を ±glide 半音の範囲で決め、
+    /// pingpong の確率でピンポン再生にするかを決め、routing に従って鳴らす補助出力バスを選ぶ。
+    /// buffer_morph の確率で morph_slot から切り出すかも決める (0 なら乱数を引かない)。
+    /// キャラクターの効き具合も character_random の範囲で決める (0 なら乱数を引かない)。
+    /// ティルトも grain_tilt_db ± tilt_random_db の範囲で決め、鳴らしているノートの明るさの分を足す
+    /// (幅が 0 なら乱数を引かない)。
+    fn grain_rate(&mut self, p: &FrameParams, rng: &mut impl Rng) -> f32 {
+        self.morphing =
+            p.buffer_morph > 0.0 && (p.buffer_morph >= 1.0 || rng.random::<f32>() < p.buffer_morph);
+        self.glide_from = if p.glide > 0.0 {
+            let st = p.glide.min(MAX_GLIDE_ST) * rng.random_range(-1.0f32..=1.0);
+            (st / 12.0).exp2()
+        } else {
+            1.0
+        };
+        self.pingpong = p.pingpong > 0.0 && rng.random::<f32>() < p.pingpong;
+        let character = Character {
+            drive_db: p.grain_drive_db,
+            bits: p.grain_bits,
+            downsample: p.grain_downsample,
+        };
+        let tilt = if p.tilt_random_db > 0.0 {
+            p.grain_tilt_db + p.tilt_random_db * rng.random_range(-1.0f32..=1.0)
+        } else {
+            p.grain_tilt_db
+        };
+        self.next_tilt = (tilt + self.note_expression(p).tilt_db).clamp(-MAX_TILT_DB, MAX_TILT_DB);
+        self.next_character = (!character.is_clean()).then(|| {
+            let random = p.character_random.clamp(0.0, 1.0);
+            if random > 0.0 {
+                character.scaled(1.0 - random * rng.random::<f32>())
+            } else {
+                character
+            }
+        });
+        let buses = (p.routing_buses.max(1) as usize).min(self.grain_buses);
+        self.next_bus = match p.routing {
+            _ if buses == 0 => 0,
+            GrainRouting::Off => 0,
+            GrainRouting::RoundRobin => {
+                self.last_bus = self.last_bus % buses + 1;
+                self.last_bus
+            }
+            GrainRouting::Random => rng.random_range(1..=buses),
+        };
+        let shimmer = match p.shimmer {
+            Shimmer::Off => 1.0,
+            Shimmer::Octave => 2.0,
+            Shimmer::FifthOctave => {
+                if rng.random::<bool>() {
+                    2.0
+                } else {
+                    1.5
+                }
+            }
+        };
+        snap_ratio(self.tracker.pitch(), p.scale, p.root)
+            * shimmer
+            * self.modulation.pitch_ratio()
+            * (self.scatter_st(p, rng) / 12.0).exp2()
+            * self.key_ratio(p)
+            * (self.note_expression(p).bend_st / 12.0).exp2()
+    }
+
+    /// トリガーでグレインを生成する。`offset` はチャンク内のフレーム位置。
+    /// マルチバンドではグレインにする帯域ごとに band_density の確率で生成する (1 以上なら乱数を引かない)
+    fn fire(
+        &mut self,
+        p: &FrameParams,
+        lens: (f32, f32),
+        n_ch: usize,
+        offset: usize,
+        trigger: Trigger,
+        rng: &mut impl Rng,
+    ) {
+        if p.bands == Bands::Off {
+            self.next_band = None;
+            self.fire_band(p, lens, n_ch, offset, trigger, rng);
+            return;
+        }
+        for &band in p.bands.indices() {
+            let density = p.band_density[band];
+            if p.granulate_bands[band]
+                && density > 0.0
+                && (density >= 1.0 || rng.random::<f32>() < density)
+            {
+                self.next_band = Some(band);
+                self.fire_band(p, lens, n_ch, offset, trigger, rng);
+            }
+        }
+    }
+
+    /// トリガーで next_band のグレインを生成する。
+    /// 遅らせたトリガーは生成するときのモードで鳴らす。バーストのグレインはトリガーの方向へパンし、
+    /// 足すグレインは移調して、Sync / Stretch でもその方向の 1 チャンネル (サラウンド・ステレオではパン) だけへ出す
+    fn fire_band(
+        &mut self,
+        p: &FrameParams,
+        (min_len_ms, max_len_ms): (f32, f32),
+        n_ch: usize,
+        offset: usize,
+        trigger: Trigger,
+        rng: &mut impl Rng,
+    ) {
+        let detune = trigger.detune.map_or(1.0, |st| (st / 12.0).exp2());
+        self.next_azimuth = trigger.azimuth;
+        match p.mode {
+            // min/max の中間の長さのグレインを全チャンネルへ出す。Stretch ではプレイヘッドの位置から切り出す
+            TriggerMode::Sync | TriggerMode::Stretch => {
+                let lag = if p.mode == TriggerMode::Stretch {
+                    self.lag
+                } else {
+                    0.0
+                };
+                let len_ms = 0.5 * (min_len_ms + max_len_ms);
+                let len = ((len_ms / 1_000.0) * self.sr) as usize;
+                let rate = self.grain_rate(p, rng) * detune;
+                if trigger.detune.is_some() {
+                    let ch = self.pick_channel(rng, n_ch);
+                    self.spawn_sync_grain(len, lag, ch, offset, rate);
+                } else {
+                    self.next_pan = None;
+                    for ch in 0..n_ch {
+                        self.spawn_sync_grain(len, lag, ch, offset, rate);
+                    }
+                }
+            }
+            TriggerMode::Random | TriggerMode::Tempo | TriggerMode::Euclid => {
+                let min_len = ((min_len_ms / 1_000.0) * self.sr) as usize;
+                let max_len = ((max_len_ms / 1_000.0) * self.sr) as usize;
+                let rate = self.grain_rate(p, rng) * detune;
+                self.spawn_grain(rng, min_len, max_len, n_ch, offset, rate);
+            }
+            // オンのステップでステップの確率に当たれば、min/max の中間の長さにステップの倍率を掛けた
+            // グレインを 1 つ出す (確率 1 のステップでは乱数を引かない)
+            TriggerMode::Sequencer => {
+                let step = self.sequence.step(trigger.step);
+                if step.on && (step.probability >= 1.0 || rng.random::<f32>() < step.probability) {
+                    let len_ms = 0.5 * (min_len_ms + max_len_ms) * step.length;
+                    let len = ((len_ms.min(MAX_GRAIN_MS) / 1_000.0) * self.sr) as usize;
+                    let rate = self.grain_rate(p, rng) * (step.pitch / 12.0).exp2() * detune;
+                    self.spawn_grain(rng, len, len, n_ch, offset, rate);
+                }
+            }
+        }
+        self.next_azimuth = None;
+    }
+
+    /// グレインの (最小, 最大) の長さを `min_ms`, `max_ms` へ 1 サンプル分追従させる。
+    /// 両端を同じ係数で追従させるので、`min_ms <= max_ms` なら追従中も最小が最大を超えない
+    fn smooth_lengths(&mut self, min_ms: f32, max_ms: f32) -> (f32, f32) {
+        let coef = (-1_000.0 / (LENGTH_SMOOTH_MS * self.sr)).exp();
+        let follow = |from: f32, to: f32| {
+            let x = to + (from - to) * coef;
+            if (x - to).abs() < 1e-3 {
+                to
+            } else {
+                x
+            }
+        };
+        let (min, max) = match self.lengths {
+            Some((min, max)) => (follow(min, min_ms), follow(max, max_ms)),
+            None => (min_ms, max_ms),
+        };
+        let (min, max) = (min.max(1.0), max.max(1.0));
+        self.lengths = Some((min, max));
+        (min, max)
+    }
+
+    /// key_track で押さえているノートへ移調する比率
+    fn key_ratio(&self, p: &FrameParams) -> f32 {
+        match self.key_note {
+            Some(note) if p.key_track => ((note as i32 - p.key_root) as f32 / 12.0).exp2(),
+            _ => 1.0,
+        }
+    }
+
+    /// 新しいグレインにかけるノートの表現。ノートでグレインを鳴らしていなければ何もしない
+    fn note_expression(&self, p: &FrameParams) -> NoteExpression {
+        if p.note_gate || p.key_track {
+            self.note_expression
+        } else {
+            NoteExpression::default()
+        }
+    }
+
+    /// グレインごとのランダムな移調 (半音)。幅が 0 なら乱数を引かない
+    fn scatter_st(&mut self, p: &FrameParams, rng: &mut impl Rng) -> f32 {
+        let spread = p.pitch_spread.clamp(0.0, MAX_PITCH_SPREAD_ST);
+        if spread == 0.0 {
+            self.pitch_walk = 0.0;
+            return 0.0;
+        }
+        match p.pitch_scatter {
+            PitchScatter::Independent => spread * rng.random_range(-1.0f32..=1.0),
+            PitchScatter::Walk => {
+                let step = p.pitch_step.clamp(0.0, MAX_PITCH_STEP_ST);
+                let mut st = self.pitch_walk + step * rng.random_range(-1.0f32..=1.0);
+                // 幅の端で折り返す (歩幅が幅より大きくても収まるまで繰り返す)
+                while st.abs() > spread {
+                    st = st.signum() * 2.0 * spread - st;
+                }
+                self.pitch_walk = st;
+                st
+            }
+        }
+    }
+
+    /// Sync / Stretch モード用: 書き込み位置から `lag` サンプル (小数可) 遡った位置で終わる `len` サンプルから
+    /// `ch` 向けのグレインを作る (Sync では lag=0 で直近の入力)。区間が無音なら生成しない。
+    /// `offset` は現在のチャンク内のフレーム位置で、リングにはそのフレームまで書き込み済みであること。
+    pub fn spawn_sync_grain(&mut self, len: usize, lag: f32, ch: usize, offset: usize, rate: f32) {
+        let len = len.max(self.min_grain_len());
+        let top = rate * self.chord_top();
+        let src_len = self.read_len(len, glide_mean_rate(top, self.glide_from));
+        // 移調で直近の入力から読む長さがリングを超える場合は広げる (Stretch の遅れの分は含めない)
+        self.need_ring(src_len + self.frame.interpolation.reach() + CHUNK_SIZE);
+        // 高次の補間は読み出し位置より先のサンプルも使うので、未書き込みの位置へ届かないよう遅らせる
+        let lag = lag.max(0.0) + (self.frame.interpolation.reach() - 1) as f32;
+        // モジュレーションの Position は残りのリングの範囲でさらに遡らせる
+        let free = (self.ring_len as f32 - src_len as f32 - lag).max(0.0);
+        let lag = lag + self.modulation.position() * free;
+        if len == 0 || (self.ring_len as f32) < src_len as f32 + lag {
+            return;
+        }
+        let start =
+            (self.wr as f64 - lag as f64 - src_len as f64).rem_euclid(self.ring.len() as f64);
+        if self.is_silent(false, start as usize, src_len) {
+            return;
+        }
+        self.push_chord(false, start, len, rate, ch, offset);
+    }
+
+    /// リング (`sample` ならサンプル) の `start` から `len` サンプルの RMS が silence_db を下回るか。
+    /// 長い区間は SILENCE_PROBE サンプルに間引いて測る。しきい値が MIN_SILENCE_DB 以下なら常に false。
+    fn is_silent(&self, sample: bool, start: usize, len: usize) -> bool {
+        let floor_db = self.frame.silence_db;
+        if floor_db <= MIN_SILENCE_DB || len == 0 {
+            return false;
+        }
+        let src = self.source(sample);
+        let stride = len.div_ceil(SILENCE_PROBE);
+        let n = len.div_ceil(stride);
+        let sum: f32 = (0..n)
+            .map(|k| src[(start + k * stride) % src.len()])
+            .map(|x| x * x)
+            .sum();
+        let floor = (floor_db * std::f32::consts::LN_10 / 20.0).exp();
+        sum / (n as f32) < floor * floor
+    }
+
+    /// 窓のフェードが潰れないグレインの最小長 (サンプル)。
+    /// Tukey / Asymmetric 窓では片側のフェードが MIN_EDGE_MS 以上になる長さ。ADSR 窓は attack / decay に任せる。
+    fn min_grain_len(&self) -> usize {
+        match self.frame.window {
+            WindowShape::Tukey => min_tukey_len(TUKEY_ALPHA, self.min_edge()),
+            WindowShape::Asymmetric => 2 * self.min_edge(),
+            WindowShape::Adsr => 0,
+        }
+    }
+
+    /// 切り出し時の窓の片側のフェードの最小長 (サンプル)
+    fn min_edge(&self) -> usize {
+        ((MIN_EDGE_MS / 1_000.0 * self.sr) as usize).max(1)
+    }
+
+    /// リング (`sample` ならサンプル) の小数位置 `start` から (末尾で折り返しながら) `rate` 倍速で読んだ
+    /// `len` サンプルをプールのバッファへ書き込み、窓処理へ回す。プールが空なら何もしない。
+    /// グライドがあれば速度比を glide_from × rate から rate へサンプルごとに近づけながら読む。
+    /// ピンポン再生なら前半で順方向に読み、後半で同じ区間を逆方向に戻る (折り返しで途切れない)。
+    /// リングの整数位置から等速で読み、鳴り終わるまで上書きされない区間なら、コピーせずリングを直接読む。
+    /// ノートが鳴らすグレインは鳴っている間もピッチベンドで読む速さが変わるので、いつもコピーする。
+    fn push_grain(
+        &mut self,
+        sample: bool,
+        start: f64,
+        len: usize,
+        rate: f32,
+        ch: usize,
+        offset: usize,
+    ) {
+        let f = self.frame;
+        let passes = f.repeats.clamp(1, MAX_REPEATS) as usize;
+        let expression = self.note_expression(&f);
+        let bend = expression.channel.map(|channel| NoteBend {
+            channel,
+            spawn_st: expression.bend_st,
+        });
+        let direct = !sample
+            && bend.is_none()
+            && self.slot().is_none()
+            && rate == 1.0
+            && self.glide_from == 1.0
+            && !self.pingpong
+            && self.next_character.is_none()
+            && self.next_tilt == 0.0
+            && !WowFlutter::is_active(&f)
+            && start.fract() == 0.0
+            && self.ring_safe(start as usize & self.ring_mask, len, passes);
+        let mut grain = Grain {
+            ch,
+            offset,
+            bus: self.next_bus,
+            band: self.next_band,
+            pan: self.next_pan,
+            pass_gain: self.next_gain,
+            bend,
+            ..Grain::default()
+        };
+        if direct {
+            grain.ring = Some((start as usize & self.ring_mask, len));
+        } else {
+            let Some(buf) = self.queues.pool.pop() else {
+                return;
+            };
+            grain.buf = buf;
+            self.copy_source(&mut grain.buf, sample, start, len, rate);
+            // キャラクターは窓をかける前にかけ、グレインの両端のフェードは汚さない
+            if let Some(character) = self.next_character {
+                character.apply(&mut grain.buf);
+            }
+            apply_tilt(&mut grain.buf, self.next_tilt, self.sr);
+        }
+        self.spawned += 1;
+        let _ = self.spawn_events.try_push(SpawnEvent {
+            timing: (self.block_at + offset) as u32,
+            ch,
+            len,
+            rate,
+        });
+
+        grain.repeats_left = passes - 1;
+        grain.pass_decay = 1.0 - f.repeat_decay.clamp(0.0, 1.0);
+
+        // ADSR 窓は読み出し時にかけるので窓処理は不要 (リングを直接読む場合も切り出し窓はかけない)。
+        // 繰り返す場合は継ぎ目で途切れないよう両端に最低 SEAM_FADE_MS のフェードを入れる。
+        if f.window == WindowShape::Adsr {
+            (grain.attack, grain.decay) = adsr_lengths(len, f.attack, f.decay);
+            if grain.repeats_left > 0 {
+                let seam = ((SEAM_FADE_MS / 1_000.0 * self.sr) as usize).min(len / 2);
+                grain.attack = grain.attack.max(seam);
+                grain.decay = grain.decay.max(seam);
+            }
+            if grain.enveloped() {
+                if direct {
+                    grain.edges = Some((0, 0));
+                }
+                self.dump_grain(&grain);
+                self.grains.push(grain);
+                return;
+            }
+        }
+        if f.window == WindowShape::Asymmetric {
+            let edges = edge_lengths(len, f.window_attack, f.window_release, self.min_edge());
+            grain.edges = Some(edges);
+        }
+        if direct {
+            // 切り出し窓は読み出し時にかける
+            self.dump_grain(&grain);
+            self.grains.push(grain);
+        } else if self.background_windowing {
+            self.pending.push(grain);
+        } else {
+            grain.apply_window();
+            self.dump_grain(&grain);
+            self.grains.push(grain);
+        }
+    }
+
+    /// リングの `start` から `len` サンプルを `passes` 回読み終えるまで、書き込みにも
+    /// チャンクの終わりの帰還にも上書きされないか。
+    /// グレインはチャンクの書き込みが済んでから合成するので、書き込み位置の先 CHUNK_SIZE + 繰り返し分を保護領域とする。
+    fn ring_safe(&self, start: usize, len: usize, passes: usize) -> bool {
+        let n = self.ring.len();
+        // start が上書きされるまでに書き込めるサンプル数
+        let ahead = (start + n - self.wr) & self.ring_mask;
+        // 現在のチャンクで書き込んだ区間には、チャンクの終わりに帰還が足される
+        let fed = if self.chunk_feedback {
+            self.chunk_written
+        } else {
+            0
+        };
+        ahead >= CHUNK_SIZE + (passes - 1) * len && ahead + len + fed <= n
+    }
+
+    /// `push_grain` のコピーする場合の読み出し (`buf` は空にしてから書き込む)
+    fn copy_source(&self, buf: &mut Vec<f32>, sample: bool, start: f64, len: usize, rate: f32) {
+        buf.clear();
+        let src = self.source(sample);
+        let from = self.glide_from as f64;
+        // ピンポン再生では前半だけリングから読み、後半は前半を逆順にたどる
+        let forward = if self.pingpong { len.div_ceil(2) } else { len };
+        let wow = WowFlutter::is_active(&self.frame);
+        if rate == 1.0 && from == 1.0 && start.fract() == 0.0 && !wow {
+            // 整数位置・等速ならそのままコピーする
+            let start = start as usize % src.len();
+            let head = forward.min(src.len() - start);
+            buf.extend_from_slice(&src[start..start + head]);
+            buf.extend_from_slice(&src[..forward - head]);
+        } else if self.frame.pitch_mode == PitchMode::Formant && from == 1.0 && !wow {
+            // フォルマントを保つ移調: 入力から検出したピッチ周期で PSOLA をかける
+            let f0 = self.tracker.pitch().unwrap_or(FALLBACK_PITCH_HZ);
+            let period = (self.sr / f0) as f64;
+            let quality = self.frame.interpolation;
+            psola_read(buf, src, start, forward, rate as f64, period, quality);
+        } else {
+            // 読み出し位置は f64 で積算し、長いグレインでも位置の丸め誤差で段差が出ないようにする
+            // 上方向の移調では次のサンプルの位置までをオーバーサンプリングして折り返しを抑える
+            // 回転むらは読み出し位置のずれとして足す
+            let rate = rate as f64;
+            let (quality, os) = (self.frame.interpolation, self.frame.oversampling);
+            let pos = |i| {
+                let wobble = if wow {
+                    self.wow.displacement(&self.frame, i, rate)
+                } else {
+                    0.0
+                };
+                glide_pos(i, forward, rate, from) + wobble
+            };
+            buf.extend((0..forward).map(|i| {
+                let at = pos(i);
+                let span = pos(i + 1) - at;
+                quality.read_oversampled(src, start + at, span, os)
+            }));
+        }
+        for i in (0..len - forward).rev() {
+            let x = buf[i];
+            buf.push(x);
+        }
+    }
+
+    /// バックグラウンドで窓処理が終わったグレインを取り込む。
+    /// 上限に達している場合は最も古いグレインをフェードアウトさせて場所を空ける。
+    pub(crate) fn collect_ready_grains(&mut self) {
+        while let Some(grain) = self.queues.ready.pop() {
+            if self.make_room(0) {
+                self.dump_grain(&grain);
+                self.grains.push(grain);
+            } else {
+                let _ = self.queues.pool.push(grain.buf);
+            }
+        }
+    }
+
+    /// 新しいグレインを 1 つ追加できるようにする。通常再生中のグレインが上限に達していれば
+    /// 最も古いもの (先頭側) を STEAL_FADE_MS かけてフェードアウトさせる。
+    /// フェードアウトは新しいグレインと同じ `offset` (チャンク内のフレーム位置) から始める。
+    /// フェードアウト中のグレインでベクタが埋まっている場合のみ false。
+    fn make_room(&mut self, offset: usize) -> bool {
+        if self.grains.len() >= self.pool_size() {
+            return false;
+        }
+        let live = self.grains.iter().filter(|g| !g.releasing()).count();
+        if live >= self.voice_limit() {
+            let fade = (STEAL_FADE_MS / 1_000.0 * self.sr) as usize;
+            if let Some(oldest) = self.grains.iter_mut().find(|g| !g.releasing()) {
+                let delay = offset.saturating_sub(oldest.offset);
+                oldest.start_release(fade, delay);
+                self.stole = true;
+            }
+        }
+        true
+    }
+
+    /// リングと帰還待ちの信号を消去する。`kill` なら鳴っているグレインも `offset` (チャンク内のフレーム位置)
+    /// から CLEAR_FADE_MS でフェードアウトさせる (窓処理待ちのグレインはそのまま鳴る)。
+    /// 消去後に書き込む入力は CLEAR_FADE_MS かけてフェードインし、無音との境目で段差が出ないようにする。
+    pub fn clear_ring(&mut self, offset: usize, kill: bool) {
+        // リングを直接読んでいるグレインは消去前の内容をコピーして鳴らし続ける (プールが空なら止める)
+        for g in self.grains.iter_mut().filter(|g| g.ring.is_some()) {
+            match self.queues.pool.pop() {
+                Some(buf) => g.detach(&self.ring, buf),
+                None => {
+                    g.pos = g.len();
+                    g.repeats_left = 0;
+                }
+            }
+        }
+        self.ring.fill(0.0);
+        self.loudness.clear();
+        self.onsets.forget();
+        self.beats.forget();
+        self.fb_pending.fill(0.0);
+        self.clear_fade = self.clear_fade_len();
+        if kill {
+            self.kill_grains(offset);
+        }
+    }
+
+    /// 鳴っているグレインを `offset` (チャンク内のフレーム位置) から CLEAR_FADE_MS でフェードアウトさせる
+    fn kill_grains(&mut self, offset: usize) {
+        let fade = self.clear_fade_len();
+        for g in self.grains.iter_mut().filter(|g| !g.releasing()) {
+            let delay = offset.saturating_sub(g.offset);
+            g.start_release(fade, delay);
+        }
+    }
+
+    fn clear_fade_len(&self) -> usize {
+        ((CLEAR_FADE_MS / 1_000.0 * self.sr) as usize).max(1)
+    }
+
+    /// 同時に鳴らす通常再生中のグレイン数の上限 (max_grains)。過負荷保護中は voice_budget まで下げる。
+    fn voice_limit(&self) -> usize {
+        let max = (self.frame.max_grains.max(1) as usize).min(self.grain_cap());
+        if self.overloaded {
+            (self.frame.voice_budget.max(1) as usize).min(max)
+        } else {
+            max
+        }
+    }
+
+    /// 過負荷保護中なら、上限を超えている分だけ古いグレインから STEAL_FADE_MS でフェードアウトさせる
+    fn shed_load(&mut self) {
+        if !self.overloaded {
+            return;
+        }
+        let limit = self.voice_limit();
+        let live = self.grains.iter().filter(|g| !g.releasing()).count();
+        let fade = (STEAL_FADE_MS / 1_000.0 * self.sr) as usize;
+        for g in self
+            .grains
+            .iter_mut()
+            .filter(|g| !g.releasing())
+            .take(live.saturating_sub(limit))
+        {
+            g.start_release(fade, 0);
+        }
+    }
+
+    /// GUI と共有する入力・ウェット・出力のレベル
+    pub fn meters(&self) -> Arc<Meters> {
+        self.meters.clone()
+    }
+
+    /// 直前のチャンクの負荷 (合成したグレインのサンプル数 / CHUNK_SIZE = 平均同時発音数)
+    pub fn load(&self) -> f32 {
+        self.load
+    }
+
+    /// ウェットバスの録音を始める / 止める (ファイルの作成と書き出しはバックグラウンドで行う)
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recorder.set_recording(recording);
+    }
+
+    /// 録音のブロックを受け渡すキュー (バックグラウンドの書き出し用)
+    pub fn record_queues(&self) -> Arc<RecordQueues> {
+        self.recorder.queues()
+    }
+
+    /// 書き出しを待っている録音のブロックがあるか
+    pub fn record_pending(&self) -> bool {
+        self.recorder.has_filled()
+    }
+
+    /// デバッグ用: ウェットバスのモノラル和をブロックごとに取り出せるようにする
+    #[cfg(feature = "debug-dump")]
+    pub fn set_dump_wet(&mut self, enabled: bool) {
+        self.wet_dump = enabled.then(Vec::new);
+    }
+
+    /// デバッグ用: 鳴り始めたグレインのコピー (窓・エンベロープ適用済み) を取り出す
+    #[cfg(feature = "debug-dump")]
+    pub fn drain_dumps(&mut self) -> std::vec::Drain<'_, Vec<f32>> {
+        self.dumps.drain(..)
+    }
+
+    /// デバッグ用: 直前のブロックのウェットバスのモノラル和を取り出す
+    #[cfg(feature = "debug-dump")]
+    pub fn take_wet_dump(&mut self) -> Option<Vec<f32>> {
+        self.wet_dump
+            .as_mut()
+            .filter(|w| !w.is_empty())
+            .map(std::mem::take)
+    }
+
+    /// デバッグ用にグレインをコピーしておく。オーディオスレッドでの確保はデバッグ時のみ許す。
+    #[cfg(feature = "debug-dump")]
+    fn dump_grain(&mut self, grain: &Grain) {
+        nih_plug::util::permit_alloc(|| {
+            let len = grain.len();
+            let mut copy = grain.samples(&self.ring);
+            if grain.enveloped() {
+                for (i, x) in copy.iter_mut().enumerate() {
+                    *x *= adsr_gain(i, len, grain.attack, grain.decay);
+                }
+            }
+            self.dumps.push(copy);
+        });
+    }
+
+    #[cfg(not(feature = "debug-dump"))]
+    #[inline(always)]
+    fn dump_grain(&mut self, _grain: &Grain) {}
+
+    /// デバッグビルドでのみ、グレインを合成した直後の状態の不変条件を確かめる。
+    /// 生成・合成の処理を書き換えて壊したときに、化けた音を出し続けずにその場で止める。
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self, n_ch: usize, at: usize, n_samples: usize) {
+        debug_assert!(
+            self.grains.len() <= self.pool_size(),
+            "{} grains exceed the pool of {}",
+            self.grains.len(),
+            self.pool_size()
+        );
+        debug_assert!(self.ring.len().is_power_of_two() && self.ring_mask == self.ring.len() - 1);
+        debug_assert!(
+            self.wr < self.ring.len(),
+            "write head {} outside the ring of {}",
+            self.wr,
+            self.ring.len()
+        );
+        for g in &self.grains {
+            debug_assert!(g.bus <= GRAIN_BUSES, "grain bus {} out of range", g.bus);
+            if let Some((start, len)) = g.ring {
+                debug_assert!(
+                    start < self.ring.len() && len <= self.ring.len(),
+                    "ring grain ({start}, {len}) outside the ring of {}",
+                    self.ring.len()
+                );
+            }
+            debug_assert!(
+                g.pos <= g.len(),
+                "grain position {} past its length {}",
+                g.pos,
+                g.len()
+            );
+        }
+        let wet = self.wet[..n_ch].iter().map(|w| &w[..n_samples]);
+        let buses = self.bus_wet[..self.grain_buses]
+            .iter()
+            .flat_map(|bus| bus[..n_ch].iter().map(|w| &w[at..at + n_samples]));
+        debug_assert!(
+            wet.chain(buses).flatten().all(|x| x.is_finite()),
+            "non-finite sample in the wet sum"
+        );
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn check_invariants(&self, _n_ch: usize, _at: usize, _n_samples: usize) {}
+
+    /// 同時発音数の上限。オフラインのバウンス中だけ MAX_OFFLINE_GRAINS まで広げる
+    fn grain_cap(&self) -> usize {
+        if self.offline {
+            MAX_OFFLINE_GRAINS
+        } else {
+            MAX_GRAINS
+        }
+    }
+
+    /// グレインを RENDER_WORKERS 個に分けてスレッドプールで合成し、部分和を決まった順にスクラッチへ足す。
+    /// (合成したサンプル数, メインのウェットで鳴ったグレイン数) を返す。オフライン専用 (スレッドへ渡すときに確保する)
+    fn render_parallel(
+        &mut self,
+        n_ch: usize,
+        at: usize,
+        n_samples: usize,
+        bands: &'static [usize],
+    ) -> (usize, usize) {
+        let per = self.grains.len().div_ceil(self.workers.len());
+        let (ring, grain_buses) = (&self.ring, self.grain_buses);
+        let jobs = self.workers.iter_mut().zip(self.grains.chunks_mut(per));
+        nih_plug::util::permit_alloc(|| {
+            rayon::scope(|s| {
+                for (worker, grains) in jobs {
+                    s.spawn(move |_| {
+                        worker.render(grains, ring, n_ch, n_samples, grain_buses, bands)
+                    });
+                }
+            })
+        });
+        let (mut rendered, mut voices) = (0, 0);
+        let used = self.grains.len().div_ceil(per);
+        for worker in &self.workers[..used] {
+            for (dst, src) in self.wet.iter_mut().zip(&worker.wet).take(n_ch) {
+                mix_add(&mut dst[..n_samples], &src[..n_samples]);
+            }
+            for (bus, part) in self
+                .bus_wet
+                .iter_mut()
+                .zip(&worker.bus_wet)
+                .take(grain_buses)
+            {
+                for (dst, src) in bus.iter_mut().zip(part).take(n_ch) {
+                    mix_add(&mut dst[at..at + n_samples], &src[..n_samples]);
+                }
+            }
+            for &band in bands {
+                let part = &worker.band_wet[band];
+                for (dst, src) in self.band_wet[band].iter_mut().zip(part).take(n_ch) {
+                    mix_add(&mut dst[..n_samples], &src[..n_samples]);
+                }
+            }
+            rendered += worker.counts.0;
+            voices += worker.counts.1;
+        }
+        (rendered, voices)
+    }
+
+    /// グレインバッファの数 (発音数の上限 + 処理待ち・フェードアウト中の分)
+    pub(crate) fn pool_size(&self) -> usize {
+        self.grain_cap() + GRAIN_POOL_SIZE - MAX_OFFLINE_GRAINS
+    }
+
+    /// プールを現在のサンプルレートでの最大グレイン長のバッファで満たし直す
+    fn refill_pool(&mut self) {
+        while self.queues.ready.pop().is_some() {}
+        while self.queues.pool.pop().is_some() {}
+        let capacity = (MAX_GRAIN_MS / 1_000.0 * self.sr) as usize + 1;
+        for _ in 0..self.pool_size() {
+            let _ = self.queues.pool.push(Vec::with_capacity(capacity));
+        }
+    }
+
+    /// 最大プリディレイ分 (+ 補間用の 2 サンプル) のディレイラインを確保する
+    fn alloc_pre_delay(&mut self, n_ch: usize) {
+        let len = (MAX_PRE_DELAY_MS / 1_000.0 * self.sr) as usize + 2;
+        self.pre_delay = vec![vec![0.0; len]; n_ch];
+        self.pre_delay_wr = 0;
+    }
+
+    /// 最大ルックアヘッド分 (+ 1 サンプル) のドライのディレイラインを確保する
+    fn alloc_lookahead(&mut self, n_ch: usize) {
+        let len = (MAX_LOOKAHEAD_MS as f32 / 1_000.0 * self.sr) as usize + 1;
+        self.lookahead = vec![vec![0.0; len]; n_ch];
+        self.lookahead_wr = 0;
+    }
+
+    /// ルックアヘッドでドライを遅らせるサンプル数
+    fn lookahead_len(&self) -> usize {
+        let ms = self.frame.lookahead_ms.clamp(0, MAX_LOOKAHEAD_MS);
+        (ms as f32 / 1_000.0 * self.sr) as usize
+    }
+
+    /// `io` の各チャンネルのドライを lookahead_len() サンプル遅らせる。
+    /// オフの間もラインへ書き続けるので、オンにした瞬間から直前のドライを遅らせて出せる
+    fn delay_dry(&mut self, io: &mut [&mut [f32]]) {
+        let delay = self.lookahead_len();
+        let len = self.lookahead.first().map_or(0, Vec::len);
+        if len == 0 {
+            return;
+        }
+        let mut wr = self.lookahead_wr;
+        for (x, line) in io.iter_mut().zip(&mut self.lookahead) {
+            wr = self.lookahead_wr;
+            for s in x.iter_mut() {
+                line[wr] = *s;
+                *s = line[(wr + len - delay) % len];
+                wr = (wr + 1) % len;
+            }
+        }
+        self.lookahead_wr = wr;
+    }
+
+    /// スクラッチバッファが足りない場合のみ確保し直す。
+    /// 通常は initialize で確保済みなのでオーディオスレッドでは確保が起きない。
+    fn ensure_scratch(&mut self, n_ch: usize, n_samples: usize) {
+        if self.wet.len() < n_ch || self.dry.len() < n_ch || self.mix_buf.len() < n_samples {
+            let len = n_samples.max(self.mix_buf.len());
+            self.wet = vec![vec![0.0; len]; n_ch.max(self.wet.len())];
+            self.bus_wet = std::array::from_fn(|_| vec![vec![0.0; len]; self.wet.len()]);
+            self.band_wet = std::array::from_fn(|_| vec![vec![0.0; len]; self.wet.len()]);
+            self.dry = vec![vec![0.0; len]; n_ch.max(self.dry.len())];
+            self.mix_buf = vec![0.0; len];
+            self.pan_buf = vec![0.0; len];
+            self.mod_in = vec![0.0; len];
+            self.mod_in_len = 0;
+            self.fb_buf = vec![0.0; len];
+            self.duck_buf = vec![0.0; len];
+            if self.pre_delay.len() < n_ch {
+                self.alloc_pre_delay(n_ch);
+            }
+            if self.lookahead.len() < n_ch {
+                self.alloc_lookahead(n_ch);
+            }
+            self.wet_filter.ensure_channels(n_ch);
+            self.crossover.ensure_channels(n_ch);
+            self.dc_blocker.ensure_channels(n_ch);
+            self.blur.ensure_channels(n_ch);
+            self.spec_buf = vec![0.0; len];
+            self.blend_buf = vec![0.0; len];
+            self.delay_buf = vec![0.0; len];
+        }
+    }
+
+    /// 次の process のブロックの補助入力 Modulation を設定する。
+    /// 設定しなかったブロック (と入力の足りない分) は無音として扱う
+    pub fn set_mod_input(&mut self, input: &[f32]) {
+        let n = input.len().min(self.mod_in.len());
+        for (m, x) in self.mod_in.iter_mut().zip(&input[..n]) {
+            *m = if x.is_finite() { *x } else { 0.0 };
+        }
+        self.mod_in_len = n;
+    }
+
+    /// `io` (チャンネルごとのスライス) をその場で処理する。
+    /// 入力はリングへ書き込まれ、出力はドライとウェットを mix で混ぜたものになる。
+    ///
+    /// 内部では通算のサンプル位置で CHUNK_SIZE ごとに区切って処理するので、同じ入力・同じ乱数列なら
+    /// ホストのブロック長によらず同じ出力になる (バックグラウンド窓処理が無効な場合)。
+    pub fn process(
+        &mut self,
+        io: &mut [&mut [f32]],
+        params: &mut impl ParamSource,
+        rng: &mut impl Rng,
+    ) {
+        let n_ch = io.len().min(MAX_CHANNELS);
+        let n_samples = io.first().map_or(0, |c| c.len());
+        if n_ch == 0 || self.ring.is_empty() {
+            return;
+        }
+        self.ensure_scratch(n_ch, n_samples);
+        self.spawn_events.clear();
+        let mut at = 0;
+        while at < n_samples {
+            // 小節に同期している間は、区間の頭でチャンクを区切ってサンプル単位で乱数列を始め直す
+            let len = (n_samples - at)
+                .min(CHUNK_SIZE - self.chunk_phase)
+                .min(self.update_reroll());
+            let mut chunk: ArrayVec<&mut [f32], MAX_CHANNELS> = io[..n_ch]
+                .iter_mut()
+                .map(|c| &mut c[at..at + len])
+                .collect();
+            // 区間の頭から始め直した乱数列で長さ・位置・パンなどを決める
+            match self.reroll_rng.take() {
+                Some(mut bar_rng) => {
+                    self.process_chunk(&mut chunk, at, params, &mut bar_rng);
+                    self.reroll_rng = Some(bar_rng);
+                }
+                None => self.process_chunk(&mut chunk, at, params, rng),
+            }
+            self.chunk_phase = (self.chunk_phase + len) % CHUNK_SIZE;
+            at += len;
+        }
+        self.mod_in_len = 0;
+
+        // グレインの統計を GUI へ公開する (出力には影響しないのでホストのブロック単位でよい)
+        let block_sec = n_samples as f32 / self.sr;
+        let coef = (-block_sec / SPAWN_RATE_SEC).exp();
+        let rate = self.spawned as f32 / block_sec.max(f32::EPSILON);
+        self.spawn_rate = rate + (self.spawn_rate - rate) * coef;
+        self.meters
+            .grains
+            .store(self.grains.len(), self.spawn_rate, self.stole);
+        self.spawned = 0;
+        self.stole = false;
+    }
+
+    /// `process` と同じだが、エンジン内部のパニック (範囲外アクセス、不変条件の違反など) を捕まえる。
+    /// パニックしたらそのブロックの `io` を無音にし、エンジンを初期状態へ戻してメッセージを返す。
+    ///
+    /// パニックの巻き戻しはメモリを確保する。nih-plug の `assert_process_allocs` が効くデバッグビルドの
+    /// プラグインでは、その確保で catch_unwind に届く前に abort するので、ホスト上で守れるのは
+    /// リリースビルドだけ。
+    pub fn process_guarded(
+        &mut self,
+        io: &mut [&mut [f32]],
+        params: &mut impl ParamSource,
+        rng: &mut impl Rng,
+    ) -> Result<(), String> {
+        let result = catch_panic(|| self.process(&mut *io, params, rng));
+        if result.is_err() {
+            for ch in io.iter_mut() {
+                ch.fill(0.0);
+            }
+            self.reset();
+        }
+        result
+    }
+
+    /// チャンクの終わりに、溜めておいた帰還をリングへ戻し、負荷を測る。
+    /// トランスポート連動でリングの書き込みが止まったチャンクでは帰還しない。
+    fn end_chunk(&mut self) {
+        if self.chunk_written == CHUNK_SIZE {
+            for (i, fb) in self.fb_pending.iter().enumerate() {
+                self.ring[(self.chunk_start + i) & self.ring_mask] += fb;
+            }
+        }
+
+        // 合成したグレインのサンプル数で負荷を測り、予算を超えたら次のチャンクから間引く。
+        // 処理時間ではなくサンプル数で測るので、ホストの負荷状況に左右されず再現性がある。
+        self.load = self.chunk_rendered as f32 / CHUNK_SIZE as f32;
+        let budget = self.frame.voice_budget.max(1) as f32;
+        self.overloaded = self.frame.guard
+            && if self.overloaded {
+                self.load >= budget * GUARD_RECOVER
+            } else {
+                self.load > budget
+            };
+    }
+
+    /// CHUNK_SIZE 以下の 1 区間を処理する。`at` はホストのブロック内での区間の先頭位置。
+    fn process_chunk(
+        &mut self,
+        io: &mut [&mut [f32]],
+        at: usize,
+        params: &mut impl ParamSource,
+        rng: &mut impl Rng,
+    ) {
+        let n_ch = io.len();
+        let n_samples = io.first().map_or(0, |c| c.len());
+        let phase = self.chunk_phase;
+        self.block_at = at;
+        if phase == 0 {
+            self.collect_sample();
+            self.collect_ring();
+            self.need_ring(self.ring_demand(self.sr));
+            self.shed_load();
+            self.chunk_start = self.wr;
+            self.chunk_written = 0;
+            self.chunk_rendered = 0;
+            self.chunk_feedback = false;
+        }
+        self.collect_ready_grains();
+        self.bend_grains();
+        // NaN / inf の入力はリングや帰還に残り続けるので、ドライも含めて無音に置き換える
+        for c in io.iter_mut() {
+            for x in c[..n_samples].iter_mut().filter(|x| !x.is_finite()) {
+                *x = 0.0;
+            }
+        }
+        self.meter_in.measure(io, n_samples, &self.meters.input);
+
+        // ── ① フレーム単位ループ: パラメータ取得・リング書き込み・グレイン生成 ──
+        let mut p = self.frame;
+        for i in 0..n_samples {
+            let mod_in = if at + i < self.mod_in_len {
+                self.mod_in[at + i]
+            } else {
+                0.0
+            };
+            self.modulation.feed_mod_in(mod_in);
+            // a. パラメータ値をサンプル単位で取得し、保存トリガーがオンになった瞬間はシーンへ保存する。
+            //    モーフィング中は連続値をシーン間の補間で置き換える (保存するのはノブの値)。
+            //    その上にマクロで割り当てた分と、チャンクの先頭で評価したモジュレーションを足す。
+            //    パラメータ源が同じフレームを返し続ける間 (スムーザーが止まっている間) は、
+            //    チャンクの先頭で重ねたフレームをそのまま使い、サンプルごとに評価し直さない。
+            if i == 0 || !params.is_steady() {
+                p = params.next_frame();
+                for (k, store) in [p.store_a, p.store_b].into_iter().enumerate() {
+                    if store && !self.storing[k] {
+                        self.scenes[k] = p;
+                        self.scenes_dirty = true;
+                    }
+                    self.storing[k] = store;
+                }
+                if p.scene_morph {
+                    let [a, b] = &self.scenes;
+                    let t = p.morph;
+                    scene::morph(&mut p, a, b, t);
+                }
+                if p.length_mode == LengthMode::RingPercent {
+                    let ring_ms = self.ring_len as f32 / self.sr * 1_000.0;
+                    let pct = p.length_pct.clamp(MIN_LENGTH_PCT, MAX_LENGTH_PCT);
+                    (p.min_ms, p.max_ms) = length_range(ring_ms * pct * 0.01, p.length_jitter);
+                }
+                let values = p.macros;
+                self.macros.apply(&mut p, &values);
+                if phase == 0 && i == 0 {
+                    self.modulation.tick(&p, rng);
+                    self.wow.advance(&p, CHUNK_SIZE);
+                }
+                self.modulation.apply(&mut p);
+                if p.reverb {
+                    // クラウドリバーブ: 長いグレインを最大の密度で重ね、正規化して帰還させる
+                    p.mode = TriggerMode::Random;
+                    p.density = 1.0;
+                    (p.min_ms, p.max_ms) = (REVERB_MIN_MS, REVERB_MAX_MS);
+                    p.feedback = REVERB_FEEDBACK;
+                    p.normalize = true;
+                }
+                p.quality.apply(&mut p);
+                if self.offline {
+                    // バウンスは品質の設定によらず最高品質にし、処理時間を気にせず発音数も広げる
+                    Quality::High.apply(&mut p);
+                    p.max_grains = MAX_OFFLINE_GRAINS as i32;
+                    p.guard = false;
+                }
+                self.frame = p;
+            }
+            //    長さは生成するグレインにだけ効く。マクロやモジュレーション、リングの伸びで段になって動いても
+            //    グレインの長さが急に揃って変わらないよう、LENGTH_SMOOTH_MS で追従させる
+            let (min_len_ms, max_len_ms) = self.smooth_lengths(p.min_ms, p.max_ms);
+            let step = 1_000.0 / (AUDITION_FADE_MS * self.sr);
+            self.audition = if p.audition {
+                (self.audition + step).min(1.0)
+            } else {
+                (self.audition - step).max(0.0)
+            };
+            let mix = p.mix.clamp(0.0, 1.0);
+            self.mix_buf[i] = mix + (1.0 - mix) * self.audition;
+            self.duck_buf[i] = p.duck_db;
+            self.fb_buf[i] = p.feedback.clamp(0.0, MAX_FEEDBACK);
+            self.chunk_feedback |= self.fb_buf[i] > 0.0;
+            self.delay_buf[i] = p.pre_delay_ms.clamp(0.0, MAX_PRE_DELAY_MS) / 1_000.0 * self.sr;
+
+            // b. 入力ゲインを掛けてモノラル化し、リングバッファへ書き込み、ピッチ検出器へも渡す。
+            //    trim_dry ならドライ (io) にも同じゲインを掛けておく。
+            //    トランスポート連動で停止中は、gate_write が無ければリングも止める。
+            //    ring_mode が Overdub なら既存の内容に重ね、Play なら書き込まない。
+            //    リングへはエキスパンダーを通した入力を書く (ピッチ検出とフォロワーには通さない入力を渡す)。
+            let generating = !p.gate || self.playing;
+            let mono_input: f32 = io.iter().map(|c| c[i]).sum::<f32>() * p.input_gain;
+            let gate_gain = self.input_gate.next(
+                mono_input.abs(),
+                p.input_gate_db,
+                p.input_gate_ratio,
+                p.input_gate_release_ms,
+            );
+            if p.trim_dry && p.input_gain != 1.0 {
+                for c in io.iter_mut() {
+                    c[i] *= p.input_gain;
+                }
+            }
+            //    clear がオンになった瞬間はリングを消去し、その後の入力はフェードインさせる。
+            if p.clear && !self.cleared {
+                self.clear_ring(i, p.clear_grains);
+            }
+            self.cleared = p.clear;
+            //    トランスポートが止まった瞬間は、前のパスの残りのグレインを止め、リングも消去する
+            if self.was_playing && !self.playing {
+                if p.stop_clear {
+                    self.clear_ring(i, p.stop_kill);
+                } else if p.stop_kill {
+                    self.kill_grains(i);
+                }
+            }
+            self.was_playing = self.playing;
+            //    Play になった瞬間にリングをループにし、Play を抜けたら継ぎ目から書き込みを再開する
+            let looping = p.ring_mode == RingMode::Play;
+            if looping && !self.looped {
+                self.close_loop();
+            } else if !looping {
+                self.loop_fade = 0;
+            }
+            self.looped = looping;
+            //    capture がオンになった瞬間に今のリングをフリーズスロットへ取り込む
+            for (k, capture) in p.capture.into_iter().enumerate() {
+                if capture && !self.capturing[k] {
+                    self.capture_slot(k);
+                }
+                self.capturing[k] = capture;
+            }
+            //    マルチバンドではグレインにする帯域だけをリングへ書く
+            let y = self.crossover.split_input(
+                mono_input,
+                p.bands,
+                (p.crossover_low_hz, p.crossover_high_hz),
+            );
+            let band_input = if p.bands == Bands::Off {
+                mono_input
+            } else {
+                granulated(y, p.granulate_bands).0
+            };
+            if (generating || p.gate_write) && p.ring_mode != RingMode::Play {
+                let kept = match p.ring_mode {
+                    RingMode::Overdub => self.ring[self.wr] * p.overdub.clamp(0.0, 1.0),
+                    _ => 0.0,
+                };
+                let mut input = band_input * gate_gain;
+                if self.clear_fade > 0 {
+                    input *= 1.0 - self.clear_fade as f32 / self.clear_fade_len() as f32;
+                    self.clear_fade -= 1;
+                }
+                self.ring[self.wr] = kept + input;
+                self.loudness.push(self.wr, kept + input);
+                self.onsets.push(input, Some(self.wr));
+                let on_grid = self.on_grid(self.clock, p.division, 0.0);
+                self.beats.push(self.wr, on_grid);
+                self.wr = (self.wr + 1) & self.ring_mask;
+                self.chunk_written += 1;
+            } else {
+                self.onsets.push(mono_input, None);
+            }
+            self.tracker.push(mono_input);
+            self.modulation.follow(mono_input);
+            if p.flux_density > 0.0 {
+                self.flux.push(mono_input);
+            }
+
+            // c. スペクトルフリーズ: オンになった瞬間に取り込み、以降は再合成を鳴らす
+            let freeze = self.freeze_switch(&p, mod_in);
+            if freeze && !self.frozen {
+                self.spectral.capture(&self.ring, self.wr);
+            }
+            self.frozen = freeze;
+            self.spec_buf[i] = self.spectral.next_sample(freeze, rng);
+            self.blend_buf[i] = if self.spectral.is_active() {
+                p.spectral.clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+
+            // d. ランダムウォークで density をゆっくり揺らす
+            let walk = self.walk.next(p.walk_rate, rng);
+            let mut density = (p.density + p.walk_depth * walk).clamp(0.0, 1.0);
+            //    ノートゲートでは押さえているノートの数だけ濃くする
+            if p.note_gate {
+                density *= self.held_notes as f32;
+            }
+            //    ノートのチャンネルプレッシャーでも濃さを変える
+            density *= self.note_expression(&p).density;
+            //    flux_density では入力のスペクトルの変化が少ない間 (持続音) だけ薄くする
+            if p.flux_density > 0.0 {
+                density *= self.flux.density_scale(p.flux_density);
+            }
+
+            // e. グレイン生成判定 (トランスポート連動で停止中、ノートゲートでノートが無いときは生成しない)
+            if !generating || (p.note_gate && self.held_notes == 0) {
+                self.clock += 1;
+                continue;
+            }
+            //    humanize_ms / scatter_ms で遅らせていたトリガーのうち、このサンプルで時間になったものを生成する
+            let lens = (min_len_ms, max_len_ms);
+            let mut k = 0;
+            while k < self.delayed.len() {
+                if self.delayed[k].0 == 0 {
+                    let (_, trigger) = self.delayed.remove(k);
+                    self.fire(&p, lens, n_ch, i, trigger, rng);
+                } else {
+                    self.delayed[k].0 -= 1;
+                    k += 1;
+                }
+            }
+            let trigger = match p.mode {
+                // 1 サンプルあたりの期待値で判定するのでブロック長に依存しない
+                TriggerMode::Random => {
+                    (rng.random::<f32>() < expected_grains(density, 1, self.sr)).then_some(0)
+                }
+                // 長さ / overlap ごとにトリガーする
+                TriggerMode::Sync | TriggerMode::Stretch => {
+                    if p.mode == TriggerMode::Stretch {
+                        self.advance_playhead(p.speed);
+                    }
+                    let due = self.sync_countdown <= 0.0;
+                    if due {
+                        let len_ms = 0.5 * (min_len_ms + max_len_ms);
+                        let len = ((len_ms / 1_000.0) * self.sr) as usize;
+                        self.sync_countdown += (len as f32 / p.overlap.factor()).max(1.0);
+                    }
+                    self.sync_countdown -= 1.0;
+                    due.then_some(0)
+                }
+                // グリッド上でのみ density の確率でトリガーする
+                TriggerMode::Tempo => (self.on_grid(self.clock, p.division, p.swing)
+                    && rng.random::<f32>() < density)
+                    .then_some(0),
+                // ユークリッドリズムの発音ステップでトリガーする
+                TriggerMode::Euclid => (self.on_grid(self.clock, p.division, p.swing)
+                    && euclid(
+                        p.euclid_steps,
+                        p.euclid_pulses,
+                        p.euclid_rotation,
+                        self.grid_step(self.clock, p.division),
+                    ))
+                .then_some(0),
+                // グリッドごとにそのステップでトリガーする
+                TriggerMode::Sequencer => self
+                    .on_grid(self.clock, p.division, p.swing)
+                    .then(|| self.grid_step(self.clock, p.division)),
+            };
+            //    Random モードは scatter_ms、それ以外は humanize_ms だけ遅らせる (0 なら乱数を引かずにすぐ生成する)
+            if let Some(step) = trigger {
+                let spread_ms = if p.mode == TriggerMode::Random {
+                    p.scatter_ms.clamp(0.0, MAX_SCATTER_MS)
+                } else {
+                    p.humanize_ms.clamp(0.0, MAX_HUMANIZE_MS)
+                };
+                let spread = (spread_ms / 1_000.0 * self.sr) as usize;
+                let delay = if spread > 0 {
+                    rng.random_range(0..=spread)
+                } else {
+                    0
+                };
+                // burst が 2 以上なら、残りのグレインを BURST_SPREAD_MS の中で遅らせ、少しずつ移調して、
+                // 元のグレインの方向から BURST_PAN_DEG の範囲でずらして足す
+                let window = (BURST_SPREAD_MS / 1_000.0 * self.sr) as usize;
+                let burst = burst_size(&p, density);
+                let center = (burst > 1).then(|| self.burst_azimuth(rng, n_ch));
+                for k in 0..burst {
+                    let (delay, detune, azimuth) = match center {
+                        Some(center) if k > 0 => {
+                            let detune = BURST_DETUNE_ST * rng.random_range(-1.0f32..=1.0);
+                            let azimuth = center + BURST_PAN_DEG * rng.random_range(-1.0f32..=1.0);
+                            (
+                                delay + rng.random_range(0..=window),
+                                Some(detune),
+                                Some(azimuth),
+                            )
+                        }
+                        _ => (delay, None, center),
+                    };
+                    let trigger = Trigger {
+                        step,
+                        detune,
+                        azimuth,
+                    };
+                    if delay == 0 || self.delayed.is_full() {
+                        self.fire(&p, lens, n_ch, i, trigger, rng);
+                    } else {
+                        self.delayed.push((delay - 1, trigger));
+                    }
+                }
+            }
+            self.clock += 1;
+        }
+        // スペクトルブラーを掛けている間は、ドライもウェットと同じだけ遅らせて揃える
+        self.blur.set_active(self.frame.spectral_blur > 0.0);
+        if self.blur.is_active() {
+            self.blur.delay_dry(io);
+        }
+        // ルックアヘッドではドライだけをさらに遅らせ、グレインがドライより先の音から鳴るようにする
+        self.delay_dry(io);
+        for (dry, src) in self.dry.iter_mut().zip(io.iter()) {
+            dry[at..at + n_samples].copy_from_slice(src);
+        }
+
+        // ── ② グレインごとにブロック分をまとめてスクラッチへ合成 (SIMD) ──
+        // 補助出力バスへ振り分けたグレインは、バスのスクラッチのホストのブロック内の位置 `at` から足す
+        for w in &mut self.wet[..n_ch] {
+            w[..n_samples].fill(0.0);
+        }
+        for bus in &mut self.bus_wet[..self.grain_buses] {
+            for w in &mut bus[..n_ch] {
+                w[at..at + n_samples].fill(0.0);
+            }
+        }
+        let bands = self.frame.bands.indices();
+        for &band in bands {
+            for w in &mut self.band_wet[band][..n_ch] {
+                w[..n_samples].fill(0.0);
+            }
+        }
+        // voices はメインのウェットで鳴ったグレイン数 (正規化用)
+        let parallel = self.grains.len() >= PARALLEL_MIN_GRAINS
+            && self.workers.first().is_some_and(|w| w.wet.len() >= n_ch);
+        let (rendered, voices) = if parallel {
+            self.render_parallel(n_ch, at, n_samples, bands)
+        } else {
+            let mix = GrainMix {
+                wet: &mut self.wet,
+                bus_wet: &mut self.bus_wet,
+                band_wet: &mut self.band_wet,
+                pan_buf: &mut self.pan_buf,
+                bus_from: at,
+                grain_buses: self.grain_buses,
+                bands,
+            };
+            render_grains(&mut self.grains, &self.ring, mix, n_ch, n_samples)
+        };
+        // 帯域ごとのグレインをその帯域に絞り、band_mix を掛けてメインのウェットへ足す
+        let f = &self.frame;
+        let hz = (f.crossover_low_hz, f.crossover_high_hz);
+        for &band in bands {
+            for (ch, (wet, src)) in self.wet[..n_ch]
+                .iter_mut()
+                .zip(&self.band_wet[band])
+                .enumerate()
+            {
+                for (w, x) in wet[..n_samples].iter_mut().zip(src) {
+                    *w += f.band_mix[band] * self.crossover.band_wet(ch, band, *x, f.bands, hz);
+                }
+            }
+        }
+        self.check_invariants(n_ch, at, n_samples);
+
+        self.chunk_rendered += rendered;
+
+        // ── ②' 鳴っているグレイン数でウェットを正規化する (ゲインはサンプルごとに滑らかに追従) ──
+        if self.frame.normalize || self.norm_gain != 1.0 {
+            let target = if self.frame.normalize {
+                1.0 / (voices.max(1) as f32).sqrt()
+            } else {
+                1.0
+            };
+            let coef = (-1_000.0 / (NORMALIZE_SMOOTH_MS * self.sr)).exp();
+            for i in 0..n_samples {
+                self.norm_gain = target + (self.norm_gain - target) * coef;
+                if (self.norm_gain - target).abs() < 1e-6 {
+                    self.norm_gain = target;
+                }
+                for w in &mut self.wet[..n_ch] {
+                    w[i] *= self.norm_gain;
+                }
+            }
+        }
+
+        // ── ③ スペクトル再合成をウェットへブレンド (フリーズしていなければ blend は 0) ──
+        for wet in &mut self.wet[..n_ch] {
+            for ((w, spec), blend) in wet.iter_mut().zip(&self.spec_buf).zip(&self.blend_buf) {
+                *w = *w * (1.0 - blend) + spec * blend;
+            }
+        }
+
+        // ── ③'' ウェットの振幅スペクトルを時間方向にぼかし、グレインの立ち上がりを滲ませる ──
+        if self.blur.is_active() {
+            self.blur
+                .process(&mut self.wet[..n_ch], n_samples, self.frame.spectral_blur);
+        }
+
+        // ── ③' ウェットの直流を取り除き、低域のうなりと耳障りな高域を Low Cut / High Cut で削る ──
+        // 非対称な素材から切り出したグレインは直流を含み、重なるほど積み上がってヘッドルームを食う
+        if self.frame.dc_block {
+            self.dc_blocker.process(&mut self.wet[..n_ch], n_samples);
+        } else {
+            self.dc_blocker.reset();
+        }
+        let (low_cut, high_cut) = (self.frame.low_cut_hz, self.frame.high_cut_hz);
+        self.wet_filter
+            .process(&mut self.wet[..n_ch], n_samples, low_cut, high_cut);
+
+        // ── ④ ウェットバスをプリディレイで遅らせる (線形補間の分数ディレイ) ──
+        let len = self.pre_delay.first().map_or(0, Vec::len);
+        if len > 0 {
+            for (wet, line) in self.wet[..n_ch].iter_mut().zip(&mut self.pre_delay) {
+                let mut wr = self.pre_delay_wr;
+                for (w, d) in wet[..n_samples].iter_mut().zip(&self.delay_buf) {
+                    line[wr] = *w;
+                    let rd = wr as f32 + len as f32 - d;
+                    let i0 = rd as usize;
+                    let frac = rd - i0 as f32;
+                    let a = line[i0 % len];
+                    let b = line[(i0 + 1) % len];
+                    *w = a + (b - a) * frac;
+                    wr = (wr + 1) % len;
+                }
+            }
+            self.pre_delay_wr = (self.pre_delay_wr + n_samples) % len;
+        }
+
+        // ── ④' ウェットのピークがしきい値を下回ったらゲートで消す (グレインの小さな残りを掃除する) ──
+        let f = self.frame;
+        if f.wet_gate_db > MIN_GATE_DB || self.wet_gate.is_closing() {
+            for i in 0..n_samples {
+                let level = self.wet[..n_ch]
+                    .iter()
+                    .fold(0.0f32, |m, w| m.max(w[i].abs()));
+                let gain = self.wet_gate.next(
+                    level,
+                    f.wet_gate_db,
+                    f.wet_gate_attack_ms,
+                    f.wet_gate_release_ms,
+                );
+                for w in &mut self.wet[..n_ch] {
+                    w[i] *= gain;
+                }
+            }
+        }
+
+        self.meter_wet
+            .measure(&self.wet[..n_ch], n_samples, &self.meters.wet);
+        self.recorder.push(&self.wet[..n_ch], n_samples);
+        #[cfg(feature = "debug-dump")]
+        if let Some(dump) = &mut self.wet_dump {
+            let wet = &self.wet[..n_ch];
+            nih_plug::util::permit_alloc(|| {
+                dump.extend((0..n_samples).map(|i| wet.iter().map(|w| w[i]).sum::<f32>()));
+            });
+        }
+
+        // ── ⑤ ウェットのモノラル和を feedback 倍してチャンクの終わりにリングへ戻す ──
+        // 書き込んだ区間に足すので、帰還は次のチャンク以降のグレインから聞こえる。
+        // 移調したグレインが積み重なっても発散しないよう tanh で抑える。
+        for (i, fb) in self.fb_buf[..n_samples].iter().enumerate() {
+            let wet: f32 = self.wet[..n_ch].iter().map(|w| w[i]).sum();
+            self.fb_pending[phase + i] = (fb * wet).tanh();
+        }
+        if phase + n_samples == CHUNK_SIZE {
+            self.end_chunk();
+        }
+
+        // ── ⑥ ドライ成分とウェット成分を mix でミックス ──
+        // ダッキングが有効ならウェットのピークのエンベロープでドライを下げる (Dry Out には掛けない)。
+        // delta ならミックスの結果からドライを引き、グレインが足した分だけを聞かせる。
+        // マルチバンドでグレインにしない帯域のドライは mix・ダッキングによらずそのまま出し、
+        // グレインにする帯域のドライは mix に band_mix を掛けた分だけ下げる
+        let (attack, release) = (self.frame.duck_attack_ms, self.frame.duck_release_ms);
+        for (i, duck) in self.duck_buf[..n_samples].iter_mut().enumerate() {
+            let level = self.wet[..n_ch]
+                .iter()
+                .fold(0.0f32, |m, w| m.max(w[i].abs()));
+            *duck = self.ducker.next(level, *duck, attack, release);
+        }
+        let delta = if self.frame.delta { 1.0 } else { 0.0 };
+        let f = &self.frame;
+        let hz = (f.crossover_low_hz, f.crossover_high_hz);
+        let (granulate, band_mix) = if f.bands == Bands::Off {
+            ([true; 3], [1.0; 3])
+        } else {
+            (f.granulate_bands, f.band_mix)
+        };
+        for (ch, (out, wet)) in io.iter_mut().zip(&self.wet).enumerate() {
+            for (((o, w), mix), duck) in out
+                .iter_mut()
+                .zip(wet)
+                .zip(&self.mix_buf)
+                .zip(&self.duck_buf)
+            {
+                let y = self.crossover.split(ch, *o, f.bands, hz);
+                let dry: f32 = y
+                    .into_iter()
+                    .zip(granulate)
+                    .zip(band_mix)
+                    .map(|((y, on), m)| {
+                        if on {
+                            y * (duck * (1.0 - mix * m) - delta)
+                        } else {
+                            y * (1.0 - delta)
+                        }
+                    })
+                    .sum();
+                *o = dry + w * mix;
+            }
+        }
+        self.meter_out.measure(io, n_samples, &self.meters.output);
+
+        // ── ⑦ 終了したグレインを除去し、バッファをプールへ返す ──
+        let pool = &self.queues.pool;
+        self.grains.retain_mut(|g| {
+            if g.done() {
+                if g.ring.is_none() {
+                    let _ = pool.push(std::mem::take(&mut g.buf));
+                }
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// `f` を実行し、パニックしたらそのメッセージを返す (ホストごと落とさないため)。
+/// メッセージの取り出しと破棄はオーディオスレッドでも確保を許す
+fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|payload| {
+        nih_plug::util::permit_alloc(|| {
+            payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string())
+        })
+    })
+}
+
+/*──────────────────── 5. Trigger normalization ────────*/
+/// `rate` 倍速で `len` サンプルのグレインを作るのに必要なリング上のサンプル数 (補間用の 1 サンプル込み)
+#[inline]
+pub fn source_len(len: usize, rate: f32) -> usize {
+    if rate == 1.0 {
+        len
+    } else {
+        (len as f32 * rate).ceil() as usize + 1
+    }
+}
+
+/// 速度比が `from` × `rate` から `rate` へ指数的に (半音単位で直線的に) 近づくグライドの平均速度比。
+/// グレインが読むリング上の長さは `len` × この値になる。
+#[inline]
+pub fn glide_mean_rate(rate: f32, from: f32) -> f32 {
+    if from == 1.0 {
+        rate
+    } else {
+        rate * (from - 1.0) / from.ln()
+    }
+}
+
+/// 同じグライドで `i` サンプル目を読む位置 (グレインの先頭から、リング上のサンプル数)。
+/// 速度比を積分した閉じた式なので、長いグレインでも誤差が積もらない。
+#[inline]
+fn glide_pos(i: usize, len: usize, rate: f64, from: f64) -> f64 {
+    if from == 1.0 {
+        i as f64 * rate
+    } else {
+        let t = i as f64 / len as f64;
+        rate * len as f64 * from * (1.0 - from.powf(-t)) / from.ln()
+    }
+}
+
+/// `src` の `pos` から前へ `back`、後ろへ `ahead` サンプル以内で最も近いゼロクロス (i - 1 と i で符号が
+/// 変わるか i が 0 の位置 i) までのずれ。`wrap` ならリングとして端をまたいで探す。無ければ None
+pub fn zero_crossing_offset(
+    src: &[f32],
+    pos: usize,
+    back: usize,
+    ahead: usize,
+    wrap: bool,
+) -> Option<isize> {
+    let n = src.len() as isize;
+    let crosses = |d: isize| {
+        let i = pos as isize + d;
+        let (a, b) = if wrap {
+            (
+                src[(i - 1).rem_euclid(n) as usize],
+                src[i.rem_euclid(n) as usize],
+            )
+        } else if i >= 1 && i < n {
+            (src[i as usize - 1], src[i as usize])
+        } else {
+            return false;
+        };
+        b == 0.0 || (a < 0.0) != (b < 0.0)
+    };
+    (0..=back.max(ahead) as isize).find_map(|k| {
+        if k <= back as isize && crosses(-k) {
+            Some(-k)
+        } else if k <= ahead as isize && crosses(k) {
+            Some(k)
+        } else {
+            None
+        }
+    })
+}
+
+/// 中心の長さ `length_ms` と散らし幅 `jitter` (%) を最小長／最大長へ変換する。
+/// 範囲は 1 ms..MAX_GRAIN_MS に収め、はみ出した側だけを切り詰める。
+#[inline]
+pub fn length_range(length_ms: f32, jitter: f32) -> (f32, f32) {
+    let spread = length_ms * jitter * 0.01;
+    (
+        (length_ms - spread).max(1.0),
+        (length_ms + spread).min(MAX_GRAIN_MS),
+    )
+}
+
+/// 1 回のトリガーで生成するグレインの数。burst_density なら density の BURST_DENSITY_KNEE から 1.0 までで
+/// burst から MAX_BURST まで直線的に増やす
+pub fn burst_size(p: &FrameParams, density: f32) -> i32 {
+    let burst = p.burst.clamp(1, MAX_BURST);
+    if !p.burst_density {
+        return burst;
+    }
+    let t = ((density - BURST_DENSITY_KNEE) / (1.0 - BURST_DENSITY_KNEE)).clamp(0.0, 1.0);
+    burst + ((MAX_BURST - burst) as f32 * t).round() as i32
+}
+
+/// `n_samples` サンプルのブロックで期待されるグレイン数。
+/// density を TRIGGER_REF_SEC あたりの発生確率として扱い、ブロック長/サンプルレートで
+/// 正規化することで、ホストのバッファサイズに関係なく同じ発生率になる。
+pub fn expected_grains(density: f32, n_samples: usize, sr: f32) -> f32 {
+    density * (n_samples as f32 / sr) / TRIGGER_REF_SEC
+}
+
+/// 書き込み位置 `wr` を持つリング `old` を `new_len` サンプルへ線形補間で再サンプリングする。
+/// 最も古いサンプルが先頭に来るよう並べ直すので、新しい書き込み位置は常に 0 になる。
+pub fn resample_ring(old: &[f32], wr: usize, new_len: usize) -> (Vec<f32>, usize) {
+    let mut ring = vec![0.0; new_len];
+    if old.is_empty() || new_len == 0 {
+        return (ring, 0);
+    }
+    let old_len = old.len();
+    let ratio = old_len as f32 / new_len as f32;
+    for (i, v) in ring.iter_mut().enumerate() {
+        let pos = i as f32 * ratio;
+        let i0 = (pos as usize).min(old_len - 1);
+        let i1 = (i0 + 1).min(old_len - 1);
+        let frac = pos - i0 as f32;
+        let a = old[(wr + i0) % old_len];
+        let b = old[(wr + i1) % old_len];
+        *v = a + (b - a) * frac;
+    }
+    (ring, 0)
+}
+
+/// 位置 `oldest` から古い順に並ぶリング `buf` をループにする。`buf` を回して `oldest` から `fade` 後の
+/// サンプルを先頭に置き、ループの最後の `fade` サンプルを、その後ろに寄せた最も古い `fade` サンプルと
+/// 等パワーでクロスフェードする。先頭の `buf.len() - fade` サンプルが継ぎ目のないループになる
+pub fn join_loop(buf: &mut [f32], oldest: usize, fade: usize) {
+    let n = buf.len();
+    if n == 0 {
+        return;
+    }
+    buf.rotate_left((oldest + fade) % n);
+    let (body, head) = buf.split_at_mut(n - fade);
+    for (d, (tail, head)) in body[n - 2 * fade..].iter_mut().zip(head.iter()).enumerate() {
+        let t = (d as f32 + 0.5) / fade as f32 * std::f32::consts::FRAC_PI_2;
+        *tail = *tail * t.cos() + *head * t.sin();
+    }
+}
+
+/// リング `old` (書き込み位置 `wr`) の新しい側から最大 `new_len` サンプルを、古い順に長さ `new_len` の
+/// リングの先頭から並べる。新しい書き込み位置は並べた直後 (リングが埋まれば 0) になる。
+pub fn fit_ring(old: &[f32], wr: usize, new_len: usize) -> (Vec<f32>, usize) {
+    let mut ring = vec![0.0; new_len];
+    let n = old.len().min(new_len);
+    for (i, v) in ring[..n].iter_mut().enumerate() {
+        *v = old[(wr + old.len() - n + i) % old.len()];
+    }
+    (ring, if n == new_len { 0 } else { n })
+}
+
+/*──────────────────── 6. SIMD mixing ─────────────────*/
+/// 4 サンプル単位でまとめて演算するための軽量ベクタ型 (LLVM が SSE/NEON に落とす)
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct F32x4([f32; 4]);
+
+impl F32x4 {
+    #[inline(always)]
+    fn load(x: &[f32]) -> Self {
+        Self([x[0], x[1], x[2], x[3]])
+    }
+
+    #[inline(always)]
+    fn store(self, x: &mut [f32]) {
+        x[..4].copy_from_slice(&self.0);
+    }
+}
+
+impl std::ops::Add for F32x4 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn add(self, rhs: Self) -> Self {
+        let (a, b) = (self.0, rhs.0);
+        Self([a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]])
+    }
+}
+
+/// `dst[i] += src[i]` を 4 サンプル単位で計算し、端数はスカラーで処理する
+#[inline]
+pub fn mix_add(dst: &mut [f32], src: &[f32]) {
+    let n = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..n], &src[..n]);
+    let mut d4 = dst.chunks_exact_mut(4);
+    let mut s4 = src.chunks_exact(4);
+    for (d, s) in (&mut d4).zip(&mut s4) {
+        (F32x4::load(d) + F32x4::load(s)).store(d);
+    }
+    for (d, s) in d4.into_remainder().iter_mut().zip(s4.remainder()) {
+        *d += s;
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, read_f32_wav};
+    use crate::macros::MacroMap;
+    use crate::modulation::{ModDest, ModSource};
+    use crate::sequencer::default_pattern;
+    use proptest::prelude::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn length_range_spreads_around_the_center() {
+        assert_eq!(length_range(200.0, 0.0), (200.0, 200.0));
+        assert_eq!(length_range(200.0, 50.0), (100.0, 300.0));
+        // 範囲外は端で切り詰める
+        assert_eq!(length_range(800.0, 50.0), (400.0, MAX_GRAIN_MS));
+        assert_eq!(length_range(10.0, 100.0), (1.0, 20.0));
+    }
+
+    #[test]
+    fn burst_size_follows_the_upper_half_of_density() {
+        let mut p = FrameParams {
+            burst: 2,
+            ..FrameParams::default()
+        };
+        assert_eq!(burst_size(&p, 1.0), 2);
+        p.burst_density = true;
+        assert_eq!(burst_size(&p, 0.0), 2);
+        assert_eq!(burst_size(&p, BURST_DENSITY_KNEE), 2);
+        assert_eq!(burst_size(&p, 0.75), 5);
+        assert_eq!(burst_size(&p, 1.0), MAX_BURST);
+        // ノートゲートで 1 を超えた density でも MAX_BURST で止まる
+        assert_eq!(burst_size(&p, 3.0), MAX_BURST);
+    }
+
+    #[test]
+    fn expected_grains_is_block_size_invariant() {
+        let sr = 44100.0;
+        let small: f32 = (0..128).map(|_| expected_grains(0.3, 32, sr)).sum();
+        let large = expected_grains(0.3, 4096, sr);
+        assert!((small - large).abs() < 1e-3, "{small} vs {large}");
+
+        // 基準時間ちょうどのブロックでは density がそのまま期待値になる
+        let ref_block = (TRIGGER_REF_SEC * 48_000.0).round() as usize;
+        assert!((expected_grains(0.5, ref_block, 48_000.0) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn grain_done_checks_bounds() {
+        let g = Grain {
+            buf: vec![0.0, 1.0],
+            pos: 2,
+            ch: 0,
+            offset: 0,
+            ..Grain::default()
+        };
+        assert!(g.done());
+        let g = Grain {
+            buf: vec![0.0, 1.0],
+            pos: 1,
+            ch: 0,
+            offset: 0,
+            ..Grain::default()
+        };
+        assert!(!g.done());
+    }
+
+    #[test]
+    fn mix_add_matches_scalar_sum() {
+        for len in [0usize, 1, 3, 4, 7, 16, 33] {
+            let src: Vec<f32> = (0..len).map(|i| i as f32 * 0.25).collect();
+            let mut dst: Vec<f32> = (0..len).map(|i| 1.0 - i as f32).collect();
+            let expected: Vec<f32> = dst.iter().zip(&src).map(|(d, s)| d + s).collect();
+            mix_add(&mut dst, &src);
+            assert_eq!(dst, expected, "len {len}");
+        }
+
+        // 長さが異なる場合は短い方に合わせる
+        let mut dst = vec![0.0f32; 5];
+        mix_add(&mut dst, &[1.0; 3]);
+        assert_eq!(dst, vec![1.0, 1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn resample_ring_preserves_chronology() {
+        // wr=2 なので最も古いサンプルは old[2]
+        let old = [2.0, 3.0, 0.0, 1.0];
+        let (ring, wr) = resample_ring(&old, 2, 8);
+        assert_eq!(wr, 0);
+        assert_eq!(ring.len(), 8);
+        let expected = [0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.0];
+        for (a, b) in ring.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{ring:?}");
+        }
+        let (empty, wr) = resample_ring(&[], 0, 4);
+        assert_eq!(empty, vec![0.0; 4]);
+        assert_eq!(wr, 0);
+    }
+
+    #[test]
+    fn ring_is_sized_from_params_and_grows_on_demand() {
+        let mut engine = Engine::default();
+        engine.set_frame(FrameParams {
+            mode: TriggerMode::Sync,
+            max_ms: 50.0,
+            ..FrameParams::default()
+        });
+        engine.initialize(1_000.0, 1, 64);
+        // 直近の入力から切り出す Sync モードの短いグレインには RING_SEC 秒の履歴は要らない
+        let fft = ((FFT_SEC * 1_000.0) as usize).next_power_of_two();
+        assert_eq!(engine.ring_len, fft.max(50 + CHUNK_SIZE + 1));
+        assert_eq!(engine.ring.len(), 128);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = i as f32 + 1.0;
+        }
+
+        // 4 倍速の 100 サンプルのグレインは 401 サンプル読むので、リングが広がるまで生成されない
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 4.0);
+        assert_eq!(engine.active_grains(), 0);
+        assert_eq!(engine.take_ring_request(), Some(512));
+        assert_eq!(engine.take_ring_request(), None);
+
+        // 広げたリングでは今までの内容が書き込み位置の手前に古い順に並ぶ
+        grow_ring(&engine.queues, 512);
+        engine.collect_ring();
+        assert_eq!(engine.ring.len(), 512);
+        assert_eq!(engine.ring_len, 401 + 1 + CHUNK_SIZE);
+        assert_eq!(engine.wr, 128);
+        assert!((0..128).all(|i| engine.ring[i] == i as f32 + 1.0));
+        assert_eq!(engine.queues.retired.pop().map(|r| r.len()), Some(128));
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 4.0);
+        assert_eq!(engine.active_grains(), 1);
+    }
+
+    #[test]
+    fn sync_mode_spawns_at_regular_interval() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 128);
+        let mut params = FrameParams {
+            min_ms: 100.0,
+            max_ms: 100.0,
+            mode: TriggerMode::Sync,
+            overlap: Overlap::X4,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+
+        // 長さ 100 サンプル / overlap 4 → 25 サンプルごとに 1 グレイン
+        let mut io = vec![1.0f32; 99];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        let mut pos: Vec<usize> = engine.grains.iter().map(|g| g.pos).collect();
+        pos.sort_unstable();
+        assert_eq!(pos, vec![24, 49, 74, 99]);
+
+        // 2 サンプル進めると最初のグレインが終わり、100 サンプル目で次のグレインが始まる
+        let mut io = [1.0f32; 2];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 4);
+        assert!(engine.grains.iter().all(|g| g.len() == 100));
+    }
+
+    #[test]
+    fn ring_percent_lengths_follow_the_ring() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 128);
+        let mut params = FrameParams {
+            length_mode: LengthMode::RingPercent,
+            length_pct: 2.0,
+            length_jitter: 0.0,
+            mode: TriggerMode::Sync,
+            overlap: Overlap::X2,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+
+        // 5 秒のリングの 2% = 100 サンプル
+        let mut io = vec![0.0f32; 128];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.ring_len, (RING_SEC * 1_000.0) as usize);
+        assert!(engine.active_grains() > 0);
+        assert!(engine.grains.iter().all(|g| g.len() == 100));
+
+        // リングが伸びればグレインも同じ比率で伸びる (LENGTH_SMOOTH_MS で追従してから)
+        engine.ring_len = 8_000;
+        engine.reset_scheduler();
+        let mut io = vec![0.0f32; 512];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.grains.iter().any(|g| g.len() == 160));
+    }
+
+    #[test]
+    fn length_sweeps_ramp_new_grains_and_survive_inverted_ranges() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            mode: TriggerMode::Sync,
+            min_ms: 20.0,
+            max_ms: 20.0,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+        let mut io = vec![1.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.grains.iter().all(|g| g.len() == 20));
+
+        // 20 ms から 200 ms へ跳んでも、新しいグレインは段を踏まずに伸びていく
+        (params.min_ms, params.max_ms) = (200.0, 200.0);
+        let mut lens = Vec::new();
+        for _ in 0..8 {
+            io.fill(1.0);
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            lens.extend(engine.grains.iter().map(|g| g.len()));
+        }
+        lens.sort_unstable();
+        lens.dedup();
+        assert_eq!(lens.last(), Some(&200));
+        assert!(lens.iter().any(|len| (30..190).contains(len)), "{lens:?}");
+
+        // パラメータ源から逆転した範囲や範囲外の長さが直接来ても、パニックせずに生成を続ける
+        params.mode = TriggerMode::Random;
+        params.density = 1.0;
+        for k in 0..64 {
+            (params.min_ms, params.max_ms) = match k % 4 {
+                0 => (300.0, 100.0),
+                1 => (-50.0, 5.0),
+                2 => (2.0 * MAX_GRAIN_MS, 10.0),
+                _ => (40.0, 3.0 * MAX_GRAIN_MS),
+            };
+            io.fill(1.0);
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            assert!(io.iter().all(|x| x.is_finite()));
+        }
+        assert!(engine.active_grains() > 0);
+    }
+
+    #[test]
+    fn stretch_playhead_follows_speed() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 1_000);
+        let mut params = FrameParams {
+            mode: TriggerMode::Stretch,
+            speed: 0.5,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+        let mut io = vec![0.0f32; 100];
+
+        // 半速ではプレイヘッドが 1 フレームあたり 0.5 サンプルずつ遅れていく
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!((engine.lag - 50.0).abs() < 1e-3, "{}", engine.lag);
+
+        // 倍速では書き込み位置に追いつき、それ以上は先行しない
+        params.speed = 2.0;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.lag, 0.0);
+
+        // 停止中はリング長 - 最大グレイン長 (4 秒) を超えると最新の入力へ戻る
+        params.speed = 0.0;
+        for _ in 0..40 {
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        }
+        assert!((engine.lag - 4_000.0).abs() < 1e-3, "{}", engine.lag);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!((engine.lag - 100.0).abs() < 1e-3, "{}", engine.lag);
+    }
+
+    #[test]
+    fn transposed_grain_reads_at_rate() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = i as f32;
+        }
+        // 窓の影響を受けない中央付近で、2 倍速なら 1 サンプルおき、0.5 倍速なら補間値になる
+        engine.spawn_sync_grain(20, 0.0, 0, 0, 2.0);
+        engine.spawn_sync_grain(20, 0.0, 0, 0, 0.5);
+        let start = engine.ring.len() - source_len(20, 2.0);
+        assert_eq!(engine.grains[0].buf[10], (start + 20) as f32);
+        let start = engine.ring.len() - source_len(20, 0.5);
+        assert_eq!(engine.grains[1].buf[10], start as f32 + 5.0);
+    }
+
+    #[test]
+    fn fractional_lag_reads_between_samples() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = i as f32;
+        }
+        // 0.5 サンプル遡った位置から切り出すと、等速でも隣り合うサンプルの中間を読む
+        engine.spawn_sync_grain(20, 0.5, 0, 0, 1.0);
+        let start = engine.ring.len() as f32 - 20.5;
+        assert_eq!(engine.grains[0].buf[10], start + 10.0);
+    }
+
+    #[test]
+    fn glide_ramps_rate_toward_target() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = i as f32 * 0.01;
+        }
+        // 1 オクターブ上から始まり、グレインの終わりで等速に戻る
+        engine.glide_from = 2.0;
+        engine.spawn_sync_grain(400, 0.0, 0, 0, 1.0);
+        let buf = &engine.grains[0].buf;
+        for i in [100, 200, 300] {
+            let step = (buf[i + 1] - buf[i]) / 0.01;
+            let expected = 2.0f32.powf(1.0 - (i as f32 + 0.5) / 400.0);
+            assert!((step - expected).abs() < 1e-3, "{i}: {step} vs {expected}");
+        }
+        // リング上で読む長さは平均速度比 1 / ln 2 倍で、確保する長さに収まる
+        let end = glide_pos(400, 400, 1.0, 2.0);
+        assert!((end - 400.0 / std::f64::consts::LN_2).abs() < 1e-9);
+        assert!(source_len(400, glide_mean_rate(1.0, 2.0)) as f64 > end);
+    }
+
+    #[test]
+    fn pingpong_plays_forward_then_backward() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = i as f32 * 0.01;
+        }
+        engine.pingpong = true;
+        for len in [400, 401] {
+            engine.spawn_sync_grain(len, 0.0, 0, 0, 1.0);
+            let buf = &engine.grains.last().unwrap().buf;
+            assert_eq!(buf.len(), len);
+            // 前半は順方向に進み、後半は同じ区間を逆にたどる (Tukey 窓も左右対称)
+            assert!(buf[150] > buf[100]);
+            assert!(buf.iter().eq(buf.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn ducking_lowers_dry_under_wet() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.5,
+            duck_db: 12.0,
+            duck_attack_ms: 1.0,
+            ..FrameParams::default()
+        };
+        // ウェットが無ければドライはそのまま
+        let mut io = [1.0f32; CHUNK_SIZE];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert!(io.iter().all(|&x| x == 0.5));
+
+        // 0 dBFS のウェットが続くとドライは 12 dB 下がる
+        engine.grains.push(Grain {
+            buf: vec![1.0; 500],
+            ..Grain::default()
+        });
+        let mut io = [1.0f32; CHUNK_SIZE];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        let dry = 10.0f32.powf(-12.0 / 20.0);
+        assert!((io[CHUNK_SIZE - 1] - (0.5 * dry + 0.5)).abs() < 1e-3);
+        // Dry Out には掛けない
+        assert_eq!(engine.dry(0, CHUNK_SIZE)[CHUNK_SIZE - 1], 1.0);
+    }
+
+    #[test]
+    fn wet_gate_removes_quiet_tails() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            wet_gate_db: -20.0,
+            wet_gate_release_ms: 5.0,
+            ..FrameParams::default()
+        };
+        // しきい値を超えるグレインは通り、-40 dB の残りは閉じたゲートで消える
+        engine.grains.push(Grain {
+            buf: [vec![0.5; 20], vec![0.01; 200]].concat(),
+            ..Grain::default()
+        });
+        let mut io = vec![0.0f32; 4 * CHUNK_SIZE];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert!((io[10] - 0.5).abs() < 1e-3, "{}", io[10]);
+        assert!(io[150].abs() < 1e-6, "{}", io[150]);
+    }
+
+    #[test]
+    fn surround_grains_pan_between_speakers_and_skip_the_lfe() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 6, 64);
+        let mut rng = SmallRng::seed_from_u64(1);
+
+        // spread 0 では正面 (5.1 の C) だけ、全周ではすべてのスピーカーを使い LFE には出さない
+        engine.frame.surround_spread = 0.0;
+        assert_eq!(engine.pick_channel(&mut rng, 6), 2);
+        assert_eq!(engine.next_pan.unwrap().gain, [1.0, 0.0]);
+        engine.frame.surround_spread = 1.0;
+        let mut used = [false; 6];
+        for _ in 0..500 {
+            engine.pick_channel(&mut rng, 6);
+            let pan = engine.next_pan.unwrap();
+            used[pan.ch[0]] = true;
+            used[pan.ch[1]] = true;
+        }
+        assert_eq!(used, [true, true, true, false, true, true]);
+        // ステレオではパンしない (バーストのグレインだけ、その方向へ等パワーでパンする)
+        engine.pick_channel(&mut rng, 2);
+        assert!(engine.next_pan.is_none());
+        engine.next_azimuth = Some(90.0);
+        assert_eq!(engine.pick_channel(&mut rng, 2), 1);
+        assert_eq!(engine.next_pan, Some(surround::stereo(90.0)));
+        engine.next_azimuth = None;
+
+        // パンしたグレインは 2 つのスピーカーへゲインを掛けて鳴る
+        let pan = surround::vbap(-15.0, &surround::SURROUND_51);
+        engine.grains.push(Grain {
+            buf: vec![0.5; 20],
+            ch: pan.ch[0],
+            pan: Some(pan),
+            ..Grain::default()
+        });
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 1.0,
+            ..FrameParams::default()
+        };
+        let mut io = vec![vec![0.0f32; CHUNK_SIZE]; 6];
+        let mut chunk: Vec<&mut [f32]> = io.iter_mut().map(|c| &mut c[..]).collect();
+        engine.process(&mut chunk, &mut params, &mut rng);
+        for (ch, out) in io.iter().enumerate() {
+            let expect = match pan.ch.iter().position(|&c| c == ch) {
+                Some(k) => 0.5 * pan.gain[k],
+                None => 0.0,
+            };
+            assert!((out[10] - expect).abs() < 1e-3, "ch {ch}: {}", out[10]);
+        }
+    }
+
+    #[test]
+    fn character_is_applied_to_copied_grains_only() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.ring.fill(0.3);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let p = FrameParams {
+            grain_bits: 2.0,
+            ..FrameParams::default()
+        };
+        engine.frame = p;
+        // キャラクターがあればリングを直接読まずにコピーし、窓の内側は 1/2 刻みに量子化される
+        engine.grain_rate(&p, &mut rng);
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        let grain = &engine.grains[0];
+        assert!(grain.ring.is_none());
+        assert_eq!(grain.buf[50], 0.5);
+        engine.clear_grains();
+
+        // キャラクターが無ければ素通り
+        let p = FrameParams::default();
+        engine.grain_rate(&p, &mut rng);
+        assert!(engine.next_character.is_none());
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains[0].samples(&engine.ring)[50], 0.3);
+        engine.clear_grains();
+
+        // ティルトはグレインごとに grain_tilt_db ± tilt_random_db の範囲 (上限で切る) から選び、コピーしてかける
+        let p = FrameParams {
+            grain_tilt_db: 10.0,
+            tilt_random_db: 3.0,
+            ..FrameParams::default()
+        };
+        for _ in 0..100 {
+            engine.grain_rate(&p, &mut rng);
+            assert!((7.0..=MAX_TILT_DB).contains(&engine.next_tilt));
+        }
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert!(engine.grains[0].ring.is_none());
+    }
+
+    #[test]
+    fn silent_regions_do_not_spawn_grains() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.frame.silence_db = -60.0;
+        engine.frame.silence_retries = 0;
+
+        // 直近の入力が無音なら Sync のグレインは作らない
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert!(engine.grains.is_empty());
+        let len = engine.ring.len();
+        engine.ring[len - 100..].fill(0.1);
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains.len(), 1);
+        engine.clear_grains();
+
+        // リングの前半だけ音がある。再試行なしでは半分ほどが捨てられ、
+        // 再試行ありではほとんどが音のある位置から作られる
+        engine.ring.fill(0.0);
+        engine.ring[..len / 2].fill(0.1);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut spawned = |engine: &mut Engine, retries: i32| {
+            engine.frame.silence_retries = retries;
+            let mut count = 0;
+            for _ in 0..200 {
+                engine.spawn_grain(&mut rng, 20, 20, 1, 0, 1.0);
+                count += engine.grains.len();
+                engine.clear_grains();
+            }
+            count
+        };
+        let once = spawned(&mut engine, 0);
+        let retried = spawned(&mut engine, MAX_SILENCE_RETRIES);
+        assert!((60..140).contains(&once), "{once}");
+        assert!(retried > 195, "{retried}");
+    }
+
+    #[test]
+    fn avoid_repeats_keeps_new_grains_off_recent_regions() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let n = engine.ring.len();
+        engine.ring.fill(0.1);
+        let mut rng = SmallRng::seed_from_u64(3);
+        // リングの 1/10 の長さのグレインを続けて作り、直前 2 つと重なった回数を数える
+        let mut overlaps = |engine: &mut Engine, avoid: i32| {
+            engine.frame.avoid_repeats = avoid;
+            engine.reset_scheduler();
+            let mut count = 0;
+            for _ in 0..200 {
+                engine.spawn_grain(&mut rng, n / 10, n / 10, 1, 0, 1.0);
+                engine.clear_grains();
+                let (_, start, len) = engine.recent.pop().unwrap();
+                engine.frame.avoid_repeats = 2;
+                count += engine.repeats(false, start, len, n) as usize;
+                engine.frame.avoid_repeats = avoid;
+                engine.recent.push((false, start, len));
+            }
+            count
+        };
+        let off = overlaps(&mut engine, 0);
+        let on = overlaps(&mut engine, 2);
+        assert!(off > 40, "{off}");
+        assert!(on < 5, "{on}");
+    }
+
+    #[test]
+    fn onset_starts_land_on_hits_written_to_the_ring() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 100);
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        let wr0 = engine.wr;
+        // 200 サンプルごとの打撃
+        for block in 0..10 {
+            let mut io: Vec<f32> = (0..100)
+                .map(|i| (-(((block * 100 + i) % 200) as f32) / 20.0).exp())
+                .collect();
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        }
+        let hits: Vec<usize> = (0..5).map(|k| (wr0 + 200 * k) & engine.ring_mask).collect();
+        assert_eq!(engine.onsets.onsets(), &hits[..]);
+
+        // 書き込み位置の 200 サンプル手前の最後の打撃は、それより長いグレインには使わない
+        engine.frame.grain_start = GrainStart::Onset;
+        let n = engine.ring.len();
+        for _ in 0..50 {
+            let start = engine.grain_start(&mut rng, false, n, 100);
+            assert!(hits.contains(&start), "{start}");
+            let start = engine.grain_start(&mut rng, false, n, 300);
+            assert!(hits[..4].contains(&start), "{start}");
+        }
+    }
+
+    #[test]
+    fn beat_grid_starts_land_where_the_grid_was_written() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 100);
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        // 120 BPM, 1/16 → 125 サンプルごと。再生位置 100 から書き始める
+        engine.set_transport(Some(120.0), Some(100), true);
+        let wr0 = engine.wr;
+        for _ in 0..10 {
+            let mut io = [0.1f32; 100];
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        }
+        let beats: Vec<usize> = (0..8)
+            .map(|k| (wr0 + 25 + 125 * k) & engine.ring_mask)
+            .collect();
+        assert_eq!(engine.beats.marks(), &beats[..]);
+
+        engine.frame.grain_start = GrainStart::Beat;
+        let n = engine.ring.len();
+        for _ in 0..50 {
+            let start = engine.grain_start(&mut rng, false, n, 100);
+            assert!(beats.contains(&start), "{start}");
+        }
+
+        // リングを消去したら覚えた位置も忘れる
+        engine.clear_ring(0, false);
+        assert!(engine.beats.marks().is_empty());
+    }
+
+    #[test]
+    fn play_mode_loops_the_ring_without_a_seam() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        // 書き込み位置から古い順に 0.05 rad/サンプルの正弦波 (最も新しい音と最も古い音の間で 0.9 跳ぶ)
+        let (n, wr) = (engine.ring.len(), 1_000);
+        engine.wr = wr;
+        for k in 0..n {
+            engine.ring[(wr + k) & engine.ring_mask] = (k as f32 * 0.05).sin();
+        }
+        let mut params = FrameParams {
+            density: 0.0,
+            ring_mode: RingMode::Play,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 16];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+
+        // ループは継ぎ目の 50 ms だけ短くなり、末尾から先頭へ折り返しても段差が無い
+        let src = engine.source(false);
+        assert_eq!(src.len(), n - 50);
+        let step = |i: usize| (src[(i + 1) % src.len()] - src[i]).abs();
+        let worst = (0..src.len()).map(step).fold(0.0f32, f32::max);
+        assert!(worst < 0.1, "{worst}");
+        // ループの先頭は元の最も古い音の 50 ms 後
+        assert!((src[0] - 2.5f32.sin()).abs() < 1e-6);
+
+        // Play を抜けると継ぎ目から書き込みを再開し、リング全体を読む
+        params.ring_mode = RingMode::Record;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.source(false).len(), n);
+        assert_eq!(engine.wr, n - 50 + 16);
+    }
+
+    #[test]
+    fn humanize_delays_grid_onsets_within_the_spread() {
+        // 1 kHz、120 BPM の 16 分音符 = 125 サンプルごとのグリッド
+        let onsets = |humanize_ms| {
+            let mut engine = Engine::default();
+            engine.initialize(1_000.0, 1, 1_000);
+            engine.set_transport(Some(120.0), None, true);
+            let mut params = FrameParams {
+                mode: TriggerMode::Tempo,
+                density: 1.0,
+                min_ms: 10.0,
+                max_ms: 10.0,
+                humanize_ms,
+                ..FrameParams::default()
+            };
+            let mut rng = SmallRng::seed_from_u64(3);
+            let mut io = vec![0.5f32; 1_000];
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            engine
+                .spawn_events()
+                .iter()
+                .map(|e| e.timing)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(onsets(0.0), (0..8).map(|k| k * 125).collect::<Vec<_>>());
+        // 20 ms までランダムに遅れるが、グリッドの順番は変わらない
+        let late = onsets(20.0);
+        assert_eq!(late.len(), 8);
+        for (k, t) in late.iter().enumerate() {
+            assert!(
+                (k as u32 * 125..=k as u32 * 125 + 20).contains(t),
+                "{late:?}"
+            );
+        }
+        assert!(late.iter().enumerate().any(|(k, t)| *t != k as u32 * 125));
+    }
+
+    #[test]
+    fn burst_spawns_a_detuned_cluster_per_trigger() {
+        // 1 kHz、120 BPM の 16 分音符 = 125 サンプルごとのグリッドで、1 回のトリガーから 4 つ
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 2, 1_000);
+        engine.set_transport(Some(120.0), None, true);
+        let mut params = FrameParams {
+            mode: TriggerMode::Tempo,
+            density: 1.0,
+            min_ms: 10.0,
+            max_ms: 10.0,
+            max_grains: MAX_GRAINS as i32,
+            burst: 4,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut left = vec![0.5f32; 1_000];
+        let mut right = vec![0.5f32; 1_000];
+        engine.process(&mut [&mut left[..], &mut right[..]], &mut params, &mut rng);
+        let events = engine.spawn_events();
+
+        // 元のグレインはグリッド上で移調せず、足したグレインは 20 ms 以内に少しだけ移調して鳴る
+        let (main, extra): (Vec<&SpawnEvent>, Vec<_>) = events.iter().partition(|e| e.rate == 1.0);
+        assert_eq!(main.len(), 8);
+        assert!(main.iter().all(|e| e.timing % 125 == 0));
+        assert_eq!(extra.len(), 8 * 3);
+        let max_ratio = (BURST_DETUNE_ST / 12.0).exp2() + 1e-6;
+        for e in &extra {
+            assert!(e.timing % 125 <= 20, "{}", e.timing);
+            assert!(
+                e.rate <= max_ratio && 1.0 / e.rate <= max_ratio,
+                "{}",
+                e.rate
+            );
+        }
+        // 足したグレインは左右のチャンネルへ散らばる
+        assert!(extra.iter().any(|e| e.ch == 0) && extra.iter().any(|e| e.ch == 1));
+    }
+
+    #[test]
+    fn burst_grains_pan_around_one_direction() {
+        // 1 回のトリガーの 4 つのグレインが鳴っている間に、ステレオでのパンの位置を比べる
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 2, 50);
+        engine.set_transport(Some(120.0), None, true);
+        let mut params = FrameParams {
+            mode: TriggerMode::Tempo,
+            density: 1.0,
+            min_ms: 200.0,
+            max_ms: 200.0,
+            max_grains: MAX_GRAINS as i32,
+            burst: 4,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut left = vec![0.5f32; 50];
+        let mut right = vec![0.5f32; 50];
+        engine.process(&mut [&mut left[..], &mut right[..]], &mut params, &mut rng);
+
+        // パンの位置を L=0〜R=π/2 の角度にすると、元のグレインの方向から ±BURST_PAN_DEG に収まる
+        let angles: Vec<f32> = engine
+            .grains
+            .iter()
+            .map(|g| {
+                let pan = g.pan.expect("burst grains are panned in stereo");
+                let (l, r) = if pan.ch[0] == 0 {
+                    (pan.gain[0], pan.gain[1])
+                } else {
+                    (pan.gain[1], pan.gain[0])
+                };
+                r.atan2(l)
+            })
+            .collect();
+        assert_eq!(angles.len(), 4);
+        let lo = angles.iter().copied().fold(f32::INFINITY, f32::min);
+        let hi = angles.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let width = 2.0 * BURST_PAN_DEG / 180.0 * std::f32::consts::FRAC_PI_2;
+        assert!(hi - lo <= width + 1e-4, "{angles:?}");
+    }
+
+    #[test]
+    fn scatter_spreads_random_onsets_over_the_following_window() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 500);
+        let mut params = FrameParams {
+            density: 1.0,
+            min_ms: 5.0,
+            max_ms: 5.0,
+            max_grains: MAX_GRAINS as i32,
+            scatter_ms: 100.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(5);
+        let mut io = vec![0.5f32; 500];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(!engine.spawn_events().is_empty());
+
+        // density を 0 にしても、窓の中へ散らしたグレインは次のブロックの先頭 100 サンプルで鳴る
+        params.density = 0.0;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        let late = engine.spawn_events();
+        assert!(!late.is_empty());
+        assert!(late.iter().all(|e| e.timing <= 100), "{:?}", late.len());
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.spawn_events().is_empty());
+    }
+
+    #[test]
+    fn freeze_modes_follow_the_footswitch() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        // スイッチの列を 1 サンプルずつ与え、フリーズしていたサンプル数を数える
+        let run = |engine: &mut Engine, mode, presses: &[(bool, usize)]| {
+            let p = |freeze| FrameParams {
+                freeze,
+                freeze_mode: mode,
+                freeze_beats: 0.25,
+                ..FrameParams::default()
+            };
+            presses
+                .iter()
+                .flat_map(|&(freeze, n)| std::iter::repeat_n(freeze, n))
+                .map(|freeze| engine.freeze_switch(&p(freeze), 0.0))
+                .collect::<Vec<_>>()
+        };
+
+        // Momentary: 押している間だけ
+        let held = run(&mut engine, FreezeMode::Momentary, &[(true, 3), (false, 2)]);
+        assert_eq!(held, [true, true, true, false, false]);
+
+        // Latch: 押すたびに切り替わり、離しても保つ
+        let latched = run(
+            &mut engine,
+            FreezeMode::Latch,
+            &[(true, 2), (false, 2), (true, 1), (false, 1)],
+        );
+        assert_eq!(latched, [true, true, true, true, false, false]);
+
+        // Timed: 120 BPM、1 kHz で 0.25 拍 = 125 サンプルだけ保つ
+        let timed = run(&mut engine, FreezeMode::Timed, &[(true, 1), (false, 200)]);
+        assert_eq!(timed.iter().filter(|f| **f).count(), 125);
+        assert!(timed[..125].iter().all(|f| *f));
+
+        // モードを変えたら前のモードの状態は残さない
+        run(&mut engine, FreezeMode::Latch, &[(true, 1), (false, 1)]);
+        let momentary = run(&mut engine, FreezeMode::Momentary, &[(false, 1)]);
+        assert_eq!(momentary, [false]);
+
+        // Sidechain: キーがしきい値 (-30 dB) を超えている間と、下回ってから 1 kHz で 200 ms = 200 サンプル
+        // (戻り 5 ms のエンベロープが下がりきるまでの数サンプルを含む) だけ保つ
+        let p = FrameParams {
+            freeze_mode: FreezeMode::Sidechain,
+            key_release_ms: 5.0,
+            ..FrameParams::default()
+        };
+        let keyed: Vec<bool> = [0.0; 10]
+            .into_iter()
+            .chain([0.5; 50])
+            .chain([0.0; 400])
+            .map(|x| engine.freeze_switch(&p, x))
+            .collect();
+        assert!(!keyed[..10].iter().any(|f| *f));
+        let frozen = keyed.iter().filter(|f| **f).count();
+        assert!((250..270).contains(&frozen), "{frozen}");
+        assert!(!keyed[300..].iter().any(|f| *f));
+    }
+
+    #[test]
+    fn freeze_slots_hold_captured_textures() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let n = engine.ring.len();
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        // 1.0 で埋めたリングをスロット 2 へ取り込み、その後リングを 0.5 で上書きする
+        let mut io = vec![1.0f32; n];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        params.capture[1] = true;
+        let mut io = vec![0.5f32; n];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        params.capture[1] = false;
+        io.fill(0.5);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+
+        // スロット 2 を選ぶと、継ぎ目の分だけ短い取り込んだ内容を読む (非直接読み出しのグレインになる)
+        engine.frame.freeze_slot = 2;
+        let slot = engine.source(false);
+        assert_eq!(slot.len(), n - 50);
+        assert!(slot.iter().all(|x| (x - 1.0).abs() < 0.5));
+        assert!(slot[..n / 2].iter().all(|x| *x == 1.0));
+        engine.spawn_grain(&mut rng, 100, 100, 1, 0, 1.0);
+        assert!(engine.grains[0].ring.is_none());
+        assert_eq!(engine.grains[0].buf[50], 1.0);
+
+        // 取り込んでいないスロットと 0 はライブのリング
+        for k in [0, 1] {
+            engine.frame.freeze_slot = k;
+            assert_eq!(engine.source(false).len(), n);
+            assert!(engine.source(false).iter().all(|x| *x == 0.5));
+        }
+    }
+
+    #[test]
+    fn buffer_morph_dissolves_one_slot_into_another() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let n = engine.ring.len();
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        // スロット 1 に 1.0、スロット 2 に 0.5 を取り込む
+        for (k, x) in [(0, 1.0f32), (1, 0.5)] {
+            let mut io = vec![x; n];
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            params.capture[k] = true;
+            engine.process(&mut [&mut io[..1]], &mut params, &mut rng);
+            params.capture[k] = false;
+        }
+
+        // morph の割合のグレインが 2 つ目のスロットから切り出される
+        let mut p = FrameParams {
+            freeze_slot: 1,
+            morph_slot: 2,
+            ..FrameParams::default()
+        };
+        for (morph, lo, hi) in [(0.0, 0, 0), (0.3, 40, 80), (1.0, 200, 200)] {
+            p.buffer_morph = morph;
+            engine.frame = p;
+            let from_b = (0..200)
+                .filter(|_| {
+                    engine.grain_rate(&p, &mut rng);
+                    engine.source(false)[100] == 0.5
+                })
+                .count();
+            assert!((lo..=hi).contains(&from_b), "{morph} {from_b}");
+        }
+    }
+
+    #[test]
+    fn lookahead_delays_only_the_dry_by_the_reported_latency() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.0,
+            lookahead_ms: 10,
+            ..FrameParams::default()
+        };
+        let input: Vec<f32> = (0..300).map(|i| i as f32 / 300.0).collect();
+        let mut io = input.clone();
+        for block in io.chunks_mut(50) {
+            engine.process(&mut [block], &mut params, &mut rand::rng());
+        }
+        assert_eq!(engine.latency(), 10);
+        assert!(io[..10].iter().all(|&x| x == 0.0));
+        assert_eq!(&io[10..], &input[..290]);
+        // リングへはドライを遅らせる前の入力を書くので、グレインはドライより 10 サンプル先の音から作られる
+        assert_eq!(engine.ring[299], input[299]);
+
+        // スペクトルブラーの遅れとは足し合わせて報告する
+        params.spectral_blur = 0.5;
+        engine.process(&mut [&mut io[..50]], &mut params, &mut rand::rng());
+        assert_eq!(engine.latency(), 64 + 10);
+        params.spectral_blur = 0.0;
+        params.lookahead_ms = 0;
+        engine.process(&mut [&mut io[..50]], &mut params, &mut rand::rng());
+        assert_eq!(engine.latency(), 0);
+    }
+
+    #[test]
+    fn spectral_blur_delays_dry_by_the_reported_latency() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        assert_eq!(engine.latency(), 0);
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.0,
+            spectral_blur: 0.5,
+            ..FrameParams::default()
+        };
+        let input: Vec<f32> = (0..300).map(|i| i as f32 / 300.0).collect();
+        let mut io = input.clone();
+        for block in io.chunks_mut(50) {
+            engine.process(&mut [block], &mut params, &mut rand::rng());
+        }
+        // 1 kHz では 40 ms が 64 サンプルの FFT に切り上がる
+        let latency = engine.latency();
+        assert_eq!(latency, 64);
+        assert!(io[..latency].iter().all(|&x| x == 0.0));
+        assert_eq!(&io[latency..], &input[..300 - latency]);
+
+        // ブラーを外すとレイテンシも無くなる
+        params.spectral_blur = 0.0;
+        let mut io = [1.0f32; 4];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.latency(), 0);
+        assert_eq!(io, [1.0; 4]);
+    }
+
+    #[test]
+    fn zero_cross_aligns_grain_edges() {
+        let mut engine = Engine::default();
+        engine.initialize(10_000.0, 1, 64);
+        // 周期 100 サンプルの正弦波: i ≡ 0 (mod 50) の直前で符号が変わる
+        for (i, x) in engine.ring.iter_mut().enumerate() {
+            *x = (std::f32::consts::TAU * (i as f32 + 0.5) / 100.0).sin();
+        }
+        assert_eq!(
+            zero_crossing_offset(&engine.ring, 120, 100, 100, true),
+            Some(-20)
+        );
+        assert_eq!(
+            zero_crossing_offset(&engine.ring, 140, 100, 100, true),
+            Some(10)
+        );
+        assert_eq!(
+            zero_crossing_offset(&engine.ring, 140, 100, 5, true),
+            Some(-40)
+        );
+        assert_eq!(zero_crossing_offset(&engine.ring, 140, 5, 5, true), None);
+
+        engine.frame.zero_cross = true;
+        let mut rng = SmallRng::seed_from_u64(1);
+        for _ in 0..50 {
+            engine.spawn_grain(&mut rng, 1_000, 2_000, 1, 0, 1.0);
+            let (start, len) = engine.grains[0].ring.unwrap();
+            assert_eq!((start % 50, (start + len) % 50), (0, 0), "{start} {len}");
+            engine.clear_grains();
+        }
+    }
+
+    #[test]
+    fn pitch_walk_drifts_in_bounded_steps() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut p = FrameParams {
+            pitch_spread: 5.0,
+            pitch_scatter: PitchScatter::Walk,
+            pitch_step: 0.5,
+            ..FrameParams::default()
+        };
+        let walk: Vec<f32> = (0..2_000)
+            .map(|_| engine.scatter_st(&p, &mut rng))
+            .collect();
+        assert!(walk.iter().all(|st| st.abs() <= 5.0));
+        assert!(walk.windows(2).all(|w| (w[1] - w[0]).abs() <= 0.5 + 1e-5));
+        // 折り返しながら幅いっぱいまで漂う
+        assert!(walk.iter().any(|&st| st > 3.0) && walk.iter().any(|&st| st < -3.0));
+
+        // Independent では隣り合うグレインも幅の中でばらばら
+        p.pitch_scatter = PitchScatter::Independent;
+        let scatter: Vec<f32> = (0..200).map(|_| engine.scatter_st(&p, &mut rng)).collect();
+        assert!(scatter.windows(2).any(|w| (w[1] - w[0]).abs() > 2.0));
+
+        // 幅 0 では移調しない
+        p.pitch_spread = 0.0;
+        assert_eq!(engine.scatter_st(&p, &mut rng), 0.0);
+    }
+
+    #[test]
+    fn chord_stacks_transposed_copies_of_one_slice() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, x) in engine.ring.iter_mut().enumerate() {
+            *x = i as f32;
+        }
+        engine.frame.chord = Chord::Power;
+        let mut rng = SmallRng::seed_from_u64(1);
+        engine.spawn_grain(&mut rng, 100, 100, 1, 0, 1.0);
+        assert_eq!(engine.grains.len(), 3);
+        assert!(engine.grains.iter().all(|g| g.len() == 100));
+
+        // 窓の平らな中央で読み位置を比べると、どの声部も同じ位置から音程の比で進んでいる
+        let k = 50.0;
+        let mid: Vec<f32> = engine
+            .grains
+            .iter()
+            .map(|g| g.samples(&engine.ring)[k as usize])
+            .collect();
+        let start = mid[0] - k;
+        assert!((mid[1] - (start + k * (7.0f32 / 12.0).exp2())).abs() < 1e-2);
+        assert!((mid[2] - (start + k * 2.0)).abs() < 1e-2);
+
+        // Custom では声部ごとの音程とレベルを使い、レベル 0 の声部は作らない
+        engine.clear_grains();
+        engine.frame.chord = Chord::Custom;
+        engine.frame.chord_voices = [
+            ChordVoice {
+                interval: -12.0,
+                level: 0.5,
+            },
+            ChordVoice {
+                interval: 3.0,
+                level: 0.0,
+            },
+            ChordVoice {
+                interval: 0.0,
+                level: 1.0,
+            },
+            ChordVoice::default(),
+        ];
+        engine.spawn_grain(&mut rng, 100, 100, 1, 0, 1.0);
+        assert_eq!(engine.grains.len(), 2);
+        let mid: Vec<f32> = engine
+            .grains
+            .iter()
+            .map(|g| g.samples(&engine.ring)[k as usize])
+            .collect();
+        let start = mid[1] - k;
+        assert!((mid[0] - (start + k * 0.5)).abs() < 1e-2);
+        assert_eq!(engine.grains[0].pass_gain, 0.5);
+        assert_eq!(engine.grains[1].pass_gain, 1.0);
+    }
+
+    #[test]
+    fn sample_source_feeds_random_grains() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut params = FrameParams {
+            density: 0.0,
+            source: Source::Sample,
+            ..FrameParams::default()
+        };
+
+        // 読み込んだサンプルはチャンクの先頭で取り込まれる
+        crate::sample::install_sample(&engine.queues, vec![0.5; 500]);
+        let mut io = vec![0.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.sample.len(), 500);
+
+        let peak = |engine: &mut Engine| {
+            let peak = engine.grains[0]
+                .samples(&engine.ring)
+                .iter()
+                .fold(0.0f32, |m, x| m.max(*x));
+            engine.clear_grains();
+            peak
+        };
+        engine.ring.fill(0.1);
+        engine.spawn_grain(&mut rng, 20, 20, 1, 0, 1.0);
+        assert_eq!(peak(&mut engine), 0.5);
+        // Sync / Stretch は常にライブ入力から
+        engine.spawn_sync_grain(20, 0.0, 0, 0, 1.0);
+        assert!((peak(&mut engine) - 0.1).abs() < 1e-6);
+        engine.frame.source = Source::Live;
+        engine.spawn_grain(&mut rng, 20, 20, 1, 0, 1.0);
+        assert!((peak(&mut engine) - 0.1).abs() < 1e-6);
+
+        // 空のサンプルで外すとライブ入力へ戻り、古いバッファは読み込み側へ返る
+        crate::sample::install_sample(&engine.queues, Vec::new());
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.sample.is_empty());
+        assert_eq!(engine.queues.retired.pop().map(|s| s.len()), Some(500));
+        engine.ring.fill(0.1);
+        engine.spawn_grain(&mut rng, 20, 20, 1, 0, 1.0);
+        assert!((peak(&mut engine) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn source_blend_mixes_live_and_sample_grains() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.sample = vec![0.5; 500];
+        engine.ring.fill(0.1);
+        engine.frame.source = Source::Sample;
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut from_sample = |engine: &mut Engine, blend: f32| {
+            engine.frame.source_blend = blend;
+            let mut count = 0;
+            for _ in 0..200 {
+                engine.spawn_grain(&mut rng, 20, 20, 1, 0, 1.0);
+                count += engine
+                    .grains
+                    .iter()
+                    .filter(|g| g.buf.contains(&0.5))
+                    .count();
+                engine.clear_grains();
+            }
+            count
+        };
+        assert_eq!(from_sample(&mut engine, 0.0), 0);
+        assert_eq!(from_sample(&mut engine, 1.0), 200);
+        let half = from_sample(&mut engine, 0.5);
+        assert!((70..130).contains(&half), "{half}");
+    }
+
+    #[test]
+    fn feedback_writes_wet_into_ring() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 2, 64);
+        for ch in 0..2 {
+            engine.grains.push(Grain {
+                buf: vec![0.5; 8],
+                pos: 0,
+                ch,
+                offset: 0,
+                ..Grain::default()
+            });
+        }
+        let mut params = FrameParams {
+            density: 0.0,
+            feedback: 0.5,
+            ..FrameParams::default()
+        };
+        // 帰還はチャンクの終わりにまとめてリングへ戻す
+        let mut l = [0.0f32; CHUNK_SIZE];
+        let mut r = [0.0f32; CHUNK_SIZE];
+        engine.process(
+            &mut [&mut l[..4], &mut r[..4]],
+            &mut params,
+            &mut rand::rng(),
+        );
+        assert!(engine.ring.iter().all(|&v| v == 0.0));
+        engine.process(
+            &mut [&mut l[4..], &mut r[4..]],
+            &mut params,
+            &mut rand::rng(),
+        );
+
+        // 入力は無音なので、リングにはウェットの和 (0.5 + 0.5) × feedback だけが入る
+        let expected = 0.5f32.tanh();
+        for v in &engine.ring[..8] {
+            assert!((v - expected).abs() < 1e-6, "{v}");
+        }
+        assert_eq!(engine.ring[8], 0.0);
+    }
+
+    #[test]
+    fn reroll_bars_repeats_the_texture_every_bar() {
+        // 1 kHz・120 BPM・4/4 → 1 小節 2000 サンプル。ブロックの終わりごとに鳴っているグレインを記録する
+        let render = |reroll_bars| {
+            let mut engine = Engine::default();
+            engine.initialize(1_000.0, 1, 100);
+            engine.set_reroll_seed(9);
+            engine.set_time_signature(Some(4), Some(4));
+            let mut params = FrameParams {
+                density: 0.8,
+                min_ms: 20.0,
+                max_ms: 200.0,
+                mix: 1.0,
+                reroll_bars,
+                ..FrameParams::default()
+            };
+            let mut rng = SmallRng::seed_from_u64(1);
+            let mut bars = vec![Vec::new(); 6];
+            for k in 0..120 {
+                engine.set_transport(Some(120.0), Some(k * 100), true);
+                let mut io: Vec<f32> = (0..100).map(|i| (i as f32 * 0.314).sin()).collect();
+                engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+                let grains = engine.grains.iter().map(|g| (g.len(), g.pos, g.ch));
+                bars[k as usize / 20].push(grains.collect::<Vec<_>>());
+            }
+            bars
+        };
+        // 前の小節から鳴り続けるグレインも揃う 5 小節目と 6 小節目を比べる
+        let bars = render(1);
+        assert!(bars[4].iter().any(|g| !g.is_empty()));
+        assert_eq!(bars[4], bars[5]);
+        let bars = render(0);
+        assert_ne!(bars[4], bars[5]);
+    }
+
+    #[test]
+    fn tempo_grid_applies_swing() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        // 120 BPM, 1/16 → 1 ステップ 125 サンプル
+        engine.set_transport(Some(120.0), Some(0), true);
+        let onsets: Vec<i64> = (0..500)
+            .filter(|&t| engine.on_grid(t, NoteDivision::Sixteenth, 0.0))
+            .collect();
+        assert_eq!(onsets, vec![0, 125, 250, 375]);
+
+        // swing 1.0 では裏のステップが半ステップ (62.5 サンプル) 遅れる
+        let onsets: Vec<i64> = (0..500)
+            .filter(|&t| engine.on_grid(t, NoteDivision::Sixteenth, 1.0))
+            .collect();
+        assert_eq!(onsets, vec![0, 188, 250, 438]);
+
+        // density 1.0 ならグリッドごとに 1 グレイン。位置はホストから与えられる
+        let mut params = FrameParams {
+            mode: TriggerMode::Tempo,
+            density: 1.0,
+            min_ms: 10.0,
+            max_ms: 10.0,
+            ..FrameParams::default()
+        };
+        engine.set_transport(None, Some(120), true);
+        let mut io = [0.0f32; 10];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.active_grains(), 1);
+        assert_eq!(engine.clock, 130);
+    }
+
+    #[test]
+    fn sequencer_plays_enabled_steps_with_their_length_and_pitch() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, x) in engine.ring.iter_mut().enumerate() {
+            *x = i as f32;
+        }
+        let mut pattern = default_pattern();
+        pattern[1] = (false, 1.0, 0.0, 1.0);
+        pattern[2] = (true, 0.5, 12.0, 1.0);
+        engine.set_sequence(Sequence::from_saved(&pattern));
+        let mut params = FrameParams {
+            mode: TriggerMode::Sequencer,
+            min_ms: 100.0,
+            max_ms: 100.0,
+            ..FrameParams::default()
+        };
+        // 120 BPM, 1/16 → 1 ステップ 125 サンプル。各ステップの頭を 10 サンプルずつ処理する
+        // (入力はリングと同じ傾きで書き続ける)
+        let mut rng = SmallRng::seed_from_u64(1);
+        for (k, wr) in [0usize, 10, 20].into_iter().enumerate() {
+            engine.set_transport(Some(120.0), Some(125 * k as i64), true);
+            let mut io: Vec<f32> = (wr..wr + 10).map(|i| i as f32).collect();
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        }
+
+        // ステップ 2 (オフ) は鳴らさず、ステップ 3 は半分の長さで 1 オクターブ上
+        let lens: Vec<usize> = engine.grains.iter().map(|g| g.len()).collect();
+        assert_eq!(lens, vec![100, 50]);
+        let buf = &engine.grains[1].buf;
+        assert_eq!(buf[26] - buf[25], 2.0);
+    }
+
+    #[test]
+    fn step_probability_thins_the_pattern_reproducibly() {
+        // 全ステップ 70% のパターンを 64 ステップ分鳴らし、鳴ったステップを返す
+        let run = |seed: u64| -> Vec<bool> {
+            let mut engine = Engine::default();
+            engine.initialize(1_000.0, 1, 64);
+            let mut pattern = default_pattern();
+            for step in &mut pattern {
+                step.3 = 0.7;
+            }
+            engine.set_sequence(Sequence::from_saved(&pattern));
+            let mut params = FrameParams {
+                mode: TriggerMode::Sequencer,
+                min_ms: 5.0,
+                max_ms: 5.0,
+                ..FrameParams::default()
+            };
+            let mut rng = SmallRng::seed_from_u64(seed);
+            (0..64)
+                .map(|k| {
+                    engine.set_transport(Some(120.0), Some(125 * k), true);
+                    let mut io = [0.0f32; 2];
+                    engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+                    let hit = engine.active_grains() > 0;
+                    // 次のステップまでにグレインを鳴らし切る
+                    let mut io = [0.0f32; 10];
+                    engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+                    hit
+                })
+                .collect()
+        };
+        let hits = run(7);
+        let n = hits.iter().filter(|&&h| h).count();
+        assert!((35..=55).contains(&n), "{n}");
+        // 同じシードなら同じステップが鳴る
+        assert_eq!(run(7), hits);
+    }
+
+    #[test]
+    fn euclidean_mode_plays_the_pattern_on_the_grid() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            mode: TriggerMode::Euclid,
+            euclid_steps: 8,
+            euclid_pulses: 3,
+            min_ms: 100.0,
+            max_ms: 100.0,
+            ..FrameParams::default()
+        };
+        // 120 BPM, 1/16 → 1 ステップ 125 サンプル。各ステップの頭を 10 サンプルずつ処理し、
+        // グレインが増えたステップを数える (グレインは 8 ステップ分の処理では鳴り終わらない)
+        let mut rng = SmallRng::seed_from_u64(1);
+        let hits: Vec<bool> = (0..8)
+            .map(|k| {
+                let before = engine.active_grains();
+                engine.set_transport(Some(120.0), Some(125 * k), true);
+                let mut io = [0.0f32; 10];
+                engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+                engine.active_grains() > before
+            })
+            .collect();
+        assert_eq!(hits, [true, false, false, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn transport_gate_pauses_generation() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            mode: TriggerMode::Sync,
+            gate: true,
+            gate_write: false,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+
+        // 停止中はグレインを生成せず、gate_write が無ければリングも止まる
+        engine.set_transport(None, None, false);
+        let mut io = [1.0f32; 32];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 0);
+        assert_eq!(engine.wr, 0);
+
+        // gate_write があればリングへの書き込みは続ける
+        params.gate_write = true;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 0);
+        assert_eq!(engine.wr, 32);
+
+        // 再生が始まれば生成を再開する
+        engine.set_transport(None, None, true);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 1);
+    }
+
+    #[test]
+    fn note_gate_generates_only_while_notes_are_held() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.25,
+            min_ms: 5.0,
+            max_ms: 5.0,
+            note_gate: true,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut spawned = |engine: &mut Engine, rng: &mut SmallRng| {
+            let mut total = 0;
+            for _ in 0..100 {
+                let mut io = [1.0f32; 64];
+                engine.process(&mut [&mut io[..]], &mut params, rng);
+                total += engine.spawn_events().len();
+            }
+            total
+        };
+
+        // ノートが無い間は生成しないが、リングへの書き込みは続ける
+        assert_eq!(spawned(&mut engine, &mut rng), 0);
+        assert_eq!(engine.wr, 100 * 64);
+
+        // 押さえているノートが増えるほど濃くなる
+        engine.set_held_notes(1);
+        let one = spawned(&mut engine, &mut rng);
+        engine.set_held_notes(3);
+        let three = spawned(&mut engine, &mut rng);
+        assert!(one > 0);
+        assert!(
+            three > 2 * one,
+            "{one} grains for one note, {three} for three"
+        );
+    }
+
+    #[test]
+    fn key_track_transposes_new_grains_by_the_held_note() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            mode: TriggerMode::Sync,
+            min_ms: 10.0,
+            max_ms: 10.0,
+            key_track: true,
+            key_root: 60,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut rates = |engine: &mut Engine, note| {
+            engine.set_key_note(note);
+            let mut io = [0.5f32; 16];
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            engine
+                .spawn_events()
+                .iter()
+                .map(|e| e.rate)
+                .collect::<Vec<_>>()
+        };
+
+        // ノートが無ければ移調せず、ルートから 1 オクターブ上なら 2 倍、5 半音下なら 2^(-5/12) 倍
+        assert!(rates(&mut engine, None).iter().all(|r| *r == 1.0));
+        assert!(rates(&mut engine, Some(72)).iter().all(|r| *r == 2.0));
+        let down = (-5.0f32 / 12.0).exp2();
+        assert!(rates(&mut engine, Some(55))
+            .iter()
+            .all(|r| (r - down).abs() < 1e-6));
+    }
+
+    #[test]
+    fn note_expression_shapes_grains_spawned_by_notes() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.ring.fill(0.3);
+        let mut rng = SmallRng::seed_from_u64(1);
+        engine.set_key_note(Some(60));
+        engine.set_note_expression(NoteExpression {
+            gain: 0.5,
+            tilt_db: 6.0,
+            bend_st: 12.0,
+            ..NoteExpression::default()
+        });
+
+        // ノートゲートでは押さえているノートの圧力をゲインに、明るさをティルトに、ピッチベンドを移調にする
+        let p = FrameParams {
+            note_gate: true,
+            grain_tilt_db: -2.0,
+            ..FrameParams::default()
+        };
+        engine.frame = p;
+        assert_eq!(engine.grain_rate(&p, &mut rng), 2.0);
+        assert_eq!(engine.next_tilt, 4.0);
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains[0].pass_gain, 0.5);
+        assert!(engine.grains[0].ring.is_none());
+        engine.clear_grains();
+
+        // ノートでグレインを鳴らしていなければかけない
+        let p = FrameParams::default();
+        engine.frame = p;
+        assert_eq!(engine.grain_rate(&p, &mut rng), 1.0);
+        assert_eq!(engine.next_tilt, 0.0);
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains[0].pass_gain, 1.0);
+    }
+
+    #[test]
+    fn pitch_bend_repitches_playing_note_grains_and_pressure_scales_density() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.ring.fill(0.3);
+        engine.set_key_note(Some(60));
+        engine.set_note_expression(NoteExpression {
+            channel: Some(2),
+            bend_st: 1.0,
+            ..NoteExpression::default()
+        });
+        engine.frame = FrameParams {
+            note_gate: true,
+            ..FrameParams::default()
+        };
+
+        // ノートが鳴らしたグレインはコピーして、チャンネルと生成時のベンドを覚えておく
+        engine.spawn_sync_grain(200, 0.0, 0, 0, 1.0);
+        assert!(engine.grains[0].ring.is_none());
+        let spawned = NoteBend {
+            channel: 2,
+            spawn_st: 1.0,
+        };
+        assert_eq!(engine.grains[0].bend, Some(spawned));
+
+        // チャンネルのベンドが 1 オクターブ上がると、鳴っているグレインを倍速で補間しながら読む
+        let mut bends = [0.0; 16];
+        bends[2] = 13.0;
+        engine.set_note_bends(bends);
+        engine.bend_grains();
+        assert_eq!(engine.grains[0].rate, 2.0);
+        let mut out = vec![0.0f32; 10];
+        assert_eq!(engine.grains[0].render(&mut out, &engine.ring), 10);
+        assert_eq!(engine.grains[0].pos, 20);
+        assert!(out.iter().all(|x| x.is_finite()));
+        engine.clear_grains();
+
+        // チャンネルプレッシャーが 0 ならノートを押さえていてもグレインを生成しない
+        engine.set_held_notes(1);
+        let mut params = FrameParams {
+            note_gate: true,
+            density: 1.0,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(3);
+        for (density, spawns) in [(0.0, false), (1.0, true)] {
+            engine.set_note_expression(NoteExpression {
+                density,
+                ..NoteExpression::default()
+            });
+            let before = engine.spawned;
+            let mut io = vec![0.3f32; 1_000];
+            engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+            assert_eq!(engine.spawned > before, spawns);
+        }
+    }
+
+    #[test]
+    fn ring_mode_records_overdubs_and_holds() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            overdub: 0.5,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+        let len = engine.ring.len();
+        let mut io = vec![1.0f32; len];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.ring.iter().all(|x| *x == 1.0));
+
+        // Overdub は既存の内容を overdub 倍して足す
+        params.ring_mode = RingMode::Overdub;
+        let mut io = vec![0.25f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.ring[..64].iter().all(|x| *x == 0.75));
+        assert_eq!(engine.ring[64], 1.0);
+
+        // Play は書き込み位置も止める (リングはループにするため回す)
+        params.ring_mode = RingMode::Play;
+        io.fill(0.25);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        let wr = engine.wr;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.wr, wr);
+        assert!(engine.ring.iter().all(|x| *x != 0.25));
+
+        params.ring_mode = RingMode::Record;
+        io.fill(0.25);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!((0..64).all(|k| engine.ring[(wr + k) & engine.ring_mask] == 0.25));
+    }
+
+    #[test]
+    fn clear_empties_ring_and_fades_grains() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 1.0,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+        engine.ring.fill(0.5);
+        engine.grains.push(Grain {
+            buf: vec![1.0; 1_000],
+            ..Grain::default()
+        });
+
+        // オンになった瞬間だけ消去し、グレインは CLEAR_FADE_MS (10 サンプル) で消える
+        params.clear = true;
+        let mut io = vec![1.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.ring[64..].iter().all(|x| *x == 0.0));
+        // 消去後の入力はフェードインする
+        assert_eq!(engine.ring[0], 0.0);
+        assert!(engine.ring[..10].windows(2).all(|w| w[0] < w[1]));
+        assert!(engine.ring[10..64].iter().all(|x| *x == 1.0));
+        assert!(io[..10].iter().all(|x| *x > 0.0 && *x <= 1.0));
+        assert!(io[10..].iter().all(|x| *x == 0.0));
+        assert_eq!(engine.active_grains(), 0);
+
+        // 押し続けても再び消去はしない
+        io.fill(1.0);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.ring[10..128].iter().all(|x| *x == 1.0));
+
+        // clear_grains がオフならグレインは鳴り続ける
+        params.clear = false;
+        params.clear_grains = false;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        engine.grains.push(Grain {
+            buf: vec![1.0; 1_000],
+            ..Grain::default()
+        });
+        params.clear = true;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io.iter().all(|x| *x == 1.0));
+        assert_eq!(engine.active_grains(), 1);
+    }
+
+    #[test]
+    fn transport_stop_kills_grains_and_optionally_clears_ring() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            stop_kill: true,
+            ..FrameParams::default()
+        };
+        let mut rng = rand::rng();
+        engine.ring.fill(0.5);
+        let grain = || Grain {
+            buf: vec![1.0; 1_000],
+            ..Grain::default()
+        };
+        engine.grains.push(grain());
+        let mut io = vec![0.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 1);
+
+        // 止まった瞬間にグレインは CLEAR_FADE_MS で消え、リングは残る
+        engine.set_transport(None, None, false);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 0);
+        assert!(engine.ring[128..].iter().all(|x| *x == 0.5));
+
+        // 止まっている間に足したグレインは止めない
+        engine.grains.push(grain());
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 1);
+
+        // stop_clear ならリングも消去する
+        params.stop_clear = true;
+        engine.set_transport(None, None, true);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        engine.set_transport(None, None, false);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert_eq!(engine.active_grains(), 0);
+        assert!(engine.ring[320..].iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn store_triggers_capture_scenes_for_morphing() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut rng = rand::rng();
+        let mut io = vec![0.0f32; 64];
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.2,
+            store_a: true,
+            ..FrameParams::default()
+        };
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        params.mix = 0.6;
+        params.store_a = false;
+        params.store_b = true;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(engine.scenes_dirty());
+        assert_eq!(engine.scene(0).mix, 0.2);
+        assert_eq!(engine.scene(1).mix, 0.6);
+
+        // 押し続けても保存し直さない
+        engine.mark_scenes_saved();
+        params.mix = 1.0;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(!engine.scenes_dirty());
+        assert_eq!(engine.scene(1).mix, 0.6);
+
+        // モーフィング中はノブではなくシーンの補間で鳴る (mix=0.4 → ドライ 0.6)
+        params.scene_morph = true;
+        params.morph = 0.5;
+        io.fill(1.0);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io.iter().all(|x| (x - 0.6).abs() < 1e-6), "{io:?}");
+    }
+
+    #[test]
+    fn mod_matrix_offsets_destinations_per_chunk() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 128);
+        let mut rng = rand::rng();
+        let mut slots = [ModSlot::default(); MOD_SLOTS];
+        slots[0] = ModSlot {
+            source: ModSource::MidiCc,
+            dest: ModDest::Mix,
+            depth: -0.5,
+        };
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 1.0,
+            mod_slots: slots,
+            ..FrameParams::default()
+        };
+        // CC=1.0 で mix 1.0 → 0.5 (ドライが半分残る)
+        engine.set_mod_cc(1.0);
+        let mut io = vec![1.0f32; 128];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io.iter().all(|x| (x - 0.5).abs() < 1e-6), "{io:?}");
+
+        // ブロックの途中で CC が変わっても、反映されるのは次のチャンクから
+        let mut io = [1.0f32; 32];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        engine.set_mod_cc(0.0);
+        let mut io = vec![1.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io[..32].iter().all(|x| (x - 0.5).abs() < 1e-6), "{io:?}");
+        assert!(io[32..].iter().all(|x| x.abs() < 1e-6), "{io:?}");
+    }
+
+    /// 毎サンプル同じフレームを返すが、止まっているとは言わないパラメータ源
+    struct PerSample(FrameParams);
+
+    impl ParamSource for PerSample {
+        fn next_frame(&mut self) -> FrameParams {
+            self.0
+        }
+    }
+
+    #[test]
+    fn steady_params_are_layered_once_per_chunk_with_the_same_result() {
+        let maps: [MacroMap; MACRO_COUNT] = [
+            vec![("density".into(), 0.4), ("max_ms".into(), -0.5)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        ];
+        let mut slots = [ModSlot::default(); MOD_SLOTS];
+        slots[0] = ModSlot {
+            source: ModSource::Lfo1,
+            dest: ModDest::Density,
+            depth: 0.3,
+        };
+        let params = FrameParams {
+            density: 0.3,
+            min_ms: 5.0,
+            max_ms: 30.0,
+            mix: 0.7,
+            scene_morph: true,
+            morph: 0.25,
+            macros: [0.6, 0.0, 0.0, 0.0],
+            mod_slots: slots,
+            ..FrameParams::default()
+        };
+        let render = |steady: bool| {
+            let mut engine = Engine::default();
+            engine.initialize(1_000.0, 1, 512);
+            engine.set_macros(Macros::from_maps(&maps));
+            engine.set_scene(
+                1,
+                FrameParams {
+                    density: 0.9,
+                    ..params
+                },
+            );
+            let mut rng = SmallRng::seed_from_u64(5);
+            let mut fixed = params;
+            let mut per_sample = PerSample(params);
+            let mut out = Vec::new();
+            for block in 0..8 {
+                let mut io: Vec<f32> = (0..512)
+                    .map(|i| ((block * 512 + i) as f32 * 0.05).sin())
+                    .collect();
+                if steady {
+                    engine.process(&mut [&mut io[..]], &mut fixed, &mut rng);
+                } else {
+                    engine.process(&mut [&mut io[..]], &mut per_sample, &mut rng);
+                }
+                out.extend(io);
+            }
+            out
+        };
+        let (steady, per_sample) = (render(true), render(false));
+        assert!(steady.iter().any(|x| x.abs() > 1e-3));
+        assert_eq!(steady, per_sample);
+    }
+
+    #[test]
+    fn audition_fades_mix_to_wet_and_back() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut rng = rand::rng();
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.25,
+            audition: true,
+            ..FrameParams::default()
+        };
+        // ウェットが無音なので出力はドライ × (1 - 実際の mix)。30 サンプルかけて 0.75 → 0 になる
+        let mut io = [1.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io[..29].windows(2).all(|w| w[1] < w[0]), "{io:?}");
+        assert!(io[29..].iter().all(|x| x.abs() < 1e-6), "{io:?}");
+
+        // オフにすると同じ時間で元の mix へ戻る
+        params.audition = false;
+        let mut io = [1.0f32; 64];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io[29..].iter().all(|x| (x - 0.75).abs() < 1e-6), "{io:?}");
+    }
+
+    #[test]
+    fn delta_outputs_what_the_grains_add() {
+        // 同じシードで delta のオン / オフを鳴らし、差分 = ミックス後 - ドライ になることを確かめる
+        let input: Vec<f32> = (0..128).map(|i| (i as f32 * 0.1).sin()).collect();
+        let render = |delta| {
+            let mut engine = Engine::default();
+            engine.initialize(1_000.0, 1, 128);
+            let mut params = FrameParams {
+                density: 1.0,
+                min_ms: 20.0,
+                max_ms: 40.0,
+                mix: 0.3,
+                delta,
+                ..FrameParams::default()
+            };
+            let mut rng = SmallRng::seed_from_u64(2);
+            let mut out = Vec::new();
+            for _ in 0..8 {
+                let mut io = input.clone();
+                engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+                out.extend(io);
+            }
+            out
+        };
+        let (mixed, delta) = (render(false), render(true));
+        for ((m, d), x) in mixed.iter().zip(&delta).zip(input.iter().cycle()) {
+            assert!((m - x - d).abs() < 1e-5, "{m} - {x} != {d}");
+        }
+        assert!(delta.iter().any(|d| d.abs() > 1e-3));
+    }
+
+    #[test]
+    fn multiband_keeps_the_bands_left_dry_out_of_the_ring() {
+        // 50 Hz の正弦波を 1 kHz で分け、Low を素通しにする。mix 100% でグレインが無くても Low はそのまま出る
+        let render = |bands| {
+            let mut engine = Engine::default();
+            engine.initialize(48_000.0, 1, 4_800);
+            let mut params = FrameParams {
+                density: 0.0,
+                mix: 1.0,
+                bands,
+                crossover_low_hz: 1_000.0,
+                ..FrameParams::default()
+            };
+            let mut io: Vec<f32> = (0..4_800)
+                .map(|i| 0.5 * (std::f32::consts::TAU * 50.0 * i as f32 / 48_000.0).sin())
+                .collect();
+            engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+            let ring = engine.ring.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            let out = io[2_400..].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            (ring, out)
+        };
+        let (ring, out) = render(Bands::Two);
+        assert!(ring < 0.01, "{ring}");
+        assert!((out - 0.5).abs() < 0.01, "{out}");
+        // Off ならすべてリングへ書き、mix 100% ではドライが残らない
+        let (ring, out) = render(Bands::Off);
+        assert!(ring > 0.49, "{ring}");
+        assert!(out < 1e-6, "{out}");
+    }
+
+    #[test]
+    fn multiband_density_and_mix_are_per_band() {
+        let sine = |hz: f32| -> Vec<f32> {
+            (0..9_600)
+                .map(|i| 0.5 * (std::f32::consts::TAU * hz * i as f32 / 48_000.0).sin())
+                .collect()
+        };
+        // Mid の density が 0 なら、グレインは High の帯域からだけ出る
+        let mut engine = Engine::default();
+        engine.initialize(48_000.0, 1, 9_600);
+        let mut params = FrameParams {
+            density: 1.0,
+            bands: Bands::Three,
+            band_density: [1.0, 0.0, 1.0],
+            ..FrameParams::default()
+        };
+        let mut io = sine(1_000.0);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert!(!engine.grains.is_empty());
+        assert!(engine.grains.iter().all(|g| g.band == Some(2)));
+
+        // High の mix の倍率が 0 なら、グレインが無くても High のドライは mix 100% で残る
+        let render = |band_mix| {
+            let mut engine = Engine::default();
+            engine.initialize(48_000.0, 1, 9_600);
+            let mut params = FrameParams {
+                density: 0.0,
+                mix: 1.0,
+                bands: Bands::Three,
+                band_mix,
+                ..FrameParams::default()
+            };
+            let mut io = sine(8_000.0);
+            engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+            io[4_800..].iter().fold(0.0f32, |m, x| m.max(x.abs()))
+        };
+        let out = render([1.0, 1.0, 0.0]);
+        assert!((out - 0.5).abs() < 0.02, "{out}");
+        let out = render([1.0, 1.0, 1.0]);
+        assert!(out < 0.02, "{out}");
+    }
+
+    #[test]
+    fn mod_input_drives_destinations_and_is_silent_when_not_set() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 128);
+        let mut rng = rand::rng();
+        let mut slots = [ModSlot::default(); MOD_SLOTS];
+        slots[0] = ModSlot {
+            source: ModSource::ModIn,
+            dest: ModDest::Mix,
+            depth: 1.0,
+        };
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.0,
+            mod_slots: slots,
+            ..FrameParams::default()
+        };
+        // 各チャンクの先頭の値で mix を動かす: 0.5 → ドライが半分、-1.0 → mix 0 のまま
+        let mut mod_in = vec![0.5f32; 128];
+        mod_in[64..].fill(-1.0);
+        engine.set_mod_input(&mod_in);
+        let mut io = vec![1.0f32; 128];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io[..64].iter().all(|x| (x - 0.5).abs() < 1e-6), "{io:?}");
+        assert!(io[64..].iter().all(|x| (x - 1.0).abs() < 1e-6), "{io:?}");
+
+        // 設定しなかったブロックは無音の入力として扱う
+        engine.set_mod_input(&[0.5; 128]);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        io.fill(1.0);
+        engine.process(&mut [&mut io[..]], &mut params, &mut rng);
+        assert!(io.iter().all(|x| (x - 1.0).abs() < 1e-6), "{io:?}");
+    }
+
+    #[test]
+    fn routed_grains_play_on_aux_buses_only() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 2, 64);
+        engine.set_grain_buses(2);
+        let mut rng = rand::rng();
+
+        // 使うバスの数は接続されているバスまでに抑え、順番に振り分ける
+        let p = FrameParams {
+            routing: GrainRouting::RoundRobin,
+            ..FrameParams::default()
+        };
+        let buses: Vec<usize> = (0..4)
+            .map(|_| {
+                engine.grain_rate(&p, &mut rng);
+                engine.next_bus
+            })
+            .collect();
+        assert_eq!(buses, vec![1, 2, 1, 2]);
+        let p = FrameParams {
+            routing: GrainRouting::Random,
+            routing_buses: 1,
+            ..FrameParams::default()
+        };
+        engine.grain_rate(&p, &mut rng);
+        assert_eq!(engine.next_bus, 1);
+
+        for (ch, bus) in [(0, 0), (1, 2)] {
+            engine.grains.push(Grain {
+                buf: vec![1.0; 8],
+                ch,
+                bus,
+                ..Grain::default()
+            });
+        }
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut left = [0.0f32; 16];
+        let mut right = [0.0f32; 16];
+        engine.process(&mut [&mut left[..], &mut right[..]], &mut params, &mut rng);
+        assert_eq!(left[..8], [1.0; 8]);
+        assert!(right.iter().all(|x| *x == 0.0));
+        assert_eq!(engine.grain_bus(1, 1, 16)[..8], [1.0; 8]);
+        assert!(engine.grain_bus(0, 1, 16).iter().all(|x| *x == 0.0));
+        assert!(engine.grain_bus(1, 0, 16).iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn normalize_scales_wet_by_inverse_sqrt_of_voices() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 512);
+        for _ in 0..4 {
+            engine.grains.push(Grain {
+                buf: vec![1.0; 1_000],
+                ..Grain::default()
+            });
+        }
+        let mut params = FrameParams {
+            density: 0.0,
+            normalize: true,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 512];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        // 4 グレインの和 4.0 が 1/√4 倍の 2.0 へ滑らかに近づく
+        assert!(io[0] > 3.9);
+        assert!(io.windows(2).all(|w| w[1] <= w[0]));
+        assert!((io[511] - 2.0).abs() < 1e-3, "{}", io[511]);
+    }
+
+    #[test]
+    fn pre_delay_shifts_wet_bus() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.grains.push(Grain {
+            buf: vec![1.0; 4],
+            pos: 0,
+            ch: 0,
+            offset: 0,
+            ..Grain::default()
+        });
+        let mut params = FrameParams {
+            density: 0.0,
+            pre_delay_ms: 10.0,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 20];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+
+        // 10ms = 10 サンプル遅れてウェットが出てくる
+        let mut expected = [0.0f32; 20];
+        expected[10..14].fill(1.0);
+        assert_eq!(io, expected);
+    }
+
+    #[test]
+    fn quality_switch_overrides_interpolation_voices_and_oversampling() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            quality: Quality::Eco,
+            interpolation: Interpolation::Sinc,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 4];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.frame.interpolation, Interpolation::Linear);
+        assert_eq!(engine.frame.oversampling, Oversampling::X1);
+        // Eco では ECO_GRAINS 声を超えると古いグレインから奪う
+        for _ in 0..MAX_GRAINS {
+            engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        }
+        let live = engine.grains.iter().filter(|g| !g.releasing()).count();
+        assert_eq!(live, ECO_GRAINS as usize);
+
+        params.quality = Quality::High;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.frame.interpolation, Interpolation::Sinc);
+        assert_eq!(engine.frame.max_grains, MAX_GRAINS as i32);
+        assert_eq!(engine.frame.oversampling, Oversampling::X4);
+
+        // Custom なら個別の設定のまま
+        params.quality = Quality::Custom;
+        params.max_grains = 3;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.frame.interpolation, Interpolation::Sinc);
+        assert_eq!(engine.frame.max_grains, 3);
+        assert_eq!(engine.frame.oversampling, Oversampling::X1);
+    }
+
+    #[test]
+    fn offline_renders_at_high_quality_with_a_larger_pool() {
+        let mut engine = Engine::default();
+        engine.set_offline(true);
+        engine.initialize(1_000.0, 1, 64);
+        assert_eq!(engine.queues.pool.len(), GRAIN_POOL_SIZE);
+        let mut params = FrameParams {
+            density: 0.0,
+            quality: Quality::Eco,
+            guard: true,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 4];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.frame.interpolation, Interpolation::Sinc);
+        assert_eq!(engine.frame.oversampling, Oversampling::X4);
+        assert!(!engine.frame.guard);
+        // リアルタイムの上限を超えてもグレインを奪わない
+        for _ in 0..MAX_OFFLINE_GRAINS {
+            engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        }
+        assert_eq!(engine.active_grains(), MAX_OFFLINE_GRAINS);
+        assert!(engine.grains.iter().all(|g| !g.releasing()));
+    }
+
+    #[test]
+    fn offline_parallel_render_matches_serial() {
+        let params = FrameParams {
+            density: 0.0,
+            mix: 1.0,
+            ..FrameParams::default()
+        };
+        let render = |parallel: bool| {
+            let mut engine = Engine::default();
+            engine.set_offline(true);
+            engine.initialize(8_000.0, 2, 256);
+            if !parallel {
+                engine.workers.clear();
+            }
+            let mut rng = SmallRng::seed_from_u64(5);
+            let mut p = params;
+            let mut l: Vec<f32> = (0..2_048).map(|i| (i as f32 * 0.05).sin()).collect();
+            let mut r: Vec<f32> = (0..2_048).map(|i| (i as f32 * 0.03).cos()).collect();
+            engine.process(&mut [&mut l[..], &mut r[..]], &mut p, &mut rng);
+            for k in 0..PARALLEL_MIN_GRAINS * 2 {
+                engine.spawn_sync_grain(200 + k * 7, 0.0, k % 2, k, 1.0);
+            }
+            assert!(engine.grains.len() >= PARALLEL_MIN_GRAINS);
+            let mut out = vec![vec![0.0f32; 256]; 2];
+            let (a, b) = out.split_at_mut(1);
+            engine.process(&mut [&mut a[0][..], &mut b[0][..]], &mut p, &mut rng);
+            out
+        };
+        let (par, ser) = (render(true), render(false));
+        assert!(par[0].iter().any(|x| x.abs() > 0.01));
+        for (a, b) in par.iter().flatten().zip(ser.iter().flatten()) {
+            assert!((a - b).abs() < 1e-4, "{a} vs {b}");
+        }
+    }
+
+    // debug-dump はグレインのコピーのために確保を許しているので対象外
+    #[cfg(not(feature = "debug-dump"))]
+    #[test]
+    fn process_does_not_allocate_after_initialize() {
+        let base = FrameParams {
+            density: 1.0,
+            mix: 0.5,
+            feedback: 0.3,
+            ..FrameParams::default()
+        };
+        let scenarios = [
+            base,
+            FrameParams {
+                mode: TriggerMode::Sync,
+                ..base
+            },
+            FrameParams {
+                mode: TriggerMode::Stretch,
+                ..base
+            },
+            FrameParams {
+                mode: TriggerMode::Tempo,
+                ..base
+            },
+            FrameParams {
+                mode: TriggerMode::Sequencer,
+                ..base
+            },
+            FrameParams {
+                mode: TriggerMode::Euclid,
+                ..base
+            },
+            FrameParams {
+                quality: Quality::High,
+                pitch_spread: 12.0,
+                glide: 5.0,
+                pingpong: 0.5,
+                zero_cross: true,
+                ..base
+            },
+            FrameParams {
+                pitch_mode: PitchMode::Formant,
+                pitch_spread: 7.0,
+                ..base
+            },
+            FrameParams {
+                chord: Chord::Major,
+                shimmer: Shimmer::Octave,
+                window: WindowShape::Adsr,
+                repeats: 3,
+                ..base
+            },
+            FrameParams {
+                grain_start: GrainStart::Onset,
+                routing: GrainRouting::RoundRobin,
+                normalize: true,
+                ..base
+            },
+            FrameParams {
+                grain_start: GrainStart::Loudness,
+                freeze: true,
+                spectral_blur: 0.5,
+                ..base
+            },
+            FrameParams {
+                grain_start: GrainStart::Beat,
+                ring_mode: RingMode::Play,
+                guard: true,
+                voice_budget: 2,
+                ..base
+            },
+        ];
+        for (k, scenario) in scenarios.into_iter().enumerate() {
+            let mut engine = Engine::default();
+            engine.initialize(8_000.0, 2, 256);
+            engine.set_grain_buses(GRAIN_BUSES);
+            // 録音のブロックも initialize で確保しておくので、録音中でも確保しない
+            engine.set_recording(true);
+            let mut rng = SmallRng::seed_from_u64(k as u64);
+            let mut left: Vec<f32> = (0..256)
+                .map(|i| ((i * 37 % 101) as f32 / 50.0) - 1.0)
+                .collect();
+            let mut right = left.clone();
+            let mut params = scenario;
+            let count = crate::alloc_check::allocations(|| {
+                for block in 0..64 {
+                    // 取り込みとクリアのトリガーも通る
+                    params.capture[0] = block == 20;
+                    params.freeze_slot = i32::from(block > 30);
+                    params.clear = block == 50;
+                    let n = 64 + block % 3 * 96;
+                    engine.process(
+                        &mut [&mut left[..n], &mut right[..n]],
+                        &mut params,
+                        &mut rng,
+                    );
+                    for _ in engine.drain_pending() {}
+                }
+            });
+            assert_eq!(count, 0, "scenario {k} allocated {count} times");
+        }
+    }
+
+    /// 不変条件を壊して確かめるための、グレインを 1 つ鳴らしているエンジン
+    #[cfg(debug_assertions)]
+    fn engine_with_a_grain() -> Engine {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut rng = SmallRng::seed_from_u64(1);
+        engine.spawn_grain(&mut rng, 32, 32, 1, 0, 1.0);
+        assert_eq!(engine.grains.len(), 1);
+        engine.check_invariants(1, 0, 64);
+        engine
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "exceed the pool")]
+    fn invariants_catch_too_many_grains() {
+        let mut engine = engine_with_a_grain();
+        for _ in 0..engine.pool_size() {
+            engine.grains.push(Grain::default());
+        }
+        engine.check_invariants(1, 0, 64);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "past its length")]
+    fn invariants_catch_positions_past_the_buffer() {
+        let mut engine = engine_with_a_grain();
+        engine.grains[0].pos = engine.grains[0].len() + 1;
+        engine.check_invariants(1, 0, 64);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "outside the ring")]
+    fn invariants_catch_a_write_head_outside_the_ring() {
+        let mut engine = engine_with_a_grain();
+        engine.wr = engine.ring.len();
+        engine.check_invariants(1, 0, 64);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "non-finite sample")]
+    fn invariants_catch_nan_in_the_wet_sum() {
+        let mut engine = engine_with_a_grain();
+        engine.wet[0][10] = f32::NAN;
+        engine.check_invariants(1, 0, 64);
+    }
+
+    #[test]
+    fn catch_panic_returns_the_message_instead_of_unwinding() {
+        assert_eq!(catch_panic(|| 3), Ok(3));
+        assert_eq!(
+            catch_panic(|| -> i32 { panic!("boom") }),
+            Err("boom".to_string())
+        );
+        let i = 7;
+        assert_eq!(
+            catch_panic(|| -> i32 { panic!("index {i} out of range") }),
+            Err("index 7 out of range".to_string())
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn panic_in_process_mutes_the_block_and_resets_the_engine() {
+        let mut engine = engine_with_a_grain();
+        // 再生位置が長さを超えた壊れたグレインで process の中をパニックさせる
+        engine.grains[0].pos = engine.grains[0].len() + 1;
+        let mut params = FrameParams {
+            mix: 0.5,
+            ..FrameParams::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut block = vec![0.5f32; 64];
+        let result = engine.process_guarded(&mut [block.as_mut_slice()], &mut params, &mut rng);
+        assert!(result.is_err());
+        assert!(block.iter().all(|&x| x == 0.0));
+        assert_eq!(engine.active_grains(), 0);
+        assert!(engine.ring.iter().all(|&x| x == 0.0));
+
+        // 初期状態へ戻ったエンジンは次のブロックから普通に処理できる
+        let mut block = vec![0.5f32; 64];
+        assert!(engine
+            .process_guarded(&mut [block.as_mut_slice()], &mut params, &mut rng)
+            .is_ok());
+        assert!(block.iter().any(|&x| x != 0.0));
+    }
+
+    #[test]
+    fn full_pool_steals_oldest_grain_with_fade() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for _ in 0..MAX_GRAINS {
+            engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        }
+        assert_eq!(engine.active_grains(), MAX_GRAINS);
+
+        // 上限時の新しいトリガーは拒否されず、最も古いグレインがフェードアウトに入る
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.active_grains(), MAX_GRAINS + 1);
+        assert!(engine.grains[0].releasing());
+        assert!(engine.grains[1..].iter().all(|g| !g.releasing()));
+
+        // STEAL_FADE_MS (5 サンプル) 後に取り除かれる
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; 5];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.active_grains(), MAX_GRAINS);
+        assert!(engine.grains.iter().all(|g| !g.releasing()));
+    }
+
+    #[test]
+    fn adsr_window_is_applied_at_read_time() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.set_background_windowing(true);
+        engine.ring.fill(1.0);
+        let mut params = FrameParams {
+            mode: TriggerMode::Sync,
+            min_ms: 10.0,
+            max_ms: 10.0,
+            overlap: Overlap::X2,
+            window: WindowShape::Adsr,
+            attack: 50.0,
+            decay: 50.0,
+            ..FrameParams::default()
+        };
+        // 入力は 0 なので最初のグレインは 9 サンプル分のリングの 1.0 と、今書いた 0 からなる
+        let mut io = [0.0f32; 5];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+
+        // バックグラウンド窓処理を経由せず、切り出し窓もかけずに即座に鳴り始める
+        assert!(engine.drain_pending().next().is_none());
+        let mut raw = vec![1.0f32; 10];
+        raw[9] = 0.0;
+        assert_eq!(engine.grains[0].samples(&engine.ring), raw);
+        assert_eq!(io, [0.0, 0.2, 0.4, 0.6, 0.8]);
+    }
+
+    #[test]
+    fn repeats_loop_grain_with_decreasing_gain() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            repeats: 3,
+            repeat_decay: 0.5,
+            ..FrameParams::default()
+        };
+        // frame を設定するために 1 サンプル処理してからグレインを作る
+        let mut io = [0.0f32; 1];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        engine.spawn_sync_grain(4, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains[0].repeats_left, 2);
+        // 窓の影響を除いてパスごとのゲインだけを見るため、1.0 のバッファを読むグレインに置き換える
+        let len = engine.grains[0].len();
+        engine.grains[0].ring = None;
+        engine.grains[0].buf = vec![1.0; len];
+
+        // 3 パス × len サンプルをゲイン 1 → 0.5 → 0.25 で再生して終わる
+        let mut io = vec![0.0f32; 3 * len + 2];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        let mut expected = vec![0.0f32; 3 * len + 2];
+        expected[..len].fill(1.0);
+        expected[len..2 * len].fill(0.5);
+        expected[2 * len..3 * len].fill(0.25);
+        assert_eq!(io, expected);
+        assert_eq!(engine.active_grains(), 0);
+    }
+
+    #[test]
+    fn input_trim_applies_to_ring_and_optionally_dry() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        let mut params = FrameParams {
+            density: 0.0,
+            mix: 0.0,
+            input_gain: 0.5,
+            ..FrameParams::default()
+        };
+        let mut io = [1.0f32; 4];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(&engine.ring[..4], &[0.5; 4]);
+        assert_eq!(io, [0.5; 4]);
+        assert_eq!(engine.dry(0, 4), &[0.5; 4]);
+
+        // trim_dry が無ければドライは元のレベルのまま
+        params.trim_dry = false;
+        let mut io = [1.0f32; 4];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(&engine.ring[4..8], &[0.5; 4]);
+        assert_eq!(io, [1.0; 4]);
+    }
+
+    #[test]
+    fn short_grains_are_clamped_to_window_edges() {
+        let sr = 48_000.0;
+        let mut engine = Engine::default();
+        engine.initialize(sr, 1, 64);
+        engine.ring.fill(1.0);
+        let edge = (MIN_EDGE_MS / 1_000.0 * sr) as usize;
+
+        // 1 サンプルのグレインを頼んでも、両端に edge サンプルのフェードが入る長さになる
+        engine.spawn_grain(&mut rand::rng(), 1, 1, 1, 0, 1.0);
+        engine.spawn_sync_grain(1, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.active_grains(), 2);
+        for g in &engine.grains {
+            let buf = g.samples(&engine.ring);
+            assert_eq!(buf.len(), min_tukey_len(TUKEY_ALPHA, edge));
+            assert!(buf[0].abs() < 1e-6);
+            assert!(buf[..edge].windows(2).all(|w| w[0] < w[1]));
+            assert!(buf[buf.len() - edge..].iter().all(|v| *v < 1.0));
+        }
+    }
+
+    #[test]
+    fn asymmetric_window_uses_separate_edges() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.ring.fill(1.0);
+        engine.frame = FrameParams {
+            window: WindowShape::Asymmetric,
+            window_attack: 0.0,
+            window_release: 0.5,
+            ..FrameParams::default()
+        };
+        engine.spawn_sync_grain(100, 0.0, 0, 0, 1.0);
+        let g = &engine.grains[0];
+        // 立ち上がりは最低長 (MIN_EDGE_MS) のみ、後半はゆっくり下がる
+        assert_eq!(g.edges, Some((1, 50)));
+        let buf = g.samples(&engine.ring);
+        assert_eq!(buf[0], 0.0);
+        assert!(buf[1..50].iter().all(|v| *v == 1.0));
+        assert!(buf[50..].windows(2).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn tail_covers_grains_ring_and_feedback() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.frame.max_ms = 100.0;
+        // リングが無音で上書きされるまで + 最後に切り出すグレインの長さ
+        assert_eq!(engine.tail_len(), Some(5_000 + 100));
+
+        // リングより長く繰り返すグレインはその残りが支配する
+        engine.grains.push(Grain {
+            buf: vec![0.0; 1_000],
+            repeats_left: 9,
+            ..Grain::default()
+        });
+        assert_eq!(engine.tail_len(), Some(10_000 + 100));
+        engine.clear_grains();
+
+        // 帰還 0.5 は 10 周で -60 dB を下回る
+        engine.frame.feedback = 0.5;
+        assert_eq!(engine.tail_len(), Some(10 * 5_000 + 100));
+
+        // 書き込みを止めたリングは鳴り続ける
+        engine.frame.ring_mode = RingMode::Play;
+        assert_eq!(engine.tail_len(), None);
+    }
+
+    #[test]
+    fn cloud_reverb_reports_a_tail_that_follows_the_decay() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.frame.reverb = true;
+        engine.frame.feedback = REVERB_FEEDBACK;
+        engine.frame.max_ms = REVERB_MAX_MS;
+        let mut tails = [2.0, 4.0].map(|decay| {
+            engine.frame.reverb_decay = decay;
+            engine.tail_len().unwrap()
+        });
+        // 減衰時間 + 帰還で遡り直す分 + 最後のグレインの長さ。リング全体 (5 秒 × 周回数) よりずっと短い
+        tails.iter_mut().for_each(|t| *t -= 600);
+        assert!((2_000..3_500).contains(&tails[0]), "{tails:?}");
+        assert!(tails[1].abs_diff(2 * tails[0]) <= 1, "{tails:?}");
+
+        // 遡る時間は指数分布で、平均は減衰時間から決まる
+        let mut rng = SmallRng::seed_from_u64(1);
+        let reach = engine.frame.interpolation.reach();
+        let n = 2_000;
+        let mean = (0..n)
+            .map(|_| {
+                let start = engine.reverb_start(&mut rng, 100);
+                (engine.wr + engine.ring.len() - 100 - reach - start) & engine.ring_mask
+            })
+            .sum::<usize>() as f32
+            / n as f32;
+        let expected = engine.reverb_mean_lag() * 1_000.0;
+        assert!(
+            (mean - expected).abs() < expected * 0.1,
+            "{mean} {expected}"
+        );
+    }
+
+    #[test]
+    fn grains_read_the_ring_without_copying() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        for (i, v) in engine.ring.iter_mut().enumerate() {
+            *v = (i % 7) as f32;
+        }
+        let n = engine.ring.len();
+        // 書き込み位置の直前の区間はコピーせずリングを読み、コピーして窓をかけた場合と同じ音になる
+        engine.spawn_sync_grain(40, 0.0, 0, 0, 1.0);
+        let mut expected = engine.ring[n - 40..].to_vec();
+        apply_tukey(&mut expected, TUKEY_ALPHA);
+        let g = &mut engine.grains[0];
+        assert!(g.buf.is_empty());
+        assert_eq!(g.ring, Some((n - 40, 40)));
+        let mut out = [0.0f32; 40];
+        let mut pos = 0;
+        while pos < out.len() {
+            pos += g.render(&mut out[pos..], &engine.ring);
+        }
+        assert!(out.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+
+        // リングを消去しても、読んでいたグレインはコピーへ切り替わって鳴り続ける
+        engine.grains[0].pos = 0;
+        engine.clear_ring(0, false);
+        let g = &engine.grains[0];
+        assert_eq!(g.ring, None);
+        assert!(g
+            .buf
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-6));
+        engine.clear_grains();
+
+        // チャンクの終わりに帰還が足される区間 (保護領域) にかかる場合はコピーする
+        engine.ring.fill(1.0);
+        engine.chunk_feedback = true;
+        engine.chunk_written = 1;
+        engine.spawn_sync_grain(40, 0.0, 0, 0, 1.0);
+        let g = &engine.grains[0];
+        assert_eq!(g.ring, None);
+        assert_eq!(g.buf.len(), 40);
+    }
+
+    #[test]
+    fn overload_guard_sheds_grains_over_budget() {
+        let mut engine = Engine::default();
+        engine.initialize(1_000.0, 1, 64);
+        engine.ring.fill(1.0);
+        let mut params = FrameParams {
+            density: 0.0,
+            guard: true,
+            voice_budget: 4,
+            ..FrameParams::default()
+        };
+        let mut io = [0.0f32; CHUNK_SIZE];
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        for _ in 0..10 {
+            engine.spawn_sync_grain(500, 0.0, 0, 0, 1.0);
+        }
+
+        // 10 グレインを合成したチャンクで予算超過を検出し、次のチャンクで古い 6 つを間引く
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert!((engine.load() - 10.0).abs() < 1e-6, "{}", engine.load());
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(engine.grains.iter().filter(|g| g.releasing()).count(), 0);
+        assert_eq!(engine.active_grains(), 4);
+        // 間引いた 6 つは STEAL_FADE_MS (5 サンプル) でフェードアウトした分だけ負荷に残る
+        assert!(
+            (engine.load() - (4.0 + 6.0 * 5.0 / CHUNK_SIZE as f32)).abs() < 1e-6,
+            "{}",
+            engine.load()
+        );
+
+        // 保護中は新しいグレインも予算内に収まるよう古いものと入れ替える
+        engine.spawn_sync_grain(500, 0.0, 0, 0, 1.0);
+        assert_eq!(engine.grains.iter().filter(|g| !g.releasing()).count(), 4);
+
+        // 保護を切れば上限は MAX_GRAINS に戻る
+        params.guard = false;
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        for _ in 0..6 {
+            engine.spawn_sync_grain(500, 0.0, 0, 0, 1.0);
+        }
+        assert_eq!(engine.grains.iter().filter(|g| !g.releasing()).count(), 10);
+    }
+
+    #[test]
+    fn grain_telemetry_reports_count_rate_and_ceiling() {
+        let sr = 1_000.0;
+        let mut engine = Engine::default();
+        engine.initialize(sr, 1, 64);
+        engine.ring.fill(1.0);
+        let mut params = FrameParams {
+            density: 0.0,
+            ..FrameParams::default()
+        };
+        let meters = engine.meters();
+
+        // 100 サンプルのブロックごとに 1 グレインで毎秒 10 個。1 秒 (時定数) で 1 - 1/e まで近づく
+        let mut io = [0.0f32; 100];
+        for _ in 0..10 {
+            engine.spawn_sync_grain(20, 0.0, 0, 0, 1.0);
+            engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        }
+        let expected = 10.0 * (1.0 - (-1.0f32).exp());
+        assert!((meters.grains.spawn_rate() - expected).abs() < 1e-3);
+        assert_eq!(meters.grains.active(), 0);
+        assert!(!meters.grains.at_ceiling());
+
+        // 上限を超えて生成すると古いグレインが奪われたことが分かる
+        for _ in 0..=MAX_GRAINS {
+            engine.spawn_sync_grain(500, 0.0, 0, 0, 1.0);
+        }
+        engine.process(&mut [&mut io[..]], &mut params, &mut rand::rng());
+        assert_eq!(meters.grains.active(), MAX_GRAINS as u32);
+        assert!(meters.grains.at_ceiling());
+    }
+
+    #[test]
+    fn sync_mode_matches_the_reference_model() {
+        let (sr, input) = read_f32_wav(include_bytes!("../tests/golden/sync_input.wav"));
+        let (_, expected) = read_f32_wav(include_bytes!("../tests/golden/sync_expected.wav"));
+        // 乱数を使わない Sync モード、ウェットのみ。グレイン長は 25 ms = 200 サンプル
+        let mut params = FrameParams {
+            mode: TriggerMode::Sync,
+            overlap: Overlap::X4,
+            min_ms: 25.0,
+            max_ms: 25.0,
+            mix: 1.0,
+            ..FrameParams::default()
+        };
+        // ブロック長によらず同じ結果になること
+        for block in [64, 1_000] {
+            let mut engine = Engine::default();
+            engine.initialize(sr as f32, 1, block);
+            let mut rng = SmallRng::seed_from_u64(0);
+            let mut out = input.clone();
+            for chunk in out.chunks_mut(block) {
+                engine.process(&mut [chunk], &mut params, &mut rng);
+            }
+            assert_golden(&out, &expected, 1e-5);
+        }
+    }
+
+    #[test]
+    fn output_is_independent_of_host_block_size() {
+        let (sr, input) = read_f32_wav(include_bytes!("../tests/golden/sync_input.wav"));
+        // 乱数・帰還・プリディレイ・リピート・スペクトル混合・過負荷ガードをすべて使う
+        let base = FrameParams {
+            density: 0.9,
+            min_ms: 5.0,
+            max_ms: 40.0,
+            speed: 1.5,
+            feedback: 0.5,
+            spectral: 0.3,
+            pre_delay_ms: 10.0,
+            repeats: 2,
+            walk_depth: 0.5,
+            guard: true,
+            voice_budget: 3,
+            ..FrameParams::default()
+        };
+        let render = |block: usize, params: &mut FrameParams| {
+            let mut engine = Engine::default();
+            engine.initialize(sr as f32, 2, block);
+            let mut rng = SmallRng::seed_from_u64(7);
+            let mut left = input.clone();
+            let mut right: Vec<f32> = input.iter().map(|x| -0.5 * x).collect();
+            for (l, r) in left.chunks_mut(block).zip(right.chunks_mut(block)) {
+                engine.process(&mut [l, r], params, &mut rng);
+            }
+            (left, right)
+        };
+        for mode in [TriggerMode::Random, TriggerMode::Sync] {
+            let mut params = FrameParams { mode, ..base };
+            let reference = render(64, &mut params);
+            assert!(reference.0.iter().any(|x| x.abs() > 1e-3));
+            for block in [1, 100, 4_096] {
+                assert_eq!(
+                    render(block, &mut params),
+                    reference,
+                    "{mode:?} block {block}"
+                );
+            }
+        }
+    }
+
+    /// 各パラメータをそれぞれの範囲内でランダムに選ぶ
+    fn frame_params() -> impl Strategy<Value = FrameParams> {
+        let timing = (
+            0.0f32..=1.0,
+            1.0f32..=MAX_GRAIN_MS,
+            1.0f32..=MAX_GRAIN_MS,
+            0usize..4,
+        );
+        let color = (
+            0.0f32..=1.0,
+            0.0f32..=2.0,
+            0.0f32..=MAX_FEEDBACK,
+            any::<bool>(),
+            0.0f32..=1.0,
+        );
+        let shape = (
+            0.0f32..=MAX_PRE_DELAY_MS,
+            any::<bool>(),
+            1i32..=MAX_REPEATS,
+            0.0f32..=1.0,
+            0.0f32..=1.0,
+            0usize..3,
+        );
+        let misc = (
+            0.0f32..=4.0,
+            any::<bool>(),
+            any::<bool>(),
+            1i32..=MAX_GRAINS as i32,
+            0.0f32..=MAX_GLIDE_ST,
+            0.0f32..=1.0,
+        );
+        (timing, color, shape, misc).prop_map(
+            |(
+                (density, min_ms, max_ms, mode),
+                (mix, speed, feedback, freeze, spectral),
+                (pre_delay_ms, adsr, repeats, repeat_decay, walk_depth, interp),
+                (input_gain, gate, guard, voice_budget, glide, pingpong),
+            )| FrameParams {
+                density,
+                min_ms,
+                max_ms,
+                mode: TriggerMode::from_index(mode),
+                mix,
+                speed,
+                shimmer: Shimmer::from_index(mode % 3),
+                feedback,
+                freeze,
+                spectral,
+                gate,
+                pre_delay_ms,
+                window: if adsr {
+                    WindowShape::Adsr
+                } else {
+                    WindowShape::Tukey
+                },
+                repeats,
+                repeat_decay,
+                walk_depth,
+                input_gain,
+                interpolation: Interpolation::from_index(interp),
+                guard,
+                voice_budget,
+                glide,
+                pingpong,
+                ..FrameParams::default()
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /// ランダムなブロック長 (0, 1 フレームを含む)・チャンネル数・パラメータ、
+        /// NaN / inf を含む入力でもパニックせず、出力が有限で有界に収まる
+        #[test]
+        fn process_is_total_and_bounded(
+            n_ch in 0usize..=4,
+            blocks in prop::collection::vec(
+                prop::sample::select(vec![0usize, 1, 2, 63, 64, 65, 512, 1500]),
+                1..12,
+            ),
+            mut params in frame_params(),
+            special in prop::sample::select(
+                vec![f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 0.0],
+            ),
+            seed in any::<u64>(),
+        ) {
+            let sr = 8_000.0;
+            let mut engine = Engine::default();
+            // 初期化時のチャンネル数・ブロック長と実際の呼び出しが食い違っても扱えること
+            engine.initialize(sr, n_ch.max(1), 64);
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut t = 0usize;
+            for n in blocks {
+                let mut chans: Vec<Vec<f32>> = (0..n_ch)
+                    .map(|c| {
+                        (0..n)
+                            .map(|i| {
+                                let k = t + i;
+                                if (k + c) % 97 == 13 {
+                                    special
+                                } else {
+                                    (k as f32 * 0.05).sin()
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+                t += n;
+                let mut io: Vec<&mut [f32]> =
+                    chans.iter_mut().map(|c| c.as_mut_slice()).collect();
+                engine.process(&mut io, &mut params, &mut rng);
+
+                // 入力は ±1、帰還は tanh で 1 以下なので、リングのサンプルは入力ゲイン + 1 以下。
+                // 最大数のグレインが同相で重なり、スペクトル再合成のピークが乗っても下の値に収まる。
+                let bound =
+                    4.0 * GRAIN_POOL_SIZE as f32 * (n_ch as f32 * params.input_gain + 1.0) + 1.0;
+                for c in &chans {
+                    for x in c {
+                        prop_assert!(x.is_finite(), "non-finite output {x}");
+                        prop_assert!(x.abs() <= bound, "{x} exceeds {bound}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Per-note expression for grains spawned while MIDI notes are held.
+//!
+//! CLAP note events arrive as the same note-ons and note-offs as MIDI 1.0 notes, and CLAP note
+//! expressions (and MIDI polyphonic aftertouch) as per-note pressure and brightness. MPE sends
+//! pitch bend and channel pressure on a channel of its own for every note. The plugin feeds all
+//! of them into [`NoteExpressions`], which keeps the latest values of every held note and channel,
+//! and once per block hands the engine the [`NoteExpression`] of the note that currently spawns
+//! grains (the same note Key Track follows):
+//!
+//! - per-note pressure: the gain of new grains (1.0 until it arrives)
+//! - channel pressure: the density of grains while the note's channel is pressed (1.0 until it arrives)
+//! - brightness: the spectral tilt of new grains, added to the grain tilt ([`MAX_TILT_DB`] at 0 and 1)
+//! - pitch bend: the transposition of grains. The note channel's bend plus, for an MPE member
+//!   channel, the bend of its zone's master channel
+//!
+//! Gain and tilt are fixed when a grain is spawned. Pitch bend also moves grains that are already
+//! playing: the engine gets the bend of every channel ([`NoteExpressions::channel_bends`]) and
+//! re-pitches each grain spawned by a note by the change since it was spawned.
+//!
+//! MPE zones follow the MPE Configuration Message (RPN 6 on channel 1 for the lower zone, on
+//! channel 16 for the upper zone). Until one arrives, channel 1 is the master of a lower zone with
+//! the other 15 channels as members.
+
+use crate::filter::MAX_TILT_DB;
+use arrayvec::ArrayVec;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MAX_EXPRESSIVE_NOTES: usize = 64; // 表現を覚えておく押さえているノートの上限 (超えたら古いものから忘れる)
+pub const NEUTRAL_BRIGHTNESS: f32 = 0.5; // ティルトをかけない brightness
+pub const MPE_CONFIGURATION_RPN: u16 = 6; // MPE Configuration Message の RPN (メンバーチャンネルの数を送る)
+const LOWER_MASTER: usize = 0; // 下側ゾーンのマスターチャンネル (チャンネル 1)
+const UPPER_MASTER: usize = 15; // 上側ゾーンのマスターチャンネル (チャンネル 16)
+
+/*──────────────────── 2. Expression ───────────────────*/
+/// 新しく生成するグレインへかけるノートの表現
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteExpression {
+    /// ノートのチャンネル (押さえているノートが無ければ None)。ピッチベンドに追従するグレインの宛先
+    pub channel: Option<u8>,
+    /// グレインのゲイン
+    pub gain: f32,
+    /// 密度に掛ける係数
+    pub density: f32,
+    /// grain_tilt_db に足すティルト (dB)
+    pub tilt_db: f32,
+    /// ピッチベンドによる移調 (半音)
+    pub bend_st: f32,
+}
+
+impl Default for NoteExpression {
+    fn default() -> Self {
+        Self {
+            channel: None,
+            gain: 1.0,
+            density: 1.0,
+            tilt_db: 0.0,
+            bend_st: 0.0,
+        }
+    }
+}
+
+/// 押さえているノート 1 つ分の表現
+#[derive(Clone, Copy, Debug)]
+struct HeldNote {
+    /// CLAP のノート ID (MIDI 1.0 のノートでは None)
+    voice_id: Option<i32>,
+    channel: u8,
+    note: u8,
+    /// 受け取った圧力 (まだ受け取っていなければ None)
+    pressure: Option<f32>,
+    brightness: f32,
+}
+
+impl HeldNote {
+    /// ノート ID が両方にあればそれで、無ければチャンネルとノート番号で同じノートか判定する
+    fn matches(&self, voice_id: Option<i32>, channel: u8, note: u8) -> bool {
+        match (voice_id, self.voice_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.channel == channel && self.note == note,
+        }
+    }
+}
+
+/// 押さえているノートとチャンネルごとの表現
+#[derive(Clone, Debug)]
+pub struct NoteExpressions {
+    /// 押さえた順
+    notes: ArrayVec<HeldNote, MAX_EXPRESSIVE_NOTES>,
+    /// チャンネルごとのピッチベンド (-1.0〜1.0)
+    bend: [f32; 16],
+    /// チャンネルごとのチャンネルプレッシャー (まだ受け取っていなければ None)
+    pressure: [Option<f32>; 16],
+    /// チャンネルごとに選ばれている RPN (MSB << 7 | LSB)
+    rpn: [u16; 16],
+    /// 下側・上側ゾーンのメンバーチャンネルの数 (0 ならそのゾーンは無い)
+    lower: u8,
+    upper: u8,
+}
+
+impl Default for NoteExpressions {
+    fn default() -> Self {
+        Self {
+            notes: ArrayVec::new(),
+            bend: [0.0; 16],
+            pressure: [None; 16],
+            rpn: [0; 16],
+            lower: 15,
+            upper: 0,
+        }
+    }
+}
+
+impl NoteExpressions {
+    pub fn note_on(&mut self, voice_id: Option<i32>, channel: u8, note: u8) {
+        self.note_off(voice_id, channel, note);
+        if self.notes.is_full() {
+            self.notes.remove(0);
+        }
+        self.notes.push(HeldNote {
+            voice_id,
+            channel,
+            note,
+            pressure: None,
+            brightness: NEUTRAL_BRIGHTNESS,
+        });
+    }
+
+    pub fn note_off(&mut self, voice_id: Option<i32>, channel: u8, note: u8) {
+        self.notes.retain(|n| !n.matches(voice_id, channel, note));
+    }
+
+    /// ノートの圧力 (0.0〜1.0)
+    pub fn set_pressure(&mut self, voice_id: Option<i32>, channel: u8, note: u8, pressure: f32) {
+        for n in self.notes.iter_mut() {
+            if n.matches(voice_id, channel, note) {
+                n.pressure = Some(pressure.clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    /// ノートの明るさ (0.0〜1.0、NEUTRAL_BRIGHTNESS でティルトなし)
+    pub fn set_brightness(
+        &mut self,
+        voice_id: Option<i32>,
+        channel: u8,
+        note: u8,
+        brightness: f32,
+    ) {
+        for n in self.notes.iter_mut() {
+            if n.matches(voice_id, channel, note) {
+                n.brightness = brightness.clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    /// チャンネルのピッチベンド (0.0〜1.0、0.5 で中央)。ノートを離しても次のノートのために覚えておく
+    pub fn set_pitch_bend(&mut self, channel: u8, value: f32) {
+        self.bend[channel as usize & 15] = (value.clamp(0.0, 1.0) - 0.5) * 2.0;
+    }
+
+    /// チャンネルのチャンネルプレッシャー (0.0〜1.0)。そのチャンネルのノートが鳴らすグレインの密度に掛ける
+    pub fn set_channel_pressure(&mut self, channel: u8, pressure: f32) {
+        self.pressure[channel as usize & 15] = Some(pressure.clamp(0.0, 1.0));
+    }
+
+    /// コントロールチェンジ (0.0〜1.0)。RPN の選択 (CC 101 / 100) とデータエントリー (CC 6) を追い、
+    /// マスターチャンネルに届いた MPE Configuration Message でゾーンを設定する
+    pub fn control_change(&mut self, channel: u8, cc: u8, value: f32) {
+        let ch = channel as usize & 15;
+        let value = (value.clamp(0.0, 1.0) * 127.0).round() as u16;
+        match cc {
+            101 => self.rpn[ch] = (self.rpn[ch] & 0x7f) | value << 7,
+            100 => self.rpn[ch] = (self.rpn[ch] & !0x7f) | value,
+            6 if self.rpn[ch] == MPE_CONFIGURATION_RPN => self.configure_zone(ch, value as u8),
+            _ => {}
+        }
+    }
+
+    /// マスターチャンネル `master` のゾーンのメンバーを `members` 個にする (0 でゾーンを無くす)。
+    /// もう一方のゾーンと重なる分は、もう一方を縮める
+    fn configure_zone(&mut self, master: usize, members: u8) {
+        let members = members.min(15);
+        match master {
+            LOWER_MASTER => {
+                self.lower = members;
+                self.upper = self.upper.min(14u8.saturating_sub(members));
+            }
+            UPPER_MASTER => {
+                self.upper = members;
+                self.lower = self.lower.min(14u8.saturating_sub(members));
+            }
+            _ => {}
+        }
+    }
+
+    /// チャンネル `ch` がメンバーになっているゾーンのマスターチャンネル
+    fn master(&self, ch: usize) -> Option<usize> {
+        if (1..=self.lower as usize).contains(&ch) {
+            Some(LOWER_MASTER)
+        } else if (UPPER_MASTER - self.upper as usize..UPPER_MASTER).contains(&ch) {
+            Some(UPPER_MASTER)
+        } else {
+            None
+        }
+    }
+
+    /// チャンネル `ch` のノートのピッチベンド (-1.0〜1.0 の和)。メンバーチャンネルならマスターのベンドも足す
+    fn bend(&self, ch: usize) -> f32 {
+        self.bend[ch] + self.master(ch).map_or(0.0, |m| self.bend[m])
+    }
+
+    /// チャンネルごとのノートのピッチベンド (半音、最大で ±2 × `bend_range`)
+    pub fn channel_bends(&self, bend_range: f32) -> [f32; 16] {
+        std::array::from_fn(|ch| self.bend(ch) * bend_range)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// ノート番号 `note` で最後に押さえたノートの表現 (押さえていなければ何もしない表現)。
+    /// ピッチベンドは最大で ±`bend_range` 半音
+    pub fn expression(&self, note: Option<u8>, bend_range: f32) -> NoteExpression {
+        let Some(held) = note.and_then(|note| self.notes.iter().rev().find(|n| n.note == note))
+        else {
+            return NoteExpression::default();
+        };
+        let ch = held.channel as usize & 15;
+        NoteExpression {
+            channel: Some(ch as u8),
+            gain: held.pressure.unwrap_or(1.0),
+            density: self.pressure[ch].unwrap_or(1.0),
+            tilt_db: (held.brightness - NEUTRAL_BRIGHTNESS) * 2.0 * MAX_TILT_DB,
+            bend_st: self.bend(ch) * bend_range,
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_follow_the_addressed_note() {
+        let mut notes = NoteExpressions::default();
+        assert_eq!(notes.expression(Some(60), 48.0), NoteExpression::default());
+        let untouched = NoteExpression {
+            channel: Some(0),
+            ..NoteExpression::default()
+        };
+
+        // MIDI 1.0 のノートはチャンネルとノート番号で、CLAP のノートはノート ID で宛先を決める
+        notes.note_on(None, 0, 60);
+        notes.note_on(Some(7), 1, 64);
+        assert_eq!(notes.expression(Some(60), 48.0), untouched);
+        notes.set_pressure(None, 0, 60, 0.25);
+        notes.set_brightness(Some(7), 15, 0, 1.0);
+        notes.set_pressure(Some(8), 1, 64, 0.0);
+        assert_eq!(notes.expression(Some(60), 48.0).gain, 0.25);
+        let e = notes.expression(Some(64), 48.0);
+        assert_eq!((e.gain, e.tilt_db), (1.0, MAX_TILT_DB));
+
+        // 離したノートの表現は忘れ、押さえ直せば元に戻る
+        notes.note_off(Some(7), 1, 64);
+        assert_eq!(notes.expression(Some(64), 48.0), NoteExpression::default());
+        notes.note_on(None, 0, 60);
+        assert_eq!(notes.expression(Some(60), 48.0), untouched);
+        assert_eq!(notes.expression(None, 48.0), NoteExpression::default());
+    }
+
+    #[test]
+    fn mpe_channels_bend_and_press_their_notes() {
+        let mut notes = NoteExpressions::default();
+        // MPE の下側ゾーン: チャンネル 1 (0) がマスター、2 (1) 以降がノートごとのチャンネル。
+        // チャンネルプレッシャーは密度に、ノートごとの圧力はゲインにかける
+        notes.set_pitch_bend(1, 0.75);
+        notes.set_channel_pressure(1, 0.5);
+        notes.note_on(None, 1, 60);
+        notes.note_on(None, 2, 64);
+        let e = notes.expression(Some(60), 48.0);
+        assert_eq!(
+            (e.channel, e.gain, e.density, e.bend_st),
+            (Some(1), 1.0, 0.5, 24.0)
+        );
+        let e = notes.expression(Some(64), 48.0);
+        assert_eq!((e.channel, e.density, e.bend_st), (Some(2), 1.0, 0.0));
+
+        // マスターチャンネルのベンドはゾーンの全ノートに足す
+        notes.set_pitch_bend(0, 0.25);
+        notes.set_pressure(None, 1, 60, 0.2);
+        let e = notes.expression(Some(60), 2.0);
+        assert_eq!((e.gain, e.density, e.bend_st), (0.2, 0.5, 0.0));
+        assert_eq!(notes.expression(Some(64), 2.0).bend_st, -1.0);
+        assert_eq!(notes.channel_bends(2.0)[..3], [-1.0, 0.0, -1.0]);
+
+        notes.reset();
+        notes.note_on(None, 1, 60);
+        let e = notes.expression(Some(60), 48.0);
+        assert_eq!(
+            e,
+            NoteExpression {
+                channel: Some(1),
+                ..NoteExpression::default()
+            }
+        );
+    }
+
+    #[test]
+    fn mpe_configuration_message_sets_up_the_upper_zone() {
+        let mut notes = NoteExpressions::default();
+        let rpn = |notes: &mut NoteExpressions, ch: u8, members: f32| {
+            notes.control_change(ch, 101, 0.0);
+            notes.control_change(ch, 100, 6.0 / 127.0);
+            notes.control_change(ch, 6, members / 127.0);
+        };
+        // 設定前はチャンネル 16 (15) も下側ゾーンのメンバー
+        notes.set_pitch_bend(0, 1.0);
+        notes.set_pitch_bend(15, 0.75);
+        assert_eq!(notes.channel_bends(1.0)[15], 1.5);
+
+        // チャンネル 16 への MCM で上側ゾーンができ、重なる下側ゾーンは縮む
+        rpn(&mut notes, 15, 7.0);
+        let bends = notes.channel_bends(1.0);
+        assert_eq!((bends[7], bends[8], bends[14]), (1.0, 0.5, 0.5));
+        assert_eq!((bends[0], bends[15]), (1.0, 0.5));
+
+        // 下側ゾーンを無くすと、チャンネル 2〜8 は自分のベンドだけになる
+        rpn(&mut notes, 0, 0.0);
+        assert_eq!(notes.channel_bends(1.0)[1], 0.0);
+        // RPN を選んでいないチャンネルのデータエントリーや、メンバーチャンネルの MCM は無視する
+        notes.control_change(3, 6, 1.0);
+        rpn(&mut notes, 4, 3.0);
+        assert_eq!(notes.channel_bends(1.0)[14], 0.5);
+    }
+}
@@ -0,0 +1,519 @@
+//! Low-cut / high-cut filters on the summed wet bus. Dense grain clouds pile up low-end rumble
+//! and harsh highs; [`WetFilter`] removes them with a 12 dB/oct high-pass and low-pass pair
+//! (Butterworth-Q state-variable filters, trapezoidal integration) so the wet signal needs no
+//! external EQ. Each stage is bypassed at the end of its range, leaving the wet untouched.
+//!
+//! [`DcBlocker`] is a fixed one-pole high-pass ahead of them: windowed slices of asymmetric
+//! material carry small DC offsets, and summed over many overlapping grains they eat headroom.
+//!
+//! [`Crossover`] splits the input into 2 or 3 Linkwitz-Riley (24 dB/oct) bands built from the
+//! same SVF, so only some bands are granulated and the rest pass through, e.g. for keeping the lows
+//! intact in a mix. The bands sum to an all-pass response, so the split adds no coloration.
+//!
+//! [`apply_tilt`] is a cheap per-grain tilt EQ: a one-pole split at [`TILT_PIVOT_HZ`] with the
+//! lows and highs scaled in opposite directions. Giving overlapping grains different random tilts
+//! decorrelates them spectrally without running a full filter per grain.
+
+use nih_plug::prelude::Enum;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MIN_CUT_HZ: f32 = 20.0; // カットオフの範囲 (Hz)。Low Cut はこの値で、High Cut は MAX_CUT_HZ でオフ
+pub const MAX_CUT_HZ: f32 = 20_000.0;
+pub const DC_BLOCK_HZ: f32 = 5.0; // DC ブロッカーのカットオフ (Hz)
+const SVF_K: f32 = std::f32::consts::SQRT_2; // 1/Q (Q = 1/√2 でバターワース)
+pub const MIN_CROSSOVER_HZ: f32 = 40.0; // クロスオーバー周波数の範囲 (Hz)
+pub const MAX_CROSSOVER_HZ: f32 = 12_000.0;
+pub const TILT_PIVOT_HZ: f32 = 1_000.0; // グレインのティルトの中心周波数 (Hz)
+pub const MAX_TILT_DB: f32 = 12.0; // ティルトの上限 (最低域と最高域の差の半分、±dB)
+
+/// SVF の係数。カットオフが変わったときだけ計算し直す
+struct Coefs {
+    hz: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl Default for Coefs {
+    fn default() -> Self {
+        Self {
+            hz: f32::NAN,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+        }
+    }
+}
+
+impl Coefs {
+    #[inline]
+    fn update(&mut self, hz: f32, sr: f32) -> &Self {
+        if hz != self.hz {
+            self.hz = hz;
+            // ナイキスト付近では tan が発散するので手前で止める
+            let g = (std::f32::consts::PI * hz.min(0.49 * sr) / sr).tan();
+            self.a1 = 1.0 / (1.0 + g * (g + SVF_K));
+            self.a2 = g * self.a1;
+            self.a3 = g * self.a2;
+        }
+        self
+    }
+}
+
+/*──────────────────── 2. SVF ──────────────────────────*/
+/// 2 次の状態変数フィルタ (Simper の TPT 形式) の状態
+#[derive(Clone, Copy, Default)]
+struct Svf {
+    ic1: f32,
+    ic2: f32,
+}
+
+impl Svf {
+    /// 1 サンプル進め、(ローパス, ハイパス) を返す
+    #[inline]
+    fn tick(&mut self, x: f32, c: &Coefs) -> (f32, f32) {
+        let v3 = x - self.ic2;
+        let v1 = c.a1 * self.ic1 + c.a2 * v3;
+        let v2 = self.ic2 + c.a2 * self.ic1 + c.a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        (v2, x - SVF_K * v1 - v2)
+    }
+}
+
+/*──────────────────── 3. DC blocker ──────────────────*/
+/// 1 次の DC ブロッカー y[n] = x[n] - x[n-1] + r·y[n-1] (チャンネルごと)
+pub struct DcBlocker {
+    r: f32,
+    /// チャンネルごとの (前の入力, 前の出力)
+    states: Vec<(f32, f32)>,
+}
+
+impl Default for DcBlocker {
+    fn default() -> Self {
+        Self {
+            r: 0.0,
+            states: Vec::new(),
+        }
+    }
+}
+
+impl DcBlocker {
+    pub fn initialize(&mut self, sr: f32, n_ch: usize) {
+        self.r = (-std::f32::consts::TAU * DC_BLOCK_HZ / sr).exp();
+        self.states = vec![(0.0, 0.0); n_ch];
+    }
+
+    /// チャンネル数が足りなければ状態を確保し直す (オーディオスレッドでは通常起きない)
+    pub fn ensure_channels(&mut self, n_ch: usize) {
+        if self.states.len() < n_ch {
+            self.states.resize(n_ch, (0.0, 0.0));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.states.fill((0.0, 0.0));
+    }
+
+    /// `wet` の各チャンネルの先頭 `n_samples` サンプルから直流成分をその場で取り除く
+    pub fn process(&mut self, wet: &mut [Vec<f32>], n_samples: usize) {
+        for (w, (x1, y1)) in wet.iter_mut().zip(&mut self.states) {
+            for x in &mut w[..n_samples] {
+                let y = *x - *x1 + self.r * *y1;
+                *x1 = *x;
+                // 無音が続いたときに非正規化数へ落ちないよう 0 に丸める
+                *y1 = if y.abs() < 1e-20 { 0.0 } else { y };
+                *x = *y1;
+            }
+        }
+    }
+}
+
+/*──────────────────── 4. Wet filter ───────────────────*/
+/// ウェットバスのチャンネルごとのハイパス (Low Cut) → ローパス (High Cut)
+pub struct WetFilter {
+    sr: f32,
+    low_cut: Coefs,
+    high_cut: Coefs,
+    /// チャンネルごとの (ハイパス, ローパス) の状態
+    states: Vec<(Svf, Svf)>,
+}
+
+impl Default for WetFilter {
+    fn default() -> Self {
+        Self {
+            sr: 44_100.0,
+            low_cut: Coefs::default(),
+            high_cut: Coefs::default(),
+            states: Vec::new(),
+        }
+    }
+}
+
+impl WetFilter {
+    pub fn initialize(&mut self, sr: f32, n_ch: usize) {
+        self.sr = sr;
+        self.low_cut = Coefs::default();
+        self.high_cut = Coefs::default();
+        self.states = vec![(Svf::default(), Svf::default()); n_ch];
+    }
+
+    /// チャンネル数が足りなければ状態を確保し直す (オーディオスレッドでは通常起きない)
+    pub fn ensure_channels(&mut self, n_ch: usize) {
+        if self.states.len() < n_ch {
+            self.states.resize(n_ch, (Svf::default(), Svf::default()));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.states.fill((Svf::default(), Svf::default()));
+    }
+
+    /// `wet` の各チャンネルの先頭 `n_samples` サンプルをその場でフィルタする。
+    /// `low_cut_hz` が MIN_CUT_HZ 以下ならハイパスを、`high_cut_hz` が MAX_CUT_HZ 以上ならローパスを通さない
+    /// (通さない段の状態は消しておき、次にかけ始めたときに古い音が出ないようにする)。
+    pub fn process(
+        &mut self,
+        wet: &mut [Vec<f32>],
+        n_samples: usize,
+        low_cut_hz: f32,
+        high_cut_hz: f32,
+    ) {
+        let low_cut = (low_cut_hz > MIN_CUT_HZ).then(|| self.low_cut.update(low_cut_hz, self.sr));
+        let high_cut =
+            (high_cut_hz < MAX_CUT_HZ).then(|| self.high_cut.update(high_cut_hz, self.sr));
+        for (w, (hp, lp)) in wet.iter_mut().zip(&mut self.states) {
+            match low_cut {
+                Some(c) => w[..n_samples]
+                    .iter_mut()
+                    .for_each(|x| *x = hp.tick(*x, c).1),
+                None => *hp = Svf::default(),
+            }
+            match high_cut {
+                Some(c) => w[..n_samples]
+                    .iter_mut()
+                    .for_each(|x| *x = lp.tick(*x, c).0),
+                None => *lp = Svf::default(),
+            }
+        }
+    }
+}
+
+/*──────────────────── 5. Crossover ────────────────────*/
+/// 帯域の分け方
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bands {
+    /// 分けずに全体をグレインにする
+    #[name = "Off"]
+    Off,
+    /// crossover_low で Low / High に分ける
+    #[name = "2 Bands"]
+    Two,
+    /// crossover_low と crossover_high で Low / Mid / High に分ける
+    #[name = "3 Bands"]
+    Three,
+}
+
+impl Bands {
+    /// 使う帯域 (0=Low, 1=Mid, 2=High)
+    pub fn indices(self) -> &'static [usize] {
+        match self {
+            Bands::Off => &[],
+            Bands::Two => &[0, 2],
+            Bands::Three => &[0, 1, 2],
+        }
+    }
+}
+
+/// Linkwitz-Riley (24 dB/oct) の 2 分割。バターワースの 2 次を 2 段重ね、ローとハイの和が全域通過になる
+#[derive(Clone, Copy, Default)]
+struct LrSplit {
+    first: Svf,
+    low: Svf,
+    high: Svf,
+}
+
+impl LrSplit {
+    /// 1 サンプル進め、(ロー, ハイ) を返す
+    #[inline]
+    fn tick(&mut self, x: f32, c: &Coefs) -> (f32, f32) {
+        let (lp, hp) = self.first.tick(x, c);
+        (self.low.tick(lp, c).0, self.high.tick(hp, c).1)
+    }
+}
+
+/// 1 つの信号の分割の状態: (低い分割, 高い分割, Mid / High と位相を揃えるために Low に掛ける全域通過)
+type BandState = (LrSplit, LrSplit, LrSplit);
+
+/// 2 つのクロスオーバー周波数の係数
+struct Splits {
+    sr: f32,
+    low: Coefs,
+    high: Coefs,
+}
+
+impl Splits {
+    /// `x` を Low / Mid / High に分ける (2 Bands では Mid が 0、Off では分けずに Low へ入れる)
+    #[inline]
+    fn split(
+        &mut self,
+        state: &mut BandState,
+        x: f32,
+        bands: Bands,
+        (low_hz, high_hz): (f32, f32),
+    ) -> [f32; 3] {
+        if bands == Bands::Off {
+            return [x, 0.0, 0.0];
+        }
+        let low_hz = low_hz.clamp(MIN_CROSSOVER_HZ, MAX_CROSSOVER_HZ);
+        let (split_low, split_high, align) = state;
+        let (lo, rest) = split_low.tick(x, self.low.update(low_hz, self.sr));
+        if bands == Bands::Three {
+            // Mid / High の分割は Low の分割より上に置く
+            let c = self
+                .high
+                .update(high_hz.clamp(low_hz, MAX_CROSSOVER_HZ), self.sr);
+            let (mid, hi) = split_high.tick(rest, c);
+            let (a, b) = align.tick(lo, c);
+            [a + b, mid, hi]
+        } else {
+            [lo, 0.0, rest]
+        }
+    }
+}
+
+/// 帯域ごとの値を (`granulate` がオンの帯域の和, オフの帯域の和) にまとめる
+#[inline]
+pub fn granulated(y: [f32; 3], granulate: [bool; 3]) -> (f32, f32) {
+    y.into_iter().zip(granulate).fold(
+        (0.0, 0.0),
+        |(g, k), (y, on)| if on { (g + y, k) } else { (g, k + y) },
+    )
+}
+
+/// 2〜3 帯域のクロスオーバー。入力とドライを Low / Mid / High に分け、帯域ごとのグレインの出力をその帯域に絞る。
+/// ドライとグレインの出力はチャンネルごと、リングへ書く入力はモノラルで分ける
+pub struct Crossover {
+    splits: Splits,
+    /// チャンネルごとのドライの状態
+    states: Vec<BandState>,
+    /// リングへ書くモノラル入力の状態
+    input: BandState,
+    /// チャンネルごと・帯域ごとのグレインの出力の状態
+    wet: Vec<[BandState; 3]>,
+}
+
+impl Default for Crossover {
+    fn default() -> Self {
+        Self {
+            splits: Splits {
+                sr: 44_100.0,
+                low: Coefs::default(),
+                high: Coefs::default(),
+            },
+            states: Vec::new(),
+            input: BandState::default(),
+            wet: Vec::new(),
+        }
+    }
+}
+
+impl Crossover {
+    pub fn initialize(&mut self, sr: f32, n_ch: usize) {
+        self.splits = Splits {
+            sr,
+            low: Coefs::default(),
+            high: Coefs::default(),
+        };
+        self.states = vec![BandState::default(); n_ch];
+        self.input = BandState::default();
+        self.wet = vec![Default::default(); n_ch];
+    }
+
+    /// チャンネル数が足りなければ状態を確保し直す (オーディオスレッドでは通常起きない)
+    pub fn ensure_channels(&mut self, n_ch: usize) {
+        if self.states.len() < n_ch {
+            self.states.resize(n_ch, BandState::default());
+            self.wet.resize(n_ch, Default::default());
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.states.fill(BandState::default());
+        self.input = BandState::default();
+        self.wet.fill(Default::default());
+    }
+
+    /// チャンネル `ch` のドライ `x` を Low / Mid / High に分ける。`hz` は (Low / Mid, Mid / High) の分割周波数
+    #[inline]
+    pub fn split(&mut self, ch: usize, x: f32, bands: Bands, hz: (f32, f32)) -> [f32; 3] {
+        self.splits.split(&mut self.states[ch], x, bands, hz)
+    }
+
+    /// リングへ書くモノラル入力 `x` を `split` と同じように分ける
+    #[inline]
+    pub fn split_input(&mut self, x: f32, bands: Bands, hz: (f32, f32)) -> [f32; 3] {
+        self.splits.split(&mut self.input, x, bands, hz)
+    }
+
+    /// 帯域 `band` のグレインを合成したチャンネル `ch` の出力 `x` を、その帯域の成分だけにする
+    #[inline]
+    pub fn band_wet(
+        &mut self,
+        ch: usize,
+        band: usize,
+        x: f32,
+        bands: Bands,
+        hz: (f32, f32),
+    ) -> f32 {
+        self.splits.split(&mut self.wet[ch][band], x, bands, hz)[band]
+    }
+}
+
+/*──────────────────── 6. Tilt ─────────────────────────*/
+/// `buf` に TILT_PIVOT_HZ を中心に傾けるティルトをかける。
+/// 正の `tilt_db` で高域を +tilt_db、低域を -tilt_db へ寄せて明るく、負で暗くする (0 なら素通り)
+pub fn apply_tilt(buf: &mut [f32], tilt_db: f32, sr: f32) {
+    if tilt_db == 0.0 {
+        return;
+    }
+    let tilt = tilt_db.clamp(-MAX_TILT_DB, MAX_TILT_DB);
+    let high = (tilt * std::f32::consts::LN_10 / 20.0).exp();
+    let low = 1.0 / high;
+    let a = (-std::f32::consts::TAU * TILT_PIVOT_HZ / sr).exp();
+    let mut lp = 0.0;
+    for x in buf.iter_mut() {
+        lp = (1.0 - a) * *x + a * lp;
+        *x = low * lp + high * (*x - lp);
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 周波数 `hz` の正弦波を通したときの定常状態の振幅比 (dB)
+    fn response_db(low_cut_hz: f32, high_cut_hz: f32, hz: f32) -> f32 {
+        let sr = 48_000.0;
+        let mut filter = WetFilter::default();
+        filter.initialize(sr, 1);
+        let n = 48_000;
+        let mut wet = vec![(0..n)
+            .map(|i| (std::f32::consts::TAU * hz * i as f32 / sr).sin())
+            .collect::<Vec<f32>>()];
+        filter.process(&mut wet, n, low_cut_hz, high_cut_hz);
+        let peak = wet[0][n / 2..].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        20.0 * peak.log10()
+    }
+
+    #[test]
+    fn stages_roll_off_at_twelve_db_per_octave() {
+        // カットオフで -3 dB
+        assert!((response_db(1_000.0, MAX_CUT_HZ, 1_000.0) + 3.0).abs() < 0.2);
+        assert!((response_db(MIN_CUT_HZ, 1_000.0, 1_000.0) + 3.0).abs() < 0.2);
+        // 2 オクターブ離れると約 -24 dB、通過域はほぼ 0 dB
+        assert!((response_db(1_000.0, MAX_CUT_HZ, 250.0) + 24.0).abs() < 1.0);
+        assert!((response_db(MIN_CUT_HZ, 1_000.0, 4_000.0) + 24.0).abs() < 1.5);
+        assert!(response_db(100.0, 10_000.0, 1_000.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn dc_blocker_removes_offset_but_passes_audio() {
+        let sr = 48_000.0;
+        let mut dc = DcBlocker::default();
+        dc.initialize(sr, 1);
+        // 0.5 の直流に 1 kHz の正弦波を重ねる。1 秒後には直流がほぼ消え、正弦波はほぼそのまま残る
+        let n = 48_000;
+        let mut wet = vec![(0..n)
+            .map(|i| 0.5 + 0.25 * (std::f32::consts::TAU * 1_000.0 * i as f32 / sr).sin())
+            .collect::<Vec<f32>>()];
+        dc.process(&mut wet, n);
+        let tail = &wet[0][n - 4_800..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        let peak = tail.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        assert!(mean.abs() < 1e-3, "{mean}");
+        assert!((peak - 0.25).abs() < 1e-3, "{peak}");
+    }
+
+    /// 周波数 `hz` の正弦波を分けたときの (グレインにする帯域, 素通しする帯域, 和) の定常状態の振幅 (RMS × √2)
+    fn split_amps(bands: Bands, granulate: [bool; 3], hz: f32) -> (f32, f32, f32) {
+        let sr = 48_000.0;
+        let mut crossover = Crossover::default();
+        crossover.initialize(sr, 1);
+        let mut sq = (0.0f32, 0.0f32, 0.0f32);
+        for i in 0..24_000 {
+            let x = (std::f32::consts::TAU * hz * i as f32 / sr).sin();
+            let (g, k) = granulated(crossover.split(0, x, bands, (200.0, 2_000.0)), granulate);
+            if i >= 12_000 {
+                sq = (sq.0 + g * g, sq.1 + k * k, sq.2 + (g + k) * (g + k));
+            }
+        }
+        let amp = |s: f32| (2.0 * s / 12_000.0).sqrt();
+        (amp(sq.0), amp(sq.1), amp(sq.2))
+    }
+
+    #[test]
+    fn crossover_bands_split_at_the_frequencies_and_sum_flat() {
+        // Low を素通しし、High をグレインにする。分割周波数ではそれぞれ -6 dB
+        let lows = [false, true, true];
+        let (g, k, _) = split_amps(Bands::Two, lows, 50.0);
+        assert!(g < 0.02 && (k - 1.0).abs() < 0.01, "{g} {k}");
+        let (g, k, _) = split_amps(Bands::Two, lows, 2_000.0);
+        assert!((g - 1.0).abs() < 0.01 && k < 0.01, "{g} {k}");
+        let (g, k, _) = split_amps(Bands::Two, lows, 200.0);
+        assert!((g - 0.5).abs() < 0.01 && (k - 0.5).abs() < 0.01, "{g} {k}");
+
+        // 3 帯域で Mid だけをグレインにする
+        let mid = [false, true, false];
+        let (g, k, _) = split_amps(Bands::Three, mid, 630.0);
+        assert!(g > 0.9 && k < 0.2, "{g} {k}");
+        let (g, _, _) = split_amps(Bands::Three, mid, 8_000.0);
+        assert!(g < 0.02, "{g}");
+
+        // 帯域の和はどの周波数でも振幅が変わらない (全域通過)
+        for hz in [50.0, 200.0, 630.0, 2_000.0, 8_000.0] {
+            for bands in [Bands::Two, Bands::Three] {
+                let (_, _, sum) = split_amps(bands, mid, hz);
+                assert!((sum - 1.0).abs() < 0.01, "{bands:?} {hz} Hz: {sum}");
+            }
+        }
+        // Off は分けずに Low へ入れる
+        let mut crossover = Crossover::default();
+        crossover.initialize(48_000.0, 1);
+        let y = crossover.split(0, 0.3, Bands::Off, (200.0, 2_000.0));
+        assert_eq!(y, [0.3, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn stages_at_the_ends_of_the_range_are_bypassed() {
+        let mut filter = WetFilter::default();
+        filter.initialize(48_000.0, 2);
+        let input: Vec<f32> = (0..256).map(|i| ((i * 37) % 11) as f32 - 5.0).collect();
+        let mut wet = vec![input.clone(), input.clone()];
+        filter.process(&mut wet, 256, MIN_CUT_HZ, MAX_CUT_HZ);
+        assert_eq!(wet, vec![input.clone(), input]);
+    }
+
+    #[test]
+    fn tilt_tips_the_spectrum_around_the_pivot() {
+        let sr = 48_000.0;
+        let level_db = |hz: f32, tilt: f32| {
+            let mut buf: Vec<f32> = (0..48_000)
+                .map(|i| (std::f32::consts::TAU * hz * i as f32 / sr).sin())
+                .collect();
+            apply_tilt(&mut buf, tilt, sr);
+            let peak = buf[24_000..].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            20.0 * peak.log10()
+        };
+        // 明るくすると低域が下がって高域が上がり、暗くするとその逆
+        assert!(level_db(100.0, 6.0) < -4.0, "{}", level_db(100.0, 6.0));
+        assert!(level_db(10_000.0, 6.0) > 4.0, "{}", level_db(10_000.0, 6.0));
+        assert!(level_db(100.0, -6.0) > 4.0);
+        assert!(level_db(10_000.0, -6.0) < -4.0);
+
+        let mut buf = vec![0.5, -0.25, 1.0];
+        apply_tilt(&mut buf, 0.0, sr);
+        assert_eq!(buf, [0.5, -0.25, 1.0]);
+    }
+}
@@ -0,0 +1,28 @@
+//! Test-only helpers for the golden fixtures in `tests/golden`, generated by the independent
+//! Sync-mode model in `scripts/make_golden.py`.
+
+/// モノラル 32bit float WAV を (サンプルレート, サンプル列) として読む
+pub fn read_f32_wav(bytes: &[u8]) -> (u32, Vec<f32>) {
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at =
+        |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    assert_eq!(&bytes[..4], b"RIFF");
+    assert_eq!(&bytes[8..16], b"WAVEfmt ");
+    assert_eq!(u16_at(20), 3, "not IEEE float");
+    assert_eq!(u16_at(22), 1, "not mono");
+    assert_eq!(&bytes[36..40], b"data");
+    let len = u32_at(40) as usize;
+    let samples = bytes[44..44 + len]
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    (u32_at(24), samples)
+}
+
+/// 出力が参照と同じ長さで、各サンプルが `tol` 以内であること
+pub fn assert_golden(actual: &[f32], expected: &[f32], tol: f32) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!((a - e).abs() <= tol, "sample {i}: {a} vs reference {e}");
+    }
+}
@@ -0,0 +1,242 @@
+//! Fractional-position reads from the ring buffer, used when grains are cut out at
+//! non-integer positions or resampled for transposition.
+//!
+//! [`Interpolation`] trades cost for quality: linear for live use, cubic Hermite and a
+//! windowed sinc for offline renders. [`Oversampling`] additionally averages several reads across
+//! each output sample's span when a grain is transposed up, so content above the new Nyquist
+//! frequency is smoothed away instead of folding back as aliasing.
+
+use nih_plug::prelude::Enum;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const SINC_HALF_TAPS: usize = 4; // 窓付き sinc の片側のタップ数 (計 8 タップ)
+
+/*──────────────────── 2. Interpolation quality ────────*/
+/// 小数位置を読むときの補間方式
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// 2 点の線形補間 (最も軽い)
+    #[name = "Linear"]
+    Linear,
+    /// 4 点の 3 次エルミート (Catmull-Rom)
+    #[name = "Cubic Hermite"]
+    Hermite,
+    /// Hann 窓付き sinc (SINC_HALF_TAPS × 2 点、最も高品質)
+    #[name = "Windowed Sinc"]
+    Sinc,
+}
+
+impl Interpolation {
+    /// 読み出し位置より後ろに必要なサンプル数 (整数部の次から数える)
+    #[inline]
+    pub fn reach(self) -> usize {
+        match self {
+            Interpolation::Linear => 1,
+            Interpolation::Hermite => 2,
+            Interpolation::Sinc => SINC_HALF_TAPS,
+        }
+    }
+
+    /// リング `ring` の小数位置 `pos` をこの方式で読む
+    #[inline]
+    pub fn read(self, ring: &[f32], pos: f64) -> f32 {
+        match self {
+            Interpolation::Linear => read_linear(ring, pos),
+            Interpolation::Hermite => read_hermite(ring, pos),
+            Interpolation::Sinc => read_sinc(ring, pos),
+        }
+    }
+}
+
+/// グレインを移調して切り出すときに 1 サンプルあたり何点読むか
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Oversampling {
+    #[name = "1x"]
+    X1,
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+}
+
+impl Oversampling {
+    #[inline]
+    pub fn factor(self) -> usize {
+        match self {
+            Oversampling::X1 => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+        }
+    }
+}
+
+impl Interpolation {
+    /// `pos` から次の出力サンプルの位置までの `span` サンプルを `os` 点で読んで平均する。
+    /// 上方向の移調 (|span| > 1) で読み飛ばす成分を均し、折り返しを抑える。
+    /// 移調していないか下げているとき、または 1x なら `read` と同じ
+    #[inline]
+    pub fn read_oversampled(self, ring: &[f32], pos: f64, span: f64, os: Oversampling) -> f32 {
+        let k = os.factor();
+        if k == 1 || span.abs() <= 1.0 {
+            return self.read(ring, pos);
+        }
+        let step = span / k as f64;
+        (0..k)
+            .map(|j| self.read(ring, pos + step * j as f64))
+            .sum::<f32>()
+            / k as f32
+    }
+}
+
+/// `pos` をリング上へ折り返し、整数部と小数部に分ける
+#[inline]
+fn split(n: usize, pos: f64) -> (usize, f32) {
+    let pos = pos.rem_euclid(n as f64);
+    let i0 = (pos as usize).min(n - 1);
+    (i0, (pos - i0 as f64) as f32)
+}
+
+/*──────────────────── 3. Readers ──────────────────────*/
+/// リング `ring` の小数位置 `pos` を (末尾で折り返しながら) 線形補間で読む。
+/// `pos` は負でも範囲外でもよく、リング長で剰余を取る。
+#[inline]
+pub fn read_linear(ring: &[f32], pos: f64) -> f32 {
+    let n = ring.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let (i0, frac) = split(n, pos);
+    let a = ring[i0];
+    let b = ring[(i0 + 1) % n];
+    a + (b - a) * frac
+}
+
+/// 前後 2 点ずつを使う 3 次エルミート (Catmull-Rom) 補間
+#[inline]
+pub fn read_hermite(ring: &[f32], pos: f64) -> f32 {
+    let n = ring.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let (i0, t) = split(n, pos);
+    let xm1 = ring[(i0 + n - 1) % n];
+    let x0 = ring[i0];
+    let x1 = ring[(i0 + 1) % n];
+    let x2 = ring[(i0 + 2) % n];
+    let c1 = 0.5 * (x1 - xm1);
+    let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+    let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+    ((c3 * t + c2) * t + c1) * t + x0
+}
+
+/// Hann 窓付き sinc 補間。重みの和で正規化して直流のゲインを 1 に保つ。
+#[inline]
+pub fn read_sinc(ring: &[f32], pos: f64) -> f32 {
+    let n = ring.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let (i0, t) = split(n, pos);
+    if t == 0.0 {
+        return ring[i0];
+    }
+    let half = SINC_HALF_TAPS as f32;
+    let mut acc = 0.0;
+    let mut norm = 0.0;
+    for k in 0..2 * SINC_HALF_TAPS {
+        // タップ位置は i0 - (half - 1) 〜 i0 + half
+        let offset = k as f32 - (half - 1.0);
+        let x = t - offset;
+        let px = std::f32::consts::PI * x;
+        let w = (px.sin() / px) * (0.5 + 0.5 * (px / half).cos());
+        acc += ring[(i0 + n * SINC_HALF_TAPS + k + 1 - SINC_HALF_TAPS) % n] * w;
+        norm += w;
+    }
+    if norm.abs() > 1e-9 {
+        acc / norm
+    } else {
+        0.0
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_read_interpolates_and_wraps() {
+        let ring = [0.0, 1.0, 2.0, 3.0];
+        assert_eq!(read_linear(&ring, 1.0), 1.0);
+        assert_eq!(read_linear(&ring, 1.25), 1.25);
+        // 末尾と先頭の間も補間する
+        assert_eq!(read_linear(&ring, 3.5), 1.5);
+        // 負の位置や 1 周以上先もリング上で折り返す
+        assert_eq!(read_linear(&ring, -0.5), 1.5);
+        assert_eq!(read_linear(&ring, 6.5), 2.5);
+        assert_eq!(read_linear(&[], 1.0), 0.0);
+    }
+
+    #[test]
+    fn higher_quality_tracks_sine_more_closely() {
+        // 補間の差が出やすいナイキスト寄り (約 0.31 サイクル/サンプル) の正弦波
+        let ring: Vec<f32> = (0..256)
+            .map(|i| (i as f32 * std::f32::consts::TAU * 80.0 / 256.0).sin())
+            .collect();
+        let max_err = |quality: Interpolation| {
+            (0..1_000)
+                .map(|j| {
+                    let pos = 10.0 + j as f64 * 0.2137;
+                    let exact = (pos as f32 * std::f32::consts::TAU * 80.0 / 256.0).sin();
+                    (quality.read(&ring, pos) - exact).abs()
+                })
+                .fold(0.0, f32::max)
+        };
+        let linear = max_err(Interpolation::Linear);
+        let hermite = max_err(Interpolation::Hermite);
+        let sinc = max_err(Interpolation::Sinc);
+        assert!(hermite < linear, "{hermite} vs {linear}");
+        assert!(sinc < hermite, "{sinc} vs {hermite}");
+
+        // 整数位置ではどの方式も元のサンプルをそのまま返す
+        for quality in [
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Sinc,
+        ] {
+            assert!((quality.read(&ring, 37.0) - ring[37]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn oversampling_suppresses_aliasing_when_transposing_up() {
+        // 0.45 サイクル/サンプルの正弦波を 1 オクターブ上げて読むと 0.1 サイクル/サンプルへ折り返す
+        let ring: Vec<f32> = (0..1_024)
+            .map(|i| (i as f32 * std::f32::consts::TAU * 0.45).sin())
+            .collect();
+        let rms = |os: Oversampling| {
+            let sum: f32 = (0..400)
+                .map(|i| {
+                    Interpolation::Hermite.read_oversampled(&ring, 8.0 + i as f64 * 2.0, 2.0, os)
+                })
+                .map(|x| x * x)
+                .sum();
+            (sum / 400.0).sqrt()
+        };
+        let (x1, x2, x4) = (
+            rms(Oversampling::X1),
+            rms(Oversampling::X2),
+            rms(Oversampling::X4),
+        );
+        assert!(x1 > 0.5, "{x1}");
+        assert!(x2 < 0.5 * x1, "{x2} vs {x1}");
+        assert!(x4 < x2, "{x4} vs {x2}");
+
+        // 移調していなければ読み方は変わらない
+        let pos = 100.3;
+        assert_eq!(
+            Interpolation::Sinc.read_oversampled(&ring, pos, 1.0, Oversampling::X4),
+            Interpolation::Sinc.read(&ring, pos)
+        );
+    }
+}
@@ -1,323 +1,3507 @@
 //! Granular Tukey-window effect (Python-compatible, nih-plug 0.11 + rand 0.9)
 
+#[cfg(all(test, not(feature = "debug-dump")))]
+mod alloc_check;
+pub mod analysis;
+pub mod automation;
+pub mod character;
+#[cfg(feature = "debug-dump")]
+pub mod dump;
+pub mod dynamics;
+pub mod engine;
+pub mod expression;
+pub mod filter;
+pub mod formant;
+#[cfg(test)]
+mod golden;
+pub mod interp;
+pub mod macros;
+pub mod meter;
+pub mod midi_out;
+pub mod migrate;
+pub mod modulation;
+pub mod preset;
+pub mod record;
+pub mod sample;
+pub mod scene;
+pub mod sequencer;
+pub mod smoothing;
+pub mod spectral;
+pub mod stft;
+pub mod stream;
+pub mod surround;
+pub mod window;
+
+use analysis::Scale;
+use character::{MAX_BITS, MAX_DOWNSAMPLE, MAX_DRIVE_DB, MIN_BITS};
+use dynamics::{
+    MAX_DUCK_ATTACK_MS, MAX_DUCK_DB, MAX_DUCK_RELEASE_MS, MAX_EXPAND_RATIO, MAX_GATE_ATTACK_MS,
+    MAX_GATE_RELEASE_MS, MAX_KEY_HOLD_MS, MIN_DUCK_ATTACK_MS, MIN_DUCK_RELEASE_MS,
+    MIN_GATE_ATTACK_MS, MIN_GATE_DB, MIN_GATE_RELEASE_MS,
+};
+use engine::{
+    length_range, Chord, ChordVoice, Engine, FrameParams, FreezeMode, Grain, GrainRouting,
+    GrainStart, LengthMode, NoteDivision, Overlap, ParamSource, PitchScatter, Quality, RingMode,
+    Shimmer, Source, TriggerMode, FREEZE_SLOTS, GRAIN_BUSES, MAX_AVOID_REPEATS, MAX_BURST,
+    MAX_CHORD_ST, MAX_FEEDBACK, MAX_FREEZE_BEATS, MAX_GLIDE_ST, MAX_GRAINS, MAX_GRAIN_MS,
+    MAX_HUMANIZE_MS, MAX_LENGTH_PCT, MAX_LOOKAHEAD_MS, MAX_PITCH_SPREAD_ST, MAX_PITCH_STEP_ST,
+    MAX_PRE_DELAY_MS, MAX_REPEATS, MAX_REROLL_BARS, MAX_REVERB_SEC, MAX_SCATTER_MS, MAX_SILENCE_DB,
+    MAX_SILENCE_RETRIES, MAX_TRIM_DB, MIN_FREEZE_BEATS, MIN_LENGTH_PCT, MIN_REVERB_SEC,
+    MIN_SILENCE_DB,
+};
+use filter::{Bands, MAX_CROSSOVER_HZ, MAX_CUT_HZ, MAX_TILT_DB, MIN_CROSSOVER_HZ, MIN_CUT_HZ};
+use formant::PitchMode;
+use interp::{Interpolation, Oversampling};
+use macros::{MacroMap, Macros, MACRO_COUNT};
+use migrate::STATE_VERSION;
+use modulation::{
+    ChaosMode, LfoShape, ModDest, ModSlot, ModSource, MAX_CHAOS_RATE, MAX_FLUTTER_CENTS,
+    MAX_FLUTTER_RATE, MAX_LFO_RATE, MAX_WALK_RATE, MAX_WOW_CENTS, MAX_WOW_RATE, MIN_CHAOS_RATE,
+    MIN_FLUTTER_RATE, MIN_LFO_RATE, MIN_WALK_RATE, MIN_WOW_RATE,
+};
 use nih_plug::prelude::*;
-use rand::{rng, Rng};
-use std::{num::NonZeroU32, sync::Arc};
+use rand::{rng, rngs::SmallRng, Rng, SeedableRng};
+use scene::{SceneMap, SCENE_COUNT};
+use sequencer::{default_pattern, SavedPattern, Sequence, MAX_EUCLID_STEPS};
+use smoothing::{MainSmoothers, MainTargets, SmoothingCurve, SmoothingSpeed};
+use std::path::Path;
+use std::{
+    num::NonZeroU32,
+    sync::{Arc, RwLock},
+};
+use surround::PanLaw;
+use window::WindowShape;
 
 /*──────────────────── 0. Parameters ────────────────────*/
 // ４つの FloatParam パラメータを持つ struct を定義する。
-// - density: グレイン生成確率 (0.0=生成なし, 1.0=毎ブロック必ず生成)
-// - min_ms: グレインの最小長 (ミリ秒単位)
+// - density: グレイン生成確率 (0.0=生成なし, 1.0=基準時間ごとに 1 グレイン)
+// - min_pct: グレインの最小長 (max_ms に対する %)。max_ms と組で持つので max_ms を超えない
 // - max_ms: グレインの最大長 (ミリ秒単位)
+// - length_mode / length_ms / length_pct / length_jitter: グレイン長の指定方法 (Min / Max、Length + Jitter、
+//   % of Ring) と、中心の長さ (ミリ秒、または % of Ring 時のリングの長さに対する %) と前後へ散らす割合 (%)
 // - mix: ウェット／ドライ比率 (0.0=ドライのみ, 1.0=100% ウェット)
+// - delta: ミックス後の出力からドライを引いた差分 (グレインが足した分) だけを聞くモニタースイッチ
+// - smoothing / smoothing_curve: density・mix・グレイン長の変化をならす時間 (Off / Fast / Standard / Slow) と曲線 (Linear / Logarithmic)
+// - audition: オンの間 mix を滑らかに 100% へ寄せてウェットだけを聞く (mix のオートメーションは変えない)
+// - mode / overlap: グレインの発生方式 (Random / Sync / Stretch / Tempo / Step Sequencer / Euclidean) と Sync・Stretch 時の重なり数
+// - speed: Stretch モードのプレイヘッド速度
+// - scale / root: 検出ピッチを合わせる音階とそのルート
+// - shimmer / feedback: グレインの上方移調とウェットのリングへの帰還量
+// - reverb / reverb_decay: 長いグレインを最大密度で重ねて残響にするクラウドリバーブと、その減衰時間
+// - chord: 1 回のトリガーで同じ区間を移調したグレインを重ねる和音 (Fifth / Octave / Power / Major / Minor / Custom)
+// - chord_1〜4_interval / chord_1〜4_level: Custom の和音の声部ごとの音程 (半音) とレベル (0 の声部は鳴らさない)
+// - freeze / spectral: スペクトルフリーズとそのブレンド量
+// - freeze_mode / freeze_beats: freeze スイッチの働き方 (Momentary / Latch / Timed / Sidechain) と Timed で保つ拍数
+// - key_threshold / key_hold / key_release: Sidechain でフリーズさせるサイドチェインのしきい値、保つ時間、エンベロープの戻り
+// - spectral_blur: ウェットの振幅スペクトルを時間方向にぼかす量 (0 より大きい間は STFT の分のレイテンシをホストへ報告)
+// - division / swing: Tempo モードのグリッド間隔とスウィング量
+// - humanize_ms: Random 以外のモードでグレインの開始をランダムに遅らせる幅 (ミリ秒単位)
+// - scatter_ms: Random モードで確率に当たったグレインの開始を一様に散らす窓の長さ (ミリ秒単位)
+// - burst: 1 回のトリガーで生成するグレインの数 (2 つ目以降は時間・移調・チャンネルを少しずつずらす)
+// - burst_density: density の上側で burst を増やし、density だけで疎な単発から密な雲まで動かす
+// - euclid_steps / euclid_pulses / euclid_rotation: Euclidean モードの 1 周のステップ数・発音数・回転
+// - reroll_bars: 再生中、この小節数ごとの頭で乱数列をシードから始め直し、テクスチャを小節単位で繰り返す
+// - gate / gate_write: トランスポート再生中のみ生成するか、停止中もリングへ書き込むか
+// - note_gate: MIDI ノートを押さえている間だけ生成し、density を押さえているノート数倍にする
+// - key_track / key_root: 新しいグレインを押さえている MIDI ノートと key_root の音程だけ移調する (鍵盤でフレーズを弾く)
+// - bend_range: MIDI / MPE のピッチベンドを振り切ったときの移調 (半音単位)
+// - pre_delay_ms: ドライに対するウェットの遅れ (ミリ秒単位)
+// - lookahead: ドライだけを遅らせ、グレインをドライより先の音から鳴らす時間 (ミリ秒単位、レイテンシとして報告)
+// - window / attack / decay: グレインの窓の形と ADSR 窓の立ち上がり・減衰 (% 単位)
+// - window_attack / window_release: Asymmetric 窓の立ち上がり・立ち下がり (グレイン長に対する割合)
+// - repeats / repeat_decay: グレインの繰り返し回数とパスごとのゲイン減衰
+// - walk_rate / walk_depth: density を揺らすランダムウォークの速さと深さ
+// - flux_density: 入力のスペクトルフラックスが小さい (持続音の) 間に density を下げる量
+// - input_trim / trim_dry: リング書き込み前の入力ゲインと、それをドライ (補助出力 Dry Out を含む) にも掛けるか
+// - quality: interpolation / max_grains / oversampling をまとめて切り替える品質 (Custom / Eco / Normal / High)
+// - interpolation: 小数位置を読むときの補間方式 (Linear / Cubic Hermite / Windowed Sinc)
+// - max_grains / oversampling: 同時発音数の上限と、上方向に移調したグレインを読むときのオーバーサンプリング
+// - pitch_mode: 移調の方式 (Resample / Formant (PSOLA))。Formant は重いがフォルマントを保つ
+// - guard / voice_budget: 平均同時発音数が予算を超えたらグレインを間引く過負荷保護
+// - glide: グレインの開始時の移調をランダムに選び、終わりに向けて本来の高さへ戻す幅 (半音単位)
+// - pitch_spread / pitch_scatter / pitch_step: グレインごとのランダムな移調の幅と、独立 / ランダムウォークの選び方と歩幅
+// - pingpong: グレインを順方向→逆方向に往復させて再生する確率
+// - zero_cross: グレインの端をリングの近くのゼロクロスへ揃える
+// - grain_tilt / tilt_random: グレインごとのティルト EQ (暗い ↔ 明るい) と、そのランダムな幅
+// - wow_depth / wow_rate / flutter_depth / flutter_rate: テープの回転むらのようにグレインの再生速度を揺らす深さ (セント) とレート
+// - grain_drive / grain_bits / grain_downsample / character_random: グレインごとのソフトクリップ・ビットクラッシュと、その効き具合のばらつき
+// - duck_depth / duck_attack / duck_release: ウェットのエンベロープでドライを下げるダッキング
+// - input_gate / input_gate_ratio / input_gate_release: リングへ書く前の入力のヒスや被りを下げるエキスパンダー
+// - wet_gate / wet_gate_attack / wet_gate_release: しきい値を下回ったウェットを消すゲート
+// - dc_block: ウェットの和から直流 (非対称な素材のグレインが重なって積み上がるオフセット) を取り除く
+// - low_cut / high_cut: ウェットの和に掛ける 12 dB/oct のハイパス / ローパスのカットオフ (範囲の端でオフ)
+// - bands / crossover_low / crossover_high / granulate_low / granulate_mid / granulate_high: 入力を 2〜3 帯域に分ける
+//   Linkwitz-Riley のクロスオーバーと、帯域ごとにグレインにするか素通しするか
+// - low_density / mid_density / high_density / low_mix / mid_mix / high_mix: マルチバンドで帯域ごとに
+//   トリガーからグレインを出す確率と、その帯域に掛ける mix の倍率
+// - silence_floor / silence_retries: 無音の区間からグレインを作らないためのしきい値と再試行回数
+// - grain_start: ライブ入力から切り出すグレインの開始位置の選び方 (一様 / 大きい音の区間へ寄せる / オンセットから / 拍のグリッドから)
+// - avoid_repeats: 直近の何個のグレインと切り出す区間が重ならないようにするか (0 でオフ)
+// - source / sample_path: Random・Tempo モードのグレインの切り出し元 (ライブ入力 / WAV サンプル) とサンプルのパス
+// - load_sample: sample::sample_dir() の一番新しい WAV を sample_path にして読み込むトリガー
+// - source_blend: Sample のときにグレインごとにサンプルから切り出す確率 (残りはライブ入力から)
+// - ring_mode / overdub: リングへの書き込み方 (Record / Overdub / Play) と重ね書きで既存の内容に掛けるゲイン
+// - capture_1〜capture_4 / freeze_slot: 今のリングをフリーズスロットへ取り込むトリガーと、グレインを切り出すスロット
+// - morph_slot / buffer_morph: freeze_slot から溶け込ませる先のスロットと、そこから切り出すグレインの割合
+// - clear / clear_grains: リングを消去するモーメンタリのトリガーと、そのとき鳴っているグレインも止めるか
+// - stop_kill / stop_clear: ホストのトランスポートが止まったときに鳴っているグレインを止めるか、リングも消去するか
+// - scene_morph / morph / store_a / store_b / scenes: 連続値の 2 つのシーンとその間のモーフィング、シーンへの保存トリガー
+// - sequence: Step Sequencer モードの 16 ステップのパターン (オン/オフ、長さの倍率、移調、発生確率。状態と一緒に保存)
+// - macro_1〜macro_4 / macro_targets: 複数の連続値のパラメータをまとめて動かすマクロとその割り当て
+// - routing / routing_buses: グレインを補助出力 Grains 1〜4 へ振り分ける方法 (Off / Round Robin / Random) と使うバスの数
+// - surround_spread: サラウンド出力 (Quad / 5.1 / 7.1) でグレインを VBAP で振る方向の広がり
+// - pan_law: 2 つのチャンネルの間にパンしたグレインのゲインの法則 (−3 dB / −4.5 dB / −6 dB)
+// - normalize: ウェットを 1/√(鳴っているグレイン数) 倍して density による音量の増減をならす
+// - seed / reseed: 状態と一緒に保存する乱数のシードと、新しいシードを選ぶトリガー
+// - record: オンの間、ウェットを WAV ファイルへ録音する (DAW のトラックを録音待機にせずに残せる)
+// - export_preset: 今のパラメータを JSON プリセットのファイルへ書き出すトリガー (preset.rs を参照)
+// - midi_out: グレインが生まれるたびに MIDI ノートを送る (外部のビジュアライザやハードウェアを同期させる)
+// - state_version: 保存した状態のバージョン (古い状態は filter_state で今のパラメータへ変換する)
+// - lfo1_* / lfo2_* / mod_random_rate / chaos_* / mod_cc: モジュレーションマトリクスの元 (LFO、ランダムウォーク、
+//   カオス、MIDI CC、補助入力 Modulation)
+// - mod_1〜mod_4 の source / dest / depth: 元 × 深さを density・長さ・位置・ピッチ・mix へ足すスロット
+// パラメータは Grain / Trigger / Pitch / Output / Modulation のグループに分けて持つ (汎用エディタでの表示用)。
+// id_prefix を付けないので ID は分ける前と同じで、保存した状態やオートメーションはそのまま読める
 #[derive(Params)]
 pub struct GranularParams {
-    /// グレインを生成する確率 (0.0=生成なし, 1.0=毎ブロック必ず生成)
-    #[id = "density"]
-    pub density: FloatParam,
+    /// 長さ・窓・繰り返し・キャラクター・切り出し元・リング・品質
+    #[nested(group = "Grain")]
+    pub grain: GrainParams,
+
+    /// 密度・トリガーモード・リズム・ゲート・トランスポート
+    #[nested(group = "Trigger")]
+    pub trigger: TriggerParams,
+
+    /// 音階・和音・移調のばらつき・グライド・回転むら
+    #[nested(group = "Pitch")]
+    pub pitch: PitchParams,
+
+    /// ミックス・帰還・フリーズ・ダイナミクス・フィルター・マルチバンド・出力先
+    #[nested(group = "Output")]
+    pub output: OutputParams,
+
+    /// シーン・マクロ・LFO・カオス・モジュレーションマトリクス
+    #[nested(group = "Modulation")]
+    pub modulation: ModulationParams,
 
-    /// グレインの最小長 (ミリ秒単位)
-    #[id = "min_ms"]
-    pub min_ms: FloatParam,
+    /// 保存したシーン A / B (FrameParams のフィールド名 → 値)。状態と一緒に保存される。
+    #[persist = "scenes"]
+    pub scenes: Arc<RwLock<[SceneMap; SCENE_COUNT]>>,
+
+    /// マクロごとの割り当て (FrameParams のフィールド名, 範囲に対する深さ -1.0〜1.0)。
+    /// 状態と一緒に保存され、initialize で読み込む。
+    #[persist = "macro_targets"]
+    pub macro_targets: Arc<RwLock<[MacroMap; MACRO_COUNT]>>,
+
+    /// Step Sequencer モードの 16 ステップ (グレインを出すか, 長さの倍率, 移調 (半音), 発生確率)。
+    /// 状態と一緒に保存され、エディタで書き換えるとブロックの頭で読み込む。
+    #[persist = "sequence"]
+    pub sequence: Arc<RwLock<SavedPattern>>,
+
+    /// 読み込む WAV ファイルのパス (空なら読み込まない)。状態と一緒に保存され、
+    /// initialize のたびにバックグラウンドタスクで読み込み直す。Load Sample で設定する
+    #[persist = "sample_path"]
+    pub sample_path: Arc<RwLock<String>>,
+
+    /// 乱数のシード。状態と一緒に保存され、initialize / reset のたびにここから乱数列を始め直すので、
+    /// プロジェクトを開き直しても同じテクスチャが同じところから再現される。
+    #[persist = "seed"]
+    pub seed: Arc<RwLock<u64>>,
+
+    /// 保存した状態のバージョン。古いバージョンの状態は filter_state で今のパラメータへ変換してから読む
+    #[persist = "state_version"]
+    pub state_version: Arc<RwLock<u32>>,
+}
+
+/// Grain グループ: 長さ・窓・繰り返し・キャラクター・切り出し元・リング・品質
+#[derive(Params)]
+pub struct GrainParams {
+    /// グレインの最小長 (最大長に対する %)
+    #[id = "min_pct"]
+    pub min_pct: FloatParam,
 
     /// グレインの最大長 (ミリ秒単位)
     #[id = "max_ms"]
     pub max_ms: FloatParam,
 
+    /// グレイン長の指定方法。Length + Jitter では Min / Max Length の代わりに下の 2 つを使う
+    #[id = "length_mode"]
+    pub length_mode: EnumParam<LengthMode>,
+
+    /// Length + Jitter 時のグレインの中心の長さ (ミリ秒単位)
+    #[id = "length_ms"]
+    pub length_ms: FloatParam,
+
+    /// % of Ring 時のグレインの中心の長さ (リングの長さに対する %)
+    #[id = "length_pct"]
+    pub length_pct: FloatParam,
+
+    /// Length + Jitter / % of Ring 時に中心の長さの前後へ散らす割合 (%)
+    #[id = "length_jitter"]
+    pub length_jitter: FloatParam,
+
+    /// グレインの窓の形 (Tukey=切り出し時, ADSR=読み出し時に attack / decay を適用)
+    #[id = "window"]
+    pub window: EnumParam<WindowShape>,
+
+    /// ADSR 窓の立ち上がり (グレイン長に対する %)
+    #[id = "attack"]
+    pub attack: FloatParam,
+
+    /// ADSR 窓の減衰 (グレイン長に対する %)
+    #[id = "decay"]
+    pub decay: FloatParam,
+
+    /// Asymmetric 窓の立ち上がり (グレイン長に対する割合)。短くすると打楽器的なグレインになる
+    #[id = "window_attack"]
+    pub window_attack: FloatParam,
+
+    /// Asymmetric 窓の立ち下がり (グレイン長に対する割合)。立ち上がりより長くすると逆再生のような質感になる
+    #[id = "window_release"]
+    pub window_release: FloatParam,
+
+    /// 各グレインを繰り返し再生する回数 (1=繰り返しなし)
+    #[id = "repeats"]
+    pub repeats: IntParam,
+
+    /// 繰り返しのたびにゲインを下げる割合 (0.0=一定)
+    #[id = "repeat_decay"]
+    pub repeat_decay: FloatParam,
+
+    /// 品質。Eco / Normal / High は補間方式・同時発音数の上限・オーバーサンプリングをまとめて決める
+    /// (ノート PC では Eco でトラッキングし、High でバウンスする)。Custom なら下の 3 つを個別に使う
+    #[id = "quality"]
+    pub quality: EnumParam<Quality>,
+
+    /// グレインの読み出しの補間方式 (ライブは Linear、オフラインのレンダーは高品質に)
+    #[id = "interpolation"]
+    pub interpolation: EnumParam<Interpolation>,
+
+    /// 同時に鳴らすグレイン数の上限。超えると最も古いグレインをフェードアウトさせる
+    #[id = "max_grains"]
+    pub max_grains: IntParam,
+
+    /// 上方向に移調したグレインを切り出すときに 1 サンプルあたり読む点数 (多いほど折り返しが減り重くなる)
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<Oversampling>,
+
+    /// 過負荷保護 (密な設定でホストのドロップアウトを起こす前にグレインを間引く)
+    #[id = "guard"]
+    pub guard: BoolParam,
+
+    /// 過負荷保護の予算 (ブロック内の平均同時発音数)
+    #[id = "voice_budget"]
+    pub voice_budget: IntParam,
+
+    /// グレインをピンポン (順方向→逆方向) で再生する確率。長いグレインのループが滑らかになる
+    #[id = "pingpong"]
+    pub pingpong: FloatParam,
+
+    /// グレインの先頭 (元の速度で読む単音のグレインは終わりも) を近くのゼロクロスへずらす。
+    /// 矩形に近い窓を選んだときのクリックを減らす
+    #[id = "zero_cross"]
+    pub zero_cross: BoolParam,
+
+    /// グレインを切り出すときにかけるソフトクリップのドライブ (dB、0=オフ)。ドライには影響しない
+    #[id = "grain_drive"]
+    pub grain_drive: FloatParam,
+
+    /// グレインを量子化するビット深度 (24=オフ)
+    #[id = "grain_bits"]
+    pub grain_bits: FloatParam,
+
+    /// グレインのサンプルを保持する間隔 (1=オフ)。サンプルレートを 1/倍率 に下げたような折り返しが出る
+    #[id = "grain_downsample"]
+    pub grain_downsample: FloatParam,
+
+    /// グレインごとにドライブ・ビット・ダウンサンプルの効き具合を 1 - この値〜1 倍のランダムにする
+    #[id = "character_random"]
+    pub character_random: FloatParam,
+
+    /// グレインを切り出すときにかけるティルト (±dB、正で高域を上げ低域を下げる)
+    #[id = "grain_tilt"]
+    pub grain_tilt: FloatParam,
+
+    /// グレインごとにティルトへランダムに足す幅 (±dB)。重なるグレインの音色がばらけ、クラウドが濁りにくくなる
+    #[id = "tilt_random"]
+    pub tilt_random: FloatParam,
+
+    /// 切り出す区間の RMS がこれを下回ったらグレインを作らない (dB、最小値でオフ)。
+    /// 無音の区間から鳴らない「死んだ」グレインで同時発音数を無駄にしない
+    #[id = "silence_floor"]
+    pub silence_floor: FloatParam,
+
+    /// 無音だったときに別の位置を試す回数 (Random / Tempo モード)
+    #[id = "silence_retries"]
+    pub silence_retries: IntParam,
+
+    /// Random / Tempo モードのグレインの切り出し元 (Live Input=リングバッファ, Sample=読み込んだ WAV)。
+    /// サンプルが読み込まれていなければライブ入力から切り出す。
+    #[id = "source"]
+    pub source: EnumParam<Source>,
+
+    /// オンになった瞬間に、sample::sample_dir() の中で最後に更新された WAV ファイルを sample_path にして読み込む
+    /// (エディタが無いのでファイルを選ぶ代わり)
+    #[id = "load_sample"]
+    pub load_sample: BoolParam,
+
+    /// ライブ入力から切り出すグレインの開始位置の選び方。Loudness はリングの粗いラウドネスマップで
+    /// 大きい音の区間へ寄せ、ルームトーンではなく音楽的な内容を含むグレインにする。
+    /// Onset は検出した立ち上がりからちょうど始め、ドラムを打撃ごとに並べ替える。
+    /// Beat Grid は書き込んだときにホストの再生位置が division のグリッド上にあった位置から始める
+    #[id = "grain_start"]
+    pub grain_start: EnumParam<GrainStart>,
+
+    /// 直近のこの数のグレインと切り出す区間が重ならないように開始位置を選び直す (Random / Tempo モード、0 でオフ)。
+    /// 疎な設定で同じ区間ばかりが続けて鳴るのを防ぐ
+    #[id = "avoid_repeats"]
+    pub avoid_repeats: IntParam,
+
+    /// source が Sample のとき、グレインごとにサンプルから切り出す確率 (0.0=すべてライブ入力,
+    /// 1.0=すべてサンプル)。ライブ入力と背景のテクスチャを交互に鳴らす。
+    #[id = "source_blend"]
+    pub source_blend: FloatParam,
+
+    /// リングへの書き込み方 (Record=入力で上書き, Overdub=既存の内容に重ねる, Play=書き込みを止める)。
+    /// リングをルーパーのように使い、その内容をグラニュレーターへ送る。
+    /// Play にした瞬間、リングの継ぎ目を 50 ms の等パワーのクロスフェードでつなぎ、ループにする。
+    #[id = "ring_mode"]
+    pub ring_mode: EnumParam<RingMode>,
+
+    /// Overdub で重ねるたびに既存の内容に掛けるゲイン (0.0=上書きと同じ, 1.0=減衰なし)
+    #[id = "overdub"]
+    pub overdub: FloatParam,
+
+    /// オンにした瞬間に今のリングをフリーズスロット 1 へ取り込む (モーメンタリ)
+    #[id = "capture_1"]
+    pub capture_1: BoolParam,
+
+    /// オンにした瞬間に今のリングをフリーズスロット 2 へ取り込む (モーメンタリ)
+    #[id = "capture_2"]
+    pub capture_2: BoolParam,
+
+    /// オンにした瞬間に今のリングをフリーズスロット 3 へ取り込む (モーメンタリ)
+    #[id = "capture_3"]
+    pub capture_3: BoolParam,
+
+    /// オンにした瞬間に今のリングをフリーズスロット 4 へ取り込む (モーメンタリ)
+    #[id = "capture_4"]
+    pub capture_4: BoolParam,
+
+    /// グレインを切り出すフリーズスロット (0=ライブのリング)。オートメーションで保持した複数のテクスチャを
+    /// 切り替える。取り込んでいないスロットはライブのリングを読む
+    #[id = "freeze_slot"]
+    pub freeze_slot: IntParam,
+
+    /// buffer_morph で溶け込ませる先のフリーズスロット (0=ライブのリング)
+    #[id = "morph_slot"]
+    pub morph_slot: IntParam,
+
+    /// グレインごとに freeze_slot ではなく morph_slot から切り出す確率。
+    /// オートメーションで 0 から 1 へ動かすと、一方のテクスチャがもう一方へ溶けていく
+    #[id = "buffer_morph"]
+    pub buffer_morph: FloatParam,
+
+    /// オンにした瞬間にリングを消去する (モーメンタリ)。曲のセクションの合間に古い音を捨てる。
+    #[id = "clear"]
+    pub clear: BoolParam,
+
+    /// 消去のときに鳴っているグレインも短いフェードで止めるか
+    #[id = "clear_grains"]
+    pub clear_grains: BoolParam,
+}
+
+/// Trigger グループ: 密度・トリガーモード・リズム・ゲート・トランスポート
+#[derive(Params)]
+pub struct TriggerParams {
+    /// グレインを生成する確率 (0.0=生成なし, 1.0=TRIGGER_REF_SEC ごとに 1 グレイン)。
+    /// ブロック長で正規化されるため、ホストのバッファサイズに依存しない。
+    #[id = "density"]
+    pub density: FloatParam,
+
+    /// グレインの発生方式 (Random=density による確率的発生, Sync=一定間隔, Stretch=一定間隔でプレイヘッドから,
+    /// Tempo=テンポのグリッド上で確率的に, Step Sequencer=グリッドを sequence のパターンで,
+    /// Euclidean=グリッドをユークリッドリズムで)
+    #[id = "mode"]
+    pub mode: EnumParam<TriggerMode>,
+
+    /// Sync / Stretch モードで同時に重なるグレイン数。発生間隔は グレイン長 / overlap になる。
+    #[id = "overlap"]
+    pub overlap: EnumParam<Overlap>,
+
+    /// Stretch モードのプレイヘッド速度 (0=停止, 1=実時間, 2=倍速)
+    #[id = "speed"]
+    pub speed: FloatParam,
+
+    /// Tempo モードでグレインを発生させる音符グリッド
+    #[id = "division"]
+    pub division: EnumParam<NoteDivision>,
+
+    /// Tempo モードのスウィング量 (0.0=なし, 1.0=裏のステップを半ステップ遅らせる)
+    #[id = "swing"]
+    pub swing: FloatParam,
+
+    /// Random 以外のモードで、トリガーごとにグレインの開始を 0〜humanize_ms のランダムな時間だけ遅らせ、
+    /// 機械的なグリッドを崩す (ミリ秒単位)
+    #[id = "humanize_ms"]
+    pub humanize_ms: FloatParam,
+
+    /// Random モードで、確率に当たったグレインをすぐ鳴らさず、その後の scatter_ms の窓の中へ一様に散らす。
+    /// グレインの開始がブロックの境目やほかのグレインと揃わなくなる (ミリ秒単位)
+    #[id = "scatter_ms"]
+    pub scatter_ms: FloatParam,
+
+    /// 1 回のトリガーで生成するグレインの数。2 つ目以降は BURST_SPREAD_MS 以内に遅らせ、
+    /// ±BURST_DETUNE_ST だけ移調し、ランダムなチャンネルへ出して、1 つのイベントから厚い塊を作る
+    #[id = "burst"]
+    pub burst: IntParam,
+
+    /// density の上半分 (BURST_DENSITY_KNEE 以上) で burst を MAX_BURST まで増やす。
+    /// 2 つのパラメータを揃えてオートメーションしなくても、density だけで疎な単発から密な雲まで動かせる
+    #[id = "burst_density"]
+    pub burst_density: BoolParam,
+
+    /// Euclidean モードの 1 周のステップ数
+    #[id = "euclid_steps"]
+    pub euclid_steps: IntParam,
+
+    /// Euclidean モードの 1 周の発音数 (ステップ数を超える分は全ステップで鳴らす)
+    #[id = "euclid_pulses"]
+    pub euclid_pulses: IntParam,
+
+    /// Euclidean モードのパターンを前へ回すステップ数
+    #[id = "euclid_rotation"]
+    pub euclid_rotation: IntParam,
+
+    /// 再生中、この小節数ごとの頭で乱数列をシードから始め直す (0 でオフ)。
+    /// グレインの長さ・位置・パンなどの選び方が小節単位で繰り返される
+    #[id = "reroll_bars"]
+    pub reroll_bars: IntParam,
+
+    /// ホストのトランスポートが再生中のときだけグレインを生成する
+    #[id = "gate"]
+    pub gate: BoolParam,
+
+    /// gate で停止中もリングバッファへの書き込みを続ける
+    #[id = "gate_write"]
+    pub gate_write: BoolParam,
+
+    /// MIDI ノートを押さえている間だけグレインを生成する。density は押さえているノートの数だけ倍になる
+    #[id = "note_gate"]
+    pub note_gate: BoolParam,
+
+    /// density を揺らすランダムウォークの速さ (Hz)
+    #[id = "walk_rate"]
+    pub walk_rate: FloatParam,
+
+    /// ランダムウォークで density を動かす幅 (0.0=揺らさない)
+    #[id = "walk_depth"]
+    pub walk_depth: FloatParam,
+
+    /// 入力のスペクトルフラックスで density を下げる量 (0.0=オフ)。変化の多いフィルでは濃く、
+    /// 持続音では薄くなるので、フィルだけをグリッチさせられる
+    #[id = "flux_density"]
+    pub flux_density: FloatParam,
+
+    /// ホストのトランスポートが止まったときに、鳴っているグレインを短いフェードで止める
+    #[id = "stop_kill"]
+    pub stop_kill: BoolParam,
+
+    /// ホストのトランスポートが止まったときにリングも消去する。
+    /// 停止と再生を繰り返しても前のパスの素材が鳴らない
+    #[id = "stop_clear"]
+    pub stop_clear: BoolParam,
+
+    /// オンにした瞬間に新しいシードを選び、そこから乱数列を始め直す (モーメンタリ)
+    #[id = "reseed"]
+    pub reseed: BoolParam,
+}
+
+/// Pitch グループ: 音階・和音・移調のばらつき・グライド・回転むら
+#[derive(Params)]
+pub struct PitchParams {
+    /// 入力の検出ピッチをこの音階の最も近い音へ合わせるようにグレインを移調する
+    #[id = "scale"]
+    pub scale: EnumParam<Scale>,
+
+    /// 音階のルート音 (0=C … 11=B)
+    #[id = "root"]
+    pub root: IntParam,
+
+    /// シマー (グレインを +12 / +7 半音移調する)。feedback と組み合わせて音を積み上げる。
+    #[id = "shimmer"]
+    pub shimmer: EnumParam<Shimmer>,
+
+    /// 和音。1 回のトリガーで同じ区間・同じ窓のグレインを声部ごとに移調して重ね、
+    /// モノフォニックの入力から和音のクラウドを作る
+    #[id = "chord"]
+    pub chord: EnumParam<Chord>,
+
+    /// Custom の和音の声部 1〜4: ルートからの音程 (±MAX_CHORD_ST 半音) とレベル (0.0〜1.0)。
+    /// レベル 0 の声部は鳴らさない
+    #[id = "chord_1_interval"]
+    pub chord_1_interval: FloatParam,
+
+    #[id = "chord_1_level"]
+    pub chord_1_level: FloatParam,
+
+    #[id = "chord_2_interval"]
+    pub chord_2_interval: FloatParam,
+
+    #[id = "chord_2_level"]
+    pub chord_2_level: FloatParam,
+
+    #[id = "chord_3_interval"]
+    pub chord_3_interval: FloatParam,
+
+    #[id = "chord_3_level"]
+    pub chord_3_level: FloatParam,
+
+    #[id = "chord_4_interval"]
+    pub chord_4_interval: FloatParam,
+
+    #[id = "chord_4_level"]
+    pub chord_4_level: FloatParam,
+
+    /// 新しく生成するグレインを、最後に押さえた MIDI ノートと key_root の音程だけ移調する
+    #[id = "key_track"]
+    pub key_track: BoolParam,
+
+    /// key_track で移調しない MIDI ノート番号
+    #[id = "key_root"]
+    pub key_root: IntParam,
+
+    /// ピッチベンドを振り切ったときの移調 (半音)。MPE のノートごとのチャンネルでは 48 が標準
+    #[id = "bend_range"]
+    pub bend_range: IntParam,
+
+    /// 移調の方式。Formant (PSOLA) は検出したピッチ周期で切り出し直して声のフォルマントを保つ
+    /// (再サンプリングより CPU を使う。グライド中のグレインは再サンプリング)
+    #[id = "pitch_mode"]
+    pub pitch_mode: EnumParam<PitchMode>,
+
+    /// グレインごとのピッチグライドの幅 (±半音、0=なし)。「ザップ」やドップラーのような質感になる
+    #[id = "glide"]
+    pub glide: FloatParam,
+
+    /// グレインごとのランダムな移調の幅 (±半音、0=なし)
+    #[id = "pitch_spread"]
+    pub pitch_spread: FloatParam,
+
+    /// ランダムな移調の選び方。Random Walk では前のグレインから pitch_step ずつ動き、旋律のように漂う
+    #[id = "pitch_scatter"]
+    pub pitch_scatter: EnumParam<PitchScatter>,
+
+    /// Random Walk の 1 グレインあたりの歩幅 (半音)
+    #[id = "pitch_step"]
+    pub pitch_step: FloatParam,
+
+    /// テープの回転むらのうち、ゆっくりした揺れ (wow) の深さ (±セント) とレート。
+    /// グレインを切り出すときの再生速度を揺らし、続けて生まれるグレインは同じ揺れを共有する
+    #[id = "wow_depth"]
+    pub wow_depth: FloatParam,
+
+    #[id = "wow_rate"]
+    pub wow_rate: FloatParam,
+
+    /// 速い揺れ (flutter) の深さ (±セント) とレート
+    #[id = "flutter_depth"]
+    pub flutter_depth: FloatParam,
+
+    #[id = "flutter_rate"]
+    pub flutter_rate: FloatParam,
+}
+
+/// Output グループ: ミックス・帰還・フリーズ・ダイナミクス・フィルター・マルチバンド・出力先
+#[derive(Params)]
+pub struct OutputParams {
     /// ウェット／ドライ比率 (0.0=ドライのみ, 1.0=100% ウェット)
     #[id = "mix"]
     pub mix: FloatParam,
+
+    /// モニター用: ミックス後の出力からドライを引いた差分 (グレインが足した分) だけを出力する。
+    /// mix を低くした薄いテクスチャを調整するときに、何が足されているかをそのまま聞ける
+    #[id = "delta"]
+    pub delta: BoolParam,
+
+    /// オンの間、mix を滑らかに 100% へ寄せてグレインのテクスチャだけを聞く。mix の値やオートメーションは
+    /// 書き換えないので、オフにすれば元の mix へ戻る
+    #[id = "audition"]
+    pub audition: BoolParam,
+
+    /// density / mix / グレイン長が変わったときに新しい値へ移る時間 (Off はオートメーションの値をそのまま使う)。
+    /// オートメーションで大きく跳んだときのクリックを防ぐ
+    #[id = "smoothing"]
+    pub smoothing: EnumParam<SmoothingSpeed>,
+
+    /// smoothing のランプの曲線 (Logarithmic は一定の比率で動き、長さの変化が耳に揃う)
+    #[id = "smoothing_curve"]
+    pub smoothing_curve: EnumParam<SmoothingCurve>,
+
+    /// ウェット出力をリングバッファへ戻す量 (0.0=帰還なし)
+    #[id = "feedback"]
+    pub feedback: FloatParam,
+
+    /// クラウドリバーブ。モード・density・長さ・feedback・normalize を残響向けに上書きし、
+    /// リングの書き込み位置から減衰時間に応じて遡った位置からグレインを切り出す
+    #[id = "reverb"]
+    pub reverb: BoolParam,
+
+    /// クラウドリバーブの減衰時間 (-60 dB までの秒数)。報告するテールの長さもこれに従う
+    #[id = "reverb_decay"]
+    pub reverb_decay: FloatParam,
+
+    /// スペクトルフリーズ。オンにした瞬間の振幅スペクトルをランダム位相で鳴らし続ける。
+    #[id = "freeze"]
+    pub freeze: BoolParam,
+
+    /// freeze スイッチの働き方。フットスイッチの CC を freeze へ割り当てたときに、押している間だけ /
+    /// 押すたびに切り替え / 押してから freeze_beats 拍だけ、のどれでフリーズするか。
+    /// Sidechain では Modulation 入力がしきい値を超えている間も自動でフリーズする
+    #[id = "freeze_mode"]
+    pub freeze_mode: EnumParam<FreezeMode>,
+
+    /// freeze_mode が Timed のときにフリーズを保つ長さ (拍)
+    #[id = "freeze_beats"]
+    pub freeze_beats: FloatParam,
+
+    /// freeze_mode が Sidechain のとき、Modulation 入力のピークがこれを超えたらフリーズする (dB)
+    #[id = "key_threshold"]
+    pub key_threshold: FloatParam,
+
+    /// サイドチェインがしきい値を下回ってからフリーズを保つ時間 (ミリ秒)。フレーズの息継ぎで解けない
+    #[id = "key_hold"]
+    pub key_hold: FloatParam,
+
+    /// サイドチェインのピークエンベロープが下がる時間 (ミリ秒)
+    #[id = "key_release"]
+    pub key_release: FloatParam,
+
+    /// フリーズ中のウェットに占めるスペクトル再合成の割合 (0.0=グレインのみ, 1.0=スペクトルのみ)
+    #[id = "spectral"]
+    pub spectral: FloatParam,
+
+    /// ウェットの振幅スペクトルを時間方向にぼかし、グレインの粒立ちを滲んだウォッシュに変える
+    /// (0.0=オフ, 1.0=時定数 2 秒)。0 より大きい間はウェットとドライが約 40 ms 遅れ、ホストへ報告する
+    #[id = "spectral_blur"]
+    pub spectral_blur: FloatParam,
+
+    /// ウェットのプリディレイ (ミリ秒単位)。ドライのアタックを際立たせる。
+    #[id = "pre_delay_ms"]
+    pub pre_delay_ms: FloatParam,
+
+    /// ドライを遅らせるルックアヘッド (ミリ秒単位、0 でオフ)。グレインがドライより先の音から鳴るので、
+    /// アタックの手前へプリエコーや逆再生のスウェルを置ける。遅らせた分はレイテンシとして報告する
+    #[id = "lookahead"]
+    pub lookahead: IntParam,
+
+    /// リングバッファへ書き込む前の入力ゲイン (リニア値、表示は dB)
+    #[id = "input_trim"]
+    pub input_trim: FloatParam,
+
+    /// 入力ゲインをドライ信号にも掛ける
+    #[id = "trim_dry"]
+    pub trim_dry: BoolParam,
+
+    /// ウェットが鳴っているときにドライを下げる最大量 (dB、0=オフ)。密なクラウドが入力に積み重ならない
+    #[id = "duck_depth"]
+    pub duck_depth: FloatParam,
+
+    /// ダッキングの立ち上がり (ミリ秒)
+    #[id = "duck_attack"]
+    pub duck_attack: FloatParam,
+
+    /// ダッキングの戻り (ミリ秒)
+    #[id = "duck_release"]
+    pub duck_release: FloatParam,
+
+    /// 入力のエキスパンダーのしきい値 (dB、最小値でオフ)。静かな部分のヒスや被りをリングに取り込まない
+    #[id = "input_gate"]
+    pub input_gate: FloatParam,
+
+    /// 入力のエキスパンダーの比率 (最大値でほぼゲート)
+    #[id = "input_gate_ratio"]
+    pub input_gate_ratio: FloatParam,
+
+    /// 入力のエキスパンダーが閉じる時間 (ミリ秒)
+    #[id = "input_gate_release"]
+    pub input_gate_release: FloatParam,
+
+    /// ウェットのゲートのしきい値 (dB、最小値でオフ)。リズミカルな素材でグレインの小さな残りを消す
+    #[id = "wet_gate"]
+    pub wet_gate: FloatParam,
+
+    /// ウェットのゲートが開く時間 (ミリ秒)
+    #[id = "wet_gate_attack"]
+    pub wet_gate_attack: FloatParam,
+
+    /// ウェットのゲートが閉じる時間 (ミリ秒)
+    #[id = "wet_gate_release"]
+    pub wet_gate_release: FloatParam,
+
+    /// ウェットのハイパスのカットオフ (Hz、最小値でオフ)。密なグレインに溜まる低域のうなりを削る
+    #[id = "low_cut"]
+    pub low_cut: FloatParam,
+
+    /// ウェットの直流を 5 Hz の 1 次ハイパスで取り除く。重なったグレインの直流がヘッドルームを食わないようにする
+    #[id = "dc_block"]
+    pub dc_block: BoolParam,
+
+    /// ウェットのローパスのカットオフ (Hz、最大値でオフ)。耳障りな高域を削る
+    #[id = "high_cut"]
+    pub high_cut: FloatParam,
+
+    /// 入力を Linkwitz-Riley (24 dB/oct) のクロスオーバーで 2〜3 帯域に分ける。グレインにしない帯域は
+    /// リングへ書かず、mix によらずそのまま出すので、ミックスで低域を崩さずに中高域だけをグレインにできる
+    #[id = "bands"]
+    pub bands: EnumParam<Bands>,
+
+    /// Low / Mid (2 Bands では Low / High) の分割周波数
+    #[id = "crossover_low"]
+    pub crossover_low: FloatParam,
+
+    /// Mid / High の分割周波数 (3 Bands のみ、crossover_low より下には置かない)
+    #[id = "crossover_high"]
+    pub crossover_high: FloatParam,
+
+    /// 帯域ごとにグレインにするか (オフの帯域はドライのまま出す。2 Bands では Mid を使わない)
+    #[id = "granulate_low"]
+    pub granulate_low: BoolParam,
+
+    #[id = "granulate_mid"]
+    pub granulate_mid: BoolParam,
+
+    #[id = "granulate_high"]
+    pub granulate_high: BoolParam,
+
+    /// 帯域ごとにトリガーからグレインを出す確率。帯域ごとに別のグレインの流れになるので、
+    /// High は密に、Mid はたまにだけ鳴らすといったことができる
+    #[id = "low_density"]
+    pub low_density: FloatParam,
+
+    #[id = "mid_density"]
+    pub mid_density: FloatParam,
+
+    #[id = "high_density"]
+    pub high_density: FloatParam,
+
+    /// 帯域ごとに mix に掛ける倍率 (0 でその帯域はドライのまま)
+    #[id = "low_mix"]
+    pub low_mix: FloatParam,
+
+    #[id = "mid_mix"]
+    pub mid_mix: FloatParam,
+
+    #[id = "high_mix"]
+    pub high_mix: FloatParam,
+
+    /// グレインを補助出力 Grains 1〜4 へ振り分ける方法。振り分けたグレインはメインのウェットに混ぜず、
+    /// プリディレイ・フリーズ・ゲート・帰還も通らない。DAW 側でグレインの流れごとに処理するために使う。
+    #[id = "routing"]
+    pub routing: EnumParam<GrainRouting>,
+
+    /// 振り分けに使う補助出力バスの数 (ホストに接続されているバスの数まで)
+    #[id = "routing_buses"]
+    pub routing_buses: IntParam,
+
+    /// Quad / 5.1 / 7.1 の出力で、グレインごとにランダムに選ぶ方向の広がり (0=正面だけ、1=全周)。
+    /// グレインは両隣のスピーカーへ VBAP でパンする。モノラル・ステレオでは使わない
+    #[id = "surround_spread"]
+    pub surround_spread: FloatParam,
+
+    /// 2 つのチャンネルの間にパンしたグレイン (サラウンド、ステレオのバースト) の中間でのゲイン
+    /// (-3 dB=等パワー, -4.5 dB, -6 dB=線形)。線形ならモノラルにまとめても位置で音量が変わらない
+    #[id = "pan_law"]
+    pub pan_law: EnumParam<PanLaw>,
+
+    /// ウェットを 1/√(鳴っているグレイン数) 倍する (ゲインは滑らかに追従する)。
+    /// density をオートメーションしても聴感上の音量がおおむね一定になる
+    #[id = "normalize"]
+    pub normalize: BoolParam,
+
+    /// オンの間、ウェットバス (mix の前) を 32bit float の WAV ファイルへ録音する。
+    /// オンにするたびに record::record_dir() へ新しいファイルを作る
+    #[id = "record"]
+    pub record: BoolParam,
+
+    /// オンになった瞬間に、その時点の全パラメータを preset::preset_dir() へ新しい JSON プリセットとして書き出す
+    #[id = "export_preset"]
+    pub export_preset: BoolParam,
+
+    /// オンの間、グレインごとにグレインの長さだけ鳴るノートを MIDI 出力へ送る
+    /// (チャンネル = 再生チャンネル、ノート = 移調、ベロシティ = 長さ。midi_out を参照)
+    #[id = "midi_out"]
+    pub midi_out: BoolParam,
 }
 
-impl Default for GranularParams {
+/// Modulation グループ: シーン・マクロ・LFO・カオス・モジュレーションマトリクス
+#[derive(Params)]
+pub struct ModulationParams {
+    /// オンなら連続値のパラメータをシーン A と B の補間で置き換える (離散的な設定はノブのまま)
+    #[id = "scene_morph"]
+    pub scene_morph: BoolParam,
+
+    /// シーン間の位置 (0.0=A, 1.0=B)。1 本のオートメーションで 2 つの音色の間を動かす。
+    #[id = "morph"]
+    pub morph: FloatParam,
+
+    /// オンにした瞬間に現在の連続値をシーン A へ保存する (モーメンタリ)
+    #[id = "store_a"]
+    pub store_a: BoolParam,
+
+    /// オンにした瞬間に現在の連続値をシーン B へ保存する (モーメンタリ)
+    #[id = "store_b"]
+    pub store_b: BoolParam,
+
+    /// マクロ 1〜4。macro_targets で割り当てた連続値のパラメータを深さに応じて動かす。
+    /// コントローラーにはマクロだけを割り当てればよい。
+    #[id = "macro_1"]
+    pub macro_1: FloatParam,
+
+    #[id = "macro_2"]
+    pub macro_2: FloatParam,
+
+    #[id = "macro_3"]
+    pub macro_3: FloatParam,
+
+    #[id = "macro_4"]
+    pub macro_4: FloatParam,
+
+    /// モジュレーションマトリクスの LFO 1 のレート (Hz)
+    #[id = "lfo1_rate"]
+    pub lfo1_rate: FloatParam,
+
+    /// LFO 1 の波形
+    #[id = "lfo1_shape"]
+    pub lfo1_shape: EnumParam<LfoShape>,
+
+    /// モジュレーションマトリクスの LFO 2 のレート (Hz)
+    #[id = "lfo2_rate"]
+    pub lfo2_rate: FloatParam,
+
+    /// LFO 2 の波形
+    #[id = "lfo2_shape"]
+    pub lfo2_shape: EnumParam<LfoShape>,
+
+    /// モジュレーションマトリクスのランダムウォークのステップレート (Hz)
+    #[id = "mod_random_rate"]
+    pub mod_random_rate: FloatParam,
+
+    /// モジュレーションマトリクスのカオスの選び方 (Sample & Hold / Drunk Walk)
+    #[id = "chaos_mode"]
+    pub chaos_mode: EnumParam<ChaosMode>,
+
+    /// カオスの値を選び直すレート (Hz)
+    #[id = "chaos_rate"]
+    pub chaos_rate: FloatParam,
+
+    /// カオスの滑らかさ (0=段になる、1=ステップ 1 つ分かけて次の値へ漂う)
+    #[id = "chaos_smooth"]
+    pub chaos_smooth: FloatParam,
+
+    /// モジュレーションマトリクスの元にする MIDI CC の番号 (1=モジュレーションホイール)
+    #[id = "mod_cc"]
+    pub mod_cc: IntParam,
+
+    /// モジュレーションのスロット 1〜4: 元 (source) の値 × 深さ (depth、-1.0〜1.0) を先 (dest) へ足す。
+    /// density と mix は範囲全体、長さは 1〜MAX_GRAIN_MS、Pitch は ±MAX_MOD_PITCH_ST 半音が深さ 1.0 にあたる。
+    #[id = "mod_1_source"]
+    pub mod_1_source: EnumParam<ModSource>,
+
+    #[id = "mod_1_dest"]
+    pub mod_1_dest: EnumParam<ModDest>,
+
+    #[id = "mod_1_depth"]
+    pub mod_1_depth: FloatParam,
+
+    #[id = "mod_2_source"]
+    pub mod_2_source: EnumParam<ModSource>,
+
+    #[id = "mod_2_dest"]
+    pub mod_2_dest: EnumParam<ModDest>,
+
+    #[id = "mod_2_depth"]
+    pub mod_2_depth: FloatParam,
+
+    #[id = "mod_3_source"]
+    pub mod_3_source: EnumParam<ModSource>,
+
+    #[id = "mod_3_dest"]
+    pub mod_3_dest: EnumParam<ModDest>,
+
+    #[id = "mod_3_depth"]
+    pub mod_3_depth: FloatParam,
+
+    #[id = "mod_4_source"]
+    pub mod_4_source: EnumParam<ModSource>,
+
+    #[id = "mod_4_dest"]
+    pub mod_4_dest: EnumParam<ModDest>,
+
+    #[id = "mod_4_depth"]
+    pub mod_4_depth: FloatParam,
+}
+
+impl Default for GrainParams {
     fn default() -> Self {
         Self {
-            density: FloatParam::new("Density", 0.2, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_smoother(SmoothingStyle::Linear(0.01)),
-
-            min_ms: FloatParam::new(
-                "Min Length (ms)",
-                20.0,
+            // 既定値は Max Length 500 ms の 4 % = 20 ms
+            min_pct: FloatParam::new(
+                "Min Length",
+                4.0,
                 FloatRange::Linear {
-                    min: 1.0,
-                    max: 1000.0,
+                    min: 0.0,
+                    max: 100.0,
                 },
             )
-            .with_smoother(SmoothingStyle::Linear(1.0)),
+            .with_smoother(SmoothingStyle::Linear(1.0))
+            .with_unit("%"),
 
             max_ms: FloatParam::new(
                 "Max Length (ms)",
                 500.0,
                 FloatRange::Linear {
                     min: 1.0,
-                    max: 1000.0,
+                    max: MAX_GRAIN_MS,
                 },
             )
             .with_smoother(SmoothingStyle::Linear(1.0)),
 
-            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_smoother(SmoothingStyle::Linear(0.01)),
+            length_mode: EnumParam::new("Length Mode", LengthMode::MinMax),
+
+            length_ms: FloatParam::new(
+                "Length (ms)",
+                260.0,
+                FloatRange::Linear {
+                    min: 1.0,
+                    max: MAX_GRAIN_MS,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(1.0)),
+
+            length_pct: FloatParam::new(
+                "Length (% of Ring)",
+                5.0,
+                FloatRange::Linear {
+                    min: MIN_LENGTH_PCT,
+                    max: MAX_LENGTH_PCT,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(1.0))
+            .with_unit("%"),
+
+            length_jitter: FloatParam::new(
+                "Length Jitter",
+                90.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(1.0))
+            .with_unit("%"),
+
+            window: EnumParam::new("Window", WindowShape::Tukey),
+
+            attack: FloatParam::new(
+                "Attack",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .with_unit("%"),
+
+            decay: FloatParam::new(
+                "Decay",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .with_unit("%"),
+
+            window_attack: FloatParam::new(
+                "Window Attack",
+                0.1,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            window_release: FloatParam::new(
+                "Window Release",
+                0.1,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            repeats: IntParam::new(
+                "Repeats",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_REPEATS,
+                },
+            ),
+
+            repeat_decay: FloatParam::new(
+                "Repeat Decay",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            quality: EnumParam::new("Quality", Quality::Custom),
+
+            interpolation: EnumParam::new("Interpolation", Interpolation::Linear),
+
+            max_grains: IntParam::new(
+                "Max Grains",
+                MAX_GRAINS as i32,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_GRAINS as i32,
+                },
+            ),
+
+            oversampling: EnumParam::new("Oversampling", Oversampling::X1),
+
+            guard: BoolParam::new("Overload Guard", false),
+
+            voice_budget: IntParam::new(
+                "Voice Budget",
+                16,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_GRAINS as i32,
+                },
+            ),
+
+            pingpong: FloatParam::new("Ping-Pong", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+
+            zero_cross: BoolParam::new("Zero-Cross Edges", false),
+
+            grain_drive: FloatParam::new(
+                "Grain Drive",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_DRIVE_DB,
+                },
+            )
+            .with_unit(" dB"),
+
+            grain_bits: FloatParam::new(
+                "Grain Bits",
+                MAX_BITS,
+                FloatRange::Linear {
+                    min: MIN_BITS,
+                    max: MAX_BITS,
+                },
+            )
+            .with_step_size(0.1),
+
+            grain_downsample: FloatParam::new(
+                "Grain Downsample",
+                1.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: MAX_DOWNSAMPLE,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit("x"),
+
+            character_random: FloatParam::new(
+                "Character Random",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            grain_tilt: FloatParam::new(
+                "Grain Tilt",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_TILT_DB,
+                    max: MAX_TILT_DB,
+                },
+            )
+            .with_unit(" dB"),
+
+            tilt_random: FloatParam::new(
+                "Tilt Random",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_TILT_DB,
+                },
+            )
+            .with_unit(" dB"),
+
+            silence_floor: FloatParam::new(
+                "Silence Floor",
+                MIN_SILENCE_DB,
+                FloatRange::Linear {
+                    min: MIN_SILENCE_DB,
+                    max: MAX_SILENCE_DB,
+                },
+            )
+            .with_unit(" dB"),
+
+            silence_retries: IntParam::new(
+                "Silence Retries",
+                2,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_SILENCE_RETRIES,
+                },
+            ),
+
+            source: EnumParam::new("Source", Source::Live),
+
+            load_sample: BoolParam::new("Load Sample", false),
+
+            grain_start: EnumParam::new("Grain Start", GrainStart::Uniform),
+
+            avoid_repeats: IntParam::new(
+                "Avoid Repeats",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_AVOID_REPEATS,
+                },
+            ),
+
+            source_blend: FloatParam::new(
+                "Source Blend",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            ring_mode: EnumParam::new("Ring Mode", RingMode::Record),
+
+            overdub: FloatParam::new("Overdub", 0.9, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            capture_1: BoolParam::new("Capture Slot 1", false),
+
+            capture_2: BoolParam::new("Capture Slot 2", false),
+
+            capture_3: BoolParam::new("Capture Slot 3", false),
+
+            capture_4: BoolParam::new("Capture Slot 4", false),
+
+            freeze_slot: IntParam::new(
+                "Freeze Slot",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: FREEZE_SLOTS as i32,
+                },
+            ),
+
+            morph_slot: IntParam::new(
+                "Morph Slot",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: FREEZE_SLOTS as i32,
+                },
+            ),
+
+            buffer_morph: FloatParam::new(
+                "Buffer Morph",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            clear: BoolParam::new("Clear Buffer", false),
+
+            clear_grains: BoolParam::new("Clear Grains", true),
+        }
+    }
+}
+
+impl Default for TriggerParams {
+    fn default() -> Self {
+        Self {
+            // 疎なテクスチャは 0.1 以下に集まるので、ノブの下半分をそこへ割り当てる (中央で約 0.06)
+            density: FloatParam::new(
+                "Density",
+                0.2,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(0.01)),
+
+            mode: EnumParam::new("Mode", TriggerMode::Random),
+
+            overlap: EnumParam::new("Overlap", Overlap::X4),
+
+            speed: FloatParam::new("Speed", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                .with_smoother(SmoothingStyle::Linear(10.0))
+                .with_unit("x"),
+
+            division: EnumParam::new("Division", NoteDivision::Sixteenth),
+
+            swing: FloatParam::new("Swing", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+
+            humanize_ms: FloatParam::new(
+                "Humanize",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_HUMANIZE_MS,
+                },
+            )
+            .with_unit(" ms"),
+
+            scatter_ms: FloatParam::new(
+                "Scatter",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_SCATTER_MS,
+                },
+            )
+            .with_unit(" ms"),
+
+            burst: IntParam::new(
+                "Burst",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_BURST,
+                },
+            ),
+
+            burst_density: BoolParam::new("Burst Follows Density", false),
+
+            euclid_steps: IntParam::new(
+                "Euclid Steps",
+                16,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_EUCLID_STEPS,
+                },
+            ),
+
+            euclid_pulses: IntParam::new(
+                "Euclid Pulses",
+                5,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_EUCLID_STEPS,
+                },
+            ),
+
+            euclid_rotation: IntParam::new(
+                "Euclid Rotation",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_EUCLID_STEPS - 1,
+                },
+            ),
+
+            reroll_bars: IntParam::new(
+                "Reroll Bars",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_REROLL_BARS,
+                },
+            )
+            .with_unit(" bars"),
+
+            gate: BoolParam::new("Transport Gate", false),
+
+            gate_write: BoolParam::new("Write While Stopped", true),
+
+            note_gate: BoolParam::new("Note Gate", false),
+
+            walk_rate: FloatParam::new(
+                "Walk Rate",
+                0.2,
+                FloatRange::Skewed {
+                    min: MIN_WALK_RATE,
+                    max: MAX_WALK_RATE,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            walk_depth: FloatParam::new(
+                "Walk Depth",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            flux_density: FloatParam::new(
+                "Adaptive Density",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            stop_kill: BoolParam::new("Kill On Stop", false),
+
+            stop_clear: BoolParam::new("Clear On Stop", false),
+
+            reseed: BoolParam::new("Reseed", false),
+        }
+    }
+}
+
+impl Default for PitchParams {
+    fn default() -> Self {
+        Self {
+            scale: EnumParam::new("Scale", Scale::Off),
+
+            root: IntParam::new("Root", 0, IntRange::Linear { min: 0, max: 11 }),
+
+            shimmer: EnumParam::new("Shimmer", Shimmer::Off),
+
+            chord: EnumParam::new("Chord", Chord::Off),
+
+            chord_1_interval: FloatParam::new(
+                "Chord 1 Interval",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_CHORD_ST,
+                    max: MAX_CHORD_ST,
+                },
+            )
+            .with_step_size(1.0)
+            .with_unit(" st"),
+
+            chord_1_level: FloatParam::new(
+                "Chord 1 Level",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            chord_2_interval: FloatParam::new(
+                "Chord 2 Interval",
+                7.0,
+                FloatRange::Linear {
+                    min: -MAX_CHORD_ST,
+                    max: MAX_CHORD_ST,
+                },
+            )
+            .with_step_size(1.0)
+            .with_unit(" st"),
+
+            chord_2_level: FloatParam::new(
+                "Chord 2 Level",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            chord_3_interval: FloatParam::new(
+                "Chord 3 Interval",
+                12.0,
+                FloatRange::Linear {
+                    min: -MAX_CHORD_ST,
+                    max: MAX_CHORD_ST,
+                },
+            )
+            .with_step_size(1.0)
+            .with_unit(" st"),
+
+            chord_3_level: FloatParam::new(
+                "Chord 3 Level",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            chord_4_interval: FloatParam::new(
+                "Chord 4 Interval",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_CHORD_ST,
+                    max: MAX_CHORD_ST,
+                },
+            )
+            .with_step_size(1.0)
+            .with_unit(" st"),
+
+            chord_4_level: FloatParam::new(
+                "Chord 4 Level",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            key_track: BoolParam::new("Key Track", false),
+
+            key_root: IntParam::new("Key Root", 60, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(formatters::v2s_i32_note_formatter())
+                .with_string_to_value(formatters::s2v_i32_note_formatter()),
+
+            bend_range: IntParam::new("Bend Range", 48, IntRange::Linear { min: 0, max: 96 })
+                .with_unit(" st"),
+
+            pitch_mode: EnumParam::new("Pitch Mode", PitchMode::Resample),
+
+            glide: FloatParam::new(
+                "Glide",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_GLIDE_ST,
+                },
+            )
+            .with_unit(" st"),
+
+            pitch_spread: FloatParam::new(
+                "Pitch Spread",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_PITCH_SPREAD_ST,
+                },
+            )
+            .with_unit(" st"),
+
+            pitch_scatter: EnumParam::new("Pitch Scatter", PitchScatter::Independent),
+
+            pitch_step: FloatParam::new(
+                "Pitch Walk Step",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_PITCH_STEP_ST,
+                },
+            )
+            .with_unit(" st"),
+
+            wow_depth: FloatParam::new(
+                "Wow Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_WOW_CENTS,
+                },
+            )
+            .with_unit(" ct"),
+
+            wow_rate: FloatParam::new(
+                "Wow Rate",
+                0.5,
+                FloatRange::Skewed {
+                    min: MIN_WOW_RATE,
+                    max: MAX_WOW_RATE,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            flutter_depth: FloatParam::new(
+                "Flutter Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_FLUTTER_CENTS,
+                },
+            )
+            .with_unit(" ct"),
+
+            flutter_rate: FloatParam::new(
+                "Flutter Rate",
+                8.0,
+                FloatRange::Linear {
+                    min: MIN_FLUTTER_RATE,
+                    max: MAX_FLUTTER_RATE,
+                },
+            )
+            .with_unit(" Hz"),
+        }
+    }
+}
+
+impl Default for OutputParams {
+    fn default() -> Self {
+        Self {
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(0.01)),
+
+            delta: BoolParam::new("Delta", false),
+
+            audition: BoolParam::new("Audition Wet", false),
+
+            smoothing: EnumParam::new("Smoothing", SmoothingSpeed::Standard),
+
+            smoothing_curve: EnumParam::new("Smoothing Curve", SmoothingCurve::Linear),
+
+            feedback: FloatParam::new(
+                "Feedback",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_FEEDBACK,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(10.0)),
+
+            reverb: BoolParam::new("Cloud Reverb", false),
+
+            reverb_decay: FloatParam::new(
+                "Reverb Decay",
+                4.0,
+                FloatRange::Skewed {
+                    min: MIN_REVERB_SEC,
+                    max: MAX_REVERB_SEC,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" s"),
+
+            freeze: BoolParam::new("Freeze", false),
+
+            freeze_mode: EnumParam::new("Freeze Mode", FreezeMode::Momentary),
+
+            freeze_beats: FloatParam::new(
+                "Freeze Beats",
+                4.0,
+                FloatRange::Skewed {
+                    min: MIN_FREEZE_BEATS,
+                    max: MAX_FREEZE_BEATS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" beats"),
+
+            key_threshold: FloatParam::new(
+                "Key Threshold",
+                -30.0,
+                FloatRange::Linear {
+                    min: MIN_GATE_DB,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+
+            key_hold: FloatParam::new(
+                "Key Hold",
+                200.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: MAX_KEY_HOLD_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            key_release: FloatParam::new(
+                "Key Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: MIN_GATE_RELEASE_MS,
+                    max: MAX_GATE_RELEASE_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            spectral: FloatParam::new("Spectral", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(10.0)),
+
+            spectral_blur: FloatParam::new(
+                "Spectral Blur",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            pre_delay_ms: FloatParam::new(
+                "Pre-Delay (ms)",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_PRE_DELAY_MS,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            lookahead: IntParam::new(
+                "Lookahead",
+                0,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_LOOKAHEAD_MS,
+                },
+            )
+            .with_unit(" ms"),
+
+            input_trim: FloatParam::new(
+                "Input Trim",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-MAX_TRIM_DB),
+                    max: util::db_to_gain(MAX_TRIM_DB),
+                    factor: FloatRange::gain_skew_factor(-MAX_TRIM_DB, MAX_TRIM_DB),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            trim_dry: BoolParam::new("Trim Dry", true),
+
+            duck_depth: FloatParam::new(
+                "Duck Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_DUCK_DB,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB"),
+
+            duck_attack: FloatParam::new(
+                "Duck Attack",
+                10.0,
+                FloatRange::Skewed {
+                    min: MIN_DUCK_ATTACK_MS,
+                    max: MAX_DUCK_ATTACK_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            duck_release: FloatParam::new(
+                "Duck Release",
+                250.0,
+                FloatRange::Skewed {
+                    min: MIN_DUCK_RELEASE_MS,
+                    max: MAX_DUCK_RELEASE_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            input_gate: FloatParam::new(
+                "Input Gate",
+                MIN_GATE_DB,
+                FloatRange::Linear {
+                    min: MIN_GATE_DB,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+
+            input_gate_ratio: FloatParam::new(
+                "Input Gate Ratio",
+                4.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: MAX_EXPAND_RATIO,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(":1"),
+
+            input_gate_release: FloatParam::new(
+                "Input Gate Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: MIN_GATE_RELEASE_MS,
+                    max: MAX_GATE_RELEASE_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            wet_gate: FloatParam::new(
+                "Wet Gate",
+                MIN_GATE_DB,
+                FloatRange::Linear {
+                    min: MIN_GATE_DB,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB"),
+
+            wet_gate_attack: FloatParam::new(
+                "Wet Gate Attack",
+                1.0,
+                FloatRange::Skewed {
+                    min: MIN_GATE_ATTACK_MS,
+                    max: MAX_GATE_ATTACK_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            wet_gate_release: FloatParam::new(
+                "Wet Gate Release",
+                50.0,
+                FloatRange::Skewed {
+                    min: MIN_GATE_RELEASE_MS,
+                    max: MAX_GATE_RELEASE_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+
+            low_cut: cutoff_param("Low Cut", MIN_CUT_HZ),
+
+            dc_block: BoolParam::new("DC Block", true),
+
+            high_cut: cutoff_param("High Cut", MAX_CUT_HZ),
+
+            bands: EnumParam::new("Multiband", Bands::Off),
+
+            crossover_low: frequency_param(
+                "Crossover Low",
+                200.0,
+                MIN_CROSSOVER_HZ,
+                MAX_CROSSOVER_HZ,
+            ),
+
+            crossover_high: frequency_param(
+                "Crossover High",
+                2_000.0,
+                MIN_CROSSOVER_HZ,
+                MAX_CROSSOVER_HZ,
+            ),
+
+            granulate_low: BoolParam::new("Granulate Low", false),
+
+            granulate_mid: BoolParam::new("Granulate Mid", true),
+
+            granulate_high: BoolParam::new("Granulate High", true),
+
+            low_density: band_param("Low Density"),
+
+            mid_density: band_param("Mid Density"),
+
+            high_density: band_param("High Density"),
+
+            low_mix: band_param("Low Mix"),
+
+            mid_mix: band_param("Mid Mix"),
+
+            high_mix: band_param("High Mix"),
+
+            routing: EnumParam::new("Grain Routing", GrainRouting::Off),
+
+            routing_buses: IntParam::new(
+                "Routing Buses",
+                GRAIN_BUSES as i32,
+                IntRange::Linear {
+                    min: 1,
+                    max: GRAIN_BUSES as i32,
+                },
+            ),
+
+            surround_spread: FloatParam::new(
+                "Surround Spread",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            pan_law: EnumParam::new("Pan Law", PanLaw::ConstantPower),
+
+            normalize: BoolParam::new("Normalize Wet", false),
+
+            record: BoolParam::new("Record", false),
+
+            export_preset: BoolParam::new("Export Preset", false),
+
+            midi_out: BoolParam::new("MIDI Out", false),
+        }
+    }
+}
+
+impl Default for ModulationParams {
+    fn default() -> Self {
+        Self {
+            scene_morph: BoolParam::new("Scene Morph", false),
+
+            morph: FloatParam::new("Morph", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            store_a: BoolParam::new("Store Scene A", false),
+
+            store_b: BoolParam::new("Store Scene B", false),
+
+            macro_1: FloatParam::new("Macro 1", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            macro_2: FloatParam::new("Macro 2", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            macro_3: FloatParam::new("Macro 3", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            macro_4: FloatParam::new("Macro 4", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            lfo1_rate: FloatParam::new(
+                "LFO 1 Rate",
+                1.0,
+                FloatRange::Skewed {
+                    min: MIN_LFO_RATE,
+                    max: MAX_LFO_RATE,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            lfo1_shape: EnumParam::new("LFO 1 Shape", LfoShape::Sine),
+
+            lfo2_rate: FloatParam::new(
+                "LFO 2 Rate",
+                1.0,
+                FloatRange::Skewed {
+                    min: MIN_LFO_RATE,
+                    max: MAX_LFO_RATE,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            lfo2_shape: EnumParam::new("LFO 2 Shape", LfoShape::Sine),
+
+            mod_random_rate: FloatParam::new(
+                "Mod Random Rate",
+                1.0,
+                FloatRange::Skewed {
+                    min: MIN_WALK_RATE,
+                    max: MAX_WALK_RATE,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            chaos_mode: EnumParam::new("Chaos Mode", ChaosMode::Drunk),
+
+            chaos_rate: FloatParam::new(
+                "Chaos Rate",
+                2.0,
+                FloatRange::Skewed {
+                    min: MIN_CHAOS_RATE,
+                    max: MAX_CHAOS_RATE,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+
+            chaos_smooth: FloatParam::new(
+                "Chaos Smooth",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+
+            mod_cc: IntParam::new("Mod CC", 1, IntRange::Linear { min: 0, max: 127 }),
+
+            mod_1_source: EnumParam::new("Mod 1 Source", ModSource::Off),
+
+            mod_1_dest: EnumParam::new("Mod 1 Destination", ModDest::Density),
+
+            mod_1_depth: FloatParam::new(
+                "Mod 1 Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            mod_2_source: EnumParam::new("Mod 2 Source", ModSource::Off),
+
+            mod_2_dest: EnumParam::new("Mod 2 Destination", ModDest::Density),
+
+            mod_2_depth: FloatParam::new(
+                "Mod 2 Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            mod_3_source: EnumParam::new("Mod 3 Source", ModSource::Off),
+
+            mod_3_dest: EnumParam::new("Mod 3 Destination", ModDest::Density),
+
+            mod_3_depth: FloatParam::new(
+                "Mod 3 Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(20.0)),
+
+            mod_4_source: EnumParam::new("Mod 4 Source", ModSource::Off),
+
+            mod_4_dest: EnumParam::new("Mod 4 Destination", ModDest::Density),
+
+            mod_4_depth: FloatParam::new(
+                "Mod 4 Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(20.0)),
+        }
+    }
+}
+
+impl Default for GranularParams {
+    fn default() -> Self {
+        Self {
+            grain: GrainParams::default(),
+            trigger: TriggerParams::default(),
+            pitch: PitchParams::default(),
+            output: OutputParams::default(),
+            modulation: ModulationParams::default(),
+
+            scenes: Arc::new(RwLock::new(Default::default())),
+
+            macro_targets: Arc::new(RwLock::new(Default::default())),
+
+            sequence: Arc::new(RwLock::new(default_pattern())),
+
+            sample_path: Arc::new(RwLock::new(String::new())),
+
+            seed: Arc::new(RwLock::new(rng().random())),
+
+            state_version: Arc::new(RwLock::new(STATE_VERSION)),
+        }
+    }
+}
+
+/// Low Cut / High Cut のカットオフのパラメータ (対数で動く)
+fn cutoff_param(name: &str, default: f32) -> FloatParam {
+    frequency_param(name, default, MIN_CUT_HZ, MAX_CUT_HZ)
+}
+
+/// マルチバンドの帯域ごとの 0.0〜1.0 のパラメータ (既定値 1.0)
+fn band_param(name: &str) -> FloatParam {
+    FloatParam::new(name, 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+        .with_smoother(SmoothingStyle::Linear(20.0))
+}
+
+/// `min`〜`max` Hz の周波数のパラメータ (対数的に効くよう低域側を広げる)
+fn frequency_param(name: &str, default: f32, min: f32, max: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min,
+            max,
+            factor: FloatRange::skew_factor(-2.0),
+        },
+    )
+    .with_smoother(SmoothingStyle::Logarithmic(50.0))
+    .with_unit(" Hz")
+    .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+    .with_string_to_value(formatters::s2v_f32_hz_then_khz())
+}
+
+/*──────────────────── 1. Parameter source ────────────*/
+/// ホストのパラメータをスムーザーを通してサンプルごとに読むパラメータ源。
+/// 3 つ目は、直前のフレームの後でどのスムーザーも止まっているか (`is_steady` で返す)
+struct SmoothedParams<'a>(&'a GranularParams, &'a mut MainSmoothers, bool);
+
+impl<'a> SmoothedParams<'a> {
+    fn new(params: &'a GranularParams, smoothers: &'a mut MainSmoothers) -> Self {
+        Self(params, smoothers, false)
+    }
+}
+
+impl ParamSource for SmoothedParams<'_> {
+    #[inline]
+    fn next_frame(&mut self) -> FrameParams {
+        // 進めた後もまだ動いているスムーザーが 1 つでもあれば、次のフレームは変わる
+        let mut moving = false;
+        let mut next = |param: &FloatParam| {
+            let value = param.smoothed.next();
+            moving |= param.smoothed.steps_left() > 0;
+            value
+        };
+        // 使わない側のスムーザーも進めておき、モードを切り替えたときに古い値から滑らないようにする
+        let min_pct = next(&self.0.grain.min_pct);
+        let max_ms = next(&self.0.grain.max_ms);
+        let length_ms = next(&self.0.grain.length_ms);
+        let length_jitter = next(&self.0.grain.length_jitter);
+        let (min_ms, max_ms) = length_bounds(
+            self.0.grain.length_mode.value(),
+            min_pct,
+            max_ms,
+            length_ms,
+            length_jitter,
+        );
+        let mut p = FrameParams {
+            density: next(&self.0.trigger.density),
+            min_ms,
+            max_ms,
+            length_mode: self.0.grain.length_mode.value(),
+            length_pct: next(&self.0.grain.length_pct),
+            length_jitter,
+            reverb: self.0.output.reverb.value(),
+            reverb_decay: next(&self.0.output.reverb_decay),
+            mix: next(&self.0.output.mix),
+            delta: self.0.output.delta.value(),
+            audition: self.0.output.audition.value(),
+            mode: self.0.trigger.mode.value(),
+            overlap: self.0.trigger.overlap.value(),
+            speed: next(&self.0.trigger.speed),
+            scale: self.0.pitch.scale.value(),
+            root: self.0.pitch.root.value(),
+            shimmer: self.0.pitch.shimmer.value(),
+            chord: self.0.pitch.chord.value(),
+            chord_voices: [
+                ChordVoice {
+                    interval: self.0.pitch.chord_1_interval.value(),
+                    level: self.0.pitch.chord_1_level.value(),
+                },
+                ChordVoice {
+                    interval: self.0.pitch.chord_2_interval.value(),
+                    level: self.0.pitch.chord_2_level.value(),
+                },
+                ChordVoice {
+                    interval: self.0.pitch.chord_3_interval.value(),
+                    level: self.0.pitch.chord_3_level.value(),
+                },
+                ChordVoice {
+                    interval: self.0.pitch.chord_4_interval.value(),
+                    level: self.0.pitch.chord_4_level.value(),
+                },
+            ],
+            feedback: next(&self.0.output.feedback),
+            freeze: self.0.output.freeze.value(),
+            freeze_mode: self.0.output.freeze_mode.value(),
+            freeze_beats: self.0.output.freeze_beats.value(),
+            key_threshold_db: self.0.output.key_threshold.value(),
+            key_hold_ms: self.0.output.key_hold.value(),
+            key_release_ms: self.0.output.key_release.value(),
+            spectral: next(&self.0.output.spectral),
+            spectral_blur: next(&self.0.output.spectral_blur),
+            division: self.0.trigger.division.value(),
+            swing: self.0.trigger.swing.value(),
+            humanize_ms: self.0.trigger.humanize_ms.value(),
+            scatter_ms: self.0.trigger.scatter_ms.value(),
+            burst: self.0.trigger.burst.value(),
+            burst_density: self.0.trigger.burst_density.value(),
+            euclid_steps: self.0.trigger.euclid_steps.value(),
+            euclid_pulses: self.0.trigger.euclid_pulses.value(),
+            euclid_rotation: self.0.trigger.euclid_rotation.value(),
+            reroll_bars: self.0.trigger.reroll_bars.value(),
+            gate: self.0.trigger.gate.value(),
+            gate_write: self.0.trigger.gate_write.value(),
+            note_gate: self.0.trigger.note_gate.value(),
+            key_track: self.0.pitch.key_track.value(),
+            key_root: self.0.pitch.key_root.value(),
+            pre_delay_ms: next(&self.0.output.pre_delay_ms),
+            lookahead_ms: self.0.output.lookahead.value(),
+            window: self.0.grain.window.value(),
+            attack: self.0.grain.attack.value(),
+            decay: self.0.grain.decay.value(),
+            repeats: self.0.grain.repeats.value(),
+            repeat_decay: self.0.grain.repeat_decay.value(),
+            walk_rate: self.0.trigger.walk_rate.value(),
+            walk_depth: next(&self.0.trigger.walk_depth),
+            flux_density: next(&self.0.trigger.flux_density),
+            input_gain: next(&self.0.output.input_trim),
+            trim_dry: self.0.output.trim_dry.value(),
+            quality: self.0.grain.quality.value(),
+            interpolation: self.0.grain.interpolation.value(),
+            max_grains: self.0.grain.max_grains.value(),
+            oversampling: self.0.grain.oversampling.value(),
+            pitch_mode: self.0.pitch.pitch_mode.value(),
+            guard: self.0.grain.guard.value(),
+            voice_budget: self.0.grain.voice_budget.value(),
+            glide: self.0.pitch.glide.value(),
+            pitch_spread: self.0.pitch.pitch_spread.value(),
+            pitch_scatter: self.0.pitch.pitch_scatter.value(),
+            pitch_step: self.0.pitch.pitch_step.value(),
+            pingpong: self.0.grain.pingpong.value(),
+            zero_cross: self.0.grain.zero_cross.value(),
+            grain_drive_db: self.0.grain.grain_drive.value(),
+            grain_bits: self.0.grain.grain_bits.value(),
+            grain_downsample: self.0.grain.grain_downsample.value(),
+            character_random: self.0.grain.character_random.value(),
+            grain_tilt_db: self.0.grain.grain_tilt.value(),
+            tilt_random_db: self.0.grain.tilt_random.value(),
+            wow_cents: self.0.pitch.wow_depth.value(),
+            wow_rate: self.0.pitch.wow_rate.value(),
+            flutter_cents: self.0.pitch.flutter_depth.value(),
+            flutter_rate: self.0.pitch.flutter_rate.value(),
+            duck_db: next(&self.0.output.duck_depth),
+            duck_attack_ms: self.0.output.duck_attack.value(),
+            duck_release_ms: self.0.output.duck_release.value(),
+            input_gate_db: self.0.output.input_gate.value(),
+            input_gate_ratio: self.0.output.input_gate_ratio.value(),
+            input_gate_release_ms: self.0.output.input_gate_release.value(),
+            wet_gate_db: self.0.output.wet_gate.value(),
+            wet_gate_attack_ms: self.0.output.wet_gate_attack.value(),
+            wet_gate_release_ms: self.0.output.wet_gate_release.value(),
+            dc_block: self.0.output.dc_block.value(),
+            low_cut_hz: next(&self.0.output.low_cut),
+            high_cut_hz: next(&self.0.output.high_cut),
+            bands: self.0.output.bands.value(),
+            crossover_low_hz: next(&self.0.output.crossover_low),
+            crossover_high_hz: next(&self.0.output.crossover_high),
+            granulate_bands: [
+                self.0.output.granulate_low.value(),
+                self.0.output.granulate_mid.value(),
+                self.0.output.granulate_high.value(),
+            ],
+            band_density: [
+                next(&self.0.output.low_density),
+                next(&self.0.output.mid_density),
+                next(&self.0.output.high_density),
+            ],
+            band_mix: [
+                next(&self.0.output.low_mix),
+                next(&self.0.output.mid_mix),
+                next(&self.0.output.high_mix),
+            ],
+            silence_db: self.0.grain.silence_floor.value(),
+            silence_retries: self.0.grain.silence_retries.value(),
+            source: self.0.grain.source.value(),
+            grain_start: self.0.grain.grain_start.value(),
+            avoid_repeats: self.0.grain.avoid_repeats.value(),
+            source_blend: self.0.grain.source_blend.value(),
+            ring_mode: self.0.grain.ring_mode.value(),
+            overdub: next(&self.0.grain.overdub),
+            capture: [
+                self.0.grain.capture_1.value(),
+                self.0.grain.capture_2.value(),
+                self.0.grain.capture_3.value(),
+                self.0.grain.capture_4.value(),
+            ],
+            freeze_slot: self.0.grain.freeze_slot.value(),
+            morph_slot: self.0.grain.morph_slot.value(),
+            buffer_morph: next(&self.0.grain.buffer_morph),
+            clear: self.0.grain.clear.value(),
+            clear_grains: self.0.grain.clear_grains.value(),
+            stop_kill: self.0.trigger.stop_kill.value(),
+            stop_clear: self.0.trigger.stop_clear.value(),
+            scene_morph: self.0.modulation.scene_morph.value(),
+            morph: next(&self.0.modulation.morph),
+            store_a: self.0.modulation.store_a.value(),
+            store_b: self.0.modulation.store_b.value(),
+            macros: [
+                next(&self.0.modulation.macro_1),
+                next(&self.0.modulation.macro_2),
+                next(&self.0.modulation.macro_3),
+                next(&self.0.modulation.macro_4),
+            ],
+            lfo_rate: [
+                self.0.modulation.lfo1_rate.value(),
+                self.0.modulation.lfo2_rate.value(),
+            ],
+            lfo_shape: [
+                self.0.modulation.lfo1_shape.value(),
+                self.0.modulation.lfo2_shape.value(),
+            ],
+            mod_random_rate: self.0.modulation.mod_random_rate.value(),
+            chaos_mode: self.0.modulation.chaos_mode.value(),
+            chaos_rate: self.0.modulation.chaos_rate.value(),
+            chaos_smooth: self.0.modulation.chaos_smooth.value(),
+            routing: self.0.output.routing.value(),
+            routing_buses: self.0.output.routing_buses.value(),
+            surround_spread: self.0.output.surround_spread.value(),
+            pan_law: self.0.output.pan_law.value(),
+            window_attack: self.0.grain.window_attack.value(),
+            window_release: self.0.grain.window_release.value(),
+            normalize: self.0.output.normalize.value(),
+            mod_slots: [
+                ModSlot {
+                    source: self.0.modulation.mod_1_source.value(),
+                    dest: self.0.modulation.mod_1_dest.value(),
+                    depth: next(&self.0.modulation.mod_1_depth),
+                },
+                ModSlot {
+                    source: self.0.modulation.mod_2_source.value(),
+                    dest: self.0.modulation.mod_2_dest.value(),
+                    depth: next(&self.0.modulation.mod_2_depth),
+                },
+                ModSlot {
+                    source: self.0.modulation.mod_3_source.value(),
+                    dest: self.0.modulation.mod_3_dest.value(),
+                    depth: next(&self.0.modulation.mod_3_depth),
+                },
+                ModSlot {
+                    source: self.0.modulation.mod_4_source.value(),
+                    dest: self.0.modulation.mod_4_dest.value(),
+                    depth: next(&self.0.modulation.mod_4_depth),
+                },
+            ],
+        };
+        // 主要なパラメータはスムーザーを通す前の値へ向けて、選んだ時間と曲線でランプさせる
+        let (min_ms, max_ms) = length_bounds(
+            self.0.grain.length_mode.value(),
+            self.0.grain.min_pct.value(),
+            self.0.grain.max_ms.value(),
+            self.0.grain.length_ms.value(),
+            self.0.grain.length_jitter.value(),
+        );
+        let targets = MainTargets {
+            density: self.0.trigger.density.value(),
+            mix: self.0.output.mix.value(),
+            min_pct: min_ms / max_ms * 100.0,
+            max_ms,
+        };
+        self.1.apply(
+            &mut p,
+            targets,
+            self.0.output.smoothing.value(),
+            self.0.output.smoothing_curve.value(),
+        );
+        self.2 = !moving && self.1.is_idle();
+        p
+    }
+
+    #[inline]
+    fn is_steady(&self) -> bool {
+        self.2
+    }
+}
+
+/// 長さの指定方法に従ってグレインの (最小, 最大) の長さ (ミリ秒) を求める。
+/// Min Length は Max Length に対する % なので、最小が最大を超えることはない
+fn length_bounds(
+    mode: LengthMode,
+    min_pct: f32,
+    max_ms: f32,
+    length_ms: f32,
+    length_jitter: f32,
+) -> (f32, f32) {
+    match mode {
+        // % of Ring はリングの長さを知っているエンジンが length_pct から求め直す
+        LengthMode::MinMax | LengthMode::RingPercent => {
+            ((max_ms * min_pct / 100.0).max(1.0), max_ms)
+        }
+        LengthMode::LengthJitter => length_range(length_ms, length_jitter),
+    }
+}
+
+/*──────────────────── 2. Internal structs ──────────────*/
+/// バックグラウンドスレッドで実行するタスク
+enum GranularTask {
+    /// 切り出し済みのグレインに窓をかけ、エンジンの ready キューへ戻す
+    Window(Grain),
+    /// デバッグ用: 鳴り始めたグレインを WAV へ書き出す (サンプル、サンプルレート)
+    #[cfg(feature = "debug-dump")]
+    DumpGrain(Vec<f32>, f32),
+    /// デバッグ用: ウェットバスの 1 ブロックを WAV へ追記する
+    #[cfg(feature = "debug-dump")]
+    DumpWet(Vec<f32>, f32),
+    /// WAV ファイルを読み込み、エンジンのサンプルソースを差し替える (パス、サンプルレート)。
+    /// パスが空ならサンプルを外す。
+    LoadSample(String, f32),
+    /// Load Sample がオンになった: サンプルのディレクトリの一番新しい WAV を sample_path にして読み込む
+    /// (サンプルレート)
+    LoadNewestSample(f32),
+    /// 指定の長さ (サンプル) のリングを確保し、エンジンのリングを広げる
+    GrowRing(usize),
+    /// ブロックの処理中に起きたパニックを報告する (パニックのメッセージ)
+    ReportPanic(String),
+    /// Record がオンになった: 新しい WAV ファイルを作ってウェットの録音を書き始める (サンプルレート)
+    StartRecording(f32),
+    /// 録音のキューに溜まったブロックをファイルへ追記する
+    FlushRecording,
+    /// Record がオフになった: 残りを書き出してファイルを閉じる
+    StopRecording,
+    /// Export Preset がオンになった: 今のパラメータをプリセットのディレクトリへ書き出す
+    ExportPreset,
+}
+
+struct Granular {
+    params: Arc<GranularParams>,
+    engine: Engine,
+    /// エンジンへ渡す乱数 (params.seed から始める)
+    rng: SmallRng,
+    /// 現在のシードと、reseed で選んだがまだ params.seed へ書き込めていないか
+    seed: u64,
+    seed_dirty: bool,
+    /// 前ブロックの reseed の状態 (オンになった瞬間にシードを選び直す)
+    reseeding: bool,
+    /// オフラインのバウンス中か (ProcessMode::Offline)
+    offline: bool,
+    /// 前ブロックでホストのトランスポートが再生中だったか
+    was_playing: bool,
+    /// ホストへ報告しているレイテンシ (サンプル。スペクトルブラーを掛けている間だけ 0 以外)
+    latency: u32,
+    /// 前ブロックの処理がパニックで失敗したか (続けて失敗している間は最初の 1 回だけ報告する)
+    failing: bool,
+    /// 前ブロックの Record の状態 (変わった瞬間に録音ファイルを開く / 閉じる)
+    recording: bool,
+    /// 前ブロックの Export Preset の状態 (オンになった瞬間にプリセットを書き出す)
+    exporting: bool,
+    /// 前ブロックの Load Sample の状態 (オンになった瞬間にサンプルを選び直す)
+    loading_sample: bool,
+    /// バックグラウンドスレッドで録音を WAV ファイルへ書き出す
+    recorder: Arc<record::RecordWriter>,
+    /// 押さえている MIDI ノート (チャンネルごとにノート番号のビットを立てる。note_gate 用)
+    held_notes: [u128; 16],
+    /// key_track で移調に使うノート (最後に押さえたノート。離したら押さえている中で最も高いノート)
+    key_note: Option<u8>,
+    /// 押さえているノートごとの圧力と明るさ (CLAP のノートの表現と MIDI のポリフォニックアフタータッチ)、
+    /// チャンネルごとのピッチベンドとチャンネルプレッシャー (MPE)
+    expressions: expression::NoteExpressions,
+    /// density / mix / グレイン長のランプ (smoothing / smoothing_curve)
+    smoothers: MainSmoothers,
+    /// グレインの生成を MIDI ノートにする (ノートオフを待っているノートを持つ)
+    grain_notes: midi_out::GrainNotes,
+    #[cfg(feature = "debug-dump")]
+    dumper: Arc<dump::Dumper>,
+}
+
+impl Default for Granular {
+    fn default() -> Self {
+        let params = Arc::new(GranularParams::default());
+        let seed = *params.seed.read().unwrap();
+        Self {
+            params,
+            engine: Engine::default(),
+            rng: SmallRng::seed_from_u64(seed),
+            seed,
+            seed_dirty: false,
+            reseeding: false,
+            offline: false,
+            was_playing: false,
+            latency: 0,
+            failing: false,
+            recording: false,
+            exporting: false,
+            loading_sample: false,
+            recorder: Arc::new(record::RecordWriter::default()),
+            held_notes: [0; 16],
+            key_note: None,
+            expressions: expression::NoteExpressions::default(),
+            smoothers: MainSmoothers::default(),
+            grain_notes: midi_out::GrainNotes::default(),
+            #[cfg(feature = "debug-dump")]
+            dumper: Arc::new(dump::Dumper::default()),
+        }
+    }
+}
+
+/*──────────────────── 3. Plugin implementation ────────*/
+impl Plugin for Granular {
+    const NAME: &'static str = "Granular";
+    const VENDOR: &'static str = "Daishi Suzuki";
+    const URL: &'static str = "https://example.com";
+    const EMAIL: &'static str = "zukky.rikugame@gmail.com";
+    const VERSION: &'static str = "0.3.0";
+
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(1)],
+            names: PortNames {
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out"],
+                ..PortNames::const_default()
+            },
+        },
+        // グレインを振り分ける補助出力 Grains 1〜4 付き (Dry Out の後ろに並ぶ)
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(2); 1 + GRAIN_BUSES],
+            names: PortNames {
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out", "Grains 1", "Grains 2", "Grains 3", "Grains 4"],
+                ..PortNames::const_default()
+            },
+        },
+        // サラウンド (Quad / 5.1 / 7.1)。グレインはスピーカーの間へ VBAP でパンする (surround.rs)
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(4),
+            main_output_channels: NonZeroU32::new(4),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(4)],
+            names: PortNames {
+                layout: Some("Quad"),
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(6),
+            main_output_channels: NonZeroU32::new(6),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(6)],
+            names: PortNames {
+                layout: Some("5.1"),
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(8),
+            main_output_channels: NonZeroU32::new(8),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[new_nonzero_u32(8)],
+            names: PortNames {
+                layout: Some("7.1"),
+                aux_inputs: &["Modulation"],
+                aux_outputs: &["Dry Out"],
+                ..PortNames::const_default()
+            },
+        },
+    ];
+
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+    const MIDI_OUTPUT: MidiConfig = MidiConfig::Basic;
+
+    type SysExMessage = ();
+    type BackgroundTask = GranularTask;
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn filter_state(state: &mut PluginState) {
+        migrate::migrate_state(state);
+    }
+
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let queues = self.engine.queues();
+        let record_queues = self.engine.record_queues();
+        let recorder = self.recorder.clone();
+        let params = self.params.clone();
+        #[cfg(feature = "debug-dump")]
+        let dumper = self.dumper.clone();
+        Box::new(move |task| match task {
+            GranularTask::Window(grain) => engine::window_grain(&queues, grain),
+            GranularTask::GrowRing(len) => engine::grow_ring(&queues, len),
+            GranularTask::ReportPanic(message) => {
+                nih_error!("audio processing panicked, block muted and engine reset: {message}");
+            }
+            GranularTask::StartRecording(sr) => match recorder.start(&record_queues, sr) {
+                Ok(path) => nih_log!("recording the wet bus to '{}'", path.display()),
+                Err(e) => nih_error!("failed to start recording: {e}"),
+            },
+            GranularTask::FlushRecording => {
+                if let Err(e) = recorder.flush(&record_queues) {
+                    nih_error!("failed to write the recording: {e}");
+                }
+            }
+            GranularTask::StopRecording => match recorder.stop(&record_queues) {
+                Ok(Some((path, dropped))) => {
+                    nih_log!("saved recording '{}'", path.display());
+                    if dropped > 0 {
+                        nih_warn!("the recording fell behind and dropped {dropped} frames");
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => nih_error!("failed to finish recording: {e}"),
+            },
+            GranularTask::ExportPreset => {
+                match preset::save_preset_to_dir(&preset::preset_dir(), &*params) {
+                    Ok(path) => nih_log!("exported preset '{}'", path.display()),
+                    Err(e) => nih_error!("failed to export preset: {e}"),
+                }
+            }
+            GranularTask::LoadSample(path, _) if path.is_empty() => {
+                sample::install_sample(&queues, Vec::new())
+            }
+            GranularTask::LoadSample(path, sr) => {
+                if let Err(e) = sample::load_sample(&queues, Path::new(&path), sr) {
+                    nih_error!("failed to load sample '{path}': {e}");
+                }
+            }
+            GranularTask::LoadNewestSample(sr) => match sample::newest_wav(&sample::sample_dir()) {
+                Ok(Some(path)) => match sample::load_sample(&queues, &path, sr) {
+                    Ok(_) => {
+                        nih_log!("loaded sample '{}'", path.display());
+                        *params.sample_path.write().unwrap() = path.to_string_lossy().into_owned();
+                    }
+                    Err(e) => nih_error!("failed to load sample '{}': {e}", path.display()),
+                },
+                Ok(None) => nih_warn!(
+                    "no WAV file to load in '{}'",
+                    sample::sample_dir().display()
+                ),
+                Err(e) => nih_error!("failed to read the sample directory: {e}"),
+            },
+            #[cfg(feature = "debug-dump")]
+            GranularTask::DumpGrain(samples, sr) => {
+                if let Err(e) = dumper.grain(&samples, sr) {
+                    nih_error!("failed to dump grain: {e}");
+                }
+            }
+            #[cfg(feature = "debug-dump")]
+            GranularTask::DumpWet(samples, sr) => {
+                if let Err(e) = dumper.wet(&samples, sr) {
+                    nih_error!("failed to dump wet bus: {e}");
+                }
+            }
+        })
+    }
+
+    fn initialize(
+        &mut self,
+        layout: &AudioIOLayout,
+        cfg: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        let n_out = layout.main_output_channels.map_or(0, NonZeroU32::get) as usize;
+        // リングは現在のパラメータに必要な長さで確保する (スムーザーはこの後で現在値に揃える)
+        self.smoothers.initialize(cfg.sample_rate);
+        self.engine
+            .set_frame(SmoothedParams::new(&self.params, &mut self.smoothers).next_frame());
+        // オフライン処理中は品質の設定によらず最高品質で鳴らす (広げたグレインプールは initialize で確保する)
+        self.offline = cfg.process_mode == ProcessMode::Offline;
+        self.engine.set_offline(self.offline);
+        self.engine
+            .initialize(cfg.sample_rate, n_out, cfg.max_buffer_size as usize);
+        // オフライン処理中はレイテンシを避け、グレインが鳴り始める位置も毎回揃うよう窓処理をオーディオスレッドで行う
+        self.engine.set_background_windowing(!self.offline);
+        self.was_playing = false;
+        // initialize で録音は止まるので、Record がオンのままなら次のブロックで新しいファイルに録り直す
+        self.recording = false;
+        // 読み込んだ状態や再初期化で Export Preset / Load Sample がオンのままでも動かさない (オフからオンになった時だけ)
+        self.exporting = self.params.output.export_preset.value();
+        self.loading_sample = self.params.grain.load_sample.value();
+        self.grain_notes.reset();
+        self.held_notes = [0; 16];
+        self.key_note = None;
+        self.expressions.reset();
+        self.latency = self.engine.latency() as u32;
+        context.set_latency_samples(self.latency);
+        // 補助出力の先頭は Dry Out、その後ろがグレインを振り分けるバス
+        self.engine
+            .set_grain_buses(layout.aux_output_ports.len().saturating_sub(1));
+        #[cfg(feature = "debug-dump")]
+        self.engine.set_dump_wet(dump::wet_enabled());
+        // 保存していたシーンをエンジンへ戻す。オーディオスレッドではキーを足せないので、
+        // 古い状態に無かったフィールドもここでマップに揃えておく。
+        let mut scenes = self.params.scenes.write().unwrap();
+        for (i, map) in scenes.iter_mut().enumerate() {
+            let mut scene = FrameParams::default();
+            scene::apply_map(&mut scene, map);
+            self.engine.set_scene(i, scene);
+            *map = scene::to_map(&scene);
+        }
+        drop(scenes);
+        let targets = self.params.macro_targets.read().unwrap();
+        self.engine.set_macros(Macros::from_maps(&targets));
+        drop(targets);
+        self.seed = *self.params.seed.read().unwrap();
+        self.seed_dirty = false;
+        self.rng = SmallRng::seed_from_u64(self.seed);
+        // サンプルはエンジンのサンプルレートで持つので、レートが変わるたびに読み込み直す
+        let path = self.params.sample_path.read().unwrap().clone();
+        context.execute(GranularTask::LoadSample(path, cfg.sample_rate));
+
+        // スムーザーの残りステップは旧レートで計算されているので現在値で確定させる
+        self.params
+            .trigger
+            .density
+            .smoothed
+            .reset(self.params.trigger.density.value());
+        self.params
+            .grain
+            .min_pct
+            .smoothed
+            .reset(self.params.grain.min_pct.value());
+        self.params
+            .grain
+            .max_ms
+            .smoothed
+            .reset(self.params.grain.max_ms.value());
+        self.params
+            .grain
+            .length_ms
+            .smoothed
+            .reset(self.params.grain.length_ms.value());
+        self.params
+            .grain
+            .length_pct
+            .smoothed
+            .reset(self.params.grain.length_pct.value());
+        self.params
+            .grain
+            .length_jitter
+            .smoothed
+            .reset(self.params.grain.length_jitter.value());
+        self.params
+            .output
+            .mix
+            .smoothed
+            .reset(self.params.output.mix.value());
+        self.params
+            .trigger
+            .speed
+            .smoothed
+            .reset(self.params.trigger.speed.value());
+        self.params
+            .output
+            .feedback
+            .smoothed
+            .reset(self.params.output.feedback.value());
+        self.params
+            .output
+            .reverb_decay
+            .smoothed
+            .reset(self.params.output.reverb_decay.value());
+        self.params
+            .grain
+            .buffer_morph
+            .smoothed
+            .reset(self.params.grain.buffer_morph.value());
+        self.params
+            .output
+            .low_cut
+            .smoothed
+            .reset(self.params.output.low_cut.value());
+        self.params
+            .output
+            .high_cut
+            .smoothed
+            .reset(self.params.output.high_cut.value());
+        self.params
+            .output
+            .crossover_low
+            .smoothed
+            .reset(self.params.output.crossover_low.value());
+        self.params
+            .output
+            .crossover_high
+            .smoothed
+            .reset(self.params.output.crossover_high.value());
+        for param in [
+            &self.params.output.low_density,
+            &self.params.output.mid_density,
+            &self.params.output.high_density,
+            &self.params.output.low_mix,
+            &self.params.output.mid_mix,
+            &self.params.output.high_mix,
+        ] {
+            param.smoothed.reset(param.value());
+        }
+        self.params
+            .output
+            .spectral
+            .smoothed
+            .reset(self.params.output.spectral.value());
+        self.params
+            .output
+            .spectral_blur
+            .smoothed
+            .reset(self.params.output.spectral_blur.value());
+        self.params
+            .output
+            .input_trim
+            .smoothed
+            .reset(self.params.output.input_trim.value());
+        self.params
+            .trigger
+            .walk_depth
+            .smoothed
+            .reset(self.params.trigger.walk_depth.value());
+        self.params
+            .trigger
+            .flux_density
+            .smoothed
+            .reset(self.params.trigger.flux_density.value());
+        self.params
+            .output
+            .pre_delay_ms
+            .smoothed
+            .reset(self.params.output.pre_delay_ms.value());
+        self.params
+            .output
+            .duck_depth
+            .smoothed
+            .reset(self.params.output.duck_depth.value());
+        self.params
+            .modulation
+            .morph
+            .smoothed
+            .reset(self.params.modulation.morph.value());
+        for param in [
+            &self.params.modulation.macro_1,
+            &self.params.modulation.macro_2,
+            &self.params.modulation.macro_3,
+            &self.params.modulation.macro_4,
+        ] {
+            param.smoothed.reset(param.value());
+        }
+        true
+    }
+
+    fn reset(&mut self) {
+        self.engine.reset();
+        self.smoothers.reset();
+        // オーディオスレッドから呼ばれることもあるので、GUI スレッドが書き込み中なら現在のシードのまま
+        if !self.seed_dirty {
+            if let Ok(seed) = self.params.seed.try_read() {
+                self.seed = *seed;
+            }
+        }
+        self.rng = SmallRng::seed_from_u64(self.seed);
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        aux: &mut AuxiliaryBuffers,
+        ctx: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let transport = ctx.transport();
+        let pos = transport.pos_samples().filter(|_| transport.playing);
+        let playing = transport.playing;
+        self.engine.set_transport(transport.tempo, pos, playing);
+        self.engine
+            .set_time_signature(transport.time_sig_numerator, transport.time_sig_denominator);
+
+        // オフラインのバウンスでは再生が始まるたびにリング・グレイン・乱数列を初期状態へ戻し、
+        // 同じプロジェクトを何度書き出しても同じ結果にする
+        if self.offline && playing && !self.was_playing {
+            self.reset();
+        }
+        self.was_playing = playing;
+
+        // モジュレーションマトリクスはチャンクごとに評価するので、ブロック内の CC は最後の値だけ使う。
+        // ノートゲートもブロックの終わりに押さえているノートの数で判定する。
+        // CLAP のノートも MIDI 1.0 のノートと同じノートオン / オフとして届き、ノートの表現はノート ID で宛先が決まる。
+        // MPE のピッチベンドとプレッシャーはノートのチャンネルに届き、ゾーンは CC で送られる RPN 6 で決まる
+        let cc = self.params.modulation.mod_cc.value() as u8;
+        while let Some(event) = ctx.next_event() {
+            match event {
+                NoteEvent::MidiCC {
+                    channel,
+                    cc: n,
+                    value,
+                    ..
+                } => {
+                    if n == cc {
+                        self.engine.set_mod_cc(value);
+                    }
+                    self.expressions.control_change(channel, n, value);
+                }
+                NoteEvent::NoteOn {
+                    voice_id,
+                    channel,
+                    note,
+                    ..
+                } => {
+                    self.held_notes[channel as usize & 15] |= 1 << (note & 127);
+                    self.key_note = Some(note & 127);
+                    self.expressions.note_on(voice_id, channel, note & 127);
+                }
+                NoteEvent::NoteOff {
+                    voice_id,
+                    channel,
+                    note,
+                    ..
+                } => {
+                    self.held_notes[channel as usize & 15] &= !(1 << (note & 127));
+                    self.expressions.note_off(voice_id, channel, note & 127);
+                    if self.key_note == Some(note & 127) {
+                        let all = self.held_notes.iter().fold(0, |all, n| all | n);
+                        self.key_note = (all != 0).then(|| 127 - all.leading_zeros() as u8);
+                    }
+                }
+                NoteEvent::PolyPressure {
+                    voice_id,
+                    channel,
+                    note,
+                    pressure,
+                    ..
+                } => {
+                    self.expressions
+                        .set_pressure(voice_id, channel, note & 127, pressure);
+                }
+                NoteEvent::PolyBrightness {
+                    voice_id,
+                    channel,
+                    note,
+                    brightness,
+                    ..
+                } => {
+                    self.expressions
+                        .set_brightness(voice_id, channel, note & 127, brightness);
+                }
+                NoteEvent::MidiPitchBend { channel, value, .. } => {
+                    self.expressions.set_pitch_bend(channel, value);
+                }
+                NoteEvent::MidiChannelPressure {
+                    channel, pressure, ..
+                } => {
+                    self.expressions.set_channel_pressure(channel, pressure);
+                }
+                _ => {}
+            }
+        }
+        let held = self
+            .held_notes
+            .iter()
+            .map(|n| n.count_ones() as usize)
+            .sum();
+        self.engine.set_held_notes(held);
+        self.engine.set_key_note(self.key_note);
+        let bend_range = self.params.pitch.bend_range.value() as f32;
+        self.engine
+            .set_note_expression(self.expressions.expression(self.key_note, bend_range));
+        self.engine
+            .set_note_bends(self.expressions.channel_bends(bend_range));
+
+        // reseed がオンになった瞬間に新しいシードを選び、このブロックからその乱数列で鳴らす
+        let reseed = self.params.trigger.reseed.value();
+        if reseed && !self.reseeding {
+            // オフラインでは書き出しごとに変わらないよう、現在の乱数列から選ぶ
+            self.seed = if self.offline {
+                self.rng.random()
+            } else {
+                rng().random()
+            };
+            self.rng = SmallRng::seed_from_u64(self.seed);
+            self.seed_dirty = true;
+        }
+        self.reseeding = reseed;
+        self.engine.set_reroll_seed(self.seed);
+        if self.seed_dirty {
+            if let Ok(mut seed) = self.params.seed.try_write() {
+                *seed = self.seed;
+                self.seed_dirty = false;
+            }
+        }
+
+        // エディタで書き換えたパターンを読む (GUI スレッドが書いていれば前のブロックのまま)
+        if let Ok(pattern) = self.params.sequence.try_read() {
+            self.engine.set_sequence(Sequence::from_saved(&pattern));
+        }
+
+        // Record がオンになった瞬間に録音ファイルを開かせ、オフになった瞬間に閉じさせる
+        let record = self.params.output.record.value();
+        if record != self.recording {
+            self.engine.set_recording(record);
+            ctx.execute_background(if record {
+                GranularTask::StartRecording(self.engine.sr)
+            } else {
+                GranularTask::StopRecording
+            });
+            self.recording = record;
+        }
+
+        // Export Preset がオンになった瞬間に、今のパラメータをプリセットとして書き出させる
+        let export = self.params.output.export_preset.value();
+        if export && !self.exporting {
+            ctx.execute_background(GranularTask::ExportPreset);
+        }
+        self.exporting = export;
+
+        // Load Sample がオンになった瞬間に、サンプルのディレクトリの一番新しい WAV を読み込ませる
+        let load = self.params.grain.load_sample.value();
+        if load && !self.loading_sample {
+            ctx.execute_background(GranularTask::LoadNewestSample(self.engine.sr));
+        }
+        self.loading_sample = load;
+
+        // 補助入力 Modulation (つながっていなければ無音) をモジュレーションマトリクスの元にする
+        if let Some(mod_in) = aux.inputs.first_mut().and_then(|b| b.as_slice().first()) {
+            self.engine.set_mod_input(mod_in);
+        }
+
+        // エンジン内部のパニック (範囲外アクセスなど) はブロックごとに捕まえる。
+        // エンジンがメイン出力を無音にして初期状態へ戻るので、補助出力も無音にしてバックグラウンドで報告する
+        let result = self.engine.process_guarded(
+            buffer.as_slice(),
+            &mut SmoothedParams::new(&self.params, &mut self.smoothers),
+            &mut self.rng,
+        );
+        if let Err(message) = result {
+            for out in aux.outputs.iter_mut() {
+                for ch in out.as_slice() {
+                    ch.fill(0.0);
+                }
+            }
+            self.grain_notes.release_all(|e| ctx.send_event(e));
+            if self.failing {
+                nih_plug::util::permit_alloc(|| drop(message));
+            } else {
+                ctx.execute_background(GranularTask::ReportPanic(message));
+            }
+            self.failing = true;
+            return ProcessStatus::Normal;
+        }
+        self.failing = false;
+
+        // このブロックで生まれたグレインをノートとして送る。オフにしたら鳴らしているノートを止める
+        if self.params.output.midi_out.value() {
+            let sr = self.engine.sr;
+            self.grain_notes
+                .process(self.engine.spawn_events(), buffer.samples(), sr, |e| {
+                    ctx.send_event(e)
+                });
+        } else {
+            self.grain_notes.release_all(|e| ctx.send_event(e));
+        }
+
+        // スペクトルブラーを掛け始めたり外したりしたら、変わったレイテンシをホストへ知らせる
+        let latency = self.engine.latency() as u32;
+        if latency != self.latency {
+            self.latency = latency;
+            ctx.set_latency_samples(latency);
+        }
+
+        // 保存トリガーで書き換わったシーンを永続化する (GUI スレッドが読んでいれば次のブロックで)
+        if self.engine.scenes_dirty() {
+            if let Ok(mut scenes) = self.params.scenes.try_write() {
+                for (i, map) in scenes.iter_mut().enumerate() {
+                    scene::update_map(map, self.engine.scene(i));
+                }
+                self.engine.mark_scenes_saved();
+            }
+        }
+
+        // 補助出力 Dry Out へ入力ゲイン適用後のドライ信号を書き出す
+        if let Some(dry_out) = aux.outputs.first_mut() {
+            for (ch, out) in dry_out.as_slice().iter_mut().enumerate() {
+                let dry = self.engine.dry(ch, out.len());
+                out[..dry.len()].copy_from_slice(dry);
+            }
+        }
+        // 補助出力 Grains 1〜4 へ振り分けたグレインを書き出す
+        for (bus, out) in aux.outputs.iter_mut().skip(1).enumerate() {
+            for (ch, out) in out.as_slice().iter_mut().enumerate() {
+                let wet = self.engine.grain_bus(bus, ch, out.len());
+                out[..wet.len()].copy_from_slice(wet);
+            }
+        }
+
+        // 入力が途切れてもグレインと帰還が鳴り終わるまで処理を続けてもらう
+        let status = match self.engine.tail_len() {
+            Some(n) => ProcessStatus::Tail(n.min(u32::MAX as usize) as u32),
+            None => ProcessStatus::KeepAlive,
+        };
+
+        // リングが足りなくなったらバックグラウンドで確保する (オフラインでは次のチャンクに間に合うようその場で)
+        if let Some(len) = self.engine.take_ring_request() {
+            if self.offline {
+                nih_plug::util::permit_alloc(|| engine::grow_ring(&self.engine.queues, len));
+            } else {
+                ctx.execute_background(GranularTask::GrowRing(len));
+            }
+        }
+
+        // 窓処理待ちのグレインをバックグラウンドスレッドへ送る
+        for grain in self.engine.drain_pending() {
+            ctx.execute_background(GranularTask::Window(grain));
+        }
+
+        // 埋まった録音のブロックをファイルへ書き出させる
+        if self.engine.record_pending() {
+            ctx.execute_background(GranularTask::FlushRecording);
+        }
+
+        #[cfg(feature = "debug-dump")]
+        {
+            let sr = self.engine.sr;
+            for grain in self.engine.drain_dumps() {
+                ctx.execute_background(GranularTask::DumpGrain(grain, sr));
+            }
+            if let Some(wet) = self.engine.take_wet_dump() {
+                ctx.execute_background(GranularTask::DumpWet(wet, sr));
+            }
+        }
+
+        status
+    }
+}
+
+/*──────────────────── 4. CLAP / VST3 export ───────────*/
+impl ClapPlugin for Granular {
+    const CLAP_ID: &'static str = "com.zukky.granular";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("Granular effect with Tukey window + parameters");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect];
+}
+impl Vst3Plugin for Granular {
+    const VST3_CLASS_ID: [u8; 16] = *b"Granular!!!!!!!!";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+nih_export_clap!(Granular);
+nih_export_vst3!(Granular);
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::{MAX_GRAINS, RING_SEC};
+
+    #[test]
+    fn params_are_grouped_without_changing_ids() {
+        let params = GranularParams::default();
+        let map = params.param_map();
+        let group = |id: &str| {
+            map.iter()
+                .find(|(i, ..)| i == id)
+                .map(|(_, _, g)| g.as_str())
+        };
+        assert_eq!(group("max_ms"), Some("Grain"));
+        assert_eq!(group("density"), Some("Trigger"));
+        assert_eq!(group("scale"), Some("Pitch"));
+        assert_eq!(group("mix"), Some("Output"));
+        assert_eq!(group("mod_1_depth"), Some("Modulation"));
+        assert!(map.iter().all(|(_, _, g)| !g.is_empty()));
+    }
+
+    #[test]
+    fn plugin_initializes_ring_size() {
+        let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        let cfg = BufferConfig {
+            sample_rate: 44100.0,
+            min_buffer_size: None,
+            max_buffer_size: 64,
+            process_mode: ProcessMode::Realtime,
+        };
+
+        struct DummyInit;
+        impl InitContext<Granular> for DummyInit {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        let mut plugin = Granular::default();
+        assert!(plugin.initialize(&layout, &cfg, &mut DummyInit));
+
+        plugin
+            .params
+            .trigger
+            .density
+            .smoothed
+            .reset(plugin.params.trigger.density.value());
+        plugin
+            .params
+            .grain
+            .min_pct
+            .smoothed
+            .reset(plugin.params.grain.min_pct.value());
+        plugin
+            .params
+            .grain
+            .max_ms
+            .smoothed
+            .reset(plugin.params.grain.max_ms.value());
+        plugin
+            .params
+            .output
+            .mix
+            .smoothed
+            .reset(plugin.params.output.mix.value());
+        let expected = (RING_SEC * cfg.sample_rate) as usize;
+        assert_eq!(plugin.engine.ring_len, expected);
+        assert_eq!(plugin.engine.ring.len(), expected.next_power_of_two());
+    }
+
+    #[test]
+    fn sample_rate_change_mid_session() {
+        let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        let cfg_44 = BufferConfig {
+            sample_rate: 44100.0,
+            min_buffer_size: None,
+            max_buffer_size: 512,
+            process_mode: ProcessMode::Realtime,
+        };
+        let cfg_96 = BufferConfig {
+            sample_rate: 96000.0,
+            ..cfg_44
+        };
+
+        struct DummyInit;
+        impl InitContext<Granular> for DummyInit {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        struct DummyCtx {
+            transport: Transport,
+        }
+        impl DummyCtx {
+            fn new(sr: f32) -> Self {
+                let mut t: Transport = unsafe { std::mem::zeroed() };
+                t.sample_rate = sr;
+                Self { transport: t }
+            }
+        }
+        impl ProcessContext<Granular> for DummyCtx {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
+            fn transport(&self) -> &Transport {
+                &self.transport
+            }
+            fn next_event(&mut self) -> Option<PluginNoteEvent<Granular>> {
+                None
+            }
+            fn send_event(&mut self, _event: PluginNoteEvent<Granular>) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        let mut plugin = Granular::default();
+        assert!(plugin.initialize(&layout, &cfg_96, &mut DummyInit));
+        // 96k のリング末尾付近まで書き込み位置を進めておく
+        plugin.engine.wr = plugin.engine.ring.len() - 10;
+
+        // 96k → 44.1k: 書き込み位置が新しいリング長を超えないこと
+        assert!(plugin.initialize(&layout, &cfg_44, &mut DummyInit));
+        assert_eq!(plugin.engine.ring_len, (RING_SEC * 44100.0) as usize);
+        assert_eq!(plugin.engine.ring.len(), 262_144);
+        assert!(plugin.engine.wr < plugin.engine.ring.len());
+        assert_eq!(plugin.engine.sr, 44100.0);
+
+        // 44.1k で一定値を書き込み、96k へ切り替える
+        let frames = 256;
+        let mut real = vec![vec![0.5f32; frames]; 1];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(frames, |s| {
+                *s = real.iter_mut().map(|c| c.as_mut_slice()).collect();
+            });
+        }
+        let mut aux_inputs: [Buffer; 0] = [];
+        let mut aux_outputs: [Buffer; 0] = [];
+        let mut aux = AuxiliaryBuffers {
+            inputs: &mut aux_inputs,
+            outputs: &mut aux_outputs,
+        };
+        plugin.process(&mut buffer, &mut aux, &mut DummyCtx::new(44100.0));
+
+        assert!(plugin.initialize(&layout, &cfg_96, &mut DummyInit));
+        assert_eq!(plugin.engine.ring_len, (RING_SEC * 96000.0) as usize);
+        assert_eq!(plugin.engine.ring.len(), 524_288);
+        assert!(plugin.engine.grains.is_empty());
+        assert_eq!(plugin.engine.wr, 0);
+        // 書き込んだ 256 サンプルは約 557 サンプル分としてリング末尾に残る
+        let written = plugin
+            .engine
+            .ring
+            .iter()
+            .filter(|v| (**v - 0.5).abs() < 1e-6)
+            .count();
+        assert!((550..=560).contains(&written), "{written}");
+        assert!(
+            (plugin.params.output.mix.smoothed.next() - plugin.params.output.mix.value()).abs()
+                < 1e-6
+        );
+
+        // 新しいレートでも process がパニックしないこと
+        plugin.process(&mut buffer, &mut aux, &mut DummyCtx::new(96000.0));
+        assert!(plugin.engine.wr < plugin.engine.ring.len());
+    }
+
+    #[test]
+    fn min_length_never_exceeds_max_length() {
+        // Min Length は Max Length に対する % なので、どの組み合わせでも最大を超えない
+        assert_eq!(
+            length_bounds(LengthMode::MinMax, 4.0, 500.0, 0.0, 0.0),
+            (20.0, 500.0)
+        );
+        assert_eq!(
+            length_bounds(LengthMode::MinMax, 100.0, 100.0, 0.0, 0.0),
+            (100.0, 100.0)
+        );
+        // 1 ms 未満にはしないが、Max Length が 1 ms でも超えない
+        assert_eq!(
+            length_bounds(LengthMode::MinMax, 0.0, 1.0, 0.0, 0.0),
+            (1.0, 1.0)
+        );
+        let params = GranularParams::default();
+        let frame = SmoothedParams::new(&params, &mut MainSmoothers::default()).next_frame();
+        assert_eq!((frame.min_ms, frame.max_ms), (20.0, 500.0));
+    }
+
+    #[test]
+    fn host_modulation_of_overdub_and_mod_depths_is_smoothed() {
+        // CLAP のモジュレーションはスムーザーの目標値を動かす。段差にならず 20 ms かけて移る
+        let params = GranularParams::default();
+        params.grain.overdub.smoothed.reset(0.9);
+        params.modulation.mod_1_depth.smoothed.reset(0.0);
+        params.grain.overdub.smoothed.set_target(1_000.0, 0.0);
+        params
+            .modulation
+            .mod_1_depth
+            .smoothed
+            .set_target(1_000.0, 1.0);
+        let mut smoothers = MainSmoothers::default();
+        let mut source = SmoothedParams::new(&params, &mut smoothers);
+        let p = source.next_frame();
+        assert!(p.overdub < 0.9 && p.overdub > 0.8, "{}", p.overdub);
+        assert!(p.mod_slots[0].depth > 0.0 && p.mod_slots[0].depth < 0.1);
+        let p = (0..19).map(|_| source.next_frame()).last().unwrap();
+        assert!(p.overdub.abs() < 1e-6, "{}", p.overdub);
+        assert!((p.mod_slots[0].depth - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn smoothed_params_are_steady_once_every_smoother_settles() {
+        let params = GranularParams::default();
+        let mut smoothers = MainSmoothers::default();
+        let mut source = SmoothedParams::new(&params, &mut smoothers);
+        source.next_frame();
+        assert!(source.is_steady());
+
+        // 20 ms のランプが動いている間は、エンジンにフレームを毎サンプル読ませる
+        params.grain.overdub.smoothed.reset(0.9);
+        params.grain.overdub.smoothed.set_target(1_000.0, 0.0);
+        for _ in 0..19 {
+            source.next_frame();
+            assert!(!source.is_steady());
+        }
+        source.next_frame();
+        assert!(source.is_steady());
+    }
+
+    #[test]
+    fn smoothers_advance_per_sample() {
+        let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        let cfg = BufferConfig {
+            sample_rate: 48000.0,
+            min_buffer_size: None,
+            max_buffer_size: 64,
+            process_mode: ProcessMode::Realtime,
+        };
+
+        struct DummyInit;
+        impl InitContext<Granular> for DummyInit {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        struct DummyCtx {
+            transport: Transport,
+        }
+        impl DummyCtx {
+            fn new(sr: f32) -> Self {
+                let mut t: Transport = unsafe { std::mem::zeroed() };
+                t.sample_rate = sr;
+                Self { transport: t }
+            }
+        }
+        impl ProcessContext<Granular> for DummyCtx {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
+            fn transport(&self) -> &Transport {
+                &self.transport
+            }
+            fn next_event(&mut self) -> Option<PluginNoteEvent<Granular>> {
+                None
+            }
+            fn send_event(&mut self, _event: PluginNoteEvent<Granular>) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
-    }
-}
-
-/*──────────────────── 1. Constants (match Python) ──────*/
-const RING_SEC: f32 = 5.0; // リングバッファの長さ (秒)
-const MAX_GRAINS: usize = 25; // 同時に立ち上がるグレイン数上限
-                              // TRIGGER_PROB は「density」パラメータで置き換え
-                              // MIN_MS / MAX_MS は「min_ms」「max_ms」パラメータで置き換え
-const TUKEY_ALPHA: f32 = 0.2; // Tukey 窓の形状
 
-/*──────────────────── 2. Internal structs ──────────────*/
-struct Grain {
-    buf: Vec<f32>,
-    pos: usize,
-    ch: usize,
-}
-impl Grain {
-    #[inline]
-    fn done(&self) -> bool {
-        self.pos >= self.buf.len()
-    }
-}
+        let mut plugin = Granular::default();
+        assert!(plugin.initialize(&layout, &cfg, &mut DummyInit));
 
-struct Granular {
-    params: Arc<GranularParams>,
-    ring: Vec<f32>,
-    wr: usize,
-    grains: Vec<Grain>,
-    sr: f32,
-}
+        // min_pct は 1 ms の線形スムージング = 48 サンプル
+        plugin
+            .params
+            .grain
+            .min_pct
+            .smoothed
+            .set_target(cfg.sample_rate, 50.0);
+        let total_steps = plugin.params.grain.min_pct.smoothed.steps_left();
+        assert_eq!(total_steps, 48);
 
-impl Default for Granular {
-    fn default() -> Self {
-        Self {
-            params: Arc::new(GranularParams::default()),
-            ring: Vec::new(),
-            wr: 0,
-            grains: Vec::new(),
-            sr: 0.0,
+        let frames = 32;
+        let mut real = vec![vec![0.0f32; frames]; 1];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(frames, |s| {
+                *s = real.iter_mut().map(|c| c.as_mut_slice()).collect();
+            });
         }
-    }
-}
-
-/*──────────────────── 3. Plugin implementation ────────*/
-impl Plugin for Granular {
-    const NAME: &'static str = "Granular";
-    const VENDOR: &'static str = "Daishi Suzuki";
-    const URL: &'static str = "https://example.com";
-    const EMAIL: &'static str = "zukky.rikugame@gmail.com";
-    const VERSION: &'static str = "0.3.0";
-
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
-        AudioIOLayout {
-            main_input_channels: NonZeroU32::new(1),
-            main_output_channels: NonZeroU32::new(1),
-            ..AudioIOLayout::const_default()
-        },
-        AudioIOLayout {
-            main_input_channels: NonZeroU32::new(2),
-            main_output_channels: NonZeroU32::new(2),
-            ..AudioIOLayout::const_default()
-        },
-    ];
-
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
-    const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
-
-    type SysExMessage = ();
-    type BackgroundTask = ();
-
-    fn params(&self) -> Arc<dyn Params> {
-        self.params.clone()
-    }
+        let mut aux_inputs: [Buffer; 0] = [];
+        let mut aux_outputs: [Buffer; 0] = [];
+        let mut aux = AuxiliaryBuffers {
+            inputs: &mut aux_inputs,
+            outputs: &mut aux_outputs,
+        };
+        let mut ctx = DummyCtx::new(cfg.sample_rate);
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
 
-    fn initialize(
-        &mut self,
-        _: &AudioIOLayout,
-        cfg: &BufferConfig,
-        _: &mut impl InitContext<Self>,
-    ) -> bool {
-        self.sr = cfg.sample_rate as f32;
-        self.ring = vec![0.0; (RING_SEC * self.sr) as usize];
-        true
+        // ブロック内の全サンプルでスムーザーが進んでいること
+        assert_eq!(
+            plugin.params.grain.min_pct.smoothed.steps_left(),
+            total_steps - frames as i32
+        );
     }
 
-    fn reset(&mut self) {
-        self.wr = 0;
-        self.grains.clear();
-        self.ring.fill(0.0);
-    }
+    #[test]
+    fn background_windowing_round_trip() {
+        let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        let cfg = BufferConfig {
+            sample_rate: 48000.0,
+            min_buffer_size: None,
+            max_buffer_size: 64,
+            process_mode: ProcessMode::Realtime,
+        };
 
-    fn process(
-        &mut self,
-        buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _ctx: &mut impl ProcessContext<Self>,
-    ) -> ProcessStatus {
-        let mut rng = rng();
-        let n_ch = buffer.channels() as usize;
-
-        // ── ① パラメータ値を取得 ──
-        let density = self.params.density.smoothed.next();
-        let min_len_ms = self.params.min_ms.smoothed.next().max(1.0);
-        let max_len_ms = self.params.max_ms.smoothed.next().max(min_len_ms);
-        let min_len = ((min_len_ms / 1_000.0) * self.sr) as usize;
-        let max_len = ((max_len_ms / 1_000.0) * self.sr) as usize;
-        let mix = self.params.mix.smoothed.next().clamp(0.0, 1.0);
-
-        // ── ① グレイン生成判定 (ブロックごと) ──
-        if self.grains.len() < MAX_GRAINS && rng.random::<f32>() < density {
-            if self.ring.len() >= max_len {
-                let len = rng.random_range(min_len..=max_len);
-                let start = rng.random_range(0..self.ring.len() - len);
-                let mut data: Vec<f32> = (0..len)
-                    .map(|i| self.ring[(start + i) % self.ring.len()])
-                    .collect();
-                apply_tukey(&mut data, TUKEY_ALPHA);
-                let ch = rng.random_range(0..n_ch);
-                self.grains.push(Grain {
-                    buf: data,
-                    pos: 0,
-                    ch,
-                });
+        struct DummyInit;
+        impl InitContext<Granular> for DummyInit {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
             }
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
 
-        // ── ② フレーム単位ループ ──
-        for mut frame in buffer.iter_samples() {
-            // a. モノラル化してリングバッファへ書き込む
-            let mut mono_input = 0.0;
-            for ch in 0..n_ch {
-                mono_input += *frame.get_mut(ch).unwrap();
+        // バックグラウンドへ送られたタスクを溜めておくコンテキスト
+        struct CapturingCtx {
+            transport: Transport,
+            tasks: std::cell::RefCell<Vec<GranularTask>>,
+        }
+        impl ProcessContext<Granular> for CapturingCtx {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
             }
-
-            // b. モノラル化したサンプルをリングバッファへ書き込み
-            self.ring[self.wr] = mono_input;
-            self.wr = (self.wr + 1) % self.ring.len();
-
-            // c. このフレーム用のグレイン合成
-            // 各チャンネル用のミックス値を初期化
-            let mut mixes = vec![0.0f32; n_ch];
-            for g in &mut self.grains {
-                if let Some(&v) = g.buf.get(g.pos) {
-                    mixes[g.ch % n_ch] += v;
-                }
+            fn execute_background(&self, task: GranularTask) {
+                self.tasks.borrow_mut().push(task);
             }
-
-            // d. ドライ成分とウェット成分を mix でミックス
-            for ch in 0..n_ch {
-                let dry = *frame.get_mut(ch).unwrap();
-                let out = dry * (1.0 - mix) + mixes[ch] * mix;
-                *frame.get_mut(ch).unwrap() = out;
+            fn execute_gui(&self, _task: GranularTask) {}
+            fn transport(&self) -> &Transport {
+                &self.transport
             }
-
-            // e. グレイン再生位置を進める
-            for g in &mut self.grains {
-                if g.pos < g.buf.len() {
-                    g.pos += 1;
-                }
+            fn next_event(&mut self) -> Option<PluginNoteEvent<Granular>> {
+                None
             }
+            fn send_event(&mut self, _event: PluginNoteEvent<Granular>) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
 
-        // ── ③ 終了したグレインを除去 ──
-        self.grains.retain(|g| !g.done());
+        let mut plugin = Granular::default();
+        assert!(plugin.initialize(&layout, &cfg, &mut DummyInit));
+        let pool = plugin.engine.pool_size();
+        assert_eq!(plugin.engine.queues.pool.len(), pool);
+        plugin.engine.ring.fill(1.0);
 
-        ProcessStatus::Normal
-    }
-}
+        // process 中に新しいグレインが生まれないよう density を 0 に固定する
+        plugin.params.trigger.density.smoothed.reset(0.0);
 
-/*──────────────────── 4. Tukey window ─────────────────*/
-fn apply_tukey(x: &mut [f32], alpha: f32) {
-    let n = x.len() as f32;
-    let edge = (alpha * (n - 1.0) * 0.5).floor();
-    for (i, v) in x.iter_mut().enumerate() {
-        let k = i as f32;
-        let w = if k < edge {
-            0.5 * (1.0 - (2.0 * std::f32::consts::PI * k / (alpha * (n - 1.0))).cos())
-        } else if k > n - edge - 1.0 {
-            let k2 = n - k - 1.0;
-            0.5 * (1.0 - (2.0 * std::f32::consts::PI * k2 / (alpha * (n - 1.0))).cos())
-        } else {
-            1.0
+        let mut ctx = CapturingCtx {
+            transport: unsafe { std::mem::zeroed() },
+            tasks: std::cell::RefCell::new(Vec::new()),
         };
-        *v *= w;
-    }
-}
-
-/*──────────────────── 5. CLAP / VST3 export ───────────*/
-impl ClapPlugin for Granular {
-    const CLAP_ID: &'static str = "com.zukky.granular";
-    const CLAP_DESCRIPTION: Option<&'static str> =
-        Some("Granular effect with Tukey window + parameters");
-    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
-    const CLAP_SUPPORT_URL: Option<&'static str> = None;
-    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect];
-}
-impl Vst3Plugin for Granular {
-    const VST3_CLASS_ID: [u8; 16] = *b"Granular!!!!!!!!";
-    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
-}
+        // 等速でないグレインはリングを直接読めないので、コピーして窓処理へ回す
+        plugin
+            .engine
+            .spawn_grain(&mut rng(), 1_000, 1_000, 1, 0, 0.5);
+        assert_eq!(plugin.engine.queues.pool.len(), pool - 1);
 
-nih_export_clap!(Granular);
-nih_export_vst3!(Granular);
+        let frames = 1;
+        let mut real = vec![vec![0.0f32; frames]; 1];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(frames, |s| {
+                *s = real.iter_mut().map(|c| c.as_mut_slice()).collect();
+            });
+        }
+        let mut aux_inputs: [Buffer; 0] = [];
+        let mut aux_outputs: [Buffer; 0] = [];
+        let mut aux = AuxiliaryBuffers {
+            inputs: &mut aux_inputs,
+            outputs: &mut aux_outputs,
+        };
 
-/*──────────────────── Tests ───────────────────────────*/
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // 窓処理が終わるまではグレインとして鳴らず、タスクとして送られる
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+        assert!(plugin.engine.grains.is_empty());
+        assert_eq!(ctx.tasks.borrow().len(), 1);
 
-    #[test]
-    fn tukey_window_symmetry_and_edges() {
-        let mut data = vec![1.0f32; 10];
-        apply_tukey(&mut data, 0.5);
-
-        let n = data.len();
-        assert!(data[0].abs() < 1e-6);
-        assert!(data[n - 1].abs() < 1e-6);
-
-        for i in 0..n {
-            let j = n - 1 - i;
-            assert!(
-                (data[i] - data[j]).abs() < 1e-6,
-                "window not symmetric at {i}"
-            );
-            assert!(data[i] <= 1.0 + 1e-6);
+        let executor = plugin.task_executor();
+        for task in ctx.tasks.take() {
+            executor(task);
         }
-    }
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+        assert_eq!(plugin.engine.grains.len(), 1);
+        let buf = &plugin.engine.grains[0].buf;
+        assert_eq!(buf.len(), 1_000);
+        assert!(buf[0].abs() < 1e-6);
+        assert!((buf[500] - 1.0).abs() < 1e-6);
 
-    #[test]
-    fn grain_done_checks_bounds() {
-        let g = Grain {
-            buf: vec![0.0, 1.0],
-            pos: 2,
-            ch: 0,
-        };
-        assert!(g.done());
-        let g = Grain {
-            buf: vec![0.0, 1.0],
-            pos: 1,
-            ch: 0,
-        };
-        assert!(!g.done());
+        // reset でバッファはプールへ戻る
+        plugin.reset();
+        assert!(plugin.engine.grains.is_empty());
+        assert_eq!(plugin.engine.queues.pool.len(), pool);
     }
 
     #[test]
-    fn tukey_alpha_zero_is_rectangular() {
-        let mut data = vec![1.0f32; 16];
-        let orig = data.clone();
-        apply_tukey(&mut data, 0.0);
-        assert_eq!(data, orig);
+    fn reset_restarts_random_sequence_from_persisted_seed() {
+        let mut plugin = Granular::default();
+        *plugin.params.seed.write().unwrap() = 42;
+        plugin.reset();
+        let first: Vec<u64> = (0..4).map(|_| plugin.rng.random()).collect();
+        plugin.reset();
+        let again: Vec<u64> = (0..4).map(|_| plugin.rng.random()).collect();
+        assert_eq!(first, again);
+        assert_eq!(plugin.seed, 42);
     }
 
     #[test]
-    fn plugin_initializes_ring_size() {
+    fn offline_bounces_are_identical() {
         let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        // リング全体に入力が行き渡るよう低いレートで試す
         let cfg = BufferConfig {
-            sample_rate: 44100.0,
+            sample_rate: 1000.0,
             min_buffer_size: None,
             max_buffer_size: 64,
-            process_mode: ProcessMode::Realtime,
+            process_mode: ProcessMode::Offline,
         };
 
         struct DummyInit;
@@ -325,32 +3509,77 @@ mod tests {
             fn plugin_api(&self) -> PluginApi {
                 PluginApi::Clap
             }
-            fn execute(&self, _task: ()) {}
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        struct DummyCtx {
+            transport: Transport,
+        }
+        impl ProcessContext<Granular> for DummyCtx {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
+            fn transport(&self) -> &Transport {
+                &self.transport
+            }
+            fn next_event(&mut self) -> Option<PluginNoteEvent<Granular>> {
+                None
+            }
+            fn send_event(&mut self, _event: PluginNoteEvent<Granular>) {}
             fn set_latency_samples(&self, _samples: u32) {}
             fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
 
         let mut plugin = Granular::default();
         assert!(plugin.initialize(&layout, &cfg, &mut DummyInit));
+        let mut ctx = DummyCtx {
+            transport: unsafe { std::mem::zeroed() },
+        };
+        ctx.transport.sample_rate = cfg.sample_rate;
 
-        plugin
-            .params
-            .density
-            .smoothed
-            .reset(plugin.params.density.value());
-        plugin
-            .params
-            .min_ms
-            .smoothed
-            .reset(plugin.params.min_ms.value());
-        plugin
-            .params
-            .max_ms
-            .smoothed
-            .reset(plugin.params.max_ms.value());
-        plugin.params.mix.smoothed.reset(plugin.params.mix.value());
-        let expected = (RING_SEC * cfg.sample_rate) as usize;
-        assert_eq!(plugin.ring.len(), expected);
+        // 停止中に入力を流してリングと乱数列を進めてから、再生を始めて 200 ブロック書き出す
+        let bounce = |plugin: &mut Granular, ctx: &mut DummyCtx, noise: f32| {
+            let frames = 64;
+            let mut out = Vec::new();
+            for block in 0..201 {
+                ctx.transport.playing = block > 0;
+                let mut real = vec![vec![0.0f32; frames]; 2];
+                for ch in &mut real {
+                    for (i, x) in ch.iter_mut().enumerate() {
+                        let t = (block * frames + i) as f32 / cfg.sample_rate;
+                        *x = (t * 50.0 * std::f32::consts::TAU).sin();
+                        if block == 0 {
+                            *x += noise;
+                        }
+                    }
+                }
+                let mut buffer = Buffer::default();
+                unsafe {
+                    buffer.set_slices(frames, |s| {
+                        *s = real.iter_mut().map(|c| c.as_mut_slice()).collect();
+                    });
+                }
+                let mut aux_inputs: [Buffer; 0] = [];
+                let mut aux_outputs: [Buffer; 0] = [];
+                let mut aux = AuxiliaryBuffers {
+                    inputs: &mut aux_inputs,
+                    outputs: &mut aux_outputs,
+                };
+                plugin.process(&mut buffer, &mut aux, ctx);
+                if block > 0 {
+                    out.extend_from_slice(&real[0]);
+                }
+            }
+            out
+        };
+        let first = bounce(&mut plugin, &mut ctx, 0.25);
+        let second = bounce(&mut plugin, &mut ctx, -0.5);
+        assert!(first.iter().any(|x| x.abs() > 0.1));
+        assert_eq!(first, second);
     }
 
     #[test]
@@ -368,7 +3597,7 @@ mod tests {
             fn plugin_api(&self) -> PluginApi {
                 PluginApi::Clap
             }
-            fn execute(&self, _task: ()) {}
+            fn execute(&self, _task: GranularTask) {}
             fn set_latency_samples(&self, _samples: u32) {}
             fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
@@ -387,8 +3616,8 @@ mod tests {
             fn plugin_api(&self) -> PluginApi {
                 PluginApi::Clap
             }
-            fn execute_background(&self, _task: ()) {}
-            fn execute_gui(&self, _task: ()) {}
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
             fn transport(&self) -> &Transport {
                 &self.transport
             }
@@ -440,7 +3669,7 @@ mod tests {
             fn plugin_api(&self) -> PluginApi {
                 PluginApi::Clap
             }
-            fn execute(&self, _task: ()) {}
+            fn execute(&self, _task: GranularTask) {}
             fn set_latency_samples(&self, _samples: u32) {}
             fn set_current_voice_capacity(&self, _capacity: u32) {}
         }
@@ -459,8 +3688,8 @@ mod tests {
             fn plugin_api(&self) -> PluginApi {
                 PluginApi::Clap
             }
-            fn execute_background(&self, _task: ()) {}
-            fn execute_gui(&self, _task: ()) {}
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
             fn transport(&self) -> &Transport {
                 &self.transport
             }
@@ -477,37 +3706,51 @@ mod tests {
 
         plugin
             .params
+            .trigger
             .density
             .smoothed
-            .reset(plugin.params.density.value());
+            .reset(plugin.params.trigger.density.value());
         plugin
             .params
-            .min_ms
+            .grain
+            .min_pct
             .smoothed
-            .reset(plugin.params.min_ms.value());
+            .reset(plugin.params.grain.min_pct.value());
         plugin
             .params
+            .grain
             .max_ms
             .smoothed
-            .reset(plugin.params.max_ms.value());
-        plugin.params.mix.smoothed.reset(plugin.params.mix.value());
+            .reset(plugin.params.grain.max_ms.value());
+        plugin
+            .params
+            .output
+            .mix
+            .smoothed
+            .reset(plugin.params.output.mix.value());
 
-        plugin.grains.clear();
-        plugin.grains.push(Grain {
+        plugin.engine.grains.clear();
+        plugin.engine.grains.push(Grain {
             buf: vec![1.0],
             pos: 0,
             ch: 0,
+            offset: 0,
+            ..Grain::default()
         });
-        plugin.grains.push(Grain {
+        plugin.engine.grains.push(Grain {
             buf: vec![0.5],
             pos: 0,
             ch: 1,
+            offset: 0,
+            ..Grain::default()
         });
-        while plugin.grains.len() < MAX_GRAINS {
-            plugin.grains.push(Grain {
+        while plugin.engine.grains.len() < MAX_GRAINS {
+            plugin.engine.grains.push(Grain {
                 buf: Vec::new(),
                 pos: 0,
                 ch: 0,
+                offset: 0,
+                ..Grain::default()
             });
         }
 
@@ -532,4 +3775,82 @@ mod tests {
         assert!((out[0][0] - 1.0).abs() < 1e-6);
         assert!((out[1][0] - 0.5).abs() < 1e-6);
     }
+
+    #[test]
+    fn dry_aux_output_carries_trimmed_input() {
+        let layout = Granular::AUDIO_IO_LAYOUTS[0];
+        let cfg = BufferConfig {
+            sample_rate: 48000.0,
+            min_buffer_size: None,
+            max_buffer_size: 64,
+            process_mode: ProcessMode::Realtime,
+        };
+
+        struct DummyInit;
+        impl InitContext<Granular> for DummyInit {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute(&self, _task: GranularTask) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        struct DummyCtx {
+            transport: Transport,
+        }
+        impl ProcessContext<Granular> for DummyCtx {
+            fn plugin_api(&self) -> PluginApi {
+                PluginApi::Clap
+            }
+            fn execute_background(&self, _task: GranularTask) {}
+            fn execute_gui(&self, _task: GranularTask) {}
+            fn transport(&self) -> &Transport {
+                &self.transport
+            }
+            fn next_event(&mut self) -> Option<PluginNoteEvent<Granular>> {
+                None
+            }
+            fn send_event(&mut self, _event: PluginNoteEvent<Granular>) {}
+            fn set_latency_samples(&self, _samples: u32) {}
+            fn set_current_voice_capacity(&self, _capacity: u32) {}
+        }
+
+        let mut plugin = Granular::default();
+        assert!(plugin.initialize(&layout, &cfg, &mut DummyInit));
+        // グレインを生まずウェットのみを出力し、入力は -6 dB 程度に絞る
+        plugin.params.trigger.density.smoothed.reset(0.0);
+        plugin.params.output.mix.smoothed.reset(1.0);
+        plugin.params.output.input_trim.smoothed.reset(0.5);
+
+        let frames = 32;
+        let mut real = vec![vec![1.0f32; frames]; 1];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.set_slices(frames, |s| {
+                *s = real.iter_mut().map(|c| c.as_mut_slice()).collect();
+            });
+        }
+        let mut dry_real = vec![vec![0.0f32; frames]; 1];
+        let mut dry_out = Buffer::default();
+        unsafe {
+            dry_out.set_slices(frames, |s| {
+                *s = dry_real.iter_mut().map(|c| c.as_mut_slice()).collect();
+            });
+        }
+        let mut aux_inputs: [Buffer; 0] = [];
+        let mut aux_outputs = [dry_out];
+        let mut aux = AuxiliaryBuffers {
+            inputs: &mut aux_inputs,
+            outputs: &mut aux_outputs,
+        };
+        let mut ctx = DummyCtx {
+            transport: unsafe { std::mem::zeroed() },
+        };
+        plugin.process(&mut buffer, &mut aux, &mut ctx);
+
+        // メイン出力はウェットのみ (無音)、Dry Out はゲイン適用後の入力
+        assert!(buffer.as_slice()[0].iter().all(|v| *v == 0.0));
+        assert!(aux.outputs[0].as_slice()[0].iter().all(|v| *v == 0.5));
+    }
 }
@@ -0,0 +1,121 @@
+//! Macro controls: a few host-visible knobs that each push several continuous parameters.
+//!
+//! An assignment names a [`FrameParams`] field and a depth given as a fraction of that field's
+//! range; a negative depth inverts the polarity. Assignments are persisted with the plugin state
+//! as `(field name, depth)` lists and resolved once, off the audio thread, into [`Macros`].
+
+use crate::engine::{FrameParams, CONTINUOUS_COUNT};
+use arrayvec::ArrayVec;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MACRO_COUNT: usize = 4; // マクロの数
+pub const MAX_MACRO_TARGETS: usize = 8; // 1 つのマクロに割り当てられる対象の上限
+
+/// 永続化用の割り当て: (FrameParams のフィールド名, 深さ)。
+/// 深さはフィールドの範囲に対する割合 (-1.0〜1.0) で、負なら逆向きに動かす。
+pub type MacroMap = Vec<(String, f32)>;
+
+/*──────────────────── 2. Resolved assignments ─────────*/
+/// オーディオスレッド用に解決した割り当て (FrameParams::continuous の番号, 深さ)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MacroTarget {
+    index: usize,
+    depth: f32,
+}
+
+/// 全マクロの割り当て
+#[derive(Clone, Debug, Default)]
+pub struct Macros {
+    targets: [ArrayVec<MacroTarget, MAX_MACRO_TARGETS>; MACRO_COUNT],
+}
+
+impl Macros {
+    /// 永続化した割り当てを解決する。知らないフィールド名は無視し、上限を超えた対象は捨てる。
+    pub fn from_maps(maps: &[MacroMap; MACRO_COUNT]) -> Self {
+        let mut names = FrameParams::default();
+        let names: ArrayVec<&str, CONTINUOUS_COUNT> =
+            names.continuous().into_iter().map(|c| c.name).collect();
+        let mut macros = Self::default();
+        for (targets, map) in macros.targets.iter_mut().zip(maps) {
+            let resolved = map.iter().filter_map(|(name, depth)| {
+                let index = names.iter().position(|n| n == name)?;
+                depth.is_finite().then(|| MacroTarget {
+                    index,
+                    depth: depth.clamp(-1.0, 1.0),
+                })
+            });
+            targets.extend(resolved.take(MAX_MACRO_TARGETS));
+        }
+        macros
+    }
+
+    /// 割り当てられた対象があるか
+    pub fn is_empty(&self) -> bool {
+        self.targets.iter().all(ArrayVec::is_empty)
+    }
+
+    /// 各マクロの値 (0.0〜1.0) × 深さ × 範囲を対象のフィールドへ足し、範囲に収める。
+    /// min_ms は足した後の max_ms を超えない
+    pub fn apply(&self, p: &mut FrameParams, values: &[f32; MACRO_COUNT]) {
+        let mut fields = p.continuous();
+        for (targets, value) in self.targets.iter().zip(values) {
+            let value = value.clamp(0.0, 1.0);
+            if value == 0.0 {
+                continue;
+            }
+            for t in targets {
+                let f = &mut fields[t.index];
+                let offset = t.depth * value * (f.max - f.min);
+                *f.value = (*f.value + offset).clamp(f.min, f.max);
+            }
+        }
+        p.limit_min_length();
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macros_push_targets_within_range() {
+        let maps: [MacroMap; MACRO_COUNT] = [
+            vec![("density".into(), 0.5), ("mix".into(), -1.0)],
+            vec![("max_ms".into(), 1.0), ("unknown".into(), 1.0)],
+            Vec::new(),
+            Vec::new(),
+        ];
+        let macros = Macros::from_maps(&maps);
+        assert!(!macros.is_empty());
+        assert_eq!(macros.targets[1].len(), 1);
+
+        let mut p = FrameParams {
+            density: 0.2,
+            mix: 0.8,
+            ..FrameParams::default()
+        };
+        macros.apply(&mut p, &[0.5, 0.0, 1.0, 1.0]);
+        assert_eq!(p.density, 0.45);
+        assert!((p.mix - 0.3).abs() < 1e-6);
+        assert_eq!(p.max_ms, FrameParams::default().max_ms);
+
+        // 範囲を超える分は端で止まる
+        macros.apply(&mut p, &[1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(p.density, 0.95);
+        assert_eq!(p.mix, 0.0);
+        assert_eq!(p.max_ms, crate::engine::MAX_GRAIN_MS);
+        assert!(Macros::default().is_empty());
+
+        // 最小の長さは最大の長さで止まる
+        let lengths = Macros::from_maps(&[
+            vec![("min_ms".into(), 1.0), ("max_ms".into(), -1.0)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        ]);
+        let mut p = FrameParams::default();
+        lengths.apply(&mut p, &[0.5, 0.0, 0.0, 0.0]);
+        assert_eq!(p.min_ms, p.max_ms);
+    }
+}
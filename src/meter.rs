@@ -0,0 +1,168 @@
+//! Peak / RMS level meters and grain telemetry. The audio thread runs the ballistics in
+//! [`Meter`] and publishes the results to [`MeterLevels`] / [`GrainTelemetry`], which the
+//! editor reads through atomics.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const PEAK_RELEASE_MS: f32 = 300.0; // ピークが 1/e まで下がる時間 (ミリ秒)。立ち上がりは即時
+pub const RMS_WINDOW_MS: f32 = 300.0; // RMS の平均化の時定数 (ミリ秒)
+pub const SPAWN_RATE_SEC: f32 = 1.0; // 毎秒のグレイン生成数を平均する時定数 (秒)
+
+/*──────────────────── 2. Shared levels ────────────────*/
+/// GUI と共有する 1 系統分のレベル (リニア値)。f32 のビット列を AtomicU32 に入れて渡す。
+#[derive(Default)]
+pub struct MeterLevels {
+    peak: AtomicU32,
+    rms: AtomicU32,
+}
+
+impl MeterLevels {
+    /// ピークレベル (リニア)
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// RMS レベル (リニア)
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, peak: f32, rms: f32) {
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// GUI と共有するグレインの統計
+#[derive(Default)]
+pub struct GrainTelemetry {
+    active: AtomicU32,
+    spawn_rate: AtomicU32,
+    ceiling: AtomicBool,
+}
+
+impl GrainTelemetry {
+    /// 直前のブロックの終わりに鳴っていたグレイン数 (フェードアウト中を含む)
+    pub fn active(&self) -> u32 {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// 毎秒のグレイン生成数 (SPAWN_RATE_SEC で平均)
+    pub fn spawn_rate(&self) -> f32 {
+        f32::from_bits(self.spawn_rate.load(Ordering::Relaxed))
+    }
+
+    /// 直前のブロックで同時発音数の上限に達し、古いグレインを奪ったなら true
+    pub fn at_ceiling(&self) -> bool {
+        self.ceiling.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn store(&self, active: usize, spawn_rate: f32, ceiling: bool) {
+        self.active.store(active as u32, Ordering::Relaxed);
+        self.spawn_rate
+            .store(spawn_rate.to_bits(), Ordering::Relaxed);
+        self.ceiling.store(ceiling, Ordering::Relaxed);
+    }
+}
+
+/// 入力・ウェット・出力の 3 系統のメーターとグレインの統計
+#[derive(Default)]
+pub struct Meters {
+    /// 入力 (入力ゲイン適用前)
+    pub input: MeterLevels,
+    /// ウェットバス (プリディレイ後、ミックス前)
+    pub wet: MeterLevels,
+    /// 最終出力
+    pub output: MeterLevels,
+    /// 同時発音数と生成レート
+    pub grains: GrainTelemetry,
+}
+
+/*──────────────────── 3. Ballistics ───────────────────*/
+/// オーディオスレッド側のメーターの状態。
+/// ピークは即時に立ち上がり指数的に下がり、RMS は 2 乗の 1 次ローパスで平均する。
+#[derive(Default)]
+pub struct Meter {
+    peak: f32,
+    mean_sq: f32,
+    peak_coef: f32,
+    rms_coef: f32,
+}
+
+impl Meter {
+    pub fn initialize(&mut self, sr: f32) {
+        self.peak_coef = (-1.0 / (PEAK_RELEASE_MS / 1_000.0 * sr).max(1.0)).exp();
+        self.rms_coef = (-1.0 / (RMS_WINDOW_MS / 1_000.0 * sr).max(1.0)).exp();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.peak = 0.0;
+        self.mean_sq = 0.0;
+    }
+
+    /// 全チャンネルの先頭 `n_samples` サンプルを計測し、結果を `levels` へ書き出す。
+    /// ピークはチャンネル間の最大値、RMS はチャンネル間の平均パワーで見る。
+    pub fn measure<C: AsRef<[f32]>>(
+        &mut self,
+        channels: &[C],
+        n_samples: usize,
+        levels: &MeterLevels,
+    ) {
+        if channels.is_empty() {
+            return;
+        }
+        let inv_ch = 1.0 / channels.len() as f32;
+        for i in 0..n_samples {
+            let mut peak = 0.0f32;
+            let mut sq = 0.0;
+            for c in channels {
+                let x = c.as_ref()[i];
+                peak = peak.max(x.abs());
+                sq += x * x;
+            }
+            self.peak = peak.max(self.peak * self.peak_coef);
+            self.mean_sq = sq * inv_ch + (self.mean_sq - sq * inv_ch) * self.rms_coef;
+        }
+        levels.store(self.peak, self.mean_sq.sqrt());
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_tracks_sine_and_releases() {
+        let sr = 48_000.0;
+        let mut meter = Meter::default();
+        meter.initialize(sr);
+        let levels = MeterLevels::default();
+
+        // 1 秒の正弦波でピークは振幅、RMS は振幅 / √2 に落ち着く
+        let sine: Vec<f32> = (0..sr as usize)
+            .map(|i| 0.5 * (i as f32 / sr * 1_000.0 * std::f32::consts::TAU).sin())
+            .collect();
+        meter.measure(&[&sine], sine.len(), &levels);
+        assert!((levels.peak() - 0.5).abs() < 0.01, "{}", levels.peak());
+        assert!(
+            (levels.rms() - 0.5 / 2f32.sqrt()).abs() < 0.01,
+            "{}",
+            levels.rms()
+        );
+
+        // 無音が PEAK_RELEASE_MS 続くとピークは 1/e まで下がり、
+        let silence = vec![0.0f32; (PEAK_RELEASE_MS / 1_000.0 * sr) as usize];
+        meter.measure(&[&silence], silence.len(), &levels);
+        assert!(
+            (levels.peak() - 0.5 / std::f32::consts::E).abs() < 0.01,
+            "{}",
+            levels.peak()
+        );
+        // RMS も平均パワーが 1/e になる (時定数が同じため)
+        let rms = 0.5 / 2f32.sqrt() / std::f32::consts::E.sqrt();
+        assert!((levels.rms() - rms).abs() < 0.01, "{}", levels.rms());
+    }
+}
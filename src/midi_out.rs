@@ -0,0 +1,209 @@
+//! MIDI output of grain spawns, so external visualizers or hardware can follow the texture.
+//!
+//! Every grain becomes a note that is held for the grain's length:
+//!
+//! - channel: the audio channel the grain plays on (mod 16)
+//! - note: [`BASE_NOTE`] plus the grain's transposition, rounded to semitones
+//! - velocity: the grain's length relative to [`MAX_GRAIN_MS`]
+//!
+//! Note-offs that fall into later blocks are held in [`GrainNotes`] and sent when their block
+//! comes. Events within a block are sent in time order, note-offs before note-ons at the same
+//! frame.
+
+use crate::engine::{SpawnEvent, MAX_GRAIN_MS};
+use arrayvec::ArrayVec;
+use nih_plug::prelude::NoteEvent;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const BASE_NOTE: u8 = 60; // 移調していないグレインのノート番号 (C4)
+pub const MAX_HELD_NOTES: usize = 256; // ノートオフを待つノートの上限 (超えたグレインはノートにしない)
+
+/// グレインを (チャンネル, ノート番号, ベロシティ) にする
+pub fn grain_note(event: &SpawnEvent, sr: f32) -> (u8, u8, f32) {
+    let channel = (event.ch % 16) as u8;
+    let st = (12.0 * event.rate.max(f32::MIN_POSITIVE).log2()).round();
+    let note = (BASE_NOTE as f32 + st).clamp(0.0, 127.0) as u8;
+    let len_ms = event.len as f32 / sr * 1_000.0;
+    let velocity = (len_ms / MAX_GRAIN_MS).clamp(1.0 / 127.0, 1.0);
+    (channel, note, velocity)
+}
+
+/*──────────────────── 2. Notes ────────────────────────*/
+/// 鳴らしているノート
+#[derive(Clone, Copy)]
+struct Held {
+    /// ノートオフの通算フレーム位置
+    end: u64,
+    channel: u8,
+    note: u8,
+}
+
+/// グレインの生成イベントをノートオン / オフにする
+#[derive(Default)]
+pub struct GrainNotes {
+    held: ArrayVec<Held, MAX_HELD_NOTES>,
+    /// 次のブロックの先頭の通算フレーム位置
+    clock: u64,
+}
+
+impl GrainNotes {
+    /// `block_len` フレームのブロックで生成した `spawns` (時刻順) をノートにして `send` へ渡し、
+    /// このブロックで終わるノートのノートオフも送る
+    pub fn process(
+        &mut self,
+        spawns: &[SpawnEvent],
+        block_len: usize,
+        sr: f32,
+        mut send: impl FnMut(NoteEvent<()>),
+    ) {
+        let start = self.clock;
+        for event in spawns {
+            let at = start + event.timing as u64;
+            self.release_before(at + 1, &mut send);
+            let (channel, note, velocity) = grain_note(event, sr);
+            // 同じノートが鳴っていれば先に止め、ノートオンとオフの対応を崩さない
+            if let Some(k) = self
+                .held
+                .iter()
+                .position(|h| h.channel == channel && h.note == note)
+            {
+                self.held.remove(k);
+                send(note_off(event.timing, channel, note));
+            }
+            if self.held.is_full() {
+                continue;
+            }
+            send(NoteEvent::NoteOn {
+                timing: event.timing,
+                voice_id: None,
+                channel,
+                note,
+                velocity,
+            });
+            self.held.push(Held {
+                end: at + event.len.max(1) as u64,
+                channel,
+                note,
+            });
+        }
+        self.release_before(start + block_len as u64, &mut send);
+        self.clock += block_len as u64;
+    }
+
+    /// 鳴っているノートをすべてブロックの先頭で止める (MIDI 出力をオフにしたとき)
+    pub fn release_all(&mut self, mut send: impl FnMut(NoteEvent<()>)) {
+        while let Some(h) = self.held.pop() {
+            send(note_off(0, h.channel, h.note));
+        }
+    }
+
+    /// 送らずに忘れる (initialize / reset のとき)
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.clock = 0;
+    }
+
+    /// 通算フレーム位置 `limit` より前に終わるノートのノートオフを終わる順に送る
+    fn release_before(&mut self, limit: u64, send: &mut impl FnMut(NoteEvent<()>)) {
+        let start = self.clock;
+        while let Some((k, h)) = self
+            .held
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| h.end < limit)
+            .min_by_key(|(_, h)| h.end)
+            .map(|(k, h)| (k, *h))
+        {
+            self.held.remove(k);
+            send(note_off((h.end - start) as u32, h.channel, h.note));
+        }
+    }
+}
+
+fn note_off(timing: u32, channel: u8, note: u8) -> NoteEvent<()> {
+    NoteEvent::NoteOff {
+        timing,
+        voice_id: None,
+        channel,
+        note,
+        velocity: 0.0,
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(timing: u32, ch: usize, len: usize, rate: f32) -> SpawnEvent {
+        SpawnEvent {
+            timing,
+            ch,
+            len,
+            rate,
+        }
+    }
+
+    /// (時刻, オンか, チャンネル, ノート)
+    fn collect(
+        notes: &mut GrainNotes,
+        spawns: &[SpawnEvent],
+        block_len: usize,
+    ) -> Vec<(u32, bool, u8, u8)> {
+        let mut out = Vec::new();
+        notes.process(spawns, block_len, 1_000.0, |e| match e {
+            NoteEvent::NoteOn {
+                timing,
+                channel,
+                note,
+                ..
+            } => out.push((timing, true, channel, note)),
+            NoteEvent::NoteOff {
+                timing,
+                channel,
+                note,
+                ..
+            } => out.push((timing, false, channel, note)),
+            _ => unreachable!(),
+        });
+        out
+    }
+
+    #[test]
+    fn grains_map_to_channel_pitch_and_length() {
+        let (channel, note, velocity) = grain_note(&spawn(0, 17, 500, 2.0), 1_000.0);
+        assert_eq!((channel, note), (1, BASE_NOTE + 12));
+        assert!((velocity - 500.0 / MAX_GRAIN_MS).abs() < 1e-6);
+        let (_, note, velocity) = grain_note(&spawn(0, 0, 0, 0.5f32.powf(1.0 / 12.0)), 1_000.0);
+        assert_eq!(note, BASE_NOTE - 1);
+        assert_eq!(velocity, 1.0 / 127.0);
+    }
+
+    #[test]
+    fn note_offs_follow_grains_across_blocks_in_time_order() {
+        let mut notes = GrainNotes::default();
+        let first = collect(
+            &mut notes,
+            &[spawn(10, 0, 30, 1.0), spawn(20, 1, 200, 2.0)],
+            64,
+        );
+        assert_eq!(
+            first,
+            [(10, true, 0, 60), (20, true, 1, 72), (40, false, 0, 60)]
+        );
+        // 鳴っている 2 つのノートは通算 220 フレーム (3 つ目のブロックの 92 フレーム目) で終わる。
+        // 同じ時刻のノートオフはノートオンより先に送る
+        let second = collect(&mut notes, &[spawn(40, 0, 116, 1.0)], 64);
+        assert_eq!(second, [(40, true, 0, 60)]);
+        let third = collect(&mut notes, &[spawn(92, 0, 10, 1.0)], 128);
+        assert_eq!(
+            third,
+            [
+                (92, false, 1, 72),
+                (92, false, 0, 60),
+                (92, true, 0, 60),
+                (102, false, 0, 60)
+            ]
+        );
+    }
+}
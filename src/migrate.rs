@@ -0,0 +1,125 @@
+//! Versioning and migration of the saved plugin state.
+//!
+//! The state carries a `state_version` field. When a parameter is renamed or its meaning
+//! changes, bump [`STATE_VERSION`] and append a step to [`MIGRATIONS`] that rewrites the old
+//! values into equivalent-sounding new ones. `Plugin::filter_state` runs every step between
+//! the saved version and the current one before the host's state is applied, so old projects
+//! load as they sounded instead of falling back to defaults. Range changes alone need no step:
+//! nih-plug stores plain values, which keep their meaning across a re-skewed range.
+
+use nih_plug::prelude::PluginState;
+use nih_plug::wrapper::state::ParamValue;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const STATE_VERSION: u32 = 2; // 書き出す状態のバージョン。パラメータの意味を変えたら上げて MIGRATIONS に変換を足す
+pub const STATE_VERSION_KEY: &str = "state_version"; // 状態の fields に保存するキー
+
+/// 状態の変換。`MIGRATIONS[v]` はバージョン `v` の状態をバージョン `v + 1` にする
+type Migration = fn(&mut PluginState);
+
+const MIGRATIONS: [Migration; STATE_VERSION as usize] =
+    [v0_clamp_min_length, v1_min_length_to_percent];
+
+/*──────────────────── 2. Migration ────────────────────*/
+/// 保存されたバージョンから現在のバージョンまで順に変換し、バージョンを現在のものにする。
+/// バージョンの無い状態は、バージョンを保存するようになる前の 0 として扱う。
+/// 新しいバージョンの状態は変換せずにそのまま読む (知らないパラメータは nih-plug が無視する)。
+pub fn migrate_state(state: &mut PluginState) {
+    let version = saved_version(state);
+    for step in MIGRATIONS.iter().skip(version as usize) {
+        step(state);
+    }
+    if version < STATE_VERSION {
+        state
+            .fields
+            .insert(STATE_VERSION_KEY.to_string(), STATE_VERSION.to_string());
+    }
+}
+
+/// 状態に保存されたバージョン (無ければ 0)
+pub fn saved_version(state: &PluginState) -> u32 {
+    state
+        .fields
+        .get(STATE_VERSION_KEY)
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/*──────────────────── 3. Steps ────────────────────────*/
+/// 0 → 1: 以前は Min Length が Max Length を超えたまま保存でき、エンジン側で Max Length に
+/// 切り詰めて鳴らしていた。今はパラメータ側で切り詰めるので、鳴っていた値をそのまま保存値にする。
+fn v0_clamp_min_length(state: &mut PluginState) {
+    if let (Some(&ParamValue::F32(min)), Some(&ParamValue::F32(max))) =
+        (state.params.get("min_ms"), state.params.get("max_ms"))
+    {
+        if min > max {
+            state
+                .params
+                .insert("min_ms".to_string(), ParamValue::F32(max));
+        }
+    }
+}
+
+/// 1 → 2: Min Length をミリ秒 (`min_ms`) で持つのをやめ、Max Length に対する % (`min_pct`) にした。
+/// 保存されていた長さを同じ長さになる % にする (Max Length が無ければその既定値 500 ms に対して)。
+fn v1_min_length_to_percent(state: &mut PluginState) {
+    if let Some(ParamValue::F32(min)) = state.params.remove("min_ms") {
+        let max = match state.params.get("max_ms") {
+            Some(&ParamValue::F32(max)) => max,
+            _ => 500.0,
+        };
+        state.params.insert(
+            "min_pct".to_string(),
+            ParamValue::F32(min_length_pct(min, max)),
+        );
+    }
+}
+
+/// ミリ秒の最小の長さ `min_ms` を、最大の長さ `max_ms` に対する % (0〜100) にする
+pub fn min_length_pct(min_ms: f32, max_ms: f32) -> f32 {
+    (min_ms / max_ms.max(1.0) * 100.0).clamp(0.0, 100.0)
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(min: f32, max: f32, version: Option<u32>) -> PluginState {
+        let mut state = PluginState::default();
+        state.params.insert("min_ms".into(), ParamValue::F32(min));
+        state.params.insert("max_ms".into(), ParamValue::F32(max));
+        if let Some(v) = version {
+            state.fields.insert(STATE_VERSION_KEY.into(), v.to_string());
+        }
+        state
+    }
+
+    #[test]
+    fn unversioned_state_is_migrated_to_current_version() {
+        let mut old = state(300.0, 100.0, None);
+        migrate_state(&mut old);
+        assert_eq!(old.params["min_pct"], ParamValue::F32(100.0));
+        assert!(!old.params.contains_key("min_ms"));
+        assert_eq!(saved_version(&old), STATE_VERSION);
+    }
+
+    #[test]
+    fn min_length_becomes_a_percentage_of_max_length() {
+        let mut old = state(50.0, 200.0, Some(1));
+        migrate_state(&mut old);
+        assert_eq!(old.params["min_pct"], ParamValue::F32(25.0));
+        assert_eq!(old.params["max_ms"], ParamValue::F32(200.0));
+        assert_eq!(min_length_pct(20.0, 500.0), 4.0);
+    }
+
+    #[test]
+    fn current_and_newer_states_are_left_alone() {
+        for version in [STATE_VERSION, STATE_VERSION + 1] {
+            let mut saved = state(300.0, 100.0, Some(version));
+            let before = saved.clone();
+            migrate_state(&mut saved);
+            assert_eq!(saved, before);
+        }
+    }
+}
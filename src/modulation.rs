@@ -0,0 +1,710 @@
+//! Internal modulation sources. [`RandomWalk`] slowly wanders so long textures evolve
+//! instead of staying statistically constant. [`Chaos`] is a faster, rougher jitter source:
+//! sample-and-hold steps or a drunk walk, with a smoothness control between hard steps and
+//! gliding drift, for the organic wobble of position and pitch that periodic LFOs can't give.
+//!
+//! [`WowFlutter`] is a tape-style speed wobble for grain playback: a slow wow sine and a fast
+//! flutter sine both vary the read rate while a grain is copied from its source. The phases run
+//! in engine time, so consecutive grains share one continuous warble like a worn tape transport.
+//!
+//! [`ModMatrix`] routes a handful of sources (two LFOs, an input envelope follower, a random
+//! walk, the chaos generator, a MIDI CC and the Modulation aux input) to a few engine
+//! destinations through depth-scaled slots. The sources are evaluated once per engine chunk and
+//! the resulting offsets are held for the whole chunk.
+
+use crate::engine::{FrameParams, MAX_GRAIN_MS};
+use nih_plug::prelude::Enum;
+use rand::Rng;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const WALK_STEP: f32 = 0.5; // 1 ステップで目標値が動く最大幅 (出力範囲 -1.0〜1.0 に対して)
+pub const MIN_WALK_RATE: f32 = 0.01; // ランダムウォークの最低ステップレート (Hz)
+pub const MAX_WALK_RATE: f32 = 2.0; // ランダムウォークの最高ステップレート (Hz)
+pub const MIN_CHAOS_RATE: f32 = 0.05; // カオスの値を選び直すレートの範囲 (Hz)
+pub const MAX_CHAOS_RATE: f32 = 30.0;
+pub const CHAOS_STEP: f32 = 0.25; // Drunk Walk で 1 ステップに動く最大幅 (出力範囲 -1.0〜1.0 に対して)
+pub const LFO_COUNT: usize = 2; // モジュレーションマトリクスの LFO の数
+pub const MOD_SLOTS: usize = 4; // モジュレーションマトリクスのスロット数
+pub const MIN_LFO_RATE: f32 = 0.01; // LFO の最低レート (Hz)
+pub const MAX_LFO_RATE: f32 = 20.0; // LFO の最高レート (Hz)
+pub const FOLLOW_ATTACK_MS: f32 = 5.0; // エンベロープフォロワーの立ち上がり (ミリ秒)
+pub const FOLLOW_RELEASE_MS: f32 = 150.0; // エンベロープフォロワーの戻り (ミリ秒)
+pub const MAX_MOD_PITCH_ST: f32 = 12.0; // Pitch 先を深さ 1.0 で動かしたときの移調量 (±半音)
+pub const MAX_WOW_CENTS: f32 = 50.0; // wow の深さの上限 (±セント)
+pub const MIN_WOW_RATE: f32 = 0.1; // wow のレートの範囲 (Hz)
+pub const MAX_WOW_RATE: f32 = 4.0;
+pub const MAX_FLUTTER_CENTS: f32 = 20.0; // flutter の深さの上限 (±セント)
+pub const MIN_FLUTTER_RATE: f32 = 4.0; // flutter のレートの範囲 (Hz)
+pub const MAX_FLUTTER_RATE: f32 = 20.0;
+
+/*──────────────────── 2. Random walk ──────────────────*/
+/// -1.0〜1.0 をさまようランダムウォーク。
+/// `rate` Hz ごとに目標値を ±WALK_STEP の範囲でランダムに動かし、その間を smoothstep で補間する。
+pub struct RandomWalk {
+    from: f32,
+    to: f32,
+    /// 現在のステップ内の位置 (0.0〜1.0)
+    phase: f32,
+    sr: f32,
+}
+
+impl Default for RandomWalk {
+    fn default() -> Self {
+        Self {
+            from: 0.0,
+            to: 0.0,
+            phase: 0.0,
+            sr: 44_100.0,
+        }
+    }
+}
+
+impl RandomWalk {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.from = 0.0;
+        self.to = 0.0;
+        self.phase = 0.0;
+    }
+
+    /// 現在値 (-1.0〜1.0)
+    #[inline]
+    pub fn value(&self) -> f32 {
+        let t = self.phase;
+        self.from + (self.to - self.from) * t * t * (3.0 - 2.0 * t)
+    }
+
+    /// 1 サンプル進めて現在値を返す
+    #[inline]
+    pub fn next(&mut self, rate: f32, rng: &mut impl Rng) -> f32 {
+        self.phase += rate.clamp(MIN_WALK_RATE, MAX_WALK_RATE) / self.sr;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.from = self.to;
+            self.to = (self.to + rng.random_range(-WALK_STEP..=WALK_STEP)).clamp(-1.0, 1.0);
+        }
+        self.value()
+    }
+}
+
+/*──────────────────── 3. Chaos ────────────────────────*/
+/// カオスの値の選び方
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChaosMode {
+    /// ステップごとに -1.0〜1.0 から新しい値を選ぶ
+    #[name = "Sample & Hold"]
+    SampleHold,
+    /// ステップごとに前の値から ±CHAOS_STEP だけ動かす (範囲の端で折り返す)
+    #[name = "Drunk Walk"]
+    Drunk,
+}
+
+/// `rate` Hz ごとに値を選び直し、smooth に応じて新しい値へ追従する -1.0〜1.0 のジッター。
+/// smooth 0 では段になり、1 ではステップ 1 つ分の時定数で滑らかに漂う
+pub struct Chaos {
+    /// 最後に選んだ値
+    held: f32,
+    /// 出力 (held へ追従する)
+    value: f32,
+    /// 現在のステップ内の位置 (0.0〜1.0)
+    phase: f32,
+    sr: f32,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self {
+            held: 0.0,
+            value: 0.0,
+            phase: 0.0,
+            sr: 44_100.0,
+        }
+    }
+}
+
+impl Chaos {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.held = 0.0;
+        self.value = 0.0;
+        self.phase = 0.0;
+    }
+
+    /// 現在値 (-1.0〜1.0)
+    #[inline]
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// 1 サンプル進めて現在値を返す
+    #[inline]
+    pub fn next(&mut self, mode: ChaosMode, rate: f32, smooth: f32, rng: &mut impl Rng) -> f32 {
+        let rate = rate.clamp(MIN_CHAOS_RATE, MAX_CHAOS_RATE);
+        self.phase += rate / self.sr;
+        if self.phase >= 1.0 {
+            self.phase = self.phase.fract();
+            self.held = match mode {
+                ChaosMode::SampleHold => rng.random_range(-1.0..=1.0),
+                ChaosMode::Drunk => {
+                    let x = self.held + rng.random_range(-CHAOS_STEP..=CHAOS_STEP);
+                    if x > 1.0 {
+                        2.0 - x
+                    } else if x < -1.0 {
+                        -2.0 - x
+                    } else {
+                        x
+                    }
+                }
+            };
+        }
+        let smooth = smooth.clamp(0.0, 1.0);
+        self.value = if smooth == 0.0 {
+            self.held
+        } else {
+            let coef = (-rate / (smooth * self.sr)).exp();
+            self.held + (self.value - self.held) * coef
+        };
+        self.value
+    }
+}
+
+/*──────────────────── 4. Wow / flutter ────────────────*/
+/// テープの回転むら: ゆっくりした wow と速い flutter の 2 つの正弦波でグレインの再生速度を揺らす。
+/// 位相はエンジンの時間で進み、続けて生まれるグレインが同じ揺れを共有する
+pub struct WowFlutter {
+    /// wow と flutter の位相 (周期、0.0〜1.0)
+    phase: [f64; 2],
+    sr: f32,
+}
+
+impl Default for WowFlutter {
+    fn default() -> Self {
+        Self {
+            phase: [0.0; 2],
+            sr: 44_100.0,
+        }
+    }
+}
+
+impl WowFlutter {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = [0.0; 2];
+    }
+
+    /// wow と flutter の (速度比の振れ幅, レート Hz)
+    fn voices(p: &FrameParams) -> [(f64, f64); 2] {
+        let swing = |cents: f32, max: f32| ((cents.clamp(0.0, max) / 1_200.0).exp2() - 1.0) as f64;
+        [
+            (
+                swing(p.wow_cents, MAX_WOW_CENTS),
+                p.wow_rate.clamp(MIN_WOW_RATE, MAX_WOW_RATE) as f64,
+            ),
+            (
+                swing(p.flutter_cents, MAX_FLUTTER_CENTS),
+                p.flutter_rate.clamp(MIN_FLUTTER_RATE, MAX_FLUTTER_RATE) as f64,
+            ),
+        ]
+    }
+
+    /// 再生速度を揺らすか
+    pub fn is_active(p: &FrameParams) -> bool {
+        p.wow_cents > 0.0 || p.flutter_cents > 0.0
+    }
+
+    /// `n` サンプル分位相を進める
+    pub fn advance(&mut self, p: &FrameParams, n: usize) {
+        for (phase, (_, hz)) in self.phase.iter_mut().zip(Self::voices(p)) {
+            *phase = (*phase + hz * n as f64 / self.sr as f64).fract();
+        }
+    }
+
+    /// `rate` 倍速で読むグレインの `i` サンプル目の読み出し位置のずれ (ソースのサンプル数)。
+    /// 速度の揺れを積分した閉じた式なので、長いグレインでも位置がずれていかない
+    #[inline]
+    pub fn displacement(&self, p: &FrameParams, i: usize, rate: f64) -> f64 {
+        let tau = std::f64::consts::TAU;
+        let t = i as f64 / self.sr as f64;
+        self.phase
+            .iter()
+            .zip(Self::voices(p))
+            .filter(|(_, (swing, _))| *swing > 0.0)
+            .map(|(phase, (swing, hz))| {
+                let a = swing * self.sr as f64 / (tau * hz);
+                a * ((tau * phase).cos() - (tau * (phase + hz * t)).cos())
+            })
+            .sum::<f64>()
+            * rate
+    }
+
+    /// displacement が取りうる最大値 (ソースのサンプル数、切り上げ)
+    pub fn reach(&self, p: &FrameParams, rate: f32) -> usize {
+        let tau = std::f64::consts::TAU;
+        let max: f64 = Self::voices(p)
+            .iter()
+            .map(|(swing, hz)| 2.0 * swing * self.sr as f64 / (tau * hz))
+            .sum();
+        (max * rate as f64).ceil() as usize
+    }
+}
+
+/*──────────────────── 5. Modulation matrix ────────────*/
+/// LFO の波形 (出力は -1.0〜1.0)
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    #[name = "Sine"]
+    Sine,
+    #[name = "Triangle"]
+    Triangle,
+    /// 上昇するノコギリ波
+    #[name = "Saw"]
+    Saw,
+    #[name = "Square"]
+    Square,
+}
+
+impl LfoShape {
+    /// 位相 `phase` (0.0〜1.0) での値
+    fn value(self, phase: f32) -> f32 {
+        match self {
+            LfoShape::Sine => (phase * std::f32::consts::TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+/// モジュレーションの元
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModSource {
+    /// 使わない
+    #[name = "Off"]
+    Off,
+    /// LFO 1 (-1.0〜1.0)
+    #[name = "LFO 1"]
+    Lfo1,
+    /// LFO 2 (-1.0〜1.0)
+    #[name = "LFO 2"]
+    Lfo2,
+    /// 入力のエンベロープ (0.0〜1.0)
+    #[name = "Envelope"]
+    Envelope,
+    /// ランダムウォーク (-1.0〜1.0)
+    #[name = "Random"]
+    Random,
+    /// MIDI CC の値 (0.0〜1.0)
+    #[name = "MIDI CC"]
+    MidiCc,
+    /// 補助入力 Modulation のチャンクの先頭のサンプル値 (-1.0〜1.0)。CV を録音したオーディオ向け
+    #[name = "Mod In"]
+    ModIn,
+    /// 補助入力 Modulation の直前のチャンクの RMS (0.0〜1.0)
+    #[name = "Mod In RMS"]
+    ModInRms,
+    /// カオス (Sample & Hold / Drunk Walk、-1.0〜1.0)
+    #[name = "Chaos"]
+    Chaos,
+}
+
+/// モジュレーションの先
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModDest {
+    #[name = "Density"]
+    Density,
+    #[name = "Min Length"]
+    MinLength,
+    #[name = "Max Length"]
+    MaxLength,
+    /// Sync / Stretch グレインを切り出す位置をリングの奥へずらす (深さ 1.0 で空いている範囲の端まで)
+    #[name = "Position"]
+    Position,
+    /// グレインの移調 (深さ 1.0 で ±MAX_MOD_PITCH_ST 半音)
+    #[name = "Pitch"]
+    Pitch,
+    #[name = "Mix"]
+    Mix,
+}
+
+const DEST_COUNT: usize = 6; // ModDest の数
+
+/// マトリクスの 1 スロット: 元の値 × 深さ (-1.0〜1.0) を先へ足す
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub dest: ModDest,
+    pub depth: f32,
+}
+
+impl Default for ModSlot {
+    fn default() -> Self {
+        Self {
+            source: ModSource::Off,
+            dest: ModDest::Density,
+            depth: 0.0,
+        }
+    }
+}
+
+/// モジュレーションマトリクス。元をブロックごとに評価し、先ごとのオフセットを保持する。
+pub struct ModMatrix {
+    /// LFO の位相 (0.0〜1.0)
+    lfo_phase: [f32; LFO_COUNT],
+    /// エンベロープフォロワーの現在値
+    env: f32,
+    attack: f32,
+    release: f32,
+    random: RandomWalk,
+    chaos: Chaos,
+    /// 最後に受け取った MIDI CC の値 (0.0〜1.0)
+    cc: f32,
+    /// 補助入力 Modulation の最新のサンプル値
+    mod_in: f32,
+    /// 前の tick から受け取った補助入力の二乗和とサンプル数 (RMS 用)
+    mod_in_sq: f32,
+    mod_in_n: usize,
+    /// 直前のチャンクの補助入力の RMS
+    mod_in_rms: f32,
+    /// 先ごとのオフセット (深さを掛けた元の値の和、-1.0〜1.0 の範囲を超えることもある)
+    offsets: [f32; DEST_COUNT],
+    /// 1 ブロックのサンプル数
+    block: usize,
+    sr: f32,
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        Self {
+            lfo_phase: [0.0; LFO_COUNT],
+            env: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            random: RandomWalk::default(),
+            chaos: Chaos::default(),
+            cc: 0.0,
+            mod_in: 0.0,
+            mod_in_sq: 0.0,
+            mod_in_n: 0,
+            mod_in_rms: 0.0,
+            offsets: [0.0; DEST_COUNT],
+            block: 1,
+            sr: 44_100.0,
+        }
+    }
+}
+
+impl ModMatrix {
+    /// `block` サンプルごとに `tick` を呼ぶ前提で初期化する
+    pub fn initialize(&mut self, sr: f32, block: usize) {
+        self.sr = sr;
+        self.block = block.max(1);
+        self.attack = (-1_000.0 / (FOLLOW_ATTACK_MS * sr)).exp();
+        self.release = (-1_000.0 / (FOLLOW_RELEASE_MS * sr)).exp();
+        // ランダムウォークとカオスはブロックごとに 1 ステップ進める
+        self.random.initialize(sr / self.block as f32);
+        self.chaos.initialize(sr / self.block as f32);
+        self.reset();
+    }
+
+    /// 元の状態を戻す (最後の MIDI CC の値はホストの状態なので残す)
+    pub fn reset(&mut self) {
+        self.lfo_phase = [0.0; LFO_COUNT];
+        self.env = 0.0;
+        self.mod_in = 0.0;
+        self.mod_in_sq = 0.0;
+        self.mod_in_n = 0;
+        self.mod_in_rms = 0.0;
+        self.random.reset();
+        self.chaos.reset();
+        self.offsets = [0.0; DEST_COUNT];
+    }
+
+    /// MIDI CC の値 (0.0〜1.0) を設定する
+    pub fn set_cc(&mut self, value: f32) {
+        self.cc = value.clamp(0.0, 1.0);
+    }
+
+    /// 入力 1 サンプルをエンベロープフォロワーへ渡す
+    #[inline]
+    pub fn follow(&mut self, x: f32) {
+        let level = x.abs();
+        let coef = if level > self.env {
+            self.attack
+        } else {
+            self.release
+        };
+        self.env = level + (self.env - level) * coef;
+    }
+
+    /// 補助入力 Modulation の 1 サンプルを渡す
+    #[inline]
+    pub fn feed_mod_in(&mut self, x: f32) {
+        self.mod_in = x;
+        self.mod_in_sq += x * x;
+        self.mod_in_n += 1;
+    }
+
+    /// ブロックの先頭で元を 1 ブロック分進め、`p` のスロットから先ごとのオフセットを求める。
+    /// 乱数はランダムウォークやカオスを使うスロットがあるときだけ消費する。
+    pub fn tick(&mut self, p: &FrameParams, rng: &mut impl Rng) {
+        let dt = self.block as f32 / self.sr;
+        for (phase, rate) in self.lfo_phase.iter_mut().zip(p.lfo_rate) {
+            *phase = (*phase + rate.clamp(MIN_LFO_RATE, MAX_LFO_RATE) * dt).fract();
+        }
+        let random = if p.mod_slots.iter().any(|s| s.source == ModSource::Random) {
+            self.random.next(p.mod_random_rate, rng)
+        } else {
+            self.random.value()
+        };
+        let chaos = if p.mod_slots.iter().any(|s| s.source == ModSource::Chaos) {
+            self.chaos
+                .next(p.chaos_mode, p.chaos_rate, p.chaos_smooth, rng)
+        } else {
+            self.chaos.value()
+        };
+        if self.mod_in_n > 0 {
+            self.mod_in_rms = (self.mod_in_sq / self.mod_in_n as f32).sqrt();
+            self.mod_in_sq = 0.0;
+            self.mod_in_n = 0;
+        }
+        self.offsets = [0.0; DEST_COUNT];
+        for slot in &p.mod_slots {
+            let value = match slot.source {
+                ModSource::Off => continue,
+                ModSource::Lfo1 => p.lfo_shape[0].value(self.lfo_phase[0]),
+                ModSource::Lfo2 => p.lfo_shape[1].value(self.lfo_phase[1]),
+                ModSource::Envelope => self.env.min(1.0),
+                ModSource::Random => random,
+                ModSource::Chaos => chaos,
+                ModSource::MidiCc => self.cc,
+                ModSource::ModIn => self.mod_in.clamp(-1.0, 1.0),
+                ModSource::ModInRms => self.mod_in_rms.min(1.0),
+            };
+            self.offsets[slot.dest as usize] += slot.depth.clamp(-1.0, 1.0) * value;
+        }
+    }
+
+    /// 先 `dest` の現在のオフセット
+    #[inline]
+    pub fn offset(&self, dest: ModDest) -> f32 {
+        self.offsets[dest as usize]
+    }
+
+    /// Pitch 先のオフセットによる再生速度比
+    #[inline]
+    pub fn pitch_ratio(&self) -> f32 {
+        (self.offset(ModDest::Pitch) * MAX_MOD_PITCH_ST / 12.0).exp2()
+    }
+
+    /// Position 先のオフセット (0.0〜1.0。負の分は無視する)
+    #[inline]
+    pub fn position(&self) -> f32 {
+        self.offset(ModDest::Position).clamp(0.0, 1.0)
+    }
+
+    /// density / min_ms / max_ms / mix にオフセット × 範囲を足し、範囲に収める。
+    /// min_ms は足した後の max_ms を超えない
+    pub fn apply(&self, p: &mut FrameParams) {
+        let length = MAX_GRAIN_MS - 1.0;
+        p.density = (p.density + self.offset(ModDest::Density)).clamp(0.0, 1.0);
+        p.max_ms = (p.max_ms + self.offset(ModDest::MaxLength) * length).clamp(1.0, MAX_GRAIN_MS);
+        p.min_ms = (p.min_ms + self.offset(ModDest::MinLength) * length).clamp(1.0, MAX_GRAIN_MS);
+        p.limit_min_length();
+        p.mix = (p.mix + self.offset(ModDest::Mix)).clamp(0.0, 1.0);
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_walk_is_bounded_and_smooth() {
+        let sr = 1_000.0;
+        let mut walk = RandomWalk::default();
+        walk.initialize(sr);
+        let mut rng = rand::rng();
+
+        let mut prev = walk.value();
+        let mut moved = false;
+        for _ in 0..100_000 {
+            let v = walk.next(MAX_WALK_RATE, &mut rng);
+            assert!((-1.0..=1.0).contains(&v));
+            // smoothstep の最大傾き 1.5 × ステップ幅 × ステップ数/サンプル を超えて跳ばない
+            assert!((v - prev).abs() <= 1.5 * WALK_STEP * MAX_WALK_RATE / sr + 1e-6);
+            moved |= v != 0.0;
+            prev = v;
+        }
+        assert!(moved);
+    }
+
+    #[test]
+    fn chaos_holds_steps_or_drifts_within_range() {
+        let sr = 1_000.0;
+        let mut rng = rand::rng();
+
+        // smooth 0 の Sample & Hold: ステップの間は値を保ち、ステップごとにだけ変わる
+        let mut chaos = Chaos::default();
+        chaos.initialize(sr);
+        let values: Vec<f32> = (0..1_000)
+            .map(|_| chaos.next(ChaosMode::SampleHold, 10.0, 0.0, &mut rng))
+            .collect();
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+        let changes = values.windows(2).filter(|w| w[0] != w[1]).count();
+        assert!((5..=10).contains(&changes), "{changes}");
+
+        // Drunk Walk は 1 ステップで CHAOS_STEP より大きく跳ばず、smooth を上げるとさらに細かく動く
+        for smooth in [0.0, 1.0] {
+            chaos.reset();
+            let mut prev = chaos.value();
+            let mut moved = false;
+            for _ in 0..100_000 {
+                let v = chaos.next(ChaosMode::Drunk, MAX_CHAOS_RATE, smooth, &mut rng);
+                assert!((-1.0..=1.0).contains(&v));
+                let limit = if smooth == 0.0 {
+                    CHAOS_STEP
+                } else {
+                    0.5 * CHAOS_STEP
+                };
+                assert!((v - prev).abs() <= limit + 1e-6, "{smooth}: {prev} -> {v}");
+                moved |= v != 0.0;
+                prev = v;
+            }
+            assert!(moved);
+        }
+    }
+
+    #[test]
+    fn wow_flutter_bends_the_read_rate_within_its_reach() {
+        let mut wow = WowFlutter::default();
+        wow.initialize(1_000.0);
+        let mut p = FrameParams::default();
+        assert!(!WowFlutter::is_active(&p));
+        assert_eq!(wow.displacement(&p, 500, 1.0), 0.0);
+
+        p.wow_cents = MAX_WOW_CENTS;
+        p.wow_rate = 1.0;
+        p.flutter_cents = 10.0;
+        p.flutter_rate = 10.0;
+        assert!(WowFlutter::is_active(&p));
+        wow.advance(&p, 123);
+        let reach = wow.reach(&p, 2.0) as f64;
+        let mut max_step: f64 = 0.0;
+        let mut prev = 0.0;
+        for i in 0..4_000 {
+            let d = wow.displacement(&p, i, 2.0);
+            assert!(d.abs() <= reach, "{d} > {reach}");
+            // 1 サンプルあたりの速度の揺れは wow と flutter の振れ幅の和を超えない
+            max_step = max_step.max((d - prev).abs());
+            prev = d;
+        }
+        assert_eq!(wow.displacement(&p, 0, 2.0), 0.0);
+        let swing = (MAX_WOW_CENTS / 1_200.0).exp2() - 1.0 + (10.0f32 / 1_200.0).exp2() - 1.0;
+        assert!(max_step <= 2.0 * swing as f64 * 1.01, "{max_step}");
+        assert!(max_step > 2.0 * swing as f64 * 0.5, "{max_step}");
+    }
+
+    #[test]
+    fn mod_matrix_sums_slots_and_clamps_destinations() {
+        let mut matrix = ModMatrix::default();
+        matrix.initialize(1_000.0, 250);
+        let mut rng = rand::rng();
+        let mut p = FrameParams {
+            lfo_rate: [1.0, 1.0],
+            lfo_shape: [LfoShape::Square, LfoShape::Saw],
+            ..FrameParams::default()
+        };
+        p.mod_slots[0] = ModSlot {
+            source: ModSource::Lfo1,
+            dest: ModDest::Density,
+            depth: 0.5,
+        };
+        p.mod_slots[1] = ModSlot {
+            source: ModSource::Envelope,
+            dest: ModDest::Density,
+            depth: 0.25,
+        };
+        p.mod_slots[2] = ModSlot {
+            source: ModSource::MidiCc,
+            dest: ModDest::Pitch,
+            depth: 1.0,
+        };
+        p.mod_slots[3] = ModSlot {
+            source: ModSource::Lfo2,
+            dest: ModDest::MaxLength,
+            depth: 1.0,
+        };
+        matrix.set_cc(1.0);
+        for _ in 0..10_000 {
+            matrix.follow(1.0);
+        }
+
+        // 1 ブロック = 0.25 秒: LFO 1 (矩形) は +1、LFO 2 (ノコギリ) は -0.5
+        matrix.tick(&p, &mut rng);
+        assert!((matrix.offset(ModDest::Density) - 0.75).abs() < 1e-3);
+        assert_eq!(matrix.pitch_ratio(), 2.0);
+        assert_eq!(matrix.offset(ModDest::MaxLength), -0.5);
+        assert_eq!(matrix.position(), 0.0);
+        let mut q = p;
+        matrix.apply(&mut q);
+        assert_eq!(q.density, 0.95);
+        assert_eq!(q.max_ms, 1.0);
+
+        // 次のブロックは LFO 1 が後半で -1
+        matrix.tick(&p, &mut rng);
+        assert!((matrix.offset(ModDest::Density) + 0.25).abs() < 1e-3);
+        let mut q = p;
+        matrix.apply(&mut q);
+        assert_eq!(q.density, 0.0);
+    }
+
+    #[test]
+    fn mod_input_gives_the_latest_value_and_the_rms_since_the_last_tick() {
+        let mut matrix = ModMatrix::default();
+        matrix.initialize(1_000.0, 4);
+        let mut rng = rand::rng();
+        let mut p = FrameParams::default();
+        p.mod_slots[0] = ModSlot {
+            source: ModSource::ModIn,
+            dest: ModDest::Density,
+            depth: 1.0,
+        };
+        p.mod_slots[1] = ModSlot {
+            source: ModSource::ModInRms,
+            dest: ModDest::Mix,
+            depth: 1.0,
+        };
+        for x in [0.5, -0.5, 0.5, -0.25] {
+            matrix.feed_mod_in(x);
+        }
+        matrix.tick(&p, &mut rng);
+        assert_eq!(matrix.offset(ModDest::Density), -0.25);
+        let rms = ((0.25 * 3.0 + 0.0625) / 4.0f32).sqrt();
+        assert!((matrix.offset(ModDest::Mix) - rms).abs() < 1e-6);
+
+        // 次の tick までに届いたサンプルだけで RMS を測り直す
+        matrix.feed_mod_in(2.0);
+        matrix.tick(&p, &mut rng);
+        assert_eq!(matrix.offset(ModDest::Density), 1.0);
+        assert_eq!(matrix.offset(ModDest::Mix), 1.0);
+    }
+}
@@ -0,0 +1,379 @@
+//! Human-readable JSON presets that can be shared outside DAW-specific state formats.
+//!
+//! A preset stores every parameter by its `#[id]` as a plain value (enums by variant name)
+//! together with a format version. Importing never touches the parameters directly: it
+//! returns a [`PluginState`] for `GuiContext::set_state`, so the host sees the change the same
+//! way as when it restores its own state.
+//!
+//! The Export Preset parameter writes the current parameters to a new file in [`preset_dir`].
+//! Importing has no control yet: applying a state needs the `GuiContext` of an editor, which the
+//! plugin doesn't have, so [`import_preset`] and [`load_preset`] can only be called from Rust.
+
+use crate::migrate::min_length_pct;
+use crate::record::{timestamped_path, user_dir};
+use nih_plug::prelude::{ParamPtr, Params, PluginState};
+use nih_plug::wrapper::state::ParamValue;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const PRESET_FORMAT: &str = "granular_effect preset"; // プリセットファイルの識別子
+pub const PRESET_VERSION: u64 = 2; // 書き出す形式のバージョン。形式を変えたら上げて、読み込み側で古い形式を変換する
+pub const PRESET_EXTENSION: &str = "json";
+pub const PRESET_DIR_ENV: &str = "GRANULAR_PRESET_DIR"; // Export Preset の保存先ディレクトリを指定する環境変数
+
+/// 操作のきっかけにするパラメータ。プリセットには書き出さず、読み込みでは既定値 (オフ) にする
+/// (読み込んだだけで録音や書き出し、サンプルの読み込みが始まらないように)
+pub const TRIGGER_IDS: &[&str] = &["record", "export_preset", "load_sample"];
+
+/// Export Preset で書き出すプリセットの保存先ディレクトリ
+pub fn preset_dir() -> PathBuf {
+    user_dir(PRESET_DIR_ENV, "Granular Presets")
+}
+
+/// プリセットの読み書きのエラー
+#[derive(Debug)]
+pub enum PresetError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// このプラグインのプリセットではない (識別子や必須の項目が無い)
+    NotAPreset,
+    /// 対応していない (新しい) バージョン
+    UnsupportedVersion(u64),
+    /// パラメータの値の型や名前が合わない
+    InvalidValue(String),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::Io(e) => write!(f, "could not access the preset file: {e}"),
+            PresetError::Json(e) => write!(f, "the preset is not valid JSON: {e}"),
+            PresetError::NotAPreset => write!(f, "not a Granular Effect preset"),
+            PresetError::UnsupportedVersion(v) => write!(
+                f,
+                "preset version {v} is not supported (expected {PRESET_VERSION} or older)"
+            ),
+            PresetError::InvalidValue(id) => write!(f, "invalid value for parameter '{id}'"),
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+impl From<io::Error> for PresetError {
+    fn from(e: io::Error) -> Self {
+        PresetError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PresetError {
+    fn from(e: serde_json::Error) -> Self {
+        PresetError::Json(e)
+    }
+}
+
+/*──────────────────── 2. Export ───────────────────────*/
+/// 全パラメータの現在値 (モジュレーション前) を JSON 文字列にする
+pub fn export_preset(params: &dyn Params) -> String {
+    let mut values = Map::new();
+    for (id, ptr, _) in params.param_map() {
+        if TRIGGER_IDS.contains(&id.as_str()) {
+            continue;
+        }
+        // SAFETY: param_map のポインタは params が生きている間有効
+        let value = unsafe { export_value(ptr) };
+        values.insert(id, value);
+    }
+    let mut doc = Map::new();
+    doc.insert("format".into(), PRESET_FORMAT.into());
+    doc.insert("version".into(), PRESET_VERSION.into());
+    doc.insert("plugin_version".into(), env!("CARGO_PKG_VERSION").into());
+    doc.insert("params".into(), Value::Object(values));
+    serde_json::to_string_pretty(&Value::Object(doc)).expect("a JSON value always serializes")
+}
+
+/// 全パラメータを JSON ファイルとして `path` へ書き出す
+pub fn save_preset(path: &Path, params: &dyn Params) -> Result<(), PresetError> {
+    fs::write(path, export_preset(params))?;
+    Ok(())
+}
+
+/// 全パラメータを `dir` の新しいファイル (`granular_<unix 秒>.json`) へ書き出し、そのパスを返す
+pub fn save_preset_to_dir(dir: &Path, params: &dyn Params) -> Result<PathBuf, PresetError> {
+    fs::create_dir_all(dir)?;
+    let path = timestamped_path(dir, PRESET_EXTENSION);
+    save_preset(&path, params)?;
+    Ok(path)
+}
+
+/// パラメータの値を JSON の値にする。float は f32 の最短表記、enum はバリアント名。
+/// float は正規化値を経由すると歪んだ範囲で丸め誤差が出るので、プレーン値をそのまま書く。
+unsafe fn export_value(ptr: ParamPtr) -> Value {
+    let normalized = ptr.unmodulated_normalized_value();
+    let plain = ptr.preview_plain(normalized);
+    match ptr {
+        ParamPtr::FloatParam(_) => ptr
+            .unmodulated_plain_value()
+            .to_string()
+            .parse::<f64>()
+            .map_or(Value::Null, Value::from),
+        ParamPtr::IntParam(_) => Value::from(plain.round() as i64),
+        ParamPtr::BoolParam(_) => Value::from(plain >= 0.5),
+        ParamPtr::EnumParam(_) => Value::from(ptr.normalized_value_to_string(normalized, false)),
+    }
+}
+
+/*──────────────────── 3. Import ───────────────────────*/
+/// JSON 文字列のプリセットを、`GuiContext::set_state` に渡せる状態にする。
+/// プリセットに無いパラメータは既定値に、知らない ID は無視する (後のバージョンとの互換のため)。
+pub fn import_preset(params: &dyn Params, json: &str) -> Result<PluginState, PresetError> {
+    let doc: Value = serde_json::from_str(json)?;
+    if doc.get("format").and_then(Value::as_str) != Some(PRESET_FORMAT) {
+        return Err(PresetError::NotAPreset);
+    }
+    let version = doc
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or(PresetError::NotAPreset)?;
+    if version == 0 || version > PRESET_VERSION {
+        return Err(PresetError::UnsupportedVersion(version));
+    }
+    let mut values = doc
+        .get("params")
+        .and_then(Value::as_object)
+        .ok_or(PresetError::NotAPreset)?
+        .clone();
+    if version < 2 {
+        v1_min_length_to_percent(&mut values);
+    }
+
+    let mut state = PluginState {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        params: BTreeMap::new(),
+        fields: params.serialize_fields(),
+    };
+    for (id, ptr, _) in params.param_map() {
+        // SAFETY: param_map のポインタは params が生きている間有効
+        let value = unsafe {
+            match values
+                .get(&id)
+                .filter(|_| !TRIGGER_IDS.contains(&id.as_str()))
+            {
+                Some(value) => import_value(ptr, value),
+                None => Some(default_value(ptr)),
+            }
+        }
+        .ok_or_else(|| PresetError::InvalidValue(id.clone()))?;
+        state.params.insert(id, value);
+    }
+    Ok(state)
+}
+
+/// バージョン 1 は Min Length をミリ秒 (`min_ms`) で持っていた。同じ長さになる Max Length に対する %
+/// (`min_pct`) にする
+fn v1_min_length_to_percent(values: &mut Map<String, Value>) {
+    if let Some(min) = values.remove("min_ms").as_ref().and_then(Value::as_f64) {
+        let max = values
+            .get("max_ms")
+            .and_then(Value::as_f64)
+            .unwrap_or(500.0);
+        let pct = min_length_pct(min as f32, max as f32);
+        values.insert("min_pct".into(), Value::from(pct));
+    }
+}
+
+/// `path` の JSON ファイルを読み込み、`GuiContext::set_state` に渡せる状態にする
+pub fn load_preset(path: &Path, params: &dyn Params) -> Result<PluginState, PresetError> {
+    import_preset(params, &fs::read_to_string(path)?)
+}
+
+/// JSON の値を nih-plug の状態の値にする。範囲外の数値はパラメータの範囲に収める。
+/// float は書き出しと同じ理由でプレーン値のまま範囲に収める。
+unsafe fn import_value(ptr: ParamPtr, value: &Value) -> Option<ParamValue> {
+    let number = value.as_f64().filter(|v| v.is_finite()).map(|v| v as f32);
+    match ptr {
+        ParamPtr::FloatParam(_) => {
+            // 逆向きの範囲もあるので端の大小は並べ替える
+            let (a, b) = (ptr.preview_plain(0.0), ptr.preview_plain(1.0));
+            number.map(|v| ParamValue::F32(v.clamp(a.min(b), a.max(b))))
+        }
+        ParamPtr::IntParam(_) => number.map(|v| state_value(ptr, ptr.preview_normalized(v))),
+        ParamPtr::BoolParam(_) => value.as_bool().map(ParamValue::Bool),
+        ParamPtr::EnumParam(_) => value
+            .as_str()
+            .and_then(|name| ptr.string_to_normalized_value(name))
+            .map(|normalized| state_value(ptr, normalized)),
+    }
+}
+
+/// パラメータの既定値を nih-plug の状態の値にする
+unsafe fn default_value(ptr: ParamPtr) -> ParamValue {
+    match ptr {
+        ParamPtr::FloatParam(_) => ParamValue::F32(ptr.default_plain_value()),
+        _ => state_value(ptr, ptr.default_normalized_value()),
+    }
+}
+
+/// 正規化値を nih-plug の状態の値 (プレーン値) にする
+unsafe fn state_value(ptr: ParamPtr, normalized: f32) -> ParamValue {
+    let plain = ptr.preview_plain(normalized);
+    match ptr {
+        ParamPtr::FloatParam(_) => ParamValue::F32(plain),
+        ParamPtr::IntParam(_) | ParamPtr::EnumParam(_) => ParamValue::I32(plain.round() as i32),
+        ParamPtr::BoolParam(_) => ParamValue::Bool(plain >= 0.5),
+    }
+}
+
+/*──────────────────── 4. Factory presets ──────────────*/
+/// 組み込みのファクトリープリセット (名前, JSON)。`presets/` のファイルをそのまま埋め込む。
+///
+/// CLAP の preset discovery は未実装。nih-plug はプラグインから preset-discovery ファクトリーを
+/// 公開する手段を持たないため、ホストのブラウザにはまだ並ばない。対応したらこの一覧をそのまま
+/// provider から返す。
+pub const FACTORY_PRESETS: &[(&str, &str)] = &[
+    ("Cloud", include_str!("../presets/cloud.json")),
+    (
+        "Frozen Stretch",
+        include_str!("../presets/frozen_stretch.json"),
+    ),
+    ("Shimmer Verb", include_str!("../presets/shimmer_verb.json")),
+    ("Stutter", include_str!("../presets/stutter.json")),
+];
+
+/// 名前でファクトリープリセットを探し、`GuiContext::set_state` に渡せる状態にする
+pub fn factory_preset(params: &dyn Params, name: &str) -> Option<Result<PluginState, PresetError>> {
+    FACTORY_PRESETS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, json)| import_preset(params, json))
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GranularParams;
+
+    fn get<'a>(state: &'a PluginState, id: &str) -> &'a ParamValue {
+        &state.params[id]
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let params = GranularParams::default();
+        let json = export_preset(&params);
+        let doc: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            doc.get("version").and_then(Value::as_u64),
+            Some(PRESET_VERSION)
+        );
+        let values = doc.get("params").and_then(Value::as_object).unwrap();
+        assert_eq!(values.len(), params.param_map().len() - TRIGGER_IDS.len());
+        assert!(!values.contains_key("record"));
+        assert_eq!(values["mode"].as_str(), Some("Random"));
+        assert_eq!(values["density"].as_f64(), Some(0.2));
+
+        let state = import_preset(&params, &json).unwrap();
+        assert_eq!(state.params.len(), params.param_map().len());
+        assert_eq!(get(&state, "density"), &ParamValue::F32(0.2));
+        assert_eq!(get(&state, "mode"), &ParamValue::I32(0));
+        assert_eq!(get(&state, "freeze"), &ParamValue::Bool(false));
+    }
+
+    #[test]
+    fn export_preset_writes_a_new_file_and_triggers_stay_off() {
+        let dir = std::env::temp_dir().join(format!("granular_presets_{}", std::process::id()));
+        let params = GranularParams::default();
+        let a = save_preset_to_dir(&dir, &params).unwrap();
+        let b = save_preset_to_dir(&dir, &params).unwrap();
+        assert_ne!(a, b);
+        assert!(load_preset(&a, &params).is_ok());
+
+        // 書き出した時にオンだった操作のパラメータを読み込んでも、録音や書き出しは始まらない
+        let json = format!(
+            r#"{{"format": "{PRESET_FORMAT}", "version": 2, "params": {{"record": true}}}}"#
+        );
+        let state = import_preset(&params, &json).unwrap();
+        assert_eq!(get(&state, "record"), &ParamValue::Bool(false));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_fills_defaults_and_clamps() {
+        let params = GranularParams::default();
+        let json = r#"{
+            "format": "granular_effect preset",
+            "version": 1,
+            "params": { "density": 0.25, "mode": "Sync", "mix": 3.0, "repeats": 2, "unknown": 1 }
+        }"#;
+        let state = import_preset(&params, json).unwrap();
+        assert_eq!(get(&state, "density"), &ParamValue::F32(0.25));
+        assert_eq!(get(&state, "mode"), &ParamValue::I32(1));
+        assert_eq!(get(&state, "mix"), &ParamValue::F32(1.0));
+        assert_eq!(get(&state, "repeats"), &ParamValue::I32(2));
+        // 書かれていないパラメータは既定値
+        assert_eq!(get(&state, "freeze"), &ParamValue::Bool(false));
+        assert!(!state.params.contains_key("unknown"));
+    }
+
+    #[test]
+    fn version_1_min_length_becomes_a_percentage() {
+        let params = GranularParams::default();
+        let json = r#"{
+            "format": "granular_effect preset",
+            "version": 1,
+            "params": { "min_ms": 50.0, "max_ms": 200.0 }
+        }"#;
+        let state = import_preset(&params, json).unwrap();
+        assert_eq!(get(&state, "min_pct"), &ParamValue::F32(25.0));
+        assert_eq!(get(&state, "max_ms"), &ParamValue::F32(200.0));
+    }
+
+    #[test]
+    fn factory_presets_are_valid() {
+        let params = GranularParams::default();
+        let ids: Vec<String> = params.param_map().into_iter().map(|(id, ..)| id).collect();
+        for (name, json) in FACTORY_PRESETS {
+            assert!(factory_preset(&params, name).unwrap().is_ok(), "{name}");
+            // 読み込みでは無視される未知の ID (書き間違い) が無いこと
+            let doc: Value = serde_json::from_str(json).unwrap();
+            for id in doc.get("params").and_then(Value::as_object).unwrap().keys() {
+                assert!(ids.contains(id), "{name}: {id}");
+            }
+        }
+        assert!(factory_preset(&params, "Missing").is_none());
+    }
+
+    #[test]
+    fn import_rejects_foreign_newer_and_invalid_presets() {
+        let params = GranularParams::default();
+        let preset = |version: &str, params: &str| {
+            format!(r#"{{"format": "{PRESET_FORMAT}", "version": {version}, "params": {params}}}"#)
+        };
+        assert!(matches!(
+            import_preset(&params, r#"{"version": 1, "params": {}}"#),
+            Err(PresetError::NotAPreset)
+        ));
+        assert!(matches!(
+            import_preset(&params, &preset("3", "{}")),
+            Err(PresetError::UnsupportedVersion(3))
+        ));
+        assert!(matches!(
+            import_preset(&params, &preset("1", r#"{"mode": "Nope"}"#)),
+            Err(PresetError::InvalidValue(id)) if id == "mode"
+        ));
+        assert!(matches!(
+            import_preset(&params, &preset("1", r#"{"density": "loud"}"#)),
+            Err(PresetError::InvalidValue(id)) if id == "density"
+        ));
+        assert!(matches!(
+            import_preset(&params, "{"),
+            Err(PresetError::Json(_))
+        ));
+    }
+}
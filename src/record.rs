@@ -0,0 +1,312 @@
+//! Wet capture: while the Record parameter is on, the wet bus is streamed to a WAV file on disk,
+//! so a good moment found while tweaking can be kept without arming a DAW track.
+//!
+//! The audio thread interleaves the wet bus into blocks allocated up front and hands full blocks
+//! to the background thread through a lock-free FIFO ([`RecordQueues`]). The background thread
+//! ([`RecordWriter`]) appends them to the file and returns the emptied blocks. If it falls behind
+//! and no empty block is left, the audio thread drops the samples and counts them instead of
+//! allocating or waiting; the count is logged when the take ends.
+//!
+//! Takes are written as 32-bit float WAV named `granular_<unix seconds>.wav` in
+//! `$GRANULAR_RECORD_DIR` (default: `<home>/Granular Recordings`).
+
+use crate::sample::float_wav_header;
+use crossbeam::queue::ArrayQueue;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const RECORD_DIR_ENV: &str = "GRANULAR_RECORD_DIR"; // 録音の保存先ディレクトリを指定する環境変数
+pub const RECORD_BLOCK_FRAMES: usize = 1_024; // FIFO で受け渡す 1 ブロックのフレーム数
+pub const RECORD_BLOCKS: usize = 64; // 受け渡し用に確保しておくブロック数 (48 kHz で約 1.4 秒分)
+
+/// 録音の保存先ディレクトリ
+pub fn record_dir() -> PathBuf {
+    user_dir(RECORD_DIR_ENV, "Granular Recordings")
+}
+
+/// 環境変数 `env` のディレクトリ。無ければホームディレクトリ (それも無ければ一時ディレクトリ) の `name`
+pub fn user_dir(env: &str, name: &str) -> PathBuf {
+    if let Some(dir) = std::env::var_os(env) {
+        return PathBuf::from(dir);
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map_or_else(std::env::temp_dir, PathBuf::from)
+        .join(name)
+}
+
+/// `dir` の中のまだ無いファイル名 `granular_<unix 秒>.<ext>` (あれば `granular_<unix 秒>_<n>.<ext>`)
+pub fn timestamped_path(dir: &Path, ext: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut path = dir.join(format!("granular_{secs}.{ext}"));
+    for n in 1.. {
+        if !path.exists() {
+            break;
+        }
+        path = dir.join(format!("granular_{secs}_{n}.{ext}"));
+    }
+    path
+}
+
+/*──────────────────── 2. FIFO ─────────────────────────*/
+/// オーディオスレッドとバックグラウンドスレッドの間でブロックを受け渡すキュー
+pub struct RecordQueues {
+    /// 空のブロック (バックグラウンド → オーディオスレッド)
+    free: ArrayQueue<Vec<f32>>,
+    /// 埋まったブロック (オーディオスレッド → バックグラウンド)
+    filled: ArrayQueue<Vec<f32>>,
+    /// 録音するチャンネル数
+    channels: AtomicUsize,
+    /// 空きのブロックが無くて捨てたフレーム数
+    dropped: AtomicUsize,
+}
+
+impl Default for RecordQueues {
+    fn default() -> Self {
+        Self {
+            free: ArrayQueue::new(RECORD_BLOCKS),
+            filled: ArrayQueue::new(RECORD_BLOCKS),
+            channels: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+/*──────────────────── 3. Audio thread ─────────────────*/
+/// オーディオスレッド側: ウェットバスをブロックへ詰めて FIFO へ送る
+#[derive(Default)]
+pub struct WetRecorder {
+    queues: Arc<RecordQueues>,
+    /// 詰めている途中のブロック
+    current: Option<Vec<f32>>,
+    n_ch: usize,
+    recording: bool,
+}
+
+impl WetRecorder {
+    pub fn queues(&self) -> Arc<RecordQueues> {
+        self.queues.clone()
+    }
+
+    /// `n_ch` チャンネル分のブロックを確保し直す (オーディオスレッドでは呼ばない)。
+    /// 書き出されていないブロックは捨てる
+    pub fn initialize(&mut self, n_ch: usize) {
+        self.current = None;
+        self.recording = false;
+        self.n_ch = n_ch;
+        while self.queues.filled.pop().is_some() {}
+        while self.queues.free.pop().is_some() {}
+        for _ in 0..RECORD_BLOCKS {
+            let _ = self
+                .queues
+                .free
+                .push(Vec::with_capacity(RECORD_BLOCK_FRAMES * n_ch));
+        }
+        self.queues.channels.store(n_ch, Ordering::Relaxed);
+    }
+
+    /// 録音を始める / 止める。止めるときは詰めている途中のブロックも送る
+    pub fn set_recording(&mut self, recording: bool) {
+        if !recording {
+            if let Some(block) = self.current.take() {
+                let _ = self.queues.filled.push(block);
+            }
+        }
+        self.recording = recording;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// 書き出しを待っているブロックがあるか
+    pub fn has_filled(&self) -> bool {
+        !self.queues.filled.is_empty()
+    }
+
+    /// 録音中なら `wet` の各チャンネルの先頭 `n_samples` サンプルをインターリーブして詰める。
+    /// initialize より多いチャンネルは録音せず、足りないチャンネルは無音にする
+    pub fn push(&mut self, wet: &[Vec<f32>], n_samples: usize) {
+        if !self.recording || self.n_ch == 0 {
+            return;
+        }
+        for i in 0..n_samples {
+            let block = match self.current.take() {
+                Some(block) if block.len() + self.n_ch <= block.capacity() => block,
+                full => {
+                    if let Some(full) = full {
+                        let _ = self.queues.filled.push(full);
+                    }
+                    match self.queues.free.pop() {
+                        Some(block) => block,
+                        None => {
+                            self.queues
+                                .dropped
+                                .fetch_add(n_samples - i, Ordering::Relaxed);
+                            return;
+                        }
+                    }
+                }
+            };
+            let block = self.current.insert(block);
+            for c in 0..self.n_ch {
+                block.push(wet.get(c).map_or(0.0, |w| w[i]));
+            }
+        }
+    }
+}
+
+/*──────────────────── 4. Background writer ────────────*/
+/// 書き出し中のファイル
+struct Take {
+    file: File,
+    path: PathBuf,
+    sr: u32,
+    n_ch: u16,
+    frames: u32,
+}
+
+/// バックグラウンドスレッド側: 埋まったブロックを WAV ファイルへ追記する
+pub struct RecordWriter {
+    dir: PathBuf,
+    take: Mutex<Option<Take>>,
+}
+
+impl Default for RecordWriter {
+    fn default() -> Self {
+        Self::new(record_dir())
+    }
+}
+
+impl RecordWriter {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            take: Mutex::new(None),
+        }
+    }
+
+    /// 新しいファイルを作って書き始める。オーディオスレッドは録音を始めた時点から詰めているので、
+    /// キューに溜まっているブロックは新しいファイルの先頭になる
+    pub fn start(&self, queues: &RecordQueues, sr: f32) -> io::Result<PathBuf> {
+        // 止める前に initialize し直された場合など、閉じられていない前の録音はそのまま閉じる
+        drop(self.lock().take());
+        fs::create_dir_all(&self.dir)?;
+        let path = timestamped_path(&self.dir, "wav");
+        let n_ch = queues.channels.load(Ordering::Relaxed) as u16;
+        let mut file = File::create(&path)?;
+        file.write_all(&float_wav_header(sr as u32, n_ch, 0))?;
+        queues.dropped.store(0, Ordering::Relaxed);
+        *self.lock() = Some(Take {
+            file,
+            path: path.clone(),
+            sr: sr as u32,
+            n_ch,
+            frames: 0,
+        });
+        Ok(path)
+    }
+
+    /// 埋まったブロックをファイルへ追記し、空にして戻す (録音していなければ捨てるだけ)
+    pub fn flush(&self, queues: &RecordQueues) -> io::Result<()> {
+        let mut take = self.lock();
+        let mut result = Ok(());
+        while let Some(mut block) = queues.filled.pop() {
+            if let (Some(t), Ok(())) = (take.as_mut(), &result) {
+                result = t.append(&block);
+            }
+            block.clear();
+            let _ = queues.free.push(block);
+        }
+        result
+    }
+
+    /// 残りを書き出してファイルを閉じる。閉じたファイルのパスと、追いつかずに捨てたフレーム数を返す
+    pub fn stop(&self, queues: &RecordQueues) -> io::Result<Option<(PathBuf, usize)>> {
+        self.flush(queues)?;
+        let dropped = queues.dropped.swap(0, Ordering::Relaxed);
+        Ok(self.lock().take().map(|t| (t.path, dropped)))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Take>> {
+        self.take.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Take {
+    /// インターリーブしたサンプルを追記し、ヘッダの長さを書き直す (途中で落ちても読めるように)
+    fn append(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.file.seek(SeekFrom::End(0))?;
+        let mut w = io::BufWriter::new(&mut self.file);
+        for x in samples {
+            w.write_all(&x.to_le_bytes())?;
+        }
+        w.flush()?;
+        drop(w);
+        self.frames += (samples.len() / self.n_ch.max(1) as usize) as u32;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file
+            .write_all(&float_wav_header(self.sr, self.n_ch, self.frames))
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::decode_wav_channels;
+
+    #[test]
+    fn recorded_takes_round_trip_through_the_fifo() {
+        let dir = std::env::temp_dir().join(format!("granular_record_test_{}", std::process::id()));
+        let writer = RecordWriter::new(dir.clone());
+        let mut recorder = WetRecorder::default();
+        recorder.initialize(2);
+        let queues = recorder.queues();
+
+        // 録音していなければ詰めない
+        let wet = vec![vec![0.5; 100], vec![-0.5; 100]];
+        recorder.push(&wet, 100);
+        assert!(!recorder.has_filled());
+
+        let path = writer.start(&queues, 48_000.0).unwrap();
+        recorder.set_recording(true);
+        let left: Vec<f32> = (0..3_000).map(|i| i as f32 / 3_000.0).collect();
+        let right: Vec<f32> = left.iter().map(|x| -x).collect();
+        for at in (0..3_000).step_by(250) {
+            let wet = [left[at..at + 250].to_vec(), right[at..at + 250].to_vec()];
+            recorder.push(&wet, 250);
+            if recorder.has_filled() {
+                writer.flush(&queues).unwrap();
+            }
+        }
+        recorder.set_recording(false);
+        assert_eq!(writer.stop(&queues).unwrap(), Some((path.clone(), 0)));
+
+        let (sr, channels) = decode_wav_channels(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(sr, 48_000.0);
+        assert_eq!(channels, vec![left, right]);
+        // 書き出したブロックはすべて空きへ戻っている
+        assert_eq!(queues.free.len(), RECORD_BLOCKS);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn a_stalled_writer_drops_samples_instead_of_allocating() {
+        let mut recorder = WetRecorder::default();
+        recorder.initialize(1);
+        let queues = recorder.queues();
+        recorder.set_recording(true);
+        let n = RECORD_BLOCKS * RECORD_BLOCK_FRAMES;
+        recorder.push(&[vec![0.25; n + 10]], n + 10);
+        assert_eq!(queues.filled.len(), RECORD_BLOCKS);
+        assert_eq!(queues.dropped.load(Ordering::Relaxed), 10);
+    }
+}
@@ -0,0 +1,310 @@
+//! WAV sample import: an alternate grain source next to the live ring buffer.
+//!
+//! The file is decoded and mixed down to mono on the background thread, resampled to the
+//! engine rate and handed to the audio thread through [`GrainQueues`]. The buffer it replaces
+//! comes back the same way, so nothing is freed on the audio thread.
+//!
+//! The Load Sample parameter picks the newest WAV file in [`sample_dir`], since the plugin has
+//! no editor for a file picker.
+//!
+//! The same decoder (kept per channel) and a 32-bit float encoder back the `granular-cli`
+//! offline tool and the wet recorder.
+
+use crate::engine::GrainQueues;
+use crate::record::user_dir;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const MAX_SAMPLE_SEC: f32 = 60.0; // 読み込むサンプルの長さの上限 (秒)。これより後ろは切り捨てる
+pub const FLOAT_WAV_HEADER_LEN: usize = 44; // 書き出す 32bit float WAV のヘッダ長
+pub const SAMPLE_DIR_ENV: &str = "GRANULAR_SAMPLE_DIR"; // Load Sample で読み込むファイルを置くディレクトリ
+
+/*──────────────────── 2. WAV encoding and decoding ────*/
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// WAV ファイルを読み込み、(サンプルレート, モノラルにまとめたサンプル) を返す
+pub fn read_wav(path: &Path) -> io::Result<(f32, Vec<f32>)> {
+    decode_wav(&fs::read(path)?)
+}
+
+/// WAV のバイト列を (サンプルレート, モノラルにまとめたサンプル) にする。複数チャンネルは平均する。
+pub fn decode_wav(bytes: &[u8]) -> io::Result<(f32, Vec<f32>)> {
+    let (sr, channels) = decode_wav_channels(bytes)?;
+    let len = channels.first().map_or(0, Vec::len);
+    let samples = (0..len)
+        .map(|i| channels.iter().map(|c| c[i]).sum::<f32>() / channels.len() as f32)
+        .collect();
+    Ok((sr, samples))
+}
+
+/// WAV のバイト列を (サンプルレート, チャンネルごとのサンプル) にする。
+/// 整数 PCM (8/16/24/32bit) と float (32/64bit) に対応する。
+pub fn decode_wav_channels(bytes: &[u8]) -> io::Result<(f32, Vec<Vec<f32>>)> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+    // チャンクを順に探す (fmt と data の間に LIST などが入っていてもよい)
+    let mut fmt = None;
+    let mut data = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_le_bytes([
+            bytes[pos + 4],
+            bytes[pos + 5],
+            bytes[pos + 6],
+            bytes[pos + 7],
+        ]) as usize;
+        // 書き込み途中で切れたファイルも読めるところまで読む
+        let body = &bytes[pos + 8..(pos + 8).saturating_add(len).min(bytes.len())];
+        match &bytes[pos..pos + 4] {
+            b"fmt " => fmt = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // チャンクは偶数バイト境界に揃えられている
+        pos = (pos + 8).saturating_add(len + (len & 1));
+    }
+    let fmt = fmt
+        .filter(|f| f.len() >= 16)
+        .ok_or_else(|| invalid("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid("missing data chunk"))?;
+
+    let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+    let mut tag = u16_at(0);
+    if tag == 0xFFFE && fmt.len() >= 26 {
+        // WAVE_FORMAT_EXTENSIBLE: サブフォーマット GUID の先頭 2 バイトが実際の形式
+        tag = u16_at(24);
+    }
+    let channels = u16_at(2) as usize;
+    let sr = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]) as f32;
+    let bits = u16_at(14);
+    if channels == 0 || sr <= 0.0 {
+        return Err(invalid("invalid channel count or sample rate"));
+    }
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (3, 64) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        _ => return Err(invalid("unsupported sample format")),
+    };
+    let width = bits as usize / 8;
+    let frames = data.len() / (width * channels);
+    let mut out = vec![Vec::with_capacity(frames); channels];
+    for frame in data.chunks_exact(width * channels) {
+        for (c, b) in out.iter_mut().zip(frame.chunks_exact(width)) {
+            // NaN / inf はリングと同様に無音に置き換える
+            let x = decode(b);
+            c.push(if x.is_finite() { x } else { 0.0 });
+        }
+    }
+    Ok((sr, out))
+}
+
+/// `n_ch` チャンネル `frames` フレームの 32bit float WAV のヘッダ (この後にインターリーブしたデータが続く)
+pub fn float_wav_header(sr: u32, n_ch: u16, frames: u32) -> [u8; FLOAT_WAV_HEADER_LEN] {
+    let data_len = frames * n_ch as u32 * 4;
+    let mut out = [0; FLOAT_WAV_HEADER_LEN];
+    let fields: [&[u8]; 12] = [
+        b"RIFF",
+        &(FLOAT_WAV_HEADER_LEN as u32 - 8 + data_len).to_le_bytes(),
+        b"WAVEfmt ",
+        &16u32.to_le_bytes(),
+        &3u16.to_le_bytes(), // WAVE_FORMAT_IEEE_FLOAT
+        &n_ch.to_le_bytes(),
+        &sr.to_le_bytes(),
+        &(sr * 4 * n_ch as u32).to_le_bytes(),
+        &(4 * n_ch).to_le_bytes(),
+        &32u16.to_le_bytes(),
+        b"data",
+        &data_len.to_le_bytes(),
+    ];
+    let mut at = 0;
+    for f in fields {
+        out[at..at + f.len()].copy_from_slice(f);
+        at += f.len();
+    }
+    out
+}
+
+/// チャンネルごとのサンプル (同じ長さ) を 32bit float の WAV のバイト列にする
+pub fn encode_wav(sr: u32, channels: &[Vec<f32>]) -> Vec<u8> {
+    let frames = channels.first().map_or(0, Vec::len);
+    let mut out = Vec::with_capacity(FLOAT_WAV_HEADER_LEN + frames * channels.len() * 4);
+    out.extend_from_slice(&float_wav_header(sr, channels.len() as u16, frames as u32));
+    for i in 0..frames {
+        for c in channels {
+            out.extend_from_slice(&c[i].to_le_bytes());
+        }
+    }
+    out
+}
+
+/*──────────────────── 3. Loading ──────────────────────*/
+/// `samples` を `from` Hz から `to` Hz へ線形補間で再サンプリングする
+pub fn resample(samples: &[f32], from: f32, to: f32) -> Vec<f32> {
+    if samples.is_empty() || from == to {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (samples.len() as f64 / ratio) as usize;
+    let last = samples.len() - 1;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let i0 = (pos as usize).min(last);
+            let i1 = (i0 + 1).min(last);
+            let frac = (pos - i0 as f64) as f32;
+            samples[i0] + (samples[i1] - samples[i0]) * frac
+        })
+        .collect()
+}
+
+/// Load Sample で読み込む WAV を置くディレクトリ
+pub fn sample_dir() -> PathBuf {
+    user_dir(SAMPLE_DIR_ENV, "Granular Samples")
+}
+
+/// `dir` の中で最後に更新された WAV ファイル (拡張子 .wav、大文字小文字は問わない)。無ければ None
+pub fn newest_wav(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut newest = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_wav = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+        if !is_wav || !path.is_file() {
+            continue;
+        }
+        let modified = fs::metadata(&path)?.modified()?;
+        if newest.as_ref().is_none_or(|(t, _)| modified > *t) {
+            newest = Some((modified, path));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+/// バックグラウンドスレッド側の処理: `path` の WAV を `sr` Hz のモノラルへ変換し、
+/// 次のチャンクでエンジンのサンプルソースとして差し替えさせる。読み込んだサンプル数を返す。
+pub fn load_sample(queues: &GrainQueues, path: &Path, sr: f32) -> io::Result<usize> {
+    let (file_sr, mut samples) = read_wav(path)?;
+    samples.truncate((MAX_SAMPLE_SEC * file_sr) as usize);
+    let samples = resample(&samples, file_sr, sr);
+    let len = samples.len();
+    install_sample(queues, samples);
+    Ok(len)
+}
+
+/// サンプルソースを `samples` に差し替えさせる (空ならサンプルを外してライブ入力へ戻す)。
+/// オーディオスレッドが返した古いバッファと、まだ取り込まれていない前回のサンプルはここで解放する。
+pub fn install_sample(queues: &GrainQueues, samples: Vec<f32>) {
+    while queues.retired.pop().is_some() {}
+    let _ = queues.samples.force_push(samples);
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用の最小限の PCM WAV (fmt の前に未知のチャンクを挟む)
+    fn wav(tag: u16, channels: u16, sr: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(4 + 10 + 24 + 8 + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"LIST");
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&[0, 0]); // 奇数長チャンクのパディング
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sr.to_le_bytes());
+        let block = channels * bits / 8;
+        out.extend_from_slice(&(sr * block as u32).to_le_bytes());
+        out.extend_from_slice(&block.to_le_bytes());
+        out.extend_from_slice(&bits.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn decodes_pcm_and_float_to_mono() {
+        // 16bit ステレオ: (0.5, -0.5) → 0.0, (0.5, 0.5) → 0.5
+        let data: Vec<u8> = [16_384i16, -16_384, 16_384, 16_384]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let (sr, mono) = decode_wav(&wav(1, 2, 44_100, 16, &data)).unwrap();
+        assert_eq!(sr, 44_100.0);
+        assert_eq!(mono, vec![0.0, 0.5]);
+
+        // 24bit モノラル: 負の値の符号拡張
+        let data = [0x00, 0x00, 0xC0, 0x00, 0x00, 0x40];
+        let (_, mono) = decode_wav(&wav(1, 1, 48_000, 24, &data)).unwrap();
+        assert_eq!(mono, vec![-0.5, 0.5]);
+
+        let data: Vec<u8> = [0.25f32, f32::NAN]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let (_, mono) = decode_wav(&wav(3, 1, 48_000, 32, &data)).unwrap();
+        assert_eq!(mono, vec![0.25, 0.0]);
+
+        assert!(decode_wav(b"RIFF\0\0\0\0AVI ").is_err());
+        assert!(decode_wav(&wav(2, 1, 48_000, 4, &[0; 4])).is_err());
+    }
+
+    #[test]
+    fn float_wav_round_trips_per_channel() {
+        let channels = vec![vec![0.25, -1.0, 0.5], vec![0.0, 0.75, -0.125]];
+        let bytes = encode_wav(48_000, &channels);
+        assert_eq!(decode_wav_channels(&bytes).unwrap(), (48_000.0, channels));
+        let (_, mono) = decode_wav(&bytes).unwrap();
+        assert_eq!(mono, vec![0.125, -0.125, 0.1875]);
+    }
+
+    #[test]
+    fn newest_wav_skips_other_files() {
+        let dir = std::env::temp_dir().join(format!("granular_samples_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(newest_wav(&dir).unwrap(), None);
+
+        let old = dir.join("old.wav");
+        let new = dir.join("new.WAV");
+        fs::write(&old, encode_wav(48_000, &[vec![0.5]])).unwrap();
+        fs::write(&new, encode_wav(48_000, &[vec![0.25]])).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        assert_eq!(newest_wav(&dir).unwrap(), Some(new));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resample_keeps_duration() {
+        let samples: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let up = resample(&samples, 24_000.0, 48_000.0);
+        assert_eq!(up.len(), 200);
+        assert_eq!(up[1], 0.5);
+        assert_eq!(up[198], 99.0);
+        let down = resample(&samples, 48_000.0, 24_000.0);
+        assert_eq!(down.len(), 50);
+        assert_eq!(down[10], 20.0);
+        assert_eq!(resample(&samples, 48_000.0, 48_000.0), samples);
+    }
+}
@@ -0,0 +1,119 @@
+//! Scene morphing: two snapshots of the continuous parameters and a crossfade between them.
+//!
+//! A scene holds every continuous field of [`FrameParams`]; discrete settings such as
+//! the trigger mode or the window shape keep following their knobs. Scenes are captured on the
+//! audio thread when a store trigger turns on and persisted with the plugin state as
+//! `{field name: value}` maps, so fields added later simply fall back to their defaults.
+
+use crate::engine::{Continuous, FrameParams};
+use std::collections::BTreeMap;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const SCENE_COUNT: usize = 2; // シーンの数 (A / B)
+
+/// 永続化用のシーン (FrameParams のフィールド名 → 値)
+pub type SceneMap = BTreeMap<String, f32>;
+
+/*──────────────────── 2. Morphing ─────────────────────*/
+/// `live` の連続値を、シーン `a` と `b` を `t` (0.0=A, 1.0=B) で線形補間した値に置き換える
+pub fn morph(live: &mut FrameParams, a: &FrameParams, b: &FrameParams, t: f32) {
+    let t = t.clamp(0.0, 1.0);
+    let (mut a, mut b) = (*a, *b);
+    for ((x, a), b) in live
+        .continuous()
+        .into_iter()
+        .zip(a.continuous())
+        .zip(b.continuous())
+    {
+        *x.value = *a.value + (*b.value - *a.value) * t;
+    }
+}
+
+/*──────────────────── 3. Persistence ──────────────────*/
+/// シーンを永続化用のマップにする
+pub fn to_map(scene: &FrameParams) -> SceneMap {
+    let mut scene = *scene;
+    scene
+        .continuous()
+        .into_iter()
+        .map(|Continuous { name, value, .. }| (name.to_string(), *value))
+        .collect()
+}
+
+/// 永続化したマップの値でシーンを上書きする。マップに無いフィールドと有限でない値はそのまま。
+pub fn apply_map(scene: &mut FrameParams, map: &SceneMap) {
+    for x in scene.continuous() {
+        if let Some(v) = map.get(x.name).filter(|v| v.is_finite()) {
+            *x.value = *v;
+        }
+    }
+}
+
+/// マップにあるフィールドの値だけを書き換える。
+/// オーディオスレッドから呼ぶので確保が起きないようキーは追加しない (`to_map` で揃えておくこと)。
+pub fn update_map(map: &mut SceneMap, scene: &FrameParams) {
+    let mut scene = *scene;
+    for x in scene.continuous() {
+        if let Some(v) = map.get_mut(x.name) {
+            *v = *x.value;
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{TriggerMode, CONTINUOUS_COUNT};
+
+    #[test]
+    fn morph_interpolates_continuous_fields_only() {
+        let a = FrameParams {
+            density: 0.0,
+            max_ms: 100.0,
+            mode: TriggerMode::Sync,
+            ..FrameParams::default()
+        };
+        let b = FrameParams {
+            density: 1.0,
+            max_ms: 500.0,
+            mode: TriggerMode::Tempo,
+            ..FrameParams::default()
+        };
+        let mut live = FrameParams::default();
+        morph(&mut live, &a, &b, 0.25);
+        assert_eq!(live.density, 0.25);
+        assert_eq!(live.max_ms, 200.0);
+        // 離散的な設定はノブのまま
+        assert_eq!(live.mode, TriggerMode::Random);
+        morph(&mut live, &a, &b, 2.0);
+        assert_eq!(live.density, 1.0);
+    }
+
+    #[test]
+    fn maps_round_trip_and_tolerate_missing_fields() {
+        let scene = FrameParams {
+            density: 0.75,
+            overdub: 0.5,
+            ..FrameParams::default()
+        };
+        let mut map = to_map(&scene);
+        assert_eq!(map.len(), CONTINUOUS_COUNT);
+        let mut restored = FrameParams::default();
+        apply_map(&mut restored, &map);
+        assert_eq!(restored, scene);
+
+        // 古い状態に無いフィールドは既定値のまま
+        map.remove("overdub");
+        map.insert("density".into(), f32::NAN);
+        let mut restored = FrameParams::default();
+        apply_map(&mut restored, &map);
+        assert_eq!(restored.overdub, FrameParams::default().overdub);
+        assert_eq!(restored.density, FrameParams::default().density);
+
+        // キーの無いフィールドは書き足さない
+        update_map(&mut map, &scene);
+        assert_eq!(map["density"], 0.75);
+        assert!(!map.contains_key("overdub"));
+    }
+}
@@ -0,0 +1,176 @@
+//! 16-step trigger sequencer for the Step Sequencer trigger mode, and the Euclidean rhythms of
+//! the Euclidean mode.
+//!
+//! The steps run on the host-synced note grid of Tempo mode (`division` / `swing`), so step 1
+//! always falls on the bar line the transport reports. Each step can spawn one grain, with its
+//! own length factor and transposition on top of the usual length and pitch, and fires with its
+//! own probability. The dice come from the engine's seeded generator, so a pattern of 70% steps
+//! still bounces identically offline.
+//!
+//! The pattern is persisted with the plugin state as plain `(on, length, pitch, probability)`
+//! tuples. The
+//! editor writes them; the audio thread picks up the current pattern at the start of each block
+//! and resolves it into a clamped [`Sequence`].
+//!
+//! [`euclid`] spreads a number of pulses as evenly as possible over a cycle of steps (Bresenham
+//! form of Bjorklund's algorithm), which gives the tresillo, cinquillo and friends for free.
+
+use std::sync::RwLock;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const SEQ_STEPS: usize = 16; // ステップ数
+pub const MIN_STEP_LENGTH: f32 = 0.25; // ステップごとの長さの倍率の範囲
+pub const MAX_STEP_LENGTH: f32 = 4.0;
+pub const MAX_STEP_PITCH_ST: f32 = 24.0; // ステップごとの移調の上限 (±半音)
+pub const MAX_EUCLID_STEPS: i32 = 32; // ユークリッドリズムの 1 周の最大ステップ数
+
+/// 永続化用のステップ: (グレインを出すか, 長さの倍率, 移調 (半音), 発生確率)
+pub type SavedStep = (bool, f32, f32, f32);
+
+/// 永続化用のパターン
+pub type SavedPattern = [SavedStep; SEQ_STEPS];
+
+/// 既定のパターン (全ステップでそのままの長さ・高さのグレインを出す)
+pub fn default_pattern() -> SavedPattern {
+    [(true, 1.0, 0.0, 1.0); SEQ_STEPS]
+}
+
+/// エディタからステップ `k` を書き換える (範囲外の値は端に寄せて保存する)
+pub fn set_step(pattern: &RwLock<SavedPattern>, k: usize, step: SavedStep) {
+    let Step {
+        on,
+        length,
+        pitch,
+        probability,
+    } = Step::from_saved(step);
+    if let (Ok(mut pattern), true) = (pattern.write(), k < SEQ_STEPS) {
+        pattern[k] = (on, length, pitch, probability);
+    }
+}
+
+/*──────────────────── 2. Sequence ─────────────────────*/
+/// オーディオスレッド用に範囲を収めたステップ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    /// このステップでグレインを出すか
+    pub on: bool,
+    /// グレイン長に掛ける倍率
+    pub length: f32,
+    /// グレインの移調 (半音)
+    pub pitch: f32,
+    /// オンのときにグレインを出す確率 (0.0〜1.0)
+    pub probability: f32,
+}
+
+impl Step {
+    /// 永続化したステップを読み、範囲外の値は端に寄せる (NaN は既定値にする)
+    fn from_saved((on, length, pitch, probability): SavedStep) -> Self {
+        let clamp = |v: f32, lo: f32, hi: f32, default: f32| {
+            if v.is_nan() {
+                default
+            } else {
+                v.clamp(lo, hi)
+            }
+        };
+        Self {
+            on,
+            length: clamp(length, MIN_STEP_LENGTH, MAX_STEP_LENGTH, 1.0),
+            pitch: clamp(pitch, -MAX_STEP_PITCH_ST, MAX_STEP_PITCH_ST, 0.0),
+            probability: clamp(probability, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// 全ステップ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sequence {
+    pub steps: [Step; SEQ_STEPS],
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::from_saved(&default_pattern())
+    }
+}
+
+impl Sequence {
+    /// 永続化したパターンを読む
+    pub fn from_saved(pattern: &SavedPattern) -> Self {
+        Self {
+            steps: pattern.map(Step::from_saved),
+        }
+    }
+
+    /// グリッドの `k` 番目 (ホストの位置から数えたステップ番号、負も可) のステップ
+    pub fn step(&self, k: i64) -> Step {
+        self.steps[k.rem_euclid(SEQ_STEPS as i64) as usize]
+    }
+}
+
+/*──────────────────── 3. Euclidean rhythm ─────────────*/
+/// `steps` ステップに `pulses` 個の発音をなるべく均等に並べたとき、`k` 番目のステップで鳴らすか。
+/// `rotation` ステップ分パターンを前へ回す (回さなければ 1 ステップ目は必ず鳴る)。
+pub fn euclid(steps: i32, pulses: i32, rotation: i32, k: i64) -> bool {
+    let steps = steps.clamp(1, MAX_EUCLID_STEPS) as i64;
+    let pulses = pulses.clamp(0, steps as i32) as i64;
+    let k = (k + rotation as i64).rem_euclid(steps);
+    k * pulses % steps < pulses
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_pattern_is_clamped_and_wraps_around_the_bar() {
+        let mut pattern = default_pattern();
+        pattern[1] = (false, 10.0, -40.0, 2.0);
+        pattern[2] = (true, f32::NAN, 7.0, f32::NAN);
+        let seq = Sequence::from_saved(&pattern);
+        assert_eq!(
+            seq.steps[1],
+            Step {
+                on: false,
+                length: MAX_STEP_LENGTH,
+                pitch: -MAX_STEP_PITCH_ST,
+                probability: 1.0,
+            }
+        );
+        assert_eq!(seq.steps[2].length, 1.0);
+        assert_eq!(seq.steps[2].probability, 1.0);
+        assert_eq!(seq.step(17), seq.steps[1]);
+        assert_eq!(seq.step(-14), seq.steps[2]);
+
+        // エディタからの書き換えも範囲に収めて保存する
+        let saved = RwLock::new(default_pattern());
+        set_step(&saved, 3, (true, 0.0, 30.0, -0.5));
+        assert_eq!(
+            saved.read().unwrap()[3],
+            (true, MIN_STEP_LENGTH, MAX_STEP_PITCH_ST, 0.0)
+        );
+    }
+
+    #[test]
+    fn euclid_spreads_pulses_evenly() {
+        let pattern = |steps, pulses, rotation| -> String {
+            (0..steps as i64)
+                .map(|k| {
+                    if euclid(steps, pulses, rotation, k) {
+                        'x'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        };
+        // トレシージョと、シンキージョ (x.xx.xx.) を回したもの
+        assert_eq!(pattern(8, 3, 0), "x..x..x.");
+        assert_eq!(pattern(8, 5, 0), "x.x.xx.x");
+        assert_eq!(pattern(8, 3, 1), "..x..x.x");
+        assert_eq!(pattern(4, 0, 0), "....");
+        assert_eq!(pattern(4, 9, 0), "xxxx");
+        // 周期的に繰り返す (負のステップも)
+        assert_eq!(euclid(8, 3, 0, -5), euclid(8, 3, 0, 3));
+    }
+}
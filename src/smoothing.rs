@@ -0,0 +1,247 @@
+//! Smoothing for the main parameters (density, mix and the grain length range), with a speed and
+//! a curve chosen by the user.
+//!
+//! The host-facing smoothers of these parameters are nearly instant so that
+//! sample-accurate automation stays sharp, but hard jumps in density or mix then click.
+//! [`MainSmoothers`] ramps from the current value to each new target over the selected time,
+//! either linearly or in the log domain (equal ratios per step, better for lengths). Ramps that
+//! start or end at zero are always linear.
+
+use crate::engine::FrameParams;
+use nih_plug::prelude::Enum;
+
+/*──────────────────── 1. Settings ─────────────────────*/
+/// スムージングの時間
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmoothingSpeed {
+    /// ホストのオートメーションの値をそのまま使う
+    #[name = "Off"]
+    Off,
+    #[name = "Fast"]
+    Fast,
+    #[name = "Standard"]
+    Standard,
+    #[name = "Slow"]
+    Slow,
+}
+
+impl SmoothingSpeed {
+    /// 新しい値へ移り切るまでの時間 (ミリ秒)
+    pub fn ms(self) -> f32 {
+        match self {
+            SmoothingSpeed::Off => 0.0,
+            SmoothingSpeed::Fast => 5.0,
+            SmoothingSpeed::Standard => 20.0,
+            SmoothingSpeed::Slow => 100.0,
+        }
+    }
+}
+
+/// スムージングの曲線
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmoothingCurve {
+    /// 値を一定の幅ずつ動かす
+    #[name = "Linear"]
+    Linear,
+    /// 値を一定の比率ずつ動かす (0 を含む区間は Linear)
+    #[name = "Logarithmic"]
+    Logarithmic,
+}
+
+impl SmoothingCurve {
+    /// `from` から `to` までの位置 `t` (0.0〜1.0) の値
+    #[inline]
+    fn interp(self, from: f32, to: f32, t: f32) -> f32 {
+        match self {
+            SmoothingCurve::Logarithmic if from > 0.0 && to > 0.0 => from * (to / from).powf(t),
+            _ => from + (to - from) * t,
+        }
+    }
+}
+
+/*──────────────────── 2. Ramps ────────────────────────*/
+/// 目標値が変わるたびに、今の値からその目標値へ決まったサンプル数で移るランプ
+#[derive(Clone, Copy, Default)]
+struct Ramp {
+    from: f32,
+    to: f32,
+    value: f32,
+    /// 目標値に着くまでの残りサンプル数と、ランプ全体のサンプル数
+    left: u32,
+    len: u32,
+    /// 最初の値を受け取ったか (受け取るまではランプせずにその値へ揃える)
+    primed: bool,
+}
+
+impl Ramp {
+    #[inline]
+    fn next(&mut self, target: f32, len: u32, curve: SmoothingCurve) -> f32 {
+        if !self.primed || len == 0 {
+            *self = Self {
+                from: target,
+                to: target,
+                value: target,
+                primed: true,
+                ..Self::default()
+            };
+        } else if target != self.to {
+            self.from = self.value;
+            self.to = target;
+            self.len = len;
+            self.left = len;
+        }
+        if self.left > 0 {
+            self.left -= 1;
+            let t = 1.0 - self.left as f32 / self.len as f32;
+            self.value = curve.interp(self.from, self.to, t);
+        }
+        self.value
+    }
+}
+
+/*──────────────────── 3. Main parameters ──────────────*/
+/// スムージングする前の主要なパラメータの値
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MainTargets {
+    pub density: f32,
+    pub mix: f32,
+    /// 最小の長さ (max_ms に対する %)
+    pub min_pct: f32,
+    pub max_ms: f32,
+}
+
+/// density / mix / 最小の長さ (max_ms に対する %) / max_ms のランプ
+#[derive(Default)]
+pub struct MainSmoothers {
+    density: Ramp,
+    mix: Ramp,
+    min_pct: Ramp,
+    max_ms: Ramp,
+    sr: f32,
+}
+
+impl MainSmoothers {
+    pub fn initialize(&mut self, sr: f32) {
+        self.sr = sr;
+        self.reset();
+    }
+
+    /// ランプを止め、次の値へそのまま揃える
+    pub fn reset(&mut self) {
+        for ramp in [
+            &mut self.density,
+            &mut self.mix,
+            &mut self.min_pct,
+            &mut self.max_ms,
+        ] {
+            ramp.primed = false;
+        }
+    }
+
+    /// どのランプも目標値に着いているか止まっていて、目標値が変わるまで同じ値を出し続けるか
+    pub fn is_idle(&self) -> bool {
+        [&self.density, &self.mix, &self.min_pct, &self.max_ms]
+            .iter()
+            .all(|ramp| !ramp.primed || ramp.left == 0)
+    }
+
+    /// `speed` が Off でなければ、`p` の主要なパラメータを `targets` へ向かうランプの値で置き換える。
+    /// Off の間は `p` をそのまま使い、次にオンにしたときはその時点の値から始める。
+    /// 最小の長さは最大の長さに対する比でランプさせるので、ランプの途中でも最大を超えない
+    pub fn apply(
+        &mut self,
+        p: &mut FrameParams,
+        targets: MainTargets,
+        speed: SmoothingSpeed,
+        curve: SmoothingCurve,
+    ) {
+        if speed == SmoothingSpeed::Off {
+            self.reset();
+            return;
+        }
+        let len = (speed.ms() / 1_000.0 * self.sr) as u32;
+        p.density = self.density.next(targets.density, len, curve);
+        p.mix = self.mix.next(targets.mix, len, curve);
+        p.max_ms = self.max_ms.next(targets.max_ms, len, curve);
+        let min_pct = self.min_pct.next(targets.min_pct, len, curve);
+        p.min_ms = (p.max_ms * min_pct / 100.0).max(1.0);
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        smoothers: &mut MainSmoothers,
+        targets: MainTargets,
+        curve: SmoothingCurve,
+        n: usize,
+    ) -> Vec<FrameParams> {
+        (0..n)
+            .map(|_| {
+                let mut p = FrameParams::default();
+                smoothers.apply(&mut p, targets, SmoothingSpeed::Fast, curve);
+                p
+            })
+            .collect()
+    }
+
+    #[test]
+    fn jumps_ramp_over_the_selected_time() {
+        let mut smoothers = MainSmoothers::default();
+        smoothers.initialize(1_000.0);
+        let start = MainTargets {
+            density: 0.0,
+            mix: 1.0,
+            min_pct: 10.0,
+            max_ms: 100.0,
+        };
+        // 最初の値へはランプせずに揃える
+        let frames = run(&mut smoothers, start, SmoothingCurve::Linear, 1);
+        assert_eq!(frames[0].density, 0.0);
+
+        // Fast (5 ms = 5 サンプル) で直線的に移る
+        let end = MainTargets {
+            density: 1.0,
+            mix: 0.0,
+            min_pct: 10.0,
+            max_ms: 400.0,
+        };
+        let frames = run(&mut smoothers, end, SmoothingCurve::Linear, 6);
+        for (p, want) in frames.iter().zip([0.2, 0.4, 0.6, 0.8, 1.0, 1.0]) {
+            assert!((p.density - want).abs() < 1e-6);
+        }
+        assert!((frames[1].mix - 0.6).abs() < 1e-6);
+
+        // Logarithmic では長さが一定の比率で動く (0 を含む density は直線のまま)
+        run(&mut smoothers, start, SmoothingCurve::Linear, 5);
+        let frames = run(&mut smoothers, end, SmoothingCurve::Logarithmic, 5);
+        let ratios: Vec<f32> = frames
+            .windows(2)
+            .map(|w| w[1].min_ms / w[0].min_ms)
+            .collect();
+        assert!(ratios.iter().all(|r| (r - ratios[0]).abs() < 1e-4));
+        assert!((frames[4].max_ms - 400.0).abs() < 1e-3);
+        assert!((frames[0].density - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn off_passes_values_through() {
+        let mut smoothers = MainSmoothers::default();
+        smoothers.initialize(1_000.0);
+        let mut p = FrameParams {
+            density: 0.7,
+            ..FrameParams::default()
+        };
+        let targets = MainTargets {
+            density: 0.1,
+            mix: 0.1,
+            min_pct: 100.0,
+            max_ms: 1.0,
+        };
+        smoothers.apply(&mut p, targets, SmoothingSpeed::Off, SmoothingCurve::Linear);
+        assert_eq!(p.density, 0.7);
+    }
+}
@@ -0,0 +1,211 @@
+//! Spectral freeze: captures the magnitude spectrum of the ring's latest frame and
+//! resynthesizes it with randomized phases via overlap-add.
+//!
+//! The FFT plans and every buffer are allocated in [`SpectralFreeze::initialize`], so
+//! capturing and resynthesis are allocation-free on the audio thread.
+
+use rand::Rng;
+use rustfft::{num_complex::Complex32, Fft, FftPlanner};
+use std::sync::Arc;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const FFT_SEC: f32 = 0.085; // 解析フレーム長の目安 (秒)。2 のべき乗へ切り上げる
+pub const HOP_DIVISOR: usize = 4; // ホップ = FFT 長 / 4 (75% オーバーラップ)
+pub const FADE_SEC: f32 = 0.01; // フリーズのオン／オフ時のフェード時間 (秒)
+
+/// 解析窓と合成窓 (どちらも Hann) を通したときの平均パワー比 3/8 を補正し、RMS をおおよそ保つ
+const WINDOW_GAIN: f32 = 1.632_993; // 1 / sqrt(3/8)
+
+/// サンプルレートに対する FFT 長
+pub fn fft_size(sr: f32) -> usize {
+    ((sr * FFT_SEC) as usize).next_power_of_two()
+}
+
+/*──────────────────── 2. Spectral freeze ──────────────*/
+#[derive(Default)]
+pub struct SpectralFreeze {
+    fwd: Option<Arc<dyn Fft<f32>>>,
+    inv: Option<Arc<dyn Fft<f32>>>,
+    /// Hann 窓
+    window: Vec<f32>,
+    /// 取り込んだ振幅スペクトル (0..=N/2)
+    mags: Vec<f32>,
+    /// FFT 用の作業領域
+    spec: Vec<Complex32>,
+    scratch: Vec<Complex32>,
+    /// オーバーラップ加算の出力リング (長さ N)
+    out: Vec<f32>,
+    pos: usize,
+    /// 次のフレームを合成するまでの残りサンプル数
+    countdown: usize,
+    /// フリーズ時のフェード用ゲイン (0.0〜1.0)
+    gain: f32,
+    fade_step: f32,
+    captured: bool,
+}
+
+impl SpectralFreeze {
+    pub fn initialize(&mut self, sr: f32) {
+        let n = fft_size(sr);
+        let mut planner = FftPlanner::new();
+        let fwd = planner.plan_fft_forward(n);
+        let inv = planner.plan_fft_inverse(n);
+        let scratch_len = fwd
+            .get_inplace_scratch_len()
+            .max(inv.get_inplace_scratch_len());
+        self.fwd = Some(fwd);
+        self.inv = Some(inv);
+        self.window = (0..n)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n as f32).cos())
+            .collect();
+        self.mags = vec![0.0; n / 2 + 1];
+        self.spec = vec![Complex32::default(); n];
+        self.scratch = vec![Complex32::default(); scratch_len];
+        self.out = vec![0.0; n];
+        self.fade_step = 1.0 / (FADE_SEC * sr).max(1.0);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.mags.fill(0.0);
+        self.out.fill(0.0);
+        self.pos = 0;
+        self.countdown = 0;
+        self.gain = 0.0;
+        self.captured = false;
+    }
+
+    /// FFT 長 (解析フレームのサンプル数)
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// フリーズ中、またはフェードアウト中なら true
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.captured && self.gain > 0.0
+    }
+
+    /// 書き込み位置 `wr` の直前 N サンプルを解析し、振幅スペクトルを保持する
+    pub fn capture(&mut self, ring: &[f32], wr: usize) {
+        let n = self.len();
+        let Some(fwd) = &self.fwd else {
+            return;
+        };
+        if ring.len() < n {
+            return;
+        }
+        let start = (wr + ring.len() - n) % ring.len();
+        for (i, (c, w)) in self.spec.iter_mut().zip(&self.window).enumerate() {
+            *c = Complex32::new(ring[(start + i) % ring.len()] * w, 0.0);
+        }
+        fwd.process_with_scratch(&mut self.spec, &mut self.scratch);
+        for (m, c) in self.mags.iter_mut().zip(&self.spec) {
+            *m = c.norm();
+        }
+        self.captured = true;
+        self.countdown = 0;
+    }
+
+    /// 1 サンプル分の再合成出力。`active` が false の間はフェードアウトして無音になる。
+    #[inline]
+    pub fn next_sample(&mut self, active: bool, rng: &mut impl Rng) -> f32 {
+        if !self.captured || self.out.is_empty() {
+            return 0.0;
+        }
+        let target = if active { 1.0 } else { 0.0 };
+        if self.gain < target {
+            self.gain = (self.gain + self.fade_step).min(1.0);
+        } else if self.gain > target {
+            self.gain = (self.gain - self.fade_step).max(0.0);
+        }
+        if self.gain == 0.0 && !active {
+            return 0.0;
+        }
+
+        if self.countdown == 0 {
+            self.synthesize_frame(rng);
+            self.countdown = self.len() / HOP_DIVISOR;
+        }
+        self.countdown -= 1;
+        let y = self.out[self.pos];
+        self.out[self.pos] = 0.0;
+        self.pos = (self.pos + 1) % self.out.len();
+        y * self.gain
+    }
+
+    /// 保持した振幅にランダムな位相を付けて逆 FFT し、出力リングへ窓をかけて加算する
+    fn synthesize_frame(&mut self, rng: &mut impl Rng) {
+        let n = self.len();
+        let Some(inv) = &self.inv else {
+            return;
+        };
+        let half = n / 2;
+        self.spec[0] = Complex32::new(self.mags[0], 0.0);
+        self.spec[half] = Complex32::new(self.mags[half], 0.0);
+        for k in 1..half {
+            let phase = rng.random::<f32>() * std::f32::consts::TAU;
+            let c = Complex32::from_polar(self.mags[k], phase);
+            self.spec[k] = c;
+            self.spec[n - k] = c.conj();
+        }
+        inv.process_with_scratch(&mut self.spec, &mut self.scratch);
+
+        // 逆 FFT の 1/N と、Hann² を 75% 重ねたときの和 1.5 で正規化する
+        let norm = WINDOW_GAIN / (n as f32 * 1.5);
+        for (j, (c, w)) in self.spec.iter().zip(&self.window).enumerate() {
+            self.out[(self.pos + j) % n] += c.re * w * norm;
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rms(x: &[f32]) -> f32 {
+        (x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn freeze_resynthesizes_captured_spectrum() {
+        let sr = 8_000.0;
+        let mut freeze = SpectralFreeze::default();
+        freeze.initialize(sr);
+        let n = freeze.len();
+        assert!(n.is_power_of_two());
+
+        let ring: Vec<f32> = (0..4 * n)
+            .map(|i| 0.5 * (i as f32 / sr * 440.0 * std::f32::consts::TAU).sin())
+            .collect();
+        let mut rng = rand::rng();
+
+        // 取り込み前は無音
+        assert_eq!(freeze.next_sample(true, &mut rng), 0.0);
+
+        freeze.capture(&ring, 0);
+        let out: Vec<f32> = (0..8 * n)
+            .map(|_| freeze.next_sample(true, &mut rng))
+            .collect();
+        // 立ち上がりの 1 フレームを除いて、元の RMS に近いレベルで鳴り続ける
+        let level = rms(&out[n..]);
+        let orig = rms(&ring);
+        assert!(out.iter().all(|v| v.is_finite()));
+        assert!(
+            level > orig * 0.5 && level < orig * 2.0,
+            "{level} vs {orig}"
+        );
+
+        // 解除するとフェードアウトして無音になる
+        let fade = (FADE_SEC * sr) as usize + 1;
+        for _ in 0..fade {
+            freeze.next_sample(false, &mut rng);
+        }
+        assert_eq!(freeze.next_sample(false, &mut rng), 0.0);
+    }
+}
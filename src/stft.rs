@@ -0,0 +1,300 @@
+//! Short-time Fourier transform with weighted overlap-add, and the spectral blur built on it.
+//!
+//! [`Stft`] hands the spectrum of every hop-spaced frame of a stream to a callback and
+//! resynthesizes the result sample by sample (Hann analysis and synthesis windows at 75% overlap,
+//! normalized so an untouched spectrum passes exactly). A frame can only be analyzed once all of
+//! it has arrived, so the output lags the input by one FFT length; [`Stft::latency`] reports that
+//! lag so the plugin can pass it on to the host.
+//!
+//! [`SpectralBlur`] smooths every bin's magnitude over time on the wet bus, smearing grain
+//! transients into washes, and delays the dry signal by the same latency so the mix stays
+//! aligned. All buffers and FFT plans are allocated in [`SpectralBlur::initialize`].
+
+use crate::spectral::HOP_DIVISOR;
+use rustfft::{num_complex::Complex32, Fft, FftPlanner};
+use std::sync::Arc;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const BLUR_FFT_SEC: f32 = 0.04; // ブラーの FFT 長の目安 (秒)。2 のべき乗へ切り上げる (= レイテンシ)
+pub const MAX_BLUR_SEC: f32 = 2.0; // blur 1.0 のときの振幅の平滑化の時定数 (秒)
+const OLA_GAIN: f32 = 2.0 / 3.0; // Hann² を FFT 長 / 4 ずつずらして重ねた和 (1.5) の逆数
+
+/*──────────────────── 2. STFT ─────────────────────────*/
+/// 1 チャンネル分の STFT と重畳加算
+#[derive(Default)]
+pub struct Stft {
+    fwd: Option<Arc<dyn Fft<f32>>>,
+    inv: Option<Arc<dyn Fft<f32>>>,
+    /// Hann 窓 (解析と合成の両方にかける)
+    window: Vec<f32>,
+    /// 直近 N サンプルの入力 (リング)
+    input: Vec<f32>,
+    /// 重畳加算の出力 (リング。読んだ位置は 0 に戻す)
+    output: Vec<f32>,
+    /// FFT 用の作業領域
+    spec: Vec<Complex32>,
+    scratch: Vec<Complex32>,
+    pos: usize,
+    /// 次のフレームを解析するまでの残りサンプル数
+    countdown: usize,
+}
+
+impl Stft {
+    /// FFT 長 `n` (2 のべき乗) で確保する
+    pub fn initialize(&mut self, n: usize, planner: &mut FftPlanner<f32>) {
+        let fwd = planner.plan_fft_forward(n);
+        let inv = planner.plan_fft_inverse(n);
+        let scratch_len = fwd
+            .get_inplace_scratch_len()
+            .max(inv.get_inplace_scratch_len());
+        self.fwd = Some(fwd);
+        self.inv = Some(inv);
+        self.window = (0..n)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n as f32).cos())
+            .collect();
+        self.input = vec![0.0; n];
+        self.output = vec![0.0; n];
+        self.spec = vec![Complex32::default(); n];
+        self.scratch = vec![Complex32::default(); scratch_len];
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.pos = 0;
+        self.countdown = self.hop();
+    }
+
+    /// 入力から出力までの遅れ (サンプル) = FFT 長
+    pub fn latency(&self) -> usize {
+        self.input.len()
+    }
+
+    pub fn hop(&self) -> usize {
+        (self.input.len() / HOP_DIVISOR).max(1)
+    }
+
+    /// `x` をその場で処理する。ホップごとに直近 N サンプルの窓付きスペクトルの 0..=N/2 を `f` に渡し、
+    /// 書き換えられたスペクトルを逆変換して重畳加算する。出力は latency() サンプル遅れる
+    pub fn process(&mut self, x: &mut [f32], mut f: impl FnMut(&mut [Complex32])) {
+        let n = self.input.len();
+        if n == 0 {
+            return;
+        }
+        for s in x {
+            self.input[self.pos] = *s;
+            *s = self.output[self.pos];
+            self.output[self.pos] = 0.0;
+            self.pos = (self.pos + 1) % n;
+            self.countdown -= 1;
+            if self.countdown == 0 {
+                self.countdown = self.hop();
+                self.frame(&mut f);
+            }
+        }
+    }
+
+    /// 直近 N サンプル (最も古いものが `pos`) を解析し、次に読む位置から重畳加算する
+    fn frame(&mut self, f: &mut impl FnMut(&mut [Complex32])) {
+        let (Some(fwd), Some(inv)) = (&self.fwd, &self.inv) else {
+            return;
+        };
+        let n = self.input.len();
+        for (k, c) in self.spec.iter_mut().enumerate() {
+            *c = Complex32::new(self.input[(self.pos + k) % n] * self.window[k], 0.0);
+        }
+        fwd.process_with_scratch(&mut self.spec, &mut self.scratch);
+        f(&mut self.spec[..=n / 2]);
+        // 実信号に戻るよう、負の周波数側を正の周波数側の共役で埋め直す
+        for k in 1..n / 2 {
+            self.spec[n - k] = self.spec[k].conj();
+        }
+        inv.process_with_scratch(&mut self.spec, &mut self.scratch);
+        let scale = OLA_GAIN / n as f32;
+        for (k, c) in self.spec.iter().enumerate() {
+            self.output[(self.pos + k) % n] += c.re * self.window[k] * scale;
+        }
+    }
+}
+
+/*──────────────────── 3. Spectral blur ────────────────*/
+/// ウェットバスの振幅スペクトルを時間方向に平滑化するブラーと、ドライを揃えるディレイ
+#[derive(Default)]
+pub struct SpectralBlur {
+    sr: f32,
+    stfts: Vec<Stft>,
+    /// チャンネルごとの平滑化した振幅 (0..=N/2)
+    mags: Vec<Vec<f32>>,
+    /// チャンネルごとの位相。振幅が消えた bin でもホップ分ずつ回し続ける
+    phases: Vec<Vec<f32>>,
+    /// ドライを latency() 遅らせるディレイライン
+    dry: Vec<Vec<f32>>,
+    dry_pos: usize,
+    active: bool,
+}
+
+impl SpectralBlur {
+    pub fn initialize(&mut self, sr: f32, n_ch: usize) {
+        self.sr = sr;
+        self.stfts.clear();
+        self.active = false;
+        self.ensure_channels(n_ch);
+    }
+
+    /// チャンネル数が足りなければ確保し直す (オーディオスレッドでは通常起きない)
+    pub fn ensure_channels(&mut self, n_ch: usize) {
+        if self.stfts.len() >= n_ch {
+            return;
+        }
+        let n = ((self.sr * BLUR_FFT_SEC) as usize).next_power_of_two();
+        let mut planner = FftPlanner::new();
+        self.stfts = (0..n_ch)
+            .map(|_| {
+                let mut stft = Stft::default();
+                stft.initialize(n, &mut planner);
+                stft
+            })
+            .collect();
+        self.mags = vec![vec![0.0; n / 2 + 1]; n_ch];
+        self.phases = vec![vec![0.0; n / 2 + 1]; n_ch];
+        self.dry = vec![vec![0.0; n]; n_ch];
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.stfts.iter_mut().for_each(Stft::reset);
+        self.mags.iter_mut().for_each(|m| m.fill(0.0));
+        self.phases.iter_mut().for_each(|p| p.fill(0.0));
+        self.dry.iter_mut().for_each(|d| d.fill(0.0));
+        self.dry_pos = 0;
+    }
+
+    /// ブラーを掛けるかを切り替える。掛け始めるときは前に掛けていたときの残りを消す
+    pub fn set_active(&mut self, active: bool) {
+        if active && !self.active {
+            self.reset();
+        }
+        self.active = active;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// ウェットとドライの遅れ (サンプル)
+    pub fn latency(&self) -> usize {
+        self.stfts.first().map_or(0, Stft::latency)
+    }
+
+    /// `io` の各チャンネルのドライを latency() サンプル遅らせる
+    pub fn delay_dry(&mut self, io: &mut [&mut [f32]]) {
+        let n = self.latency();
+        let mut pos = self.dry_pos;
+        for (x, line) in io.iter_mut().zip(&mut self.dry) {
+            pos = self.dry_pos;
+            for s in x.iter_mut() {
+                std::mem::swap(s, &mut line[pos]);
+                pos = (pos + 1) % n;
+            }
+        }
+        self.dry_pos = pos;
+    }
+
+    /// `wet` の各チャンネルの先頭 `n_samples` サンプルの振幅を、時定数 amount × MAX_BLUR_SEC で平滑化する
+    pub fn process(&mut self, wet: &mut [Vec<f32>], n_samples: usize, amount: f32) {
+        let Some(stft) = self.stfts.first() else {
+            return;
+        };
+        let hop = stft.hop() as f32;
+        let coef = (-hop / (amount.clamp(1e-3, 1.0) * MAX_BLUR_SEC * self.sr)).exp();
+        // 振幅が消えた bin の位相は、その bin の周波数でホップ分進める
+        let advance = std::f32::consts::TAU / HOP_DIVISOR as f32;
+        for (((w, stft), mags), phases) in wet
+            .iter_mut()
+            .zip(&mut self.stfts)
+            .zip(&mut self.mags)
+            .zip(&mut self.phases)
+        {
+            stft.process(&mut w[..n_samples], |spec| {
+                for (k, ((c, m), ph)) in spec
+                    .iter_mut()
+                    .zip(mags.iter_mut())
+                    .zip(phases.iter_mut())
+                    .enumerate()
+                {
+                    let mag = c.norm();
+                    *m = mag + (*m - mag) * coef;
+                    *ph = if mag > 1e-9 {
+                        c.arg()
+                    } else {
+                        (*ph + advance * k as f32) % std::f32::consts::TAU
+                    };
+                    *c = Complex32::from_polar(*m, *ph);
+                }
+            });
+        }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 再現性のある広帯域の信号
+    fn noise(n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| ((i * 7_919 % 1_013) as f32 / 506.5 - 1.0) * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn untouched_spectrum_passes_with_one_fft_of_latency() {
+        let mut stft = Stft::default();
+        stft.initialize(256, &mut FftPlanner::new());
+        let input = noise(2_000);
+        let mut x = input.clone();
+        // ブロックに分けても同じ
+        for block in x.chunks_mut(100) {
+            stft.process(block, |_| {});
+        }
+        assert_eq!(stft.latency(), 256);
+        for (y, x) in x[256..].iter().zip(&input) {
+            assert!((y - x).abs() < 1e-4, "{y} {x}");
+        }
+    }
+
+    #[test]
+    fn blur_smears_a_click_into_a_wash() {
+        let sr = 8_000.0;
+        // クリックの後のエネルギー (レイテンシの 2 倍以降)
+        let tail = |amount: f32| -> f32 {
+            let mut blur = SpectralBlur::default();
+            blur.initialize(sr, 1);
+            blur.set_active(true);
+            let n = 8_000;
+            let mut wet = vec![vec![0.0f32; n]];
+            wet[0][0] = 1.0;
+            blur.process(&mut wet, n, amount);
+            let skip = 2 * blur.latency();
+            wet[0][skip..].iter().map(|x| x * x).sum()
+        };
+        assert!(tail(0.5) > 1e-3, "{}", tail(0.5));
+        assert!(tail(0.5) > 100.0 * tail(1e-3));
+    }
+
+    #[test]
+    fn dry_is_delayed_by_the_latency() {
+        let mut blur = SpectralBlur::default();
+        blur.initialize(8_000.0, 2);
+        let n = blur.latency();
+        let input = noise(3 * n);
+        let (mut a, mut b) = (input.clone(), input.clone());
+        for (a, b) in a.chunks_mut(100).zip(b.chunks_mut(100)) {
+            blur.delay_dry(&mut [a, b]);
+        }
+        assert_eq!(&a[n..], &input[..2 * n]);
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,267 @@
+//! Running the engine on an application's own audio stream, without a plugin host.
+//!
+//! [`Granulator`] wraps an [`Engine`] behind one call that processes an interleaved buffer in
+//! place, which is the shape of a cpal data callback (or any other callback-based API). Move it
+//! into the callback; the [`Controls`] returned with it stay on the application side. They send new
+//! [`FrameParams`] to the audio thread through a lock-free queue, and [`Controls::poll`] (call it
+//! from the main loop) allocates a longer ring when the parameters need one, so the callback
+//! never allocates or locks.
+//!
+//! With the `rodio` feature, [`GranularSource`] wraps any rodio `Source` of `f32` samples and
+//! granulates it as it plays. The wrapped source should keep one channel count and sample rate.
+
+use crate::engine::{grow_ring, Engine, FrameParams, GrainQueues, MAX_CHANNELS};
+use arrayvec::ArrayVec;
+use crossbeam::queue::ArrayQueue;
+use rand::{rngs::SmallRng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/*──────────────────── 1. Constants ────────────────────*/
+pub const STREAM_BLOCK: usize = 512; // エンジンへ渡すブロック長 (フレーム)。コールバックの長さはこれより長くてもよい
+const PARAM_SLOTS: usize = 4; // オーディオスレッドが取り込む前に溜めておけるパラメータの数 (あふれたら古いものを捨てる)
+
+/*──────────────────── 2. Controls ─────────────────────*/
+/// アプリケーション側とオーディオスレッドで共有する状態
+struct Shared {
+    /// 送られたパラメータ (取り込むのは最後のものだけ)
+    params: ArrayQueue<FrameParams>,
+    /// オーディオスレッドが確保してほしいリングの長さ (0 なら無し)
+    ring: AtomicUsize,
+}
+
+/// [`Granulator`] をアプリケーション側から操作する
+#[derive(Clone)]
+pub struct Controls {
+    shared: Arc<Shared>,
+    queues: Arc<GrainQueues>,
+}
+
+impl Controls {
+    /// 次のコールバックの頭から `params` で処理させる
+    pub fn set_params(&self, params: FrameParams) {
+        let _ = self.shared.params.force_push(params);
+    }
+
+    /// オーディオスレッドが広いリングを必要としていれば確保して渡す。メインループなどから定期的に呼ぶこと
+    pub fn poll(&self) {
+        let len = self.shared.ring.swap(0, Ordering::Relaxed);
+        if len > 0 {
+            grow_ring(&self.queues, len);
+        }
+    }
+}
+
+/*──────────────────── 3. Granulator ───────────────────*/
+/// インターリーブされたバッファをその場で処理するエンジン
+pub struct Granulator {
+    engine: Engine,
+    params: FrameParams,
+    rng: SmallRng,
+    /// チャンネルごとに分けたブロック (MAX_CHANNELS 本まで、STREAM_BLOCK フレーム確保済み)
+    planar: Vec<Vec<f32>>,
+    /// インターリーブされたバッファのチャンネル数
+    channels: usize,
+    shared: Arc<Shared>,
+}
+
+impl Granulator {
+    /// `sr` Hz・`channels` チャンネルのストリーム用に初期化する。同じ `seed` なら同じ入力から同じ出力になる
+    pub fn new(sr: f32, channels: usize, params: FrameParams, seed: u64) -> (Self, Controls) {
+        let mut engine = Engine::default();
+        engine.initialize(sr, channels.min(MAX_CHANNELS), STREAM_BLOCK);
+        let shared = Arc::new(Shared {
+            params: ArrayQueue::new(PARAM_SLOTS),
+            ring: AtomicUsize::new(0),
+        });
+        let controls = Controls {
+            shared: shared.clone(),
+            queues: engine.queues(),
+        };
+        let granulator = Self {
+            engine,
+            params,
+            rng: SmallRng::seed_from_u64(seed),
+            planar: vec![vec![0.0; STREAM_BLOCK]; channels.min(MAX_CHANNELS)],
+            channels: channels.max(1),
+            shared,
+        };
+        (granulator, controls)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// 今のパラメータ (最後に取り込んだもの)
+    pub fn params(&self) -> &FrameParams {
+        &self.params
+    }
+
+    /// インターリーブされた `data` をその場で処理する。MAX_CHANNELS を超えるチャンネルと、
+    /// 末尾の半端なフレームはそのまま通す
+    pub fn process_interleaved(&mut self, data: &mut [f32]) {
+        while let Some(params) = self.shared.params.pop() {
+            self.params = params;
+        }
+        let n_ch = self.channels;
+        for block in data.chunks_mut(STREAM_BLOCK * n_ch) {
+            let frames = block.len() / n_ch;
+            for (c, planar) in self.planar.iter_mut().enumerate() {
+                for (i, x) in planar[..frames].iter_mut().enumerate() {
+                    *x = block[i * n_ch + c];
+                }
+            }
+            {
+                let mut io: ArrayVec<&mut [f32], MAX_CHANNELS> =
+                    self.planar.iter_mut().map(|c| &mut c[..frames]).collect();
+                self.engine
+                    .process(&mut io, &mut self.params, &mut self.rng);
+            }
+            for (c, planar) in self.planar.iter().enumerate() {
+                for (i, &x) in planar[..frames].iter().enumerate() {
+                    block[i * n_ch + c] = x;
+                }
+            }
+        }
+        if let Some(len) = self.engine.take_ring_request() {
+            self.shared.ring.fetch_max(len, Ordering::Relaxed);
+        }
+    }
+}
+
+/*──────────────────── 4. rodio ────────────────────────*/
+/// rodio の `Source` をグラニュレートしながら再生する `Source`
+#[cfg(feature = "rodio")]
+pub struct GranularSource<S> {
+    inner: S,
+    granulator: Granulator,
+    /// 処理済みのブロック (インターリーブ)
+    buf: Vec<f32>,
+    /// buf の次に返す位置
+    pos: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+#[cfg(feature = "rodio")]
+impl<S: rodio::Source<Item = f32>> GranularSource<S> {
+    /// `inner` のチャンネル数とサンプルレートで初期化する
+    pub fn new(inner: S, params: FrameParams, seed: u64) -> (Self, Controls) {
+        let channels = inner.channels().max(1);
+        let sample_rate = inner.sample_rate();
+        let (granulator, controls) =
+            Granulator::new(sample_rate as f32, channels as usize, params, seed);
+        let source = Self {
+            inner,
+            granulator,
+            buf: Vec::with_capacity(STREAM_BLOCK * channels as usize),
+            pos: 0,
+            channels,
+            sample_rate,
+        };
+        (source, controls)
+    }
+}
+
+#[cfg(feature = "rodio")]
+impl<S: rodio::Source<Item = f32>> Iterator for GranularSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos == self.buf.len() {
+            // 1 ブロック分を読んでまとめて処理する (buf は確保済みなので再生中に確保しない)
+            self.buf.clear();
+            let n = STREAM_BLOCK * self.channels as usize;
+            self.buf.extend(self.inner.by_ref().take(n));
+            if self.buf.is_empty() {
+                return None;
+            }
+            self.granulator.process_interleaved(&mut self.buf);
+            self.pos = 0;
+        }
+        let x = self.buf[self.pos];
+        self.pos += 1;
+        Some(x)
+    }
+}
+
+#[cfg(feature = "rodio")]
+impl<S: rodio::Source<Item = f32>> rodio::Source for GranularSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved_stream_matches_planar_engine() {
+        let sr = 48_000.0;
+        let frames = 3 * STREAM_BLOCK + 77;
+        let input: Vec<[f32; 2]> = (0..frames)
+            .map(|i| {
+                let t = i as f32 / sr;
+                [
+                    (t * 440.0 * std::f32::consts::TAU).sin(),
+                    (t * 660.0 * std::f32::consts::TAU).sin(),
+                ]
+            })
+            .collect();
+
+        // 同じシードのエンジンを STREAM_BLOCK ごとにチャンネル別で回したもの
+        let mut engine = Engine::default();
+        engine.initialize(sr, 2, STREAM_BLOCK);
+        let mut params = FrameParams::default();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut planar: Vec<Vec<f32>> = (0..2)
+            .map(|c| input.iter().map(|f| f[c]).collect())
+            .collect();
+        for at in (0..frames).step_by(STREAM_BLOCK) {
+            let end = (at + STREAM_BLOCK).min(frames);
+            let mut io: Vec<&mut [f32]> = planar.iter_mut().map(|c| &mut c[at..end]).collect();
+            engine.process(&mut io, &mut params, &mut rng);
+        }
+
+        // コールバックの長さは不揃いでもよい
+        let (mut granulator, controls) = Granulator::new(sr, 2, FrameParams::default(), 3);
+        let mut data: Vec<f32> = input.iter().flatten().copied().collect();
+        let mut at = 0;
+        for len in [100, 700, 1_234].iter().cycle() {
+            if at == data.len() {
+                break;
+            }
+            let end = (at + len * 2).min(data.len());
+            granulator.process_interleaved(&mut data[at..end]);
+            at = end;
+        }
+        for (i, frame) in data.chunks(2).enumerate() {
+            assert_eq!(frame, [planar[0][i], planar[1][i]], "frame {i}");
+        }
+
+        // 送ったパラメータは次のコールバックで取り込む (溜まっていれば最後のもの)
+        let mut params = FrameParams::default();
+        for mix in [0.25, 0.5] {
+            params.mix = mix;
+            controls.set_params(params);
+        }
+        granulator.process_interleaved(&mut [0.0; 8]);
+        assert_eq!(granulator.params().mix, 0.5);
+        controls.poll();
+    }
+}
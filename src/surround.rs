@@ -0,0 +1,223 @@
+//! VBAP panning of grains across surround speaker layouts.
+//!
+//! With quad, 5.1 or 7.1 outputs each grain gets a random direction on the horizontal circle and
+//! is panned between the two speakers on either side of it with vector base amplitude panning
+//! (Pulkki, 1997): the gains solve `g1·l1 + g2·l2 = p` for the speakers' unit vectors `l1`, `l2`
+//! and the grain's direction `p`, then are scaled to constant power. `Surround Spread` sets how
+//! far from the front the directions reach. The LFE channel never gets grains. Mono, stereo and
+//! other channel counts keep sending each grain to one random channel, except that burst clusters
+//! on a stereo output are panned between L and R at constant power around one direction.
+//!
+//! [`PanLaw`] then rescales the two gains of every panned grain: constant power (−3 dB in the
+//! middle), −4.5 dB, or linear (−6 dB, the sum of the gains stays 1 so a mono fold-down keeps
+//! the level of a grain wherever it sits).
+
+use nih_plug::prelude::Enum;
+
+/*──────────────────── 1. Layouts ──────────────────────*/
+// スピーカーの方位 (度、0 = 正面、正 = 右)。None は LFE。チャンネルの並びは WAVEFORMATEXTENSIBLE / CLAP の順
+/// Quad: L R Ls Rs
+pub const QUAD: [Option<f32>; 4] = [Some(-45.0), Some(45.0), Some(-135.0), Some(135.0)];
+/// 5.1: L R C LFE Ls Rs
+pub const SURROUND_51: [Option<f32>; 6] = [
+    Some(-30.0),
+    Some(30.0),
+    Some(0.0),
+    None,
+    Some(-110.0),
+    Some(110.0),
+];
+/// 7.1: L R C LFE Lrs Rrs Lss Rss
+pub const SURROUND_71: [Option<f32>; 8] = [
+    Some(-30.0),
+    Some(30.0),
+    Some(0.0),
+    None,
+    Some(-150.0),
+    Some(150.0),
+    Some(-90.0),
+    Some(90.0),
+];
+
+/// `n_ch` チャンネルの出力のスピーカー配置 (サラウンドでなければ None)
+pub fn speakers(n_ch: usize) -> Option<&'static [Option<f32>]> {
+    match n_ch {
+        4 => Some(&QUAD),
+        6 => Some(&SURROUND_51),
+        8 => Some(&SURROUND_71),
+        _ => None,
+    }
+}
+
+/*──────────────────── 2. VBAP ─────────────────────────*/
+/// 2 つのスピーカーへのパン (ch[0] のほうがゲインが大きい)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pan {
+    pub ch: [usize; 2],
+    pub gain: [f32; 2],
+}
+
+/// 方位 `azimuth` (度) の音源を、その両隣のスピーカーへ VBAP でパンする。ゲインの 2 乗和は 1
+pub fn vbap(azimuth: f32, speakers: &[Option<f32>]) -> Pan {
+    // 音源から左回り・右回りにいちばん近いスピーカー
+    let nearest = |dir: f32| {
+        speakers
+            .iter()
+            .enumerate()
+            .filter_map(|(ch, az)| az.map(|az| (ch, (dir * (azimuth - az)).rem_euclid(360.0))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("layout without speakers")
+    };
+    let (a, da) = nearest(1.0);
+    let (b, db) = nearest(-1.0);
+    if a == b || da == 0.0 {
+        return Pan {
+            ch: [a, b],
+            gain: [1.0, 0.0],
+        };
+    }
+    // 2 つのスピーカーの単位ベクトルを基底にして音源の方向ベクトルを表す
+    let unit = |deg: f32| {
+        let r = deg.to_radians();
+        (r.sin(), r.cos())
+    };
+    let (px, py) = unit(azimuth);
+    let (ax, ay) = unit(azimuth - da);
+    let (bx, by) = unit(azimuth + db);
+    let det = ax * by - bx * ay;
+    let ga = ((px * by - bx * py) / det).max(0.0);
+    let gb = ((ax * py - px * ay) / det).max(0.0);
+    let norm = (ga * ga + gb * gb).sqrt().max(f32::EPSILON);
+    let (ga, gb) = (ga / norm, gb / norm);
+    if ga >= gb {
+        Pan {
+            ch: [a, b],
+            gain: [ga, gb],
+        }
+    } else {
+        Pan {
+            ch: [b, a],
+            gain: [gb, ga],
+        }
+    }
+}
+
+/// 方位 `azimuth` (度、-90 = 左、90 = 右。範囲の外は端に揃える) の音源を、ステレオの L と R へ
+/// 等パワーでパンする
+pub fn stereo(azimuth: f32) -> Pan {
+    let angle = (azimuth.clamp(-90.0, 90.0) + 90.0) / 180.0 * std::f32::consts::FRAC_PI_2;
+    let (l, r) = (angle.cos(), angle.sin());
+    if l >= r {
+        Pan {
+            ch: [0, 1],
+            gain: [l, r],
+        }
+    } else {
+        Pan {
+            ch: [1, 0],
+            gain: [r, l],
+        }
+    }
+}
+
+/*──────────────────── 3. Pan law ──────────────────────*/
+/// 2 つのチャンネルの中間に振ったグレインをそれぞれのチャンネルでどれだけ下げるか
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanLaw {
+    /// 等パワー (ゲインの 2 乗和が 1)。中間で −3 dB
+    #[name = "-3 dB (Constant Power)"]
+    ConstantPower,
+    /// 等パワーと線形の幾何平均。中間で −4.5 dB
+    #[name = "-4.5 dB"]
+    Compromise,
+    /// 線形 (ゲインの和が 1)。中間で −6 dB、モノラルにまとめても位置によらず同じ音量
+    #[name = "-6 dB (Linear)"]
+    Linear,
+}
+
+impl PanLaw {
+    /// 等パワーの `pan` のゲインをこの法則のゲインにする (片方だけに振ったグレインは変わらない)
+    pub fn apply(self, pan: Pan) -> Pan {
+        let sum = pan.gain[0] + pan.gain[1];
+        let gain = match self {
+            PanLaw::ConstantPower => return pan,
+            PanLaw::Compromise => pan.gain.map(|g| g / sum.sqrt()),
+            PanLaw::Linear => pan.gain.map(|g| g / sum),
+        };
+        Pan { gain, ..pan }
+    }
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vbap_pans_between_neighbours_at_constant_power() {
+        // スピーカーの真上ではそのスピーカーだけ
+        let pan = vbap(30.0, &SURROUND_51);
+        assert_eq!(pan.ch[0], 1);
+        assert_eq!(pan.gain, [1.0, 0.0]);
+
+        // L と C の中間では同じゲイン
+        let pan = vbap(-15.0, &SURROUND_51);
+        assert_eq!(pan.ch, [0, 2]);
+        assert!((pan.gain[0] - pan.gain[1]).abs() < 1e-5);
+
+        // 真後ろは Ls と Rs の間 (180 度をまたぐ)、LFE には振らない
+        let pan = vbap(180.0, &SURROUND_51);
+        let mut ch = pan.ch;
+        ch.sort();
+        assert_eq!(ch, [4, 5]);
+
+        for layout in [&QUAD[..], &SURROUND_51[..], &SURROUND_71[..]] {
+            for k in 0..72 {
+                let pan = vbap(k as f32 * 5.0 - 180.0, layout);
+                let power: f32 = pan.gain.iter().map(|g| g * g).sum();
+                assert!((power - 1.0).abs() < 1e-4, "power {power}");
+                assert!(pan.gain[0] >= pan.gain[1] && pan.gain[1] >= 0.0);
+                assert!(pan.ch.iter().all(|&ch| layout[ch].is_some()));
+            }
+        }
+        assert!(speakers(2).is_none());
+    }
+
+    #[test]
+    fn pan_laws_set_the_level_in_the_middle() {
+        let db = |g: f32| 20.0 * g.log10();
+        let center = stereo(0.0);
+        let cases = [
+            (PanLaw::ConstantPower, -3.01),
+            (PanLaw::Compromise, -4.52),
+            (PanLaw::Linear, -6.02),
+        ];
+        for (law, expect) in cases {
+            let pan = law.apply(center);
+            assert!((db(pan.gain[0]) - expect).abs() < 0.01, "{law:?}");
+            assert!((pan.gain[0] - pan.gain[1]).abs() < 1e-6, "{law:?}");
+            // 片方だけに振ったグレインはどの法則でも 0 dB
+            assert_eq!(law.apply(stereo(-90.0)).gain[0], 1.0);
+        }
+        // 線形ではどこに振ってもゲインの和が 1
+        for k in 0..=36 {
+            let pan = PanLaw::Linear.apply(vbap(k as f32 * 10.0 - 180.0, &SURROUND_51));
+            assert!((pan.gain[0] + pan.gain[1] - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn stereo_pans_at_constant_power() {
+        assert_eq!(stereo(-90.0).ch[0], 0);
+        assert!(stereo(-90.0).gain[1].abs() < 1e-6);
+        assert_eq!(stereo(120.0).ch[0], 1);
+        assert!(stereo(120.0).gain[1].abs() < 1e-6);
+        let center = stereo(0.0);
+        assert!((center.gain[0] - center.gain[1]).abs() < 1e-6);
+        for k in 0..=36 {
+            let pan = stereo(k as f32 * 5.0 - 90.0);
+            let power: f32 = pan.gain.iter().map(|g| g * g).sum();
+            assert!((power - 1.0).abs() < 1e-5, "power {power}");
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Grain envelopes: the Tukey window applied when a grain is cut, the asymmetric cosine
+//! window with separate attack and release lengths, and the ADSR-style envelope evaluated per
+//! sample while the grain is read.
+
+use nih_plug::prelude::Enum;
+
+/*──────────────────── 1. Window shape ─────────────────*/
+/// グレインの窓の形
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowShape {
+    /// 切り出し時に Tukey 窓をかける (従来の動作)
+    #[name = "Tukey"]
+    Tukey,
+    /// 読み出し時に attack / decay (グレイン長に対する %) のエンベロープをかける
+    #[name = "ADSR"]
+    Adsr,
+    /// 切り出し時に立ち上がりと立ち下がりの長さが別々のコサインフェードをかける
+    /// (window_attack / window_release、グレイン長に対する割合)
+    #[name = "Asymmetric"]
+    Asymmetric,
+}
+
+/*──────────────────── 2. Tukey window ─────────────────*/
+/// `x` に Tukey 窓をかける。scipy.signal.windows.tukey (sym=True) と同じ定義で、
+/// 両端の floor(alpha * (n - 1) / 2) + 1 サンプルずつがコサインのフェードになる。
+pub fn apply_tukey(x: &mut [f32], alpha: f32) {
+    let n = x.len();
+    let fade = tukey_fade_len(n, alpha);
+    for i in (0..fade.min(n)).chain(n.saturating_sub(fade).max(fade)..n) {
+        x[i] *= tukey_gain(i, n, alpha);
+    }
+}
+
+/// 長さ `n` の Tukey 窓の片側のフェードのサンプル数 (これより内側のゲインは 1)
+#[inline]
+pub fn tukey_fade_len(n: usize, alpha: f32) -> usize {
+    if alpha <= 0.0 || n <= 1 {
+        return 0;
+    }
+    (alpha.min(1.0) * (n - 1) as f32 * 0.5).floor() as usize + 1
+}
+
+/// 長さ `n` の Tukey 窓の `i` サンプル目の値。リングを直接読むグレインは読み出し時にこれを掛ける。
+#[inline]
+pub fn tukey_gain(i: usize, n: usize, alpha: f32) -> f32 {
+    if alpha <= 0.0 || n <= 1 {
+        return 1.0;
+    }
+    let span = alpha.min(1.0) * (n - 1) as f32;
+    let width = (span * 0.5).floor() as usize;
+    // 立ち下がりは立ち上がりの鏡像
+    let k = if i <= width {
+        i
+    } else if i >= n - 1 - width {
+        n - 1 - i
+    } else {
+        return 1.0;
+    };
+    0.5 * (1.0 - (2.0 * std::f32::consts::PI * k as f32 / span).cos())
+}
+
+/// 片側のフェード (立ち上がり・立ち下がり) が `edge` サンプル以上になる Tukey 窓の最小長。
+/// これより短いと `apply_tukey` のフェードが 0〜数サンプルになり、ほぼ矩形窓になってクリックする。
+pub fn min_tukey_len(alpha: f32, edge: usize) -> usize {
+    if alpha <= 0.0 || edge == 0 {
+        return 0;
+    }
+    let alpha = alpha.min(1.0);
+    let fade = |n: usize| (alpha * (n - 1) as f32 * 0.5).floor() as usize;
+    // floor(alpha * (n - 1) / 2) >= edge の解の目安から、丸め誤差を見込んで 1 つ手前から探す
+    let mut n = ((2.0 * edge as f32 / alpha).ceil() as usize).max(1);
+    while fade(n) < edge {
+        n += 1;
+    }
+    n.max(2 * edge)
+}
+
+/*──────────────────── 3. ADSR envelope ────────────────*/
+/// グレイン長 `len` に対する attack / decay (%) をサンプル数へ換算する。
+/// 合計がグレイン長を超える場合は比率を保って縮める。
+pub fn adsr_lengths(len: usize, attack_pct: f32, decay_pct: f32) -> (usize, usize) {
+    let a = attack_pct.clamp(0.0, 100.0);
+    let d = decay_pct.clamp(0.0, 100.0);
+    let scale = if a + d > 100.0 { 100.0 / (a + d) } else { 1.0 };
+    let attack = (len as f32 * a * scale / 100.0) as usize;
+    let decay = (len as f32 * d * scale / 100.0) as usize;
+    (attack, decay.min(len - attack.min(len)))
+}
+
+/// 長さ `len` のグレインの `pos` サンプル目のエンベロープ値。
+/// 0 から 1 へ `attack` サンプルで立ち上がり、最後の `decay` サンプルで 0 へ下がる。
+#[inline]
+pub fn adsr_gain(pos: usize, len: usize, attack: usize, decay: usize) -> f32 {
+    if pos < attack {
+        pos as f32 / attack as f32
+    } else if decay > 0 && pos + decay >= len {
+        (len - 1 - pos.min(len - 1)) as f32 / decay as f32
+    } else {
+        1.0
+    }
+}
+
+/*──────────────────── 4. Asymmetric window ───────────*/
+/// グレイン長 `len` に対する立ち上がり・立ち下がりの割合 (0.0〜1.0) をサンプル数へ換算する。
+/// どちらも最低 `min_edge` サンプルにし、合計がグレイン長を超える場合は比率を保って縮める。
+pub fn edge_lengths(len: usize, attack: f32, release: f32, min_edge: usize) -> (usize, usize) {
+    let a = ((len as f32 * attack.clamp(0.0, 1.0)) as usize).max(min_edge);
+    let r = ((len as f32 * release.clamp(0.0, 1.0)) as usize).max(min_edge);
+    if a + r <= len {
+        return (a, r);
+    }
+    let a = (len as f32 * a as f32 / (a + r) as f32) as usize;
+    (a, len - a)
+}
+
+/// `x` の先頭 `attack` サンプルと末尾 `release` サンプルに、それぞれの長さのコサインフェードをかける。
+/// 両端のサンプルは 0 になる。
+pub fn apply_edges(x: &mut [f32], attack: usize, release: usize) {
+    let n = x.len();
+    for (i, v) in x[..attack.min(n)].iter_mut().enumerate() {
+        *v *= edge_fade(i, attack);
+    }
+    for (k, v) in x[n - release.min(n)..].iter_mut().rev().enumerate() {
+        *v *= edge_fade(k, release);
+    }
+}
+
+/// `apply_edges` と同じ窓の、長さ `n` のグレインの `i` サンプル目の値
+#[inline]
+pub fn edge_gain(i: usize, n: usize, attack: usize, release: usize) -> f32 {
+    let mut g = 1.0;
+    if i < attack {
+        g *= edge_fade(i, attack);
+    }
+    if i < n && n - 1 - i < release {
+        g *= edge_fade(n - 1 - i, release);
+    }
+    g
+}
+
+/// 長さ `len` のコサインフェードの `k` サンプル目 (0 から 1 へ上がる)
+#[inline]
+fn edge_fade(k: usize, len: usize) -> f32 {
+    0.5 * (1.0 - (std::f32::consts::PI * k as f32 / len as f32).cos())
+}
+
+/*──────────────────── Tests ───────────────────────────*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tukey_window_symmetry_and_edges() {
+        let mut data = vec![1.0f32; 10];
+        apply_tukey(&mut data, 0.5);
+
+        let n = data.len();
+        assert!(data[0].abs() < 1e-6);
+        assert!(data[n - 1].abs() < 1e-6);
+
+        for i in 0..n {
+            let j = n - 1 - i;
+            assert!(
+                (data[i] - data[j]).abs() < 1e-6,
+                "window not symmetric at {i}"
+            );
+            assert!(data[i] <= 1.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn tukey_taper_ends_on_the_cosine() {
+        // scipy.signal.windows.tukey(10, 0.5)
+        let expected = [
+            0.0, 0.41317591, 0.96984631, 1.0, 1.0, 1.0, 1.0, 0.96984631, 0.41317591, 0.0,
+        ];
+        let mut data = vec![1.0f32; 10];
+        apply_tukey(&mut data, 0.5);
+        for (i, (a, e)) in data.iter().zip(expected).enumerate() {
+            assert!((a - e).abs() < 1e-6, "sample {i}: {a} vs {e}");
+        }
+
+        // フェードの最後のサンプル (k = floor(span / 2)) も 1 ではなくコサインの上に乗る
+        let (n, alpha) = (200, 0.2);
+        let span = alpha * (n - 1) as f32;
+        let width = (span * 0.5).floor() as usize;
+        let mut data = vec![1.0f32; n];
+        apply_tukey(&mut data, alpha);
+        for k in 0..=width {
+            let w = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * k as f32 / span).cos());
+            assert!((data[k] - w).abs() < 1e-6, "sample {k}: {} vs {w}", data[k]);
+            assert!((data[n - 1 - k] - w).abs() < 1e-6);
+        }
+        assert!(data[width] < 1.0);
+        assert_eq!(data[width + 1], 1.0);
+    }
+
+    #[test]
+    fn tukey_alpha_zero_is_rectangular() {
+        let mut data = vec![1.0f32; 16];
+        let orig = data.clone();
+        apply_tukey(&mut data, 0.0);
+        assert_eq!(data, orig);
+    }
+
+    #[test]
+    fn adsr_envelope_shapes() {
+        // attack 20% / decay 50% の 10 サンプルのグレイン
+        let (a, d) = adsr_lengths(10, 20.0, 50.0);
+        assert_eq!((a, d), (2, 5));
+        let env: Vec<f32> = (0..10).map(|i| adsr_gain(i, 10, a, d)).collect();
+        assert_eq!(env[0], 0.0);
+        assert_eq!(env[1], 0.5);
+        assert_eq!(&env[2..5], &[1.0, 1.0, 1.0]);
+        assert_eq!(env[9], 0.0);
+        assert!(env[5..].windows(2).all(|w| w[1] < w[0]));
+
+        // 合計が 100% を超えたら比率を保って縮める
+        assert_eq!(adsr_lengths(100, 100.0, 100.0), (50, 50));
+        // 0% ずつなら矩形
+        assert!((0..10).all(|i| adsr_gain(i, 10, 0, 0) == 1.0));
+    }
+
+    #[test]
+    fn asymmetric_window_has_separate_edges() {
+        // 速い立ち上がり (10%) と長い立ち下がり (60%)
+        let (a, r) = edge_lengths(100, 0.1, 0.6, 2);
+        assert_eq!((a, r), (10, 60));
+        let mut data = vec![1.0f32; 100];
+        apply_edges(&mut data, a, r);
+        assert_eq!(data[0], 0.0);
+        assert_eq!(data[99], 0.0);
+        assert!(data[..10].windows(2).all(|w| w[0] < w[1]));
+        assert!(data[10..40].iter().all(|v| *v == 1.0));
+        assert!(data[40..].windows(2).all(|w| w[0] > w[1]));
+        assert!((data[5] - 0.5).abs() < 1e-6);
+        assert!((data[69] - 0.5).abs() < 1e-6);
+
+        // 最低長を確保し、合計がグレイン長を超えたら比率を保って縮める
+        assert_eq!(edge_lengths(100, 0.0, 0.0, 2), (2, 2));
+        assert_eq!(edge_lengths(100, 0.9, 0.6, 2), (60, 40));
+    }
+
+    #[test]
+    fn min_tukey_len_guarantees_edges() {
+        for alpha in [0.05f32, 0.2, 0.5, 1.0] {
+            for edge in [1usize, 4, 44, 441] {
+                let n = min_tukey_len(alpha, edge);
+                assert!(n >= 2 * edge);
+                // ちょうどこの長さで片側 edge サンプル以上のフェードになり、1 短いと足りない
+                let fade = |n: usize| (alpha * (n - 1) as f32 * 0.5).floor() as usize;
+                assert!(fade(n) >= edge, "alpha={alpha} edge={edge} n={n}");
+                assert!(
+                    n <= 2 * edge || fade(n - 1) < edge,
+                    "alpha={alpha} edge={edge} n={n}"
+                );
+
+                let mut data = vec![1.0f32; n];
+                apply_tukey(&mut data, alpha);
+                // 先頭 edge サンプルは 1.0 未満 (フェード中)
+                assert!(data[..edge].iter().all(|v| *v < 1.0));
+                assert!(data[n - edge..].iter().all(|v| *v < 1.0));
+            }
+        }
+        assert_eq!(min_tukey_len(0.0, 10), 0);
+    }
+}